@@ -0,0 +1,79 @@
+//! Plays a 500 Hz triangle wave on DAC0 (PB13) at 16 kHz, paced by TIOA0
+//! of TC0 and fed by XDMAC channel 0, whose interrupt refills the buffers.
+
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use panic_halt as _;
+use cortex_m_rt::entry;
+use samv71_hal::clocks::{self, MainClock, MasterClockSource, MckDivider, Prescaler};
+use samv71_hal::dacc::{self, AudioOut, Dacc};
+use samv71_hal::dma::XdmacExt;
+use samv71_hal::dma::peripheral::{Dacc0, PingPongBuffers};
+use samv71_hal::pac::{self as sam, interrupt};
+use samv71_hal::prelude::*;
+use samv71_hal::sync::InterruptMutex;
+use samv71_hal::tc::TcExt;
+
+const RATE: u32 = 16_000;
+/// One period of the wave.
+const PERIOD: usize = 32;
+const WAVE: [u16; PERIOD] = triangle();
+
+static mut BUFFERS: PingPongBuffers<256> = PingPongBuffers::new(dacc::MID_SCALE as u32);
+static AUDIO: InterruptMutex<AudioOut<Dacc0, 0, 0, 256, 64>> = InterruptMutex::new();
+/// Next sample of the wave.
+static PHASE: AtomicUsize = AtomicUsize::new(0);
+
+const fn triangle() -> [u16; PERIOD] {
+    let mut wave = [0; PERIOD];
+    let mut i = 0;
+    while i < PERIOD {
+        let rise = if i < PERIOD / 2 { i } else { PERIOD - i };
+        wave[i] = (0x400 + rise * 0x800 / (PERIOD / 2)) as u16;
+        i += 1;
+    }
+    wave
+}
+
+fn refill(buffer: &mut [u32]) -> usize {
+    let mut phase = PHASE.load(Ordering::Relaxed);
+    for word in buffer.iter_mut() {
+        *word = WAVE[phase] as u32;
+        phase = (phase + 1) % PERIOD;
+    }
+    PHASE.store(phase, Ordering::Relaxed);
+    buffer.len()
+}
+
+#[entry]
+fn main() -> ! {
+    let cp = sam::CorePeripherals::take().unwrap();
+    let dp = sam::Peripherals::take().unwrap();
+    let clocks = clocks::Config::new(
+        MainClock::Crystal(12.mhz()),
+        MasterClockSource::PllA(25),
+        Prescaler::Div1,
+        MckDivider::Div2,
+    );
+    let hal = samv71_hal::init(dp, cp.SYST, samv71_hal::resources::Config::new(clocks, true));
+
+    let tc0 = hal.tc0.split(&hal.pmc);
+    let xdmac = hal.xdmac.split(&hal.pmc);
+    let dacc = Dacc::new(hal.dacc, &hal.clocks, &hal.pmc);
+    // Only taken here, once.
+    let buffers = unsafe { &mut *core::ptr::addr_of_mut!(BUFFERS) };
+    let audio = dacc.audio_out(Dacc0, RATE.hz(), tc0.ch0, xdmac.ch0, buffers, Some(refill), &hal.clocks);
+    AUDIO.init(audio);
+    unsafe { sam::NVIC::unmask(sam::Interrupt::XDMAC) };
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+#[interrupt]
+fn XDMAC() {
+    AUDIO.lock(|audio| audio.on_interrupt());
+}
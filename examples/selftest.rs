@@ -0,0 +1,113 @@
+//! Runs the drivers against themselves, without external wiring, and
+//! prints one line per check on the EDBG virtual COM port at 115200 bauds,
+//! ending with `PASS` or `FAIL` for a test runner to match on.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write as _;
+use panic_halt as _;
+use cortex_m_rt::entry;
+use embedded_hal::serial::{Read, Write};
+use samv71_hal::board;
+use samv71_hal::clocks::{self, MainClock, MasterClockSource, MckDivider, Prescaler};
+use samv71_hal::dma::{self, XdmacExt};
+use samv71_hal::pac as sam;
+use samv71_hal::prelude::*;
+use samv71_hal::serial::{uart, usart};
+
+const PATTERN: [u8; 8] = [0x00, 0xFF, 0x55, 0xAA, 0x01, 0x80, 0x7E, 0x81];
+
+/// Sends `PATTERN` one byte at a time and checks each comes back.
+fn echo<S, E>(serial: &mut S) -> Result<bool, E>
+where
+    S: Read<u8, Error = E> + Write<u8, Error = E>,
+{
+    for &byte in PATTERN.iter() {
+        nb::block!(serial.write(byte))?;
+        if nb::block!(serial.read())? != byte {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn verdict<E>(result: Result<bool, E>) -> &'static str {
+    match result {
+        Ok(true) => "ok",
+        Ok(false) => "mismatch",
+        Err(_) => "error",
+    }
+}
+
+static SOURCE: [u8; 64] = source_pattern();
+static mut DESTINATION: [u8; 64] = [0; 64];
+
+const fn source_pattern() -> [u8; 64] {
+    let mut pattern = [0; 64];
+    let mut i = 0;
+    while i < pattern.len() {
+        pattern[i] = i as u8 ^ 0xA5;
+        i += 1;
+    }
+    pattern
+}
+
+#[entry]
+fn main() -> ! {
+    let cp = sam::CorePeripherals::take().unwrap();
+    let dp = sam::Peripherals::take().unwrap();
+    let clocks = clocks::Config::new(
+        MainClock::Crystal(12.mhz()),
+        MasterClockSource::PllA(25),
+        Prescaler::Div1,
+        MckDivider::Div2,
+    );
+    let hal = samv71_hal::init(dp, cp.SYST, samv71_hal::resources::Config::new(clocks, true));
+
+    let console_config = usart::Config::new(
+        115_200.bps(),
+        usart::Parity::NoParity,
+        usart::ChannelMode::Normal,
+        usart::CharLength::EightBit,
+        usart::SyncMode::Async,
+        usart::UsartMode::Normal,
+    );
+    let mut console = board::edbg_serial(hal.usart1, hal.piob.pb4, hal.pioa.pa21, &console_config, &hal.clocks, &hal.pmc)
+        .words::<u8>();
+    let mut failures = 0;
+
+    let (pb1, pb0, piod) = (hal.piob.pb1, hal.piob.pb0, hal.piod);
+    let (uart_pins, usart_pins) = cortex_m::interrupt::free(move |cs| {
+        (
+            (piod.pd26.into_alternate_af2(cs).disable(cs), piod.pd25.into_alternate_af2(cs).disable(cs)),
+            (pb1.into_alternate_af2(cs).disable(cs), pb0.into_alternate_af2(cs).disable(cs)),
+        )
+    });
+
+    let config = uart::Config::new(115_200.bps(), uart::Parity::NoParity, uart::ChannelMode::Normal);
+    let mut uart2 = uart::Serial::uart2(hal.uart2, uart_pins, config, &hal.clocks, &hal.pmc).unwrap();
+    uart2.enable_local_loopback();
+    let result = verdict(echo(&mut uart2));
+    failures += (result != "ok") as u32;
+    writeln!(console, "uart2 local loopback: {}", result).ok();
+
+    let mut usart0 = usart::Serial::usart0(hal.usart0, usart_pins, &console_config, &hal.clocks, &hal.pmc).words::<u8>();
+    usart0.enable_local_loopback();
+    let result = verdict(echo(&mut usart0));
+    failures += (result != "ok") as u32;
+    writeln!(console, "usart0 local loopback: {}", result).ok();
+
+    let xdmac = hal.xdmac.split(&hal.pmc);
+    // Only taken here, once.
+    let destination = unsafe { &mut *core::ptr::addr_of_mut!(DESTINATION) };
+    let (_, (source, destination)) = dma::copy(xdmac.ch0, &SOURCE, destination).wait();
+    let result = if source == destination { "ok" } else { "mismatch" };
+    failures += (result != "ok") as u32;
+    writeln!(console, "xdmac memory copy: {}", result).ok();
+
+    writeln!(console, "{}", if failures == 0 { "PASS" } else { "FAIL" }).ok();
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
@@ -0,0 +1,61 @@
+//! Shares the halves of UART2 (PD25, PD26) between the main loop and its
+//! interrupt: the handler echoes what comes in, while the main loop prints
+//! a line every second through the same transmitter.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write as _;
+use panic_halt as _;
+use cortex_m_rt::entry;
+use embedded_hal::serial::{Read, Write};
+use samv71_hal::clocks::{self, MainClock, MasterClockSource, MckDivider, Prescaler};
+use samv71_hal::pac::{self as sam, interrupt, UART2};
+use samv71_hal::prelude::*;
+use samv71_hal::serial::uart::{ChannelMode, Config, Event, Parity, Rx, Serial, Tx};
+use samv71_hal::sync::{CsCell, InterruptMutex};
+
+static TX: InterruptMutex<Tx<UART2>> = InterruptMutex::new();
+static RX: CsCell<Rx<UART2>> = CsCell::new();
+
+#[entry]
+fn main() -> ! {
+    let cp = sam::CorePeripherals::take().unwrap();
+    let dp = sam::Peripherals::take().unwrap();
+    let clocks = clocks::Config::new(
+        MainClock::Crystal(12.mhz()),
+        MasterClockSource::PllA(25),
+        Prescaler::Div1,
+        MckDivider::Div2,
+    );
+    let mut hal = samv71_hal::init(dp, cp.SYST, samv71_hal::resources::Config::new(clocks, true));
+
+    let piod = hal.piod;
+    let pins = cortex_m::interrupt::free(move |cs| {
+        (piod.pd26.into_alternate_af2(cs).disable(cs), piod.pd25.into_alternate_af2(cs).disable(cs))
+    });
+    let config = Config::new(115_200.bps(), Parity::NoParity, ChannelMode::Normal);
+    let mut serial = Serial::uart2(hal.uart2, pins, config, &hal.clocks, &hal.pmc).unwrap();
+    serial.listen(Event::RxReady);
+    let (tx, rx) = serial.split();
+
+    TX.init(tx);
+    cortex_m::interrupt::free(|cs| RX.put(cs, rx));
+    unsafe { sam::NVIC::unmask(sam::Interrupt::UART2) };
+
+    let mut seconds = 0u32;
+    loop {
+        hal.delay.delay_ms(1000u32);
+        seconds += 1;
+        TX.lock(|tx| writeln!(tx, "up {} s", seconds).ok());
+    }
+}
+
+#[interrupt]
+fn UART2() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(Ok(byte)) = RX.with(cs, |rx| rx.read()) {
+            TX.lock(|tx| nb::block!(tx.write(byte)).ok());
+        }
+    });
+}
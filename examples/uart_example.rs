@@ -3,17 +3,25 @@
 
 use panic_halt as _;
 use cortex_m_rt::entry;
+use samv71_hal::clocks::{self, MainClock, MasterClockSource, MckDivider, Prescaler};
 use samv71_hal::serial::uart::{Serial, Config, Parity, ChannelMode};
-use samv71_hal::serial::BaudRate;
-use samv71_hal::gpio::GpioExt;
 use samv71_hal::pac as sam;
 use samv71_hal::prelude::*;
 
 
 #[entry]
 fn main() -> ! {
-    let p = sam::Peripherals::take().unwrap();
-    let piod = p.PIOD.split();
+    let cp = sam::CorePeripherals::take().unwrap();
+    let dp = sam::Peripherals::take().unwrap();
+    let clocks = clocks::Config::new(
+        MainClock::Crystal(12.mhz()),
+        MasterClockSource::PllA(25),
+        Prescaler::Div1,
+        MckDivider::Div2,
+    );
+    let hal = samv71_hal::init(dp, cp.SYST, samv71_hal::resources::Config::new(clocks, true));
+
+    let piod = hal.piod;
     let pins = cortex_m::interrupt::free(move |cs|
         {
             (
@@ -22,12 +30,11 @@ fn main() -> ! {
             )
         });
 
-    let config = Config::new(9600.into(), Parity::NoParity, ChannelMode::Normal, false);
-    let mut serial2= Serial::uart2(p.UART2, pins, config, &p.PMC);
-    serial2.write(0x28);
+    let config = Config::new(9600.bps(), Parity::NoParity, ChannelMode::Normal);
+    let mut serial2= Serial::uart2(hal.uart2, pins, config, &hal.clocks, &hal.pmc).unwrap();
+    serial2.write(0x28).ok();
 
     loop {
 
     }
 }
-
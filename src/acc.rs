@@ -0,0 +1,143 @@
+//! Analog comparator controller.
+//!
+//! Besides being read, the comparator output can stop PWM channels on its
+//! own, through fault input 6 of both PWMs. `protect_bridge` sets that path
+//! up in one go:
+//!
+//! ```ignore
+//! let config = acc::Config::new(PlusInput::Afec0Ad0, MinusInput::Dac0).hysteresis(2);
+//! let acc = acc::protect_bridge(dp.ACC, config, &mut pwm0, 0b0011, &pmc);
+//! ```
+
+use crate::pac::{ACC, PMC};
+use crate::pmc::PeripheralId;
+use crate::pwm;
+use crate::write_protect::WriteProtect;
+
+/// PWM fault input wired to the comparator output.
+const PWM_FAULT_INPUT: u8 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlusInput {
+    Afec0Ad0,
+    Afec0Ad1,
+    Afec0Ad2,
+    Afec0Ad3,
+    Afec0Ad4,
+    Afec0Ad5,
+    Afec1Ad0,
+    Afec1Ad1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MinusInput {
+    TemperatureSensor,
+    Vrefp,
+    Dac0,
+    Dac1,
+    Afec0Ad0,
+    Afec0Ad1,
+    Afec0Ad2,
+    Afec0Ad3,
+}
+
+pub struct Config {
+    plus: PlusInput,
+    minus: MinusInput,
+    hysteresis: u8,
+    invert: bool,
+    low_power: bool,
+}
+
+impl Config {
+    pub fn new(plus: PlusInput, minus: MinusInput) -> Self {
+        Config { plus, minus, hysteresis: 0, invert: false, low_power: false }
+    }
+
+    /// From 0, none, to 3, the widest band.
+    pub fn hysteresis(mut self, level: u8) -> Self {
+        assert!(level < 4, "hysteresis level out of range");
+        self.hysteresis = level;
+        self
+    }
+
+    /// Makes the output high while the plus input is below the minus one.
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    /// Trades response time, from about 0.1 µs to a few µs, for current.
+    pub fn low_power(mut self, low_power: bool) -> Self {
+        self.low_power = low_power;
+        self
+    }
+}
+
+pub struct Acc {
+    acc: ACC,
+}
+
+impl Acc {
+    pub fn new(acc: ACC, config: Config, pmc: &PMC) -> Self {
+        pmc.with_unlocked(|pmc| unsafe {
+            pmc.pmc_pcer1.write_with_zero(|w| w.bits(1 << (ACC::PID - 32)));
+        });
+        acc.with_unlocked(|acc| unsafe {
+            acc.acr.write_with_zero(|w| {
+                let w = w.hyst().bits(config.hysteresis);
+                if config.low_power {
+                    w.isel().lopw()
+                } else {
+                    w.isel().hisp()
+                }
+            });
+            acc.mr.write_with_zero(|w| {
+                w.selplus().bits(config.plus as u8)
+                    .selminus().bits(config.minus as u8)
+                    .inv().bit(config.invert)
+                    .edgetyp().any()
+                    .acen().en()
+            });
+        });
+        Acc { acc }
+    }
+
+    /// Level of the comparator output, after inversion.
+    pub fn output(&self) -> bool {
+        self.acc.isr.read().sco().bit()
+    }
+
+    /// Feeds the output level to the PWM fault inputs.
+    pub fn enable_fault_output(&mut self) {
+        self.acc.with_unlocked(|acc| acc.mr.modify(|_, w| w.selfs().output().fe().en()));
+    }
+
+    pub fn disable_fault_output(&mut self) {
+        self.acc.with_unlocked(|acc| acc.mr.modify(|_, w| w.fe().dis()));
+    }
+
+    pub fn free(self, pmc: &PMC) -> ACC {
+        self.acc.with_unlocked(|acc| acc.mr.modify(|_, w| w.fe().dis().acen().dis()));
+        pmc.with_unlocked(|pmc| unsafe {
+            pmc.pmc_pcdr1.write_with_zero(|w| w.bits(1 << (ACC::PID - 32)));
+        });
+        self.acc
+    }
+}
+
+/// Drives both outputs of the `channels` of `pwm`, a bit mask, low as soon
+/// as the comparator output goes high, without the CPU. The outputs stay
+/// low until `pwm::Parts::clear_faults` is called with the output back low.
+pub fn protect_bridge<PWM: pwm::Instance>(
+    acc: ACC,
+    config: Config,
+    pwm: &mut pwm::Parts<PWM>,
+    channels: u8,
+    pmc: &PMC,
+) -> Acc {
+    let mut acc = Acc::new(acc, config, pmc);
+    pwm.enable_fault(PWM_FAULT_INPUT, channels);
+    acc.enable_fault_output();
+    acc
+}
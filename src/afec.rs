@@ -0,0 +1,323 @@
+//! Analog front-end controllers.
+
+use core::convert::Infallible;
+use core::slice;
+use embedded_dma::WriteBuffer;
+use crate::clocks::Clocks;
+use crate::dma::{self, peripheral::{Afec0, Afec1, Source}, Transfer};
+use crate::pac::afec0::mr::STARTUP_A;
+use crate::pac::afec0::RegisterBlock;
+use crate::pac::{AFEC0, AFEC1, PMC, TC0, TC1};
+use crate::pmc::PeripheralId;
+use crate::tc;
+use crate::time::Hertz;
+use crate::write_protect::WriteProtect;
+
+pub const CHANNELS: u8 = 12;
+/// AFEC0 channel of the temperature sensor.
+pub const TEMPERATURE: u8 = 11;
+/// Highest AFE clock.
+const AFE_CLOCK_MAX: u32 = 40_000_000;
+/// Mid-scale analog offset, for single-ended inputs.
+const OFFSET_MID: u16 = 0x200;
+
+pub trait Instance: PeripheralId {
+    /// TC block whose TIOA lines can trigger the AFEC.
+    type Timer: tc::Instance;
+    /// DMA request line of the last converted data.
+    type Request: Source;
+    const REQUEST: Self::Request;
+
+    fn ptr() -> *const RegisterBlock;
+}
+
+macro_rules! afec_instance {
+    ($($AFEC:ident: ($TC:ident, $Request:ident),)+) => {
+        $(
+            impl Instance for $AFEC {
+                type Timer = $TC;
+                type Request = $Request;
+                const REQUEST: $Request = $Request;
+
+                fn ptr() -> *const RegisterBlock {
+                    $AFEC::ptr() as *const RegisterBlock
+                }
+            }
+        )+
+    }
+}
+
+afec_instance! {
+    AFEC0: (TC0, Afec0),
+    AFEC1: (TC1, Afec1),
+}
+
+/// Conversion trigger. Apart from the analog comparator, each AFEC has its
+/// own trigger lines: timer and PWM triggers come from TC0 and PWM0 for
+/// AFEC0 and from TC1 and PWM1 for AFEC1, which must then run in step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// `DualAfec::start`.
+    Software,
+    /// AFE0_ADTRG and AFE1_ADTRG pins, usually wired together.
+    External,
+    /// TIOA of channel `n` of TC0 and TC1.
+    Tioa(u8),
+    /// PWM event line 0 of PWM0 and PWM1.
+    PwmEvent0,
+    /// PWM event line 1 of PWM0 and PWM1.
+    PwmEvent1,
+    /// Analog comparator, shared by both.
+    Comparator,
+}
+
+impl Trigger {
+    fn select(&self) -> Option<u8> {
+        match *self {
+            Trigger::Software => None,
+            Trigger::External => Some(0),
+            Trigger::Tioa(n) => {
+                assert!(n < 3, "TC channel out of range");
+                Some(1 + n)
+            }
+            Trigger::PwmEvent0 => Some(4),
+            Trigger::PwmEvent1 => Some(5),
+            Trigger::Comparator => Some(6),
+        }
+    }
+}
+
+fn registers<A: Instance>() -> &'static RegisterBlock {
+    unsafe { &*A::ptr() }
+}
+
+pub(crate) fn enable_clock<A: Instance>(pmc: &PMC, enable: bool) {
+    pmc.with_unlocked(|pmc| unsafe {
+        let mask = 1 << (A::PID % 32);
+        match (A::PID < 32, enable) {
+            (true, true) => pmc.pmc_pcer0.write_with_zero(|w| w.bits(mask)),
+            (false, true) => pmc.pmc_pcer1.write_with_zero(|w| w.bits(mask)),
+            (true, false) => pmc.pmc_pcdr0.write_with_zero(|w| w.bits(mask)),
+            (false, false) => pmc.pmc_pcdr1.write_with_zero(|w| w.bits(mask)),
+        }
+    });
+}
+
+/// Divides MCK down to at most the highest AFE clock.
+pub(crate) fn prescaler(clocks: &Clocks) -> u8 {
+    (clocks.mck().0.div_ceil(AFE_CLOCK_MAX) - 1) as u8
+}
+
+/// Starts conversions on hardware trigger `select`, see `Trigger`, or only
+/// on the software one.
+pub(crate) fn set_trigger<A: Instance>(select: Option<u8>) {
+    registers::<A>().with_unlocked(|afec| afec.mr.modify(|_, w| match select {
+        Some(select) => unsafe { w.trgen().en().trgsel().bits(select) },
+        None => w.trgen().dis(),
+    }));
+}
+
+/// Resets the AFEC and sets it up for 12-bit single-ended conversions of
+/// the `channels` mask, all of them being converted once per trigger.
+pub(crate) fn configure<A: Instance>(channels: u16, trigger: Trigger, prescaler: u8) {
+    registers::<A>().with_unlocked(|afec| unsafe {
+        afec.cr.write_with_zero(|w| w.swrst().set_bit());
+        afec.mr.write(|w| {
+            w.prescal().bits(prescaler)
+                .startup().variant(STARTUP_A::SUT64)
+                .one().set_bit()
+                .tracktim().bits(15)
+                .transfer().bits(2)
+        });
+        afec.emr.write(|w| {
+            w.res().no_average()
+                .tag().set_bit()
+                .stm().set_bit()
+                .signmode().all_unsigned()
+        });
+        afec.acr.write(|w| w.ibctl().bits(1).pga0en().set_bit().pga1en().set_bit());
+        for channel in (0..CHANNELS).filter(|channel| channels & (1 << channel) != 0) {
+            afec.cselr.write(|w| w.csel().bits(channel));
+            afec.cocr.write(|w| w.aoff().bits(OFFSET_MID));
+        }
+        afec.cher.write_with_zero(|w| w.bits(channels as u32));
+    });
+    set_trigger::<A>(trigger.select());
+}
+
+/// Splits a tagged result, as moved by the DMA, into channel and value.
+pub fn untag(result: u32) -> (u8, u16) {
+    ((result >> 24) as u8 & 0xF, result as u16)
+}
+
+pub struct Afec<A> {
+    afec: A,
+    prescaler: u8,
+}
+
+impl<A: Instance> Afec<A> {
+    pub fn new(afec: A, clocks: &Clocks, pmc: &PMC) -> Self {
+        enable_clock::<A>(pmc, true);
+        Afec { afec, prescaler: prescaler(clocks) }
+    }
+
+    pub fn free(self, pmc: &PMC) -> A {
+        enable_clock::<A>(pmc, false);
+        self.afec
+    }
+
+    /// Converts the `channels` mask `rate` times per second, triggered by
+    /// TIOA of `timer`, with the DMA moving the tagged results to `buffer`.
+    pub fn start_periodic<const TC: u8, const CH: u8, B>(
+        self,
+        rate: Hertz,
+        channels: u16,
+        mut timer: tc::Channel<A::Timer, TC>,
+        dma: dma::Channel<CH>,
+        buffer: B,
+        clocks: &Clocks,
+    ) -> Stream<A, TC, CH, B>
+    where
+        B: WriteBuffer<Word = u32>,
+    {
+        let stream = self.stream(channels, Trigger::Tioa(TC), dma, buffer);
+        timer.start_trigger_rate(clocks, rate);
+        stream.with_timer(timer)
+    }
+
+    pub(crate) fn stream<const CH: u8, B>(self, channels: u16, trigger: Trigger, dma: dma::Channel<CH>, buffer: B) -> PendingStream<A, CH, B>
+    where
+        B: WriteBuffer<Word = u32>,
+    {
+        configure::<A>(channels, trigger, self.prescaler);
+        let transfer = dma::peripheral::peripheral_to_memory(dma, A::REQUEST, buffer);
+        PendingStream { afec: self, transfer }
+    }
+}
+
+/// Stream set up but for its timer, which must start after the AFEC and
+/// the DMA so that the first trigger is not lost.
+pub(crate) struct PendingStream<A: Instance, const CH: u8, B> {
+    afec: Afec<A>,
+    transfer: Transfer<CH, (A::Request, B)>,
+}
+
+impl<A: Instance, const CH: u8, B> PendingStream<A, CH, B> {
+    pub(crate) fn with_timer<const TC: u8>(self, timer: tc::Channel<A::Timer, TC>) -> Stream<A, TC, CH, B> {
+        Stream { afec: self.afec, timer, transfer: Some(self.transfer) }
+    }
+}
+
+/// Conversions running at a fixed rate into a DMA buffer.
+pub struct Stream<A: Instance, const TC: u8, const CH: u8, B> {
+    afec: Afec<A>,
+    timer: tc::Channel<A::Timer, TC>,
+    transfer: Option<Transfer<CH, (A::Request, B)>>,
+}
+
+impl<A: Instance, const TC: u8, const CH: u8, B> Stream<A, TC, CH, B>
+where
+    B: WriteBuffer<Word = u32>,
+{
+    /// Once the buffer is full, passes the results to `f`, see `untag`, and
+    /// starts filling it again. Returns whether it did. Conversions ending
+    /// in between are lost, so call it soon after the DMA completes.
+    pub fn poll(&mut self, f: impl FnOnce(&[u32])) -> bool {
+        if !self.transfer.as_ref().is_some_and(|transfer| transfer.is_done()) {
+            return false;
+        }
+        let (channel, (request, mut buffer)) = self.transfer.take().unwrap().wait();
+        let results = unsafe {
+            let (address, length) = buffer.write_buffer();
+            slice::from_raw_parts(address as *const u32, length)
+        };
+        f(results);
+        self.transfer = Some(dma::peripheral::peripheral_to_memory(channel, request, buffer));
+        true
+    }
+
+    pub fn stop(mut self) -> (Afec<A>, tc::Channel<A::Timer, TC>, dma::Channel<CH>, B) {
+        self.timer.disable();
+        let (channel, (_, buffer)) = self.transfer.take().unwrap().stop();
+        (self.afec, self.timer, channel, buffer)
+    }
+}
+
+/// AFEC0 and AFEC1 converting one channel each on the same kind of trigger,
+/// e.g. two motor phase currents.
+///
+/// Only `Trigger::External` and `Trigger::Comparator` are one signal seen by
+/// both, so only they sample at the same instant. `Trigger::Software` starts
+/// AFEC1 one register write after AFEC0. `Trigger::Tioa` takes AFEC0 from TC0
+/// and AFEC1 from TC1, and the PWM events AFEC0 from PWM0 and AFEC1 from
+/// PWM1: independent blocks, which the caller has to start in step for the
+/// samples to line up, and which still drift apart if clocked differently.
+pub struct DualAfec {
+    afec0: AFEC0,
+    afec1: AFEC1,
+    channels: (u8, u8),
+}
+
+impl DualAfec {
+    /// `channels` are the AFEC0 and AFEC1 inputs to sample.
+    pub fn new(
+        afec0: AFEC0,
+        afec1: AFEC1,
+        channels: (u8, u8),
+        trigger: Trigger,
+        clocks: &Clocks,
+        pmc: &PMC,
+    ) -> Self {
+        assert!(channels.0 < CHANNELS && channels.1 < CHANNELS, "AFEC channel out of range");
+        let prescaler = prescaler(clocks);
+
+        enable_clock::<AFEC0>(pmc, true);
+        enable_clock::<AFEC1>(pmc, true);
+        configure::<AFEC0>(1 << channels.0, trigger, prescaler);
+        configure::<AFEC1>(1 << channels.1, trigger, prescaler);
+        DualAfec { afec0, afec1, channels }
+    }
+
+    pub fn free(self, pmc: &PMC) -> (AFEC0, AFEC1) {
+        enable_clock::<AFEC0>(pmc, false);
+        enable_clock::<AFEC1>(pmc, false);
+        (self.afec0, self.afec1)
+    }
+
+    /// Raw registers of AFEC0 and AFEC1, e.g. for gain or averaging
+    /// settings.
+    ///
+    /// # Safety
+    /// Channel selection, trigger and tagging must stay as set up by `new`.
+    pub unsafe fn registers(&self) -> (&RegisterBlock, &RegisterBlock) {
+        (registers::<AFEC0>(), registers::<AFEC1>())
+    }
+
+    /// Starts a conversion on both, back to back, with the software trigger:
+    /// AFEC1 samples a few bus cycles after AFEC0.
+    pub fn start(&mut self) {
+        unsafe {
+            registers::<AFEC0>().cr.write_with_zero(|w| w.start().set_bit());
+            registers::<AFEC1>().cr.write_with_zero(|w| w.start().set_bit());
+        }
+    }
+
+    /// Returns the AFEC0 and AFEC1 results once both conversions of the
+    /// last trigger are done.
+    pub fn read_pair(&mut self) -> nb::Result<(u16, u16), Infallible> {
+        let (afec0, afec1) = (registers::<AFEC0>(), registers::<AFEC1>());
+        let done = |afec: &RegisterBlock, channel: u8| {
+            afec.isr.read().bits() & (1 << channel) != 0
+        };
+        if !done(afec0, self.channels.0) || !done(afec1, self.channels.1) {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok((read(afec0, self.channels.0), read(afec1, self.channels.1)))
+    }
+}
+
+/// Reading the channel data clears its end of conversion flag.
+fn read(afec: &RegisterBlock, channel: u8) -> u16 {
+    unsafe { afec.cselr.write(|w| w.csel().bits(channel)) };
+    afec.cdr.read().data().bits()
+}
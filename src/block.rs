@@ -0,0 +1,259 @@
+//! 512-byte block access, the shape a USB mass storage class or a FAT
+//! library expects of its backing store.
+//!
+//! `StorageBlocks` puts any `nvstore::Storage`, the internal flash or a
+//! serial flash, behind it. Blocks smaller than the erase size are written
+//! by reading the sector, erasing it and programming it back:
+//!
+//! ```ignore
+//! let flash = SpiFlash::probe(transport)?;
+//! let mut disk: StorageBlocks<_, 4096> = StorageBlocks::new(flash, 0, 8 * 1024 * 1024)?;
+//! disk.write_blocks(lba, &data)?;
+//! ```
+//!
+//! A power loss during a write can lose the other blocks of the sector.
+
+use crate::nvstore::Storage;
+
+pub const BLOCK_SIZE: usize = 512;
+
+pub trait BlockDevice {
+    type Error;
+
+    fn block_count(&self) -> u32;
+    /// Reads whole blocks from `lba` on into `data`.
+    fn read_blocks(&mut self, lba: u32, data: &mut [u8]) -> Result<(), Self::Error>;
+    /// Writes whole blocks from `lba` on.
+    fn write_blocks(&mut self, lba: u32, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    Storage(E),
+    /// The region or `SECTOR` does not match the erase size.
+    Geometry,
+    /// Past the last block.
+    OutOfRange,
+    /// Not a whole number of blocks.
+    Unaligned,
+}
+
+/// `length` bytes of `storage` from `offset` as blocks, through a buffer of
+/// one erase sector of `SECTOR` bytes.
+pub struct StorageBlocks<S, const SECTOR: usize> {
+    storage: S,
+    offset: u32,
+    length: u32,
+    sector: [u8; SECTOR],
+}
+
+impl<S: Storage, const SECTOR: usize> StorageBlocks<S, SECTOR> {
+    pub fn new(storage: S, offset: u32, length: u32) -> Result<Self, Error<S::Error>> {
+        let sector = SECTOR as u32;
+        if storage.erase_size() != sector
+            || !sector.is_multiple_of(BLOCK_SIZE as u32)
+            || !offset.is_multiple_of(sector)
+            || !length.is_multiple_of(sector)
+        {
+            return Err(Error::Geometry);
+        }
+        Ok(StorageBlocks { storage, offset, length, sector: [0; SECTOR] })
+    }
+
+    pub fn free(self) -> S {
+        self.storage
+    }
+
+    /// Byte range of `data` at `lba`, checked.
+    fn range(&self, lba: u32, length: usize) -> Result<u32, Error<S::Error>> {
+        if !length.is_multiple_of(BLOCK_SIZE) {
+            return Err(Error::Unaligned);
+        }
+        let blocks = (length / BLOCK_SIZE) as u32;
+        match lba.checked_add(blocks) {
+            Some(end) if end <= self.block_count() => Ok(lba * BLOCK_SIZE as u32),
+            _ => Err(Error::OutOfRange),
+        }
+    }
+}
+
+impl<S: Storage, const SECTOR: usize> BlockDevice for StorageBlocks<S, SECTOR> {
+    type Error = Error<S::Error>;
+
+    fn block_count(&self) -> u32 {
+        self.length / BLOCK_SIZE as u32
+    }
+
+    fn read_blocks(&mut self, lba: u32, data: &mut [u8]) -> Result<(), Self::Error> {
+        let start = self.range(lba, data.len())?;
+        self.storage.read(self.offset + start, data).map_err(Error::Storage)
+    }
+
+    fn write_blocks(&mut self, lba: u32, mut data: &[u8]) -> Result<(), Self::Error> {
+        let mut position = self.range(lba, data.len())?;
+        while !data.is_empty() {
+            let sector = self.offset + position - position % SECTOR as u32;
+            let start = (position % SECTOR as u32) as usize;
+            let count = (SECTOR - start).min(data.len());
+            self.storage.read(sector, &mut self.sector).map_err(Error::Storage)?;
+            // Rewritten with the same contents is common with FAT.
+            if self.sector[start..start + count] != data[..count] {
+                self.sector[start..start + count].copy_from_slice(&data[..count]);
+                self.storage.erase(sector, SECTOR as u32).map_err(Error::Storage)?;
+                self.storage.write(sector, &self.sector).map_err(Error::Storage)?;
+            }
+            position += count as u32;
+            data = &data[count..];
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::convert::TryInto;
+    use std::vec;
+    use std::vec::Vec;
+
+    struct RamFlash {
+        memory: Vec<u8>,
+        erases: usize,
+    }
+
+    impl Storage for RamFlash {
+        type Error = ();
+
+        fn erase_size(&self) -> u32 {
+            1024
+        }
+
+        fn write_size(&self) -> u32 {
+            16
+        }
+
+        fn read(&mut self, offset: u32, data: &mut [u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            data.copy_from_slice(&self.memory[offset..offset + data.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            for (cell, &byte) in self.memory[offset..offset + data.len()].iter_mut().zip(data) {
+                assert_eq!(*cell, 0xFF, "programmed twice");
+                *cell = byte;
+            }
+            Ok(())
+        }
+
+        fn erase(&mut self, offset: u32, length: u32) -> Result<(), ()> {
+            self.memory[offset as usize..(offset + length) as usize].fill(0xFF);
+            self.erases += 1;
+            Ok(())
+        }
+    }
+
+    const CACHE_LINE: usize = 32;
+
+    /// `RamFlash` behind a data cache that keeps every line read until it
+    /// is invalidated, as `Flash` does after erasing or programming.
+    struct Cached {
+        flash: RamFlash,
+        lines: BTreeMap<usize, [u8; CACHE_LINE]>,
+        invalidate: bool,
+    }
+
+    impl Cached {
+        fn invalidate(&mut self, offset: u32, length: usize) {
+            if self.invalidate {
+                let (start, end) = (offset as usize / CACHE_LINE, (offset as usize + length).div_ceil(CACHE_LINE));
+                self.lines.retain(|&line, _| !(start..end).contains(&line));
+            }
+        }
+    }
+
+    impl Storage for Cached {
+        type Error = ();
+
+        fn erase_size(&self) -> u32 {
+            self.flash.erase_size()
+        }
+
+        fn write_size(&self) -> u32 {
+            self.flash.write_size()
+        }
+
+        fn read(&mut self, offset: u32, data: &mut [u8]) -> Result<(), ()> {
+            for (i, byte) in data.iter_mut().enumerate() {
+                let address = offset as usize + i;
+                let memory = &self.flash.memory;
+                let line = self.lines.entry(address / CACHE_LINE).or_insert_with(|| {
+                    let start = address - address % CACHE_LINE;
+                    memory[start..start + CACHE_LINE].try_into().unwrap()
+                });
+                *byte = line[address % CACHE_LINE];
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), ()> {
+            self.flash.write(offset, data)?;
+            self.invalidate(offset, data.len());
+            Ok(())
+        }
+
+        fn erase(&mut self, offset: u32, length: u32) -> Result<(), ()> {
+            self.flash.erase(offset, length)?;
+            self.invalidate(offset, length as usize);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_across_sectors() {
+        let flash = RamFlash { memory: vec![0xFF; 4096], erases: 0 };
+        let mut disk: StorageBlocks<_, 1024> = StorageBlocks::new(flash, 1024, 3072).unwrap();
+        assert_eq!(disk.block_count(), 6);
+
+        disk.write_blocks(0, &[1; 512]).unwrap();
+        disk.write_blocks(1, &[2; 1024]).unwrap();
+        let mut data = [0; 1536];
+        disk.read_blocks(0, &mut data).unwrap();
+        assert!(data[..512].iter().all(|&b| b == 1) && data[512..].iter().all(|&b| b == 2));
+
+        disk.write_blocks(0, &[1; 512]).unwrap();
+        assert_eq!(disk.free().erases, 3);
+    }
+
+    #[test]
+    fn bounds() {
+        let flash = RamFlash { memory: vec![0xFF; 4096], erases: 0 };
+        assert!(matches!(StorageBlocks::<_, 512>::new(flash, 0, 4096), Err(Error::Geometry)));
+        let flash = RamFlash { memory: vec![0xFF; 4096], erases: 0 };
+        let mut disk: StorageBlocks<_, 1024> = StorageBlocks::new(flash, 0, 4096).unwrap();
+        assert_eq!(disk.write_blocks(7, &[0; 1024]), Err(Error::OutOfRange));
+        assert_eq!(disk.read_blocks(0, &mut [0; 100]), Err(Error::Unaligned));
+    }
+
+    #[test]
+    fn reads_after_write_miss_stale_cache_lines() {
+        let run = |invalidate| {
+            let flash = RamFlash { memory: vec![0xFF; 4096], erases: 0 };
+            let cached = Cached { flash, lines: BTreeMap::new(), invalidate };
+            let mut disk: StorageBlocks<_, 1024> = StorageBlocks::new(cached, 0, 4096).unwrap();
+            let mut data = [0; 1024];
+            disk.write_blocks(1, &[1; 512]).unwrap();
+            disk.write_blocks(0, &[2; 512]).unwrap();
+            disk.read_blocks(0, &mut data).unwrap();
+            data
+        };
+        let data = run(true);
+        assert!(data[..512].iter().all(|&b| b == 2) && data[512..].iter().all(|&b| b == 1));
+        // Without the invalidation the fixture does serve stale lines.
+        assert_ne!(run(false), data);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::clocks::Clocks;
+use crate::gpio::{pioa::*, piob::*, pioc::*, piod::*, Alternate, AF0, AF3};
+use crate::pac::{MATRIX, PMC, USART1};
+use crate::serial::usart;
+use crate::write_protect::WriteProtect;
+
+pub type Led0<MODE> = PA23<MODE>;
+pub type Led1<MODE> = PC9<MODE>;
+pub type Sw0<MODE> = PA9<MODE>;
+pub type Sw1<MODE> = PB12<MODE>;
+
+pub type EdbgTx = PB4<Alternate<AF3>>;
+pub type EdbgRx = PA21<Alternate<AF0>>;
+pub type EdbgSerial = usart::Serial<USART1, EdbgTx, EdbgRx>;
+
+pub type ArduinoD0<MODE> = PD28<MODE>;
+pub type ArduinoD1<MODE> = PD30<MODE>;
+pub type ArduinoD2<MODE> = PA5<MODE>;
+pub type ArduinoD3<MODE> = PA6<MODE>;
+pub type ArduinoD4<MODE> = PD27<MODE>;
+pub type ArduinoD5<MODE> = PD11<MODE>;
+pub type ArduinoD6<MODE> = PC19<MODE>;
+pub type ArduinoD7<MODE> = PA2<MODE>;
+pub type ArduinoD8<MODE> = PA17<MODE>;
+pub type ArduinoD9<MODE> = PC9<MODE>;
+pub type ArduinoD10<MODE> = PD25<MODE>;
+pub type ArduinoD11<MODE> = PD21<MODE>;
+pub type ArduinoD12<MODE> = PD20<MODE>;
+pub type ArduinoD13<MODE> = PD22<MODE>;
+
+/// USART1 routed to the EDBG virtual COM port. PB4 is the TDI JTAG pin after
+/// reset, so it is handed over to the PIO controller through CCFG_SYSIO.
+pub fn edbg_serial<TXMODE, RXMODE>(
+    usart: USART1,
+    tx: PB4<TXMODE>,
+    rx: PA21<RXMODE>,
+    config: &usart::Config,
+    clocks: &Clocks,
+    pmc: &PMC) -> EdbgSerial {
+    unsafe {
+        (*MATRIX::ptr()).with_unlocked(|matrix| {
+            matrix.ccfg_sysio.modify(|_, w| w.sysio4().set_bit());
+        });
+    }
+    let pins = cortex_m::interrupt::free(move |cs| {
+        (
+            tx.into_alternate_af3(cs).disable(cs),
+            rx.into_alternate_af0(cs).disable(cs),
+        )
+    });
+    usart::Serial::usart1(usart, pins, config, clocks, pmc)
+}
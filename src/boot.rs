@@ -0,0 +1,51 @@
+use cortex_m::peripheral::{NVIC, SCB, SYST};
+use crate::pac::PMC;
+use crate::write_protect::WriteProtect;
+
+/// TBLOFF only holds bits 31:7; the SAMV71 table (16 + 74 entries) needs
+/// 512-byte alignment.
+const VECTOR_TABLE_ALIGN: u32 = 512;
+
+/// # Safety
+/// `address` must hold a valid vector table for as long as it is in use.
+pub unsafe fn relocate_vector_table(scb: &mut SCB, address: u32) {
+    assert!(address.is_multiple_of(VECTOR_TABLE_ALIGN));
+    scb.vtor.write(address);
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+/// Brings the core peripherals and clocks HAL drivers touch back close to
+/// their reset state, so the next image starts from a known place: every
+/// interrupt is disabled and unpended, SysTick is stopped and all
+/// peripheral clocks are gated.
+pub fn deinit(nvic: &mut NVIC, syst: &mut SYST, pmc: &PMC) {
+    cortex_m::interrupt::disable();
+    unsafe {
+        for (icer, icpr) in nvic.icer.iter().zip(nvic.icpr.iter()) {
+            icer.write(u32::MAX);
+            icpr.write(u32::MAX);
+        }
+    }
+    syst.disable_interrupt();
+    syst.disable_counter();
+    syst.clear_current();
+    SCB::clear_pendst();
+    pmc.with_unlocked(|pmc| unsafe {
+        pmc.pmc_pcdr0.write_with_zero(|w| w.bits(u32::MAX));
+        pmc.pmc_pcdr1.write_with_zero(|w| w.bits(u32::MAX));
+    });
+}
+
+/// Masks interrupts, points VTOR at the image at `address` and jumps to its
+/// reset handler with the stack pointer taken from the image. PRIMASK is
+/// still set when the application starts.
+///
+/// # Safety
+/// `address` must hold a valid vector table of an application image.
+/// Peripherals are left as they are; call `deinit` first.
+pub unsafe fn jump_to_application(scb: &mut SCB, address: u32) -> ! {
+    cortex_m::interrupt::disable();
+    relocate_vector_table(scb, address);
+    cortex_m::asm::bootload(address as *const u32)
+}
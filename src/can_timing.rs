@@ -0,0 +1,214 @@
+//! Bit timing of the MCAN controllers, from the bitrates rather than by hand.
+//!
+//! ```ignore
+//! let timing = BitTiming::from_bitrate(clocks.pck5(), 500.khz(), 2.mhz(), 800)?;
+//! mcan.btp.write(|w| unsafe { w.bits(timing.btp()) });
+//! mcan.fbtp.write(|w| unsafe { w.bits(timing.fbtp()) });
+//! ```
+//!
+//! Both registers are only writable with INIT and CCE set in CCCR. The
+//! limits are those of the BTP and FBTP registers of this MCAN revision.
+
+use crate::time::Hertz;
+
+/// Largest bitrate error accepted, in parts per million.
+pub const MAX_BITRATE_ERROR: u32 = 1000;
+/// Largest distance from the requested sample point, in per mille.
+pub const MAX_SAMPLE_POINT_ERROR: u16 = 25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Arbitration, and all of a classic CAN frame.
+    Nominal,
+    /// Data of a CAN FD frame with bitrate switching.
+    Data,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitTimingError {
+    /// Sample point outside 50% to 95%.
+    SamplePoint,
+    /// The data bitrate is below the nominal one.
+    DataSlowerThanNominal,
+    /// Fewer time quanta per bit than the phase allows, even undivided.
+    TooFast(Phase),
+    /// More time quanta per bit than the phase allows, even fully divided.
+    TooSlow(Phase),
+    /// No divider gets within `MAX_BITRATE_ERROR`. `error` is the best one
+    /// in parts per million, as a hint for another peripheral clock.
+    Inexact { phase: Phase, error: u32 },
+    /// No segments get within `MAX_SAMPLE_POINT_ERROR` of the sample point.
+    Unreachable(Phase),
+}
+
+/// Segments of one phase in time quanta, the register fields plus one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segments {
+    pub prescaler: u16,
+    /// Propagation and phase 1 segments.
+    pub tseg1: u8,
+    pub tseg2: u8,
+    pub sjw: u8,
+    /// Bitrate error, in parts per million.
+    pub error: u32,
+}
+
+impl Segments {
+    /// Time quanta per bit, the synchronization segment included.
+    pub fn quanta(&self) -> u32 {
+        1 + self.tseg1 as u32 + self.tseg2 as u32
+    }
+
+    /// Sample point in per mille.
+    pub fn sample_point(&self) -> u16 {
+        ((1 + self.tseg1 as u32) * 1000 / self.quanta()) as u16
+    }
+
+    pub fn bitrate(&self, pclk: Hertz) -> Hertz {
+        Hertz(pclk.0 / (self.prescaler as u32 * self.quanta()))
+    }
+}
+
+struct Limits {
+    prescaler: u16,
+    tseg1: (u8, u8),
+    tseg2: (u8, u8),
+    sjw: u8,
+}
+
+const NOMINAL: Limits = Limits { prescaler: 1024, tseg1: (2, 64), tseg2: (1, 16), sjw: 16 };
+const DATA: Limits = Limits { prescaler: 32, tseg1: (2, 16), tseg2: (1, 8), sjw: 4 };
+
+impl Limits {
+    fn quanta(&self) -> (u32, u32) {
+        (1 + (self.tseg1.0 + self.tseg2.0) as u32, 1 + (self.tseg1.1 + self.tseg2.1) as u32)
+    }
+
+    /// Segments of `quanta` closest to `sample_point`.
+    fn split(&self, quanta: u32, sample_point: u16) -> Option<(u8, u8)> {
+        let after = quanta * (1000 - sample_point as u32);
+        let tseg2 = ((after + 500) / 1000).clamp(self.tseg2.0 as u32, self.tseg2.1 as u32);
+        let tseg1 = quanta - 1 - tseg2;
+        if tseg1 < self.tseg1.0 as u32 {
+            return None;
+        }
+        // Phase 1 too long: move the excess to phase 2 if there is room.
+        let excess = tseg1.saturating_sub(self.tseg1.1 as u32);
+        let tseg2 = tseg2 + excess;
+        if tseg2 > self.tseg2.1 as u32 {
+            return None;
+        }
+        Some(((tseg1 - excess) as u8, tseg2 as u8))
+    }
+
+    fn solve(&self, phase: Phase, pclk: u32, bitrate: u32, sample_point: u16) -> Result<Segments, BitTimingError> {
+        let (min, max) = self.quanta();
+        let clocks = (pclk + bitrate / 2) / bitrate;
+        if clocks < min {
+            return Err(BitTimingError::TooFast(phase));
+        }
+        if clocks > max * self.prescaler as u32 {
+            return Err(BitTimingError::TooSlow(phase));
+        }
+
+        // Lowest bitrate error first, then closest sample point, then the
+        // most quanta for the finest resynchronization.
+        let mut best: Option<(Segments, u16)> = None;
+        let mut closest = u32::MAX;
+        for prescaler in 1..=self.prescaler {
+            let quanta = (clocks + prescaler as u32 / 2) / prescaler as u32;
+            if !(min..=max).contains(&quanta) {
+                continue;
+            }
+            let actual = pclk as u64 / (prescaler as u64 * quanta as u64);
+            let error = (actual.abs_diff(bitrate as u64) * 1_000_000 / bitrate as u64) as u32;
+            closest = closest.min(error);
+            if error > MAX_BITRATE_ERROR {
+                continue;
+            }
+            let Some((tseg1, tseg2)) = self.split(quanta, sample_point) else {
+                continue;
+            };
+            let sjw = tseg1.min(tseg2).min(self.sjw);
+            let segments = Segments { prescaler, tseg1, tseg2, sjw, error };
+            let distance = segments.sample_point().abs_diff(sample_point);
+            if best.is_none_or(|(b, d)| (error, distance) < (b.error, d)) {
+                best = Some((segments, distance));
+            }
+        }
+
+        match best {
+            Some((segments, distance)) if distance <= MAX_SAMPLE_POINT_ERROR => Ok(segments),
+            Some(_) => Err(BitTimingError::Unreachable(phase)),
+            None if closest > MAX_BITRATE_ERROR => Err(BitTimingError::Inexact { phase, error: closest }),
+            None => Err(BitTimingError::Unreachable(phase)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitTiming {
+    pub nominal: Segments,
+    pub data: Segments,
+}
+
+impl BitTiming {
+    /// Timing for `nominal` and `data` bitrates from the MCAN clock `pclk`,
+    /// both sampled at `sample_point` per mille. For classic CAN, pass the
+    /// nominal bitrate twice.
+    pub fn from_bitrate(pclk: Hertz, nominal: Hertz, data: Hertz, sample_point: u16) -> Result<BitTiming, BitTimingError> {
+        if !(500..=950).contains(&sample_point) {
+            return Err(BitTimingError::SamplePoint);
+        }
+        if data.0 < nominal.0 {
+            return Err(BitTimingError::DataSlowerThanNominal);
+        }
+        Ok(BitTiming {
+            nominal: NOMINAL.solve(Phase::Nominal, pclk.0, nominal.0, sample_point)?,
+            data: DATA.solve(Phase::Data, pclk.0, data.0, sample_point)?,
+        })
+    }
+
+    /// BTP register value.
+    pub fn btp(&self) -> u32 {
+        let n = &self.nominal;
+        (n.prescaler as u32 - 1) << 16 | (n.tseg1 as u32 - 1) << 8 | (n.tseg2 as u32 - 1) << 4 | (n.sjw as u32 - 1)
+    }
+
+    /// FBTP register value, transceiver delay compensation off.
+    pub fn fbtp(&self) -> u32 {
+        let d = &self.data;
+        (d.prescaler as u32 - 1) << 16 | (d.tseg1 as u32 - 1) << 8 | (d.tseg2 as u32 - 1) << 4 | (d.sjw as u32 - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::U32Ext;
+
+    #[test]
+    fn common_bitrates() {
+        let timing = BitTiming::from_bitrate(80.mhz(), 500.khz(), 2.mhz(), 800).unwrap();
+        assert_eq!(timing.nominal.bitrate(80.mhz()), 500.khz());
+        assert_eq!(timing.data.bitrate(80.mhz()), 2.mhz());
+        assert_eq!((timing.nominal.sample_point(), timing.data.sample_point()), (800, 800));
+        assert_eq!(timing.nominal.error, 0);
+        // Quanta of 25 ns: 80 per nominal bit, 20 per data bit.
+        assert_eq!(timing.btp(), 0x0001_3EFF);
+        assert_eq!(timing.fbtp(), 0x0001_0E33);
+    }
+
+    #[test]
+    fn unattainable() {
+        use BitTimingError::*;
+        assert_eq!(BitTiming::from_bitrate(80.mhz(), 500.khz(), 500.khz(), 400), Err(SamplePoint));
+        assert_eq!(BitTiming::from_bitrate(80.mhz(), 1.mhz(), 500.khz(), 800), Err(DataSlowerThanNominal));
+        assert_eq!(BitTiming::from_bitrate(12.mhz(), 1.mhz(), 5.mhz(), 750), Err(TooFast(Phase::Data)));
+        assert_eq!(BitTiming::from_bitrate(80.mhz(), 500.hz(), 1.mhz(), 800), Err(TooSlow(Phase::Nominal)));
+        assert!(matches!(
+            BitTiming::from_bitrate(40.mhz(), 500.khz(), 3_300.khz(), 800),
+            Err(Inexact { phase: Phase::Data, .. })
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+//! Wall clock and uptime.
+//!
+//! The RTC keeps UTC across resets and, with a backup battery on VDDIO,
+//! across power loss; the RTT counts the time since `Clock::new`. The backup
+//! registers hold what `synchronize` needs to measure the RTC drift from
+//! one synchronization to the next:
+//!
+//! | GPBR | Content                                         |
+//! |------|-------------------------------------------------|
+//! | 0    | Unix time of the last synchronization           |
+//! | 1    | RTC correction in force, in ppm                 |
+//! | 2    | `MAGIC` once the other two are valid            |
+
+use core::cell::Cell;
+use crate::pac::{GPBR, RTT};
+use crate::rtc::{DateTime, Rtc};
+
+/// RTT prescaler, for a 1024 Hz count from the 32768 Hz slow clock.
+const RTT_PRESCALER: u16 = 32;
+const RTT_HZ: u64 = 1024;
+/// Shortest span between synchronizations worth measuring the drift on.
+const MIN_DRIFT_SPAN: u64 = 3600;
+const MAGIC: u32 = 0x636c_6b31;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+pub struct Clock {
+    rtc: Rtc,
+    rtt: RTT,
+    gpbr: GPBR,
+    /// RTT value at the last `uptime` read, and how many times it wrapped.
+    last: Cell<u32>,
+    wraps: Cell<u32>,
+}
+
+impl Clock {
+    /// Restarts the RTT from zero; the RTC keeps running.
+    pub fn new(rtc: Rtc, rtt: RTT, gpbr: GPBR) -> Self {
+        rtt.mr.write(|w| unsafe { w.rtpres().bits(RTT_PRESCALER).rttrst().set_bit() });
+        Clock { rtc, rtt, gpbr, last: Cell::new(0), wraps: Cell::new(0) }
+    }
+
+    pub fn free(self) -> (Rtc, RTT, GPBR) {
+        (self.rtc, self.rtt, self.gpbr)
+    }
+
+    /// The RTT is clocked asynchronously, so it is read until two reads
+    /// agree.
+    fn rtt_ticks(&self) -> u32 {
+        let mut last = self.rtt.vr.read().crtv().bits();
+        loop {
+            let current = self.rtt.vr.read().crtv().bits();
+            if current == last {
+                return current;
+            }
+            last = current;
+        }
+    }
+
+    /// Milliseconds since `new`. The RTT wraps every 48 days, which is only
+    /// accounted for if this is called at least that often.
+    pub fn uptime_ms(&self) -> u64 {
+        let ticks = self.rtt_ticks();
+        if ticks < self.last.get() {
+            self.wraps.set(self.wraps.get() + 1);
+        }
+        self.last.set(ticks);
+        ((self.wraps.get() as u64) << 32 | ticks as u64) * 1000 / RTT_HZ
+    }
+
+    /// Seconds since 1970-01-01 00:00:00 UTC.
+    pub fn now_utc(&self) -> u64 {
+        to_unix(&self.rtc.date_time())
+    }
+
+    /// Sets the RTC without touching its correction.
+    pub fn set_utc(&mut self, unix: u64) {
+        self.rtc.set_date_time(from_unix(unix));
+        let correction = self.correction().unwrap_or(0);
+        self.store(unix, correction);
+    }
+
+    /// Sets the RTC to `unix`, from a trusted source such as NTP or GNSS.
+    /// If the previous synchronization is long enough ago, the error the RTC
+    /// built up since is turned into a new correction, returned in ppm.
+    pub fn synchronize(&mut self, unix: u64) -> Option<i32> {
+        let measured = match (self.last_sync(), self.correction()) {
+            (Some(last), Some(correction)) if unix >= last + MIN_DRIFT_SPAN => {
+                let gained = self.now_utc() as i64 - unix as i64;
+                let drift = (gained * 1_000_000 / (unix - last) as i64) as i32;
+                Some(correction - drift)
+            }
+            _ => None,
+        };
+        self.rtc.set_date_time(from_unix(unix));
+        let correction = measured.or_else(|| self.correction()).unwrap_or(0);
+        self.rtc.set_correction(correction);
+        self.store(unix, correction);
+        measured
+    }
+
+    fn is_valid(&self) -> bool {
+        self.gpbr.gpbr[2].read().bits() == MAGIC
+    }
+
+    fn last_sync(&self) -> Option<u64> {
+        self.is_valid().then(|| self.gpbr.gpbr[0].read().bits() as u64)
+    }
+
+    /// Correction last programmed by `synchronize`.
+    pub fn correction(&self) -> Option<i32> {
+        self.is_valid().then(|| self.gpbr.gpbr[1].read().bits() as i32)
+    }
+
+    fn store(&mut self, unix: u64, correction: i32) {
+        unsafe {
+            self.gpbr.gpbr[0].write_with_zero(|w| w.bits(unix as u32));
+            self.gpbr.gpbr[1].write_with_zero(|w| w.bits(correction as u32));
+            self.gpbr.gpbr[2].write_with_zero(|w| w.bits(MAGIC));
+        }
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date, after Howard
+/// Hinnant's `days_from_civil`.
+fn days_from_civil(year: u16, month: u8, day: u8) -> u64 {
+    let year = year as u64 - (month <= 2) as u64;
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month = month as u64;
+    let day_of_year = (153 * if month > 2 { month - 3 } else { month + 9 } + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn to_unix(time: &DateTime) -> u64 {
+    days_from_civil(time.year, time.month, time.day) * SECONDS_PER_DAY
+        + time.hour as u64 * 3600
+        + time.minute as u64 * 60
+        + time.second as u64
+}
+
+fn from_unix(unix: u64) -> DateTime {
+    let days = unix / SECONDS_PER_DAY + 719_468;
+    let seconds = unix % SECONDS_PER_DAY;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    DateTime {
+        year: (year_of_era + era * 400 + (month <= 2) as u64) as u16,
+        month: month as u8,
+        day: (day_of_year - (153 * mp + 2) / 5 + 1) as u8,
+        // 1970-01-01 was a Thursday.
+        weekday: ((unix / SECONDS_PER_DAY + 3) % 7 + 1) as u8,
+        hour: (seconds / 3600) as u8,
+        minute: (seconds / 60 % 60) as u8,
+        second: (seconds % 60) as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_unix, to_unix, DateTime};
+
+    #[test]
+    fn unix_conversions() {
+        let leap_day = DateTime { year: 2024, month: 2, day: 29, weekday: 4, hour: 12, minute: 34, second: 56 };
+        assert_eq!(to_unix(&leap_day), 1_709_210_096);
+        assert_eq!(from_unix(1_709_210_096), leap_day);
+        let epoch = DateTime { year: 1970, month: 1, day: 1, weekday: 4, hour: 0, minute: 0, second: 0 };
+        assert_eq!(from_unix(0), epoch);
+        assert!((0..4000u64).map(|day| day * 86_399).all(|t| to_unix(&from_unix(t)) == t));
+    }
+}
@@ -0,0 +1,439 @@
+use core::cell::Cell;
+use cortex_m::interrupt::Mutex;
+use crate::pac::pmc::{ckgr_mor::MOSCRCF_A, pmc_mckr::{CSS_A, MDIV_A, PRES_A}};
+use crate::pac::{EFC, PMC};
+use crate::pmc::PckSource;
+use crate::time::Hertz;
+use crate::write_protect::WriteProtect;
+
+const FLASH_WAIT_STATES_MAX: u8 = 6;
+/// Highest HCLK per flash wait state, at VDDIO from 3.0 V to 3.6 V.
+const FLASH_WAIT_STATE_STEP: u32 = 23_000_000;
+const CRYSTAL_STARTUP_TIME: u8 = 62;
+const PLLA_COUNT: u8 = 0x3F;
+/// Output range of the PLLA.
+const PLLA_MIN: u32 = 160_000_000;
+const PLLA_MAX: u32 = 500_000_000;
+/// Usual ratio of an audio master clock to the sample rate.
+pub const AUDIO_MCLK_RATIO: u32 = 256;
+/// Main clock after a crystal failure.
+const FALLBACK_RC: Hertz = Hertz(12_000_000);
+
+/// Called from `on_clock_failure` with the clocks the fallback left running.
+pub type FailureHandler = fn(Clocks);
+
+static FAILURE: Mutex<Cell<Option<(Clocks, FailureHandler)>>> = Mutex::new(Cell::new(None));
+
+pub enum MainClock {
+    FastRc4MHz,
+    FastRc8MHz,
+    FastRc12MHz,
+    Crystal(Hertz),
+    Bypass(Hertz),
+}
+
+pub enum MasterClockSource {
+    MainClock,
+    PllA(u16),
+}
+
+pub enum Prescaler {
+    Div1,
+    Div2,
+    Div3,
+    Div4,
+    Div8,
+    Div16,
+    Div32,
+    Div64,
+}
+
+pub enum MckDivider {
+    Div1,
+    Div2,
+    Div3,
+    Div4,
+}
+
+pub struct Config {
+    main_clock: MainClock,
+    source: MasterClockSource,
+    prescaler: Prescaler,
+    divider: MckDivider,
+    wait_states: Option<u8>,
+}
+
+impl Config {
+    pub fn new(main_clock: MainClock, source: MasterClockSource, prescaler: Prescaler, divider: MckDivider) -> Config {
+        Config { main_clock, source, prescaler, divider, wait_states: None }
+    }
+
+    /// Flash wait states to use instead of those for HCLK at 3.3 V, which
+    /// are too few with a lower VDDIO.
+    pub fn flash_wait_states(mut self, wait_states: u8) -> Config {
+        assert!(wait_states < 16, "flash wait states out of range");
+        self.wait_states = Some(wait_states);
+        self
+    }
+}
+
+/// FWS for `hclk`, after the EEFC table at VDDIO from 3.0 V to 3.6 V.
+fn flash_wait_states(hclk: Hertz) -> u8 {
+    ((hclk.0.saturating_sub(1) / FLASH_WAIT_STATE_STEP) as u8).min(FLASH_WAIT_STATES_MAX)
+}
+
+#[derive(Clone, Copy)]
+pub struct Clocks {
+    main_clk: Hertz,
+    plla_clk: Option<Hertz>,
+    hclk: Hertz,
+    mck: Hertz,
+}
+
+impl Clocks {
+    pub fn main_clk(&self) -> Hertz {
+        self.main_clk
+    }
+
+    pub fn plla_clk(&self) -> Option<Hertz> {
+        self.plla_clk
+    }
+
+    pub fn hclk(&self) -> Hertz {
+        self.hclk
+    }
+
+    pub fn mck(&self) -> Hertz {
+        self.mck
+    }
+
+    /// Programmable clock setup closest to `frequency`, e.g. an I2S master
+    /// clock of `AUDIO_MCLK_RATIO` × 44.1 kHz, for `enable_pck`.
+    ///
+    /// The PCK prescaler only divides by integers, so the result is exact
+    /// only when one of the clocks is a multiple of `frequency`: a 12.288
+    /// MHz crystal for 48 kHz, 11.2896 MHz for 44.1 kHz.
+    pub fn audio_clock(&self, frequency: Hertz) -> AudioClock {
+        let sources = [
+            Some((PckSource::MainClock, self.main_clk)),
+            self.plla_clk.map(|plla_clk| (PckSource::PllA, plla_clk)),
+            Some((PckSource::Mck, self.mck)),
+        ];
+        closest(sources.iter().flatten().copied(), frequency)
+    }
+}
+
+impl Clocks {
+    /// Clocks once the main clock is the 12 MHz RC, with the PLLA multiplier
+    /// to use, if any: the prescalers stay, and the PLLA ends up as close
+    /// below its former frequency as it can. When even that is under the
+    /// PLLA range, MCK runs from the RC directly.
+    fn on_fallback_rc(&self) -> (Option<u16>, Clocks) {
+        let hclk_div = self.plla_clk.unwrap_or(self.main_clk).0 / self.hclk.0;
+        let mck_div = self.hclk.0 / self.mck.0;
+        let multiplier = self
+            .plla_clk
+            .map(|plla_clk| plla_clk.0.min(PLLA_MAX) / FALLBACK_RC.0)
+            .filter(|&multiplier| FALLBACK_RC.0 * multiplier >= PLLA_MIN)
+            .map(|multiplier| multiplier as u16);
+        let plla_clk = multiplier.map(|multiplier| Hertz(FALLBACK_RC.0 * multiplier as u32));
+        let hclk = plla_clk.unwrap_or(FALLBACK_RC).0 / hclk_div;
+        let clocks = Clocks {
+            main_clk: FALLBACK_RC,
+            plla_clk,
+            hclk: Hertz(hclk),
+            mck: Hertz(hclk / mck_div),
+        };
+        (multiplier, clocks)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioClock {
+    pub source: PckSource,
+    pub prescaler: u8,
+    /// Frequency actually reached.
+    pub frequency: Hertz,
+}
+
+fn closest(sources: impl Iterator<Item = (PckSource, Hertz)>, target: Hertz) -> AudioClock {
+    sources
+        .map(|(source, clock)| {
+            let divider = ((clock.0 + target.0 / 2) / target.0).clamp(1, 256);
+            AudioClock {
+                source,
+                prescaler: (divider - 1) as u8,
+                frequency: Hertz(clock.0 / divider),
+            }
+        })
+        .min_by_key(|clock| clock.frequency.0.abs_diff(target.0))
+        .unwrap()
+}
+
+pub trait ClocksExt {
+    fn freeze(&self, config: Config) -> Clocks;
+
+    /// Enables the detector of crystal failures. On one the PMC switches
+    /// the main clock to the RC oscillator and MCK to the main clock by
+    /// itself; the PMC interrupt, forwarded to `on_clock_failure`, then
+    /// restores the PLLA from the RC and calls `handler`.
+    fn detect_clock_failure(&self, clocks: &Clocks, handler: FailureHandler);
+
+    /// Runs from the 12 MHz RC oscillator instead of the crystal, through
+    /// the PLLA if it was in use, and returns the new clocks. Peripherals
+    /// keep their settings: they only stay exact with a 12 MHz crystal.
+    fn fall_back_to_rc(&self, clocks: &Clocks) -> Clocks;
+}
+
+impl ClocksExt for PMC {
+    fn freeze(&self, config: Config) -> Clocks {
+        self.with_unlocked(|pmc| pmc.configure(config))
+    }
+
+    fn detect_clock_failure(&self, clocks: &Clocks, handler: FailureHandler) {
+        cortex_m::interrupt::free(|cs| FAILURE.borrow(cs).set(Some((*clocks, handler))));
+        self.with_unlocked(|pmc| unsafe {
+            pmc.ckgr_mor.modify(|_, w| w.key().passwd().cfden().set_bit());
+            pmc.pmc_ier.write_with_zero(|w| w.cfdev().set_bit());
+        });
+    }
+
+    fn fall_back_to_rc(&self, clocks: &Clocks) -> Clocks {
+        cortex_m::interrupt::free(|_| self.with_unlocked(|pmc| pmc.fall_back(clocks)))
+    }
+}
+
+/// Handles the PMC interrupt of a crystal failure, see
+/// `ClocksExt::detect_clock_failure`:
+///
+/// ```ignore
+/// #[interrupt]
+/// fn PMC() {
+///     samv71_hal::clocks::on_clock_failure();
+/// }
+/// ```
+pub fn on_clock_failure() {
+    let pmc = unsafe { &*PMC::ptr() };
+    // Reading SR clears CFDEV.
+    if !pmc.pmc_sr.read().cfdev().bit() {
+        return;
+    }
+    let Some((clocks, handler)) = cortex_m::interrupt::free(|cs| FAILURE.borrow(cs).get()) else {
+        return;
+    };
+    let clocks = cortex_m::interrupt::free(|_| pmc.with_unlocked(|pmc| pmc.fall_back(&clocks)));
+    cortex_m::interrupt::free(|cs| FAILURE.borrow(cs).set(Some((clocks, handler))));
+    handler(clocks);
+}
+
+trait ClockSetup {
+    fn configure(&self, config: Config) -> Clocks;
+
+    fn select_fast_rc(&self, frequency: MOSCRCF_A, hertz: Hertz) -> Hertz;
+
+    fn select_main_oscillator(&self, crystal: bool);
+
+    fn fall_back(&self, clocks: &Clocks) -> Clocks;
+}
+
+impl ClockSetup for crate::pac::pmc::RegisterBlock {
+    fn configure(&self, config: Config) -> Clocks {
+        let efc = unsafe { &*EFC::ptr() };
+        efc.with_unlocked(|efc| efc.fmr.modify(|_, w| unsafe { w.fws().bits(FLASH_WAIT_STATES_MAX) }));
+
+        if !self.pmc_mckr.read().css().is_main_clk() {
+            self.pmc_mckr.modify(|_, w| w.css().variant(CSS_A::MAIN_CLK));
+            while !self.pmc_sr.read().mckrdy().bit() {}
+        }
+
+        let main_clk = match config.main_clock {
+            MainClock::FastRc4MHz => self.select_fast_rc(MOSCRCF_A::_4_MHZ, Hertz(4_000_000)),
+            MainClock::FastRc8MHz => self.select_fast_rc(MOSCRCF_A::_8_MHZ, Hertz(8_000_000)),
+            MainClock::FastRc12MHz => self.select_fast_rc(MOSCRCF_A::_12_MHZ, Hertz(12_000_000)),
+            MainClock::Crystal(freq) => {
+                self.ckgr_mor.modify(|_, w| unsafe {
+                    w.key().passwd()
+                        .moscxtby().clear_bit()
+                        .moscxten().set_bit()
+                        .moscxtst().bits(CRYSTAL_STARTUP_TIME)
+                });
+                while !self.pmc_sr.read().moscxts().bit() {}
+                self.select_main_oscillator(true);
+                freq
+            }
+            MainClock::Bypass(freq) => {
+                self.ckgr_mor.modify(|_, w|
+                    w.key().passwd()
+                        .moscxten().clear_bit()
+                        .moscxtby().set_bit()
+                );
+                self.select_main_oscillator(true);
+                freq
+            }
+        };
+
+        let (css, plla_clk) = match config.source {
+            MasterClockSource::MainClock => (CSS_A::MAIN_CLK, None),
+            MasterClockSource::PllA(multiplier) => {
+                assert!(multiplier >= 2);
+                let plla_clk = main_clk.0 * multiplier as u32;
+                assert!((PLLA_MIN..=PLLA_MAX).contains(&plla_clk));
+                self.ckgr_pllar.write(|w| unsafe {
+                    w.one().set_bit()
+                        .mula().bits(multiplier - 1)
+                        .pllacount().bits(PLLA_COUNT)
+                        .diva().bits(1)
+                });
+                while !self.pmc_sr.read().locka().bit() {}
+                (CSS_A::PLLA_CLK, Some(Hertz(plla_clk)))
+            }
+        };
+
+        let (pres, pres_div) = match config.prescaler {
+            Prescaler::Div1 => (PRES_A::CLK_1, 1),
+            Prescaler::Div2 => (PRES_A::CLK_2, 2),
+            Prescaler::Div3 => (PRES_A::CLK_3, 3),
+            Prescaler::Div4 => (PRES_A::CLK_4, 4),
+            Prescaler::Div8 => (PRES_A::CLK_8, 8),
+            Prescaler::Div16 => (PRES_A::CLK_16, 16),
+            Prescaler::Div32 => (PRES_A::CLK_32, 32),
+            Prescaler::Div64 => (PRES_A::CLK_64, 64),
+        };
+        let (mdiv, mck_div) = match config.divider {
+            MckDivider::Div1 => (MDIV_A::EQ_PCK, 1),
+            MckDivider::Div2 => (MDIV_A::PCK_DIV2, 2),
+            MckDivider::Div3 => (MDIV_A::PCK_DIV3, 3),
+            MckDivider::Div4 => (MDIV_A::PCK_DIV4, 4),
+        };
+
+        let hclk = plla_clk.unwrap_or(main_clk).0 / pres_div;
+        let mck = hclk / mck_div;
+        assert!(hclk <= 300_000_000);
+        assert!(mck <= 150_000_000);
+
+        self.pmc_mckr.modify(|_, w| w.pres().variant(pres).mdiv().variant(mdiv));
+        while !self.pmc_sr.read().mckrdy().bit() {}
+        self.pmc_mckr.modify(|_, w| w.css().variant(css));
+        while !self.pmc_sr.read().mckrdy().bit() {}
+
+        // Down from the maximum set while switching.
+        let fws = config.wait_states.unwrap_or_else(|| flash_wait_states(Hertz(hclk)));
+        efc.with_unlocked(|efc| efc.fmr.modify(|_, w| unsafe { w.fws().bits(fws) }));
+
+        Clocks {
+            main_clk,
+            plla_clk,
+            hclk: Hertz(hclk),
+            mck: Hertz(mck),
+        }
+    }
+
+    fn select_fast_rc(&self, frequency: MOSCRCF_A, hertz: Hertz) -> Hertz {
+        self.ckgr_mor.modify(|_, w|
+            w.key().passwd()
+                .moscrcen().set_bit()
+                .moscrcf().variant(frequency)
+        );
+        while !self.pmc_sr.read().moscrcs().bit() {}
+        self.select_main_oscillator(false);
+        hertz
+    }
+
+    fn select_main_oscillator(&self, crystal: bool) {
+        self.ckgr_mor.modify(|_, w| w.key().passwd().moscsel().bit(crystal));
+        while !self.pmc_sr.read().moscsels().bit() {}
+    }
+
+    fn fall_back(&self, clocks: &Clocks) -> Clocks {
+        let (multiplier, fallback) = clocks.on_fallback_rc();
+        let efc = unsafe { &*EFC::ptr() };
+        let fws = efc.fmr.read().fws().bits();
+        efc.with_unlocked(|efc| efc.fmr.modify(|_, w| unsafe { w.fws().bits(FLASH_WAIT_STATES_MAX) }));
+        if !self.pmc_mckr.read().css().is_main_clk() {
+            self.pmc_mckr.modify(|_, w| w.css().variant(CSS_A::MAIN_CLK));
+            while !self.pmc_sr.read().mckrdy().bit() {}
+        }
+        self.select_fast_rc(MOSCRCF_A::_12_MHZ, FALLBACK_RC);
+        self.ckgr_mor.modify(|_, w| w.key().passwd().moscxten().clear_bit());
+        if let Some(multiplier) = multiplier {
+            self.ckgr_pllar.write(|w| unsafe {
+                w.one().set_bit()
+                    .mula().bits(multiplier - 1)
+                    .pllacount().bits(PLLA_COUNT)
+                    .diva().bits(1)
+            });
+            while !self.pmc_sr.read().locka().bit() {}
+            self.pmc_mckr.modify(|_, w| w.css().variant(CSS_A::PLLA_CLK));
+            while !self.pmc_sr.read().mckrdy().bit() {}
+        }
+        // The former setting may be one for a low VDDIO, above the table.
+        let fws = fws.max(flash_wait_states(fallback.hclk));
+        efc.with_unlocked(|efc| efc.fmr.modify(|_, w| unsafe { w.fws().bits(fws) }));
+        fallback
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_clock_from_crystal() {
+        let sources = [(PckSource::PllA, Hertz(294_912_000)), (PckSource::MainClock, Hertz(12_288_000))];
+        let clock = closest(sources.iter().copied(), Hertz(AUDIO_MCLK_RATIO * 48_000));
+        assert_eq!(clock, AudioClock { source: PckSource::PllA, prescaler: 23, frequency: Hertz(12_288_000) });
+
+        let sources = [(PckSource::MainClock, Hertz(12_000_000)), (PckSource::Mck, Hertz(150_000_000))];
+        let clock = closest(sources.iter().copied(), Hertz(AUDIO_MCLK_RATIO * 44_100));
+        assert_eq!(clock, AudioClock { source: PckSource::Mck, prescaler: 12, frequency: Hertz(11_538_461) });
+    }
+
+    #[test]
+    fn wait_states_for_hclk() {
+        assert_eq!(flash_wait_states(Hertz(12_000_000)), 0);
+        assert_eq!(flash_wait_states(Hertz(23_000_000)), 0);
+        assert_eq!(flash_wait_states(Hertz(24_000_000)), 1);
+        assert_eq!(flash_wait_states(Hertz(138_000_000)), 5);
+        assert_eq!(flash_wait_states(Hertz(150_000_000)), 6);
+        assert_eq!(flash_wait_states(Hertz(300_000_000)), 6);
+    }
+
+    #[test]
+    fn fallback_clocks() {
+        let clocks = Clocks {
+            main_clk: Hertz(12_288_000),
+            plla_clk: Some(Hertz(294_912_000)),
+            hclk: Hertz(294_912_000),
+            mck: Hertz(147_456_000),
+        };
+        let (multiplier, fallback) = clocks.on_fallback_rc();
+        assert_eq!(multiplier, Some(24));
+        assert_eq!((fallback.hclk, fallback.mck), (Hertz(288_000_000), Hertz(144_000_000)));
+
+        let clocks = Clocks { main_clk: Hertz(16_000_000), plla_clk: None, hclk: Hertz(8_000_000), mck: Hertz(8_000_000) };
+        let (multiplier, fallback) = clocks.on_fallback_rc();
+        assert_eq!((multiplier, fallback.mck), (None, Hertz(6_000_000)));
+
+        // 13 × 12 MHz is under the PLLA range, and 14 × would be faster
+        // than before.
+        let clocks = Clocks {
+            main_clk: Hertz(16_000_000),
+            plla_clk: Some(Hertz(160_000_000)),
+            hclk: Hertz(160_000_000),
+            mck: Hertz(80_000_000),
+        };
+        let (multiplier, fallback) = clocks.on_fallback_rc();
+        assert_eq!(multiplier, None);
+        assert_eq!((fallback.plla_clk, fallback.hclk, fallback.mck), (None, Hertz(12_000_000), Hertz(6_000_000)));
+
+        let clocks = Clocks {
+            main_clk: Hertz(20_000_000),
+            plla_clk: Some(Hertz(500_000_000)),
+            hclk: Hertz(250_000_000),
+            mck: Hertz(125_000_000),
+        };
+        let (multiplier, fallback) = clocks.on_fallback_rc();
+        assert_eq!((multiplier, fallback.plla_clk), (Some(41), Some(Hertz(492_000_000))));
+        assert_eq!((fallback.hclk, fallback.mck), (Hertz(246_000_000), Hertz(123_000_000)));
+    }
+}
@@ -0,0 +1,57 @@
+/// CRC-16/XMODEM: polynomial 0x1021, zero initial value.
+pub(crate) fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { crc << 1 ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// CRC-16/MODBUS: reflected polynomial 0xA001, all ones initial value. Sent
+/// low byte first; a frame followed by its CRC checks to zero.
+#[cfg(feature = "modbus")]
+pub(crate) fn crc16_modbus(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// CRC-8/MAXIM of 1-Wire ROM codes and scratchpads: reflected polynomial
+/// 0x8C, zero initial value. Data followed by its CRC checks to zero.
+pub(crate) fn crc8_maxim(data: &[u8]) -> u8 {
+    let mut crc = 0;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0x8C } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(super::crc16(0, b"123456789"), 0x31C3);
+    }
+
+    #[test]
+    fn crc8_maxim_check_value() {
+        assert_eq!(super::crc8_maxim(b"123456789"), 0xA1);
+    }
+
+    #[cfg(feature = "modbus")]
+    #[test]
+    fn crc16_modbus_check_value() {
+        assert_eq!(super::crc16_modbus(b"123456789"), 0x4B37);
+        assert_eq!(super::crc16_modbus(b"123456789\x37\x4B"), 0);
+    }
+}
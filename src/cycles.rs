@@ -0,0 +1,161 @@
+use cortex_m::peripheral::{DCB, DWT};
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use embedded_hal_1::delay::DelayNs;
+use crate::clocks::Clocks;
+use crate::time::Hertz;
+
+/// Point in time read from the DWT cycle counter. It wraps every 2^32 core
+/// cycles, about 14 s at 300 MHz, so only shorter spans can be measured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instant(u32);
+
+impl Instant {
+    pub fn now() -> Self {
+        Instant(DWT::cycle_count())
+    }
+
+    pub fn elapsed_cycles(&self) -> u32 {
+        DWT::cycle_count().wrapping_sub(self.0)
+    }
+
+    pub fn cycles_since(&self, earlier: Instant) -> u32 {
+        self.0.wrapping_sub(earlier.0)
+    }
+}
+
+/// Busy waits for at least `cycles` core cycles. The cycle counter must be
+/// running, see `Cycles::new`.
+pub fn delay_cycles(cycles: u32) {
+    let start = Instant::now();
+    while start.elapsed_cycles() < cycles {}
+}
+
+/// Running DWT cycle counter. Keeps the core clock to convert cycles into
+/// time, and the cost of reading the counter twice so measurements can
+/// leave it out.
+#[derive(Clone, Copy)]
+pub struct Cycles {
+    hclk: Hertz,
+    overhead: u32,
+}
+
+impl Cycles {
+    pub fn new(dcb: &mut DCB, dwt: &mut DWT, clocks: &Clocks) -> Self {
+        dcb.enable_trace();
+        // The Cortex-M7 DWT comes up software locked.
+        DWT::unlock();
+        dwt.enable_cycle_counter();
+
+        let start = Instant::now();
+        let overhead = start.elapsed_cycles();
+        Cycles { hclk: clocks.hclk(), overhead }
+    }
+
+    pub fn overhead(&self) -> u32 {
+        self.overhead
+    }
+
+    pub fn to_nanos(&self, cycles: u32) -> u64 {
+        cycles as u64 * 1_000_000_000 / self.hclk.0 as u64
+    }
+
+    pub fn from_nanos(&self, nanos: u64) -> u64 {
+        nanos * self.hclk.0 as u64 / 1_000_000_000
+    }
+
+    pub fn stopwatch(&self) -> Stopwatch {
+        Stopwatch {
+            cycles: *self,
+            start: Instant::now(),
+            lap: Instant::now(),
+        }
+    }
+
+    /// Runs `f` and returns its result with the cycles it took.
+    pub fn measure<R>(&self, f: impl FnOnce() -> R) -> (R, u32) {
+        let start = Instant::now();
+        let result = f();
+        (result, start.elapsed_cycles().saturating_sub(self.overhead))
+    }
+}
+
+impl DelayUs<u32> for Cycles {
+    fn delay_us(&mut self, us: u32) {
+        let mut cycles = us as u64 * (self.hclk.0 / 1_000_000) as u64;
+        while cycles != 0 {
+            let chunk = cycles.min(u32::MAX as u64 / 2) as u32;
+            delay_cycles(chunk.saturating_sub(self.overhead));
+            cycles -= chunk as u64;
+        }
+    }
+}
+
+impl DelayUs<u16> for Cycles {
+    fn delay_us(&mut self, us: u16) {
+        DelayUs::<u32>::delay_us(self, us as u32)
+    }
+}
+
+impl DelayUs<u8> for Cycles {
+    fn delay_us(&mut self, us: u8) {
+        DelayUs::<u32>::delay_us(self, us as u32)
+    }
+}
+
+impl DelayMs<u32> for Cycles {
+    fn delay_ms(&mut self, ms: u32) {
+        for _ in 0..ms {
+            DelayUs::<u32>::delay_us(self, 1_000);
+        }
+    }
+}
+
+impl DelayMs<u16> for Cycles {
+    fn delay_ms(&mut self, ms: u16) {
+        DelayMs::<u32>::delay_ms(self, ms as u32)
+    }
+}
+
+impl DelayMs<u8> for Cycles {
+    fn delay_ms(&mut self, ms: u8) {
+        DelayMs::<u32>::delay_ms(self, ms as u32)
+    }
+}
+
+impl DelayNs for Cycles {
+    fn delay_ns(&mut self, ns: u32) {
+        let cycles = self.from_nanos(ns as u64) as u32;
+        delay_cycles(cycles.saturating_sub(self.overhead));
+    }
+}
+
+/// Measures a code path, optionally in laps. Readings have the counter
+/// overhead taken out.
+pub struct Stopwatch {
+    cycles: Cycles,
+    start: Instant,
+    lap: Instant,
+}
+
+impl Stopwatch {
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+        self.lap = self.start;
+    }
+
+    /// Cycles since the previous lap, or the start.
+    pub fn lap(&mut self) -> u32 {
+        let now = Instant::now();
+        let cycles = now.cycles_since(self.lap);
+        self.lap = now;
+        cycles.saturating_sub(self.cycles.overhead)
+    }
+
+    pub fn elapsed_cycles(&self) -> u32 {
+        self.start.elapsed_cycles().saturating_sub(self.cycles.overhead)
+    }
+
+    pub fn elapsed_nanos(&self) -> u64 {
+        self.cycles.to_nanos(self.elapsed_cycles())
+    }
+}
@@ -0,0 +1,248 @@
+//! Digital to analog converter controller.
+//!
+//! Besides single conversions, a channel can play samples at a fixed rate:
+//! TIOA of a TC0 channel triggers the conversions and the XDMAC feeds them
+//! from two buffers in turn. Each time the XDMAC is done with a buffer, its
+//! interrupt refills it from the `play` queue, then from the refill
+//! callback once the queue runs out.
+//!
+//! ```ignore
+//! static mut BUFFERS: PingPongBuffers<256> = PingPongBuffers::new(dacc::MID_SCALE as u32);
+//! static AUDIO: InterruptMutex<AudioOut<Dacc0, 0, 0, 256, 1024>> = InterruptMutex::new();
+//!
+//! fn refill(buffer: &mut [u32]) -> usize {
+//!     // Writes samples, one per word, and returns how many.
+//! }
+//!
+//! let buffers = unsafe { &mut *core::ptr::addr_of_mut!(BUFFERS) };
+//! let audio = dacc.audio_out(Dacc0, 16_000.hz(), tc0.ch0, xdmac.ch0, buffers, Some(refill), &clocks);
+//! AUDIO.init(audio);
+//!
+//! #[interrupt]
+//! fn XDMAC() {
+//!     AUDIO.lock(|audio| audio.on_interrupt());
+//! }
+//! ```
+
+use core::convert::Infallible;
+use crate::clocks::Clocks;
+use crate::dma::{self, peripheral::{Dacc0, Dacc1, Destination, PingPong, PingPongBuffers}};
+use crate::pac::{DACC, PMC, TC0};
+use crate::pmc::PeripheralId;
+use crate::ring::Ring;
+use crate::tc;
+use crate::time::Hertz;
+use crate::write_protect::WriteProtect;
+
+pub const CHANNELS: u8 = 2;
+/// Output half way through the 12-bit range, played when the queue and the
+/// refill callback run dry.
+pub const MID_SCALE: u16 = 0x800;
+/// Highest DAC clock.
+const DAC_CLOCK_MAX: u32 = 12_000_000;
+
+/// Writes samples to the start of a buffer, one 12-bit sample per word, and
+/// returns how many. Called from the DMA interrupt, once the `play` queue
+/// ran out.
+pub type Refill = fn(&mut [u32]) -> usize;
+
+/// Fills `buffer` from `queue`, then from `refill`, then with `MID_SCALE`.
+fn fill<const Q: usize>(buffer: &mut [u32], queue: &mut Ring<u16, Q>, refill: Option<Refill>) {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match queue.pop() {
+            Some(sample) => buffer[filled] = sample as u32,
+            None => break,
+        }
+        filled += 1;
+    }
+    if let Some(refill) = refill {
+        filled += refill(&mut buffer[filled..]).min(buffer.len() - filled);
+    }
+    buffer[filled..].fill(MID_SCALE as u32);
+}
+
+/// DMA request line of a channel, which tells the channel fed.
+pub trait Output: Destination {
+    const CH: u8;
+}
+
+impl Output for Dacc0 {
+    const CH: u8 = 0;
+}
+
+impl Output for Dacc1 {
+    const CH: u8 = 1;
+}
+
+/// PRESCALER for the DAC clock, MCK / (PRESCALER + 2), to stay at most
+/// `DAC_CLOCK_MAX`.
+fn prescaler(mck: Hertz) -> u8 {
+    mck.0.div_ceil(DAC_CLOCK_MAX).saturating_sub(2).min(15) as u8
+}
+
+/// Converts on `channel` at each hardware trigger `select`: 0 for DATRG,
+/// 1 to 3 for TIOA0-2 of TC0, then the PWM0 and PWM1 event lines. `None`
+/// returns the channel to free-running mode.
+pub(crate) fn set_trigger(dacc: &DACC, channel: u8, select: Option<u8>) {
+    let shift = 4 + 4 * channel as u32;
+    let bits = match select {
+        Some(select) => (select as u32) << shift | 1 << channel,
+        None => 0,
+    };
+    dacc.with_unlocked(|dacc| dacc.trigr.modify(|r, w| unsafe {
+        w.bits(r.bits() & !(0x7 << shift | 1 << channel) | bits)
+    }));
+}
+
+pub struct Dacc {
+    dacc: DACC,
+}
+
+impl Dacc {
+    /// Resets the DACC for single-ended 12-bit conversions, both channels
+    /// disabled and in free-running mode.
+    pub fn new(dacc: DACC, clocks: &Clocks, pmc: &PMC) -> Self {
+        pmc.with_unlocked(|pmc| unsafe { pmc.pmc_pcer0.write_with_zero(|w| w.bits(1 << DACC::PID)) });
+        let prescaler = prescaler(clocks.mck());
+        dacc.with_unlocked(|dacc| unsafe {
+            dacc.cr.write_with_zero(|w| w.swrst().set_bit());
+            dacc.mr.write(|w| w.prescaler().bits(prescaler));
+            // Full bias current, for the highest conversion rate.
+            dacc.acr.write(|w| w.ibctlch0().bits(3).ibctlch1().bits(3));
+        });
+        Dacc { dacc }
+    }
+
+    pub fn free(self, pmc: &PMC) -> DACC {
+        unsafe { self.dacc.chdr.write_with_zero(|w| w.bits(0b11)) };
+        pmc.with_unlocked(|pmc| unsafe { pmc.pmc_pcdr0.write_with_zero(|w| w.bits(1 << DACC::PID)) });
+        self.dacc
+    }
+
+    pub fn enable(&mut self, channel: u8) {
+        assert!(channel < CHANNELS, "DACC channel out of range");
+        self.dacc.with_unlocked(|dacc| unsafe { dacc.cher.write_with_zero(|w| w.bits(1 << channel)) });
+    }
+
+    pub fn disable(&mut self, channel: u8) {
+        assert!(channel < CHANNELS, "DACC channel out of range");
+        self.dacc.with_unlocked(|dacc| unsafe { dacc.chdr.write_with_zero(|w| w.bits(1 << channel)) });
+    }
+
+    /// Whether `channel` is through its startup time and converts.
+    pub fn is_ready(&self, channel: u8) -> bool {
+        self.dacc.chsr.read().bits() & (1 << (channel + 8)) != 0
+    }
+
+    /// Queues `value`, 12 bits, for conversion on `channel`.
+    pub fn write(&mut self, channel: u8, value: u16) -> nb::Result<(), Infallible> {
+        if self.dacc.isr.read().bits() & (1 << channel) == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        unsafe { self.dacc.cdr[channel as usize].write_with_zero(|w| w.bits(value as u32)) };
+        Ok(())
+    }
+
+    /// Plays samples on the channel of `output` `rate` times per second,
+    /// paced by TIOA of `timer`. `buffers` sets the latency: the XDMAC
+    /// interrupt, forwarded to `AudioOut::on_interrupt`, has to be served
+    /// within `N` samples.
+    #[allow(clippy::too_many_arguments)]
+    pub fn audio_out<O, const TC: u8, const CH: u8, const N: usize, const Q: usize>(
+        mut self,
+        output: O,
+        rate: Hertz,
+        mut timer: tc::Channel<TC0, TC>,
+        dma: dma::Channel<CH>,
+        buffers: &'static mut PingPongBuffers<N>,
+        refill: Option<Refill>,
+        clocks: &Clocks,
+    ) -> AudioOut<O, TC, CH, N, Q>
+    where
+        O: Output,
+    {
+        assert!(TC < 3, "TC channel out of range");
+        set_trigger(&self.dacc, O::CH, Some(1 + TC));
+        self.enable(O::CH);
+        let mut stream = dma::peripheral::memory_to_peripheral_ping_pong(dma, output, buffers);
+        stream.listen();
+        // Last, so that no trigger comes before the DMA is ready.
+        timer.start_trigger_rate(clocks, rate);
+        AudioOut { dacc: self, timer, stream, queue: Ring::new(0), refill }
+    }
+}
+
+/// A DACC channel playing samples from a queue of `Q` and a refill
+/// callback, through buffers of `N` samples.
+pub struct AudioOut<O, const TC: u8, const CH: u8, const N: usize, const Q: usize> {
+    dacc: Dacc,
+    timer: tc::Channel<TC0, TC>,
+    stream: PingPong<CH, O, N>,
+    queue: Ring<u16, Q>,
+    refill: Option<Refill>,
+}
+
+impl<O: Output, const TC: u8, const CH: u8, const N: usize, const Q: usize> AudioOut<O, TC, CH, N, Q> {
+    /// Queues as many of `samples` as fit and returns how many.
+    pub fn play(&mut self, samples: &[u16]) -> usize {
+        samples.iter().take_while(|&&sample| self.queue.push(sample)).count()
+    }
+
+    /// Refills the buffer the XDMAC is done with from the queue, then from
+    /// the refill callback, padding with `MID_SCALE`. To be called from the
+    /// XDMAC interrupt, in place of or after `dma::on_interrupt`.
+    pub fn on_interrupt(&mut self) {
+        self.stream.read_status();
+        let (queue, refill) = (&mut self.queue, self.refill);
+        self.stream.poll(|buffer| fill(buffer, queue, refill));
+    }
+
+    /// Replaces the refill callback.
+    pub fn set_refill(&mut self, refill: Option<Refill>) {
+        self.refill = refill;
+    }
+
+    /// Whether every queued sample went to the buffers.
+    pub fn is_drained(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Stops the timer and the DMA. Queued samples are dropped.
+    pub fn stop(mut self) -> (Dacc, tc::Channel<TC0, TC>, dma::Channel<CH>, O, &'static mut PingPongBuffers<N>) {
+        self.timer.disable();
+        let (channel, output, buffers) = self.stream.stop();
+        set_trigger(&self.dacc.dacc, O::CH, None);
+        self.dacc.disable(O::CH);
+        (self.dacc, self.timer, channel, output, buffers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dac_clock_prescaler() {
+        // 150 MHz / 13 = 11.5 MHz.
+        assert_eq!(prescaler(Hertz(150_000_000)), 11);
+        assert_eq!(prescaler(Hertz(24_000_000)), 0);
+        assert_eq!(prescaler(Hertz(12_000_000)), 0);
+    }
+
+    #[test]
+    fn fills_from_queue_then_refill() {
+        fn refill(buffer: &mut [u32]) -> usize {
+            buffer[..2].copy_from_slice(&[7, 8]);
+            2
+        }
+        let mut queue = Ring::<u16, 4>::new(0);
+        queue.push(1);
+        queue.push(2);
+        let mut buffer = [0; 6];
+        fill(&mut buffer, &mut queue, Some(refill));
+        assert_eq!(buffer, [1, 2, 7, 8, MID_SCALE as u32, MID_SCALE as u32]);
+        fill(&mut buffer, &mut queue, None);
+        assert_eq!(buffer, [MID_SCALE as u32; 6]);
+    }
+}
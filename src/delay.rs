@@ -0,0 +1,72 @@
+use cortex_m::peripheral::{syst::SystClkSource, SYST};
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use crate::clocks::Clocks;
+
+const SYST_MAX_RELOAD: u32 = 0x00FF_FFFF;
+
+pub struct Delay {
+    syst: SYST,
+    clocks: Clocks,
+}
+
+impl Delay {
+    pub fn new(mut syst: SYST, clocks: Clocks) -> Self {
+        syst.set_clock_source(SystClkSource::Core);
+        Delay { syst, clocks }
+    }
+
+    pub fn free(self) -> SYST {
+        self.syst
+    }
+}
+
+impl DelayUs<u32> for Delay {
+    fn delay_us(&mut self, us: u32) {
+        let mut ticks = us as u64 * (self.clocks.hclk().0 / 1_000_000) as u64;
+        while ticks != 0 {
+            let reload = if ticks <= SYST_MAX_RELOAD as u64 {
+                ticks as u32
+            } else {
+                SYST_MAX_RELOAD
+            };
+            self.syst.set_reload(reload);
+            self.syst.clear_current();
+            self.syst.enable_counter();
+            ticks -= reload as u64;
+            while !self.syst.has_wrapped() {}
+            self.syst.disable_counter();
+        }
+    }
+}
+
+impl DelayUs<u16> for Delay {
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(us as u32)
+    }
+}
+
+impl DelayUs<u8> for Delay {
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(us as u32)
+    }
+}
+
+impl DelayMs<u32> for Delay {
+    fn delay_ms(&mut self, ms: u32) {
+        for _ in 0..ms {
+            self.delay_us(1_000u32);
+        }
+    }
+}
+
+impl DelayMs<u16> for Delay {
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(ms as u32)
+    }
+}
+
+impl DelayMs<u8> for Delay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(ms as u32)
+    }
+}
@@ -0,0 +1,60 @@
+//! Per-device values derived from the EEFC unique identifier, so boards need
+//! no provisioning to tell themselves apart.
+
+use crate::flash::Flash;
+
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceId([u32; 4]);
+
+impl DeviceId {
+    pub fn read(flash: &mut Flash) -> DeviceId {
+        DeviceId(flash.unique_id())
+    }
+
+    pub fn words(&self) -> [u32; 4] {
+        self.0
+    }
+
+    fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.0.iter().flat_map(|word| word.to_le_bytes())
+    }
+
+    /// Locally administered unicast address from a 64-bit FNV-1a hash of the
+    /// identifier. It stays the same across resets and firmware updates.
+    pub fn mac_address(&self) -> [u8; 6] {
+        let hash = self.bytes().fold(FNV_OFFSET, |hash, byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME));
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&hash.to_be_bytes()[2..]);
+        mac[0] = mac[0] & !0x01 | 0x02;
+        mac
+    }
+
+    /// The identifier as 32 upper case hex digits, as a USB serial number.
+    pub fn serial_number<'a>(&self, buffer: &'a mut [u8; 32]) -> &'a str {
+        for (digits, byte) in buffer.chunks_exact_mut(2).zip(self.bytes()) {
+            digits[0] = HEX[(byte >> 4) as usize];
+            digits[1] = HEX[(byte & 0xF) as usize];
+        }
+        core::str::from_utf8(buffer).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeviceId;
+
+    #[test]
+    fn derived_values() {
+        let id = DeviceId([0x3433_3132, 0x3837_3635, 0x0000_0000, 0xFFFF_FFFF]);
+        let mac = id.mac_address();
+        assert_eq!(mac[0] & 0x03, 0x02);
+        assert_ne!(mac, DeviceId([0x3433_3132, 0x3837_3635, 0x0000_0000, 0xFFFF_FFFE]).mac_address());
+
+        let mut buffer = [0; 32];
+        assert_eq!(id.serial_number(&mut buffer), "323133343536373800000000FFFFFFFF");
+    }
+}
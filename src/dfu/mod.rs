@@ -0,0 +1,4 @@
+//! Firmware updates into internal flash.
+
+pub mod serial;
+pub mod slots;
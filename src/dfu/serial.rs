@@ -0,0 +1,394 @@
+//! XMODEM-CRC and YMODEM receiver writing straight into internal flash.
+
+use embedded_hal::serial::{Read, Write};
+use embedded_hal::timer::CountDown;
+
+use crate::crc::crc16;
+use crate::flash::{Flash, FlashError, ERASE_SIZE, PAGE_SIZE};
+use crate::time::{Microseconds, U32Ext};
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC_MODE: u8 = b'C';
+const MAX_RETRIES: u8 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Xmodem,
+    /// Single file YMODEM batch. The size from the header trims the padding
+    /// of the last block.
+    Ymodem,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub received: u32,
+    /// Image size announced by the sender, YMODEM only.
+    pub total: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    Serial(E),
+    Flash(FlashError),
+    Timeout,
+    /// The sender aborted the transfer.
+    Cancelled,
+    /// The image does not fit in the configured region.
+    TooLarge,
+    /// A block arrived out of order.
+    Sequence,
+    /// The flash contents do not match the received image.
+    Verify,
+}
+
+impl<E> From<FlashError> for Error<E> {
+    fn from(error: FlashError) -> Self {
+        Error::Flash(error)
+    }
+}
+
+pub struct Config {
+    pub protocol: Protocol,
+    /// Start of the image in flash, a multiple of `ERASE_SIZE`.
+    pub offset: u32,
+    /// Largest image accepted, in bytes.
+    pub limit: u32,
+    /// How long to wait for each byte, and between `C` requests at the start.
+    pub timeout: Microseconds,
+}
+
+impl Config {
+    pub fn new(protocol: Protocol, offset: u32, limit: u32) -> Config {
+        Config {
+            protocol,
+            offset,
+            limit,
+            timeout: 1_000.ms(),
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Microseconds) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Parses a YMODEM block 0. `None` is the empty header closing the batch,
+/// otherwise the file size if the sender included one.
+pub(crate) fn parse_header(block: &[u8]) -> Option<Option<u32>> {
+    if block.first().copied().unwrap_or(0) == 0 {
+        return None;
+    }
+    let name_end = block.iter().position(|&b| b == 0)?;
+    let digits = block[name_end + 1..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit());
+    let mut size: Option<u32> = None;
+    for &digit in digits {
+        size = Some(
+            size.unwrap_or(0)
+                .checked_mul(10)?
+                .checked_add((digit - b'0') as u32)?,
+        );
+    }
+    Some(size)
+}
+
+enum Packet {
+    Data { block: u8, length: usize },
+    End,
+}
+
+struct Link<'a, S, T> {
+    serial: &'a mut S,
+    timer: &'a mut T,
+    timeout: Microseconds,
+}
+
+impl<'a, S, T, E> Link<'a, S, T>
+where
+    S: Read<u8, Error = E> + Write<u8, Error = E>,
+    T: CountDown<Time = Microseconds>,
+{
+    fn read(&mut self) -> Result<u8, Error<E>> {
+        self.timer.start(self.timeout);
+        loop {
+            match self.serial.read() {
+                Ok(byte) => return Ok(byte),
+                Err(nb::Error::Other(e)) => return Err(Error::Serial(e)),
+                Err(nb::Error::WouldBlock) => {}
+            }
+            if self.timer.wait().is_ok() {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    fn send(&mut self, byte: u8) -> Result<(), Error<E>> {
+        nb::block!(self.serial.write(byte)).map_err(Error::Serial)
+    }
+
+    /// Drains the line until it stays quiet for a whole timeout.
+    fn purge(&mut self) {
+        while !matches!(self.read(), Err(Error::Timeout)) {}
+    }
+
+    fn cancel(&mut self) {
+        let _ = self.send(CAN);
+        let _ = self.send(CAN);
+    }
+
+    /// Reads the rest of a packet after its header byte. Corrupted packets
+    /// come back as `Err(None)` so they can be NAKed.
+    fn packet(&mut self, header: u8, data: &mut [u8; 1024]) -> Result<Packet, Option<Error<E>>> {
+        let length = match header {
+            SOH => 128,
+            STX => 1024,
+            EOT => return Ok(Packet::End),
+            CAN => {
+                return match self.read() {
+                    Ok(CAN) => Err(Some(Error::Cancelled)),
+                    _ => Err(None),
+                }
+            }
+            _ => return Err(None),
+        };
+        let block = self.read().map_err(|_| None)?;
+        let complement = self.read().map_err(|_| None)?;
+        for byte in data[..length].iter_mut() {
+            *byte = self.read().map_err(|_| None)?;
+        }
+        let crc = (self.read().map_err(|_| None)? as u16) << 8 | self.read().map_err(|_| None)? as u16;
+        if block != !complement || crc != crc16(0, &data[..length]) {
+            return Err(None);
+        }
+        Ok(Packet::Data { block, length })
+    }
+
+    /// Waits for the next valid packet, NAKing corrupted ones. `request` is
+    /// sent before each attempt until the first byte arrives.
+    fn next_packet(&mut self, data: &mut [u8; 1024], request: Option<u8>) -> Result<Packet, Error<E>> {
+        let mut retries = 0;
+        let mut reply = request;
+        loop {
+            if let Some(reply) = reply {
+                self.send(reply)?;
+            }
+            let result = match self.read() {
+                Ok(header) => self.packet(header, data),
+                Err(Error::Timeout) => Err(None),
+                Err(e) => Err(Some(e)),
+            };
+            match result {
+                Ok(packet) => return Ok(packet),
+                Err(Some(e)) => {
+                    if !matches!(e, Error::Cancelled) {
+                        self.cancel();
+                    }
+                    return Err(e);
+                }
+                Err(None) => {
+                    retries += 1;
+                    if retries > MAX_RETRIES {
+                        self.cancel();
+                        return Err(Error::Timeout);
+                    }
+                    self.purge();
+                    reply = match request {
+                        Some(CRC_MODE) => request,
+                        _ => Some(NAK),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Buffers received data into whole pages and erases ahead of them, as a
+/// page can only be programmed once between erases.
+struct Writer<'a> {
+    flash: &'a mut Flash,
+    start: u32,
+    address: u32,
+    erased: u32,
+    page: [u8; PAGE_SIZE],
+    fill: usize,
+    crc: u16,
+}
+
+impl<'a> Writer<'a> {
+    fn push<E>(&mut self, mut data: &[u8]) -> Result<(), Error<E>> {
+        self.crc = crc16(self.crc, data);
+        while !data.is_empty() {
+            let count = (PAGE_SIZE - self.fill).min(data.len());
+            self.page[self.fill..self.fill + count].copy_from_slice(&data[..count]);
+            self.fill += count;
+            data = &data[count..];
+            if self.fill == PAGE_SIZE {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush<E>(&mut self) -> Result<(), Error<E>> {
+        if self.fill == 0 {
+            return Ok(());
+        }
+        if self.address >= self.erased {
+            self.flash.erase(self.erased, ERASE_SIZE)?;
+            self.erased += ERASE_SIZE;
+        }
+        self.flash.write(self.address, &self.page[..self.fill])?;
+        self.address += PAGE_SIZE as u32;
+        self.fill = 0;
+        Ok(())
+    }
+
+    /// Compares the programmed image with the CRC of the received data.
+    fn verify<E>(&self, length: u32) -> Result<(), Error<E>> {
+        let mut crc = 0;
+        let mut buffer = [0u8; 64];
+        for chunk in (self.start..self.start + length).step_by(buffer.len()) {
+            let count = (self.start + length - chunk).min(buffer.len() as u32) as usize;
+            self.flash.read(chunk, &mut buffer[..count])?;
+            crc = crc16(crc, &buffer[..count]);
+        }
+        if crc == self.crc {
+            Ok(())
+        } else {
+            Err(Error::Verify)
+        }
+    }
+}
+
+/// Receives one image from `serial` into `flash` and returns its length.
+/// The region is erased as the transfer goes, so a failed update leaves
+/// it partially written. `progress` runs after each stored block.
+pub fn receive<S, T, E, F>(
+    serial: &mut S,
+    timer: &mut T,
+    flash: &mut Flash,
+    config: &Config,
+    mut progress: F,
+) -> Result<u32, Error<E>>
+where
+    S: Read<u8, Error = E> + Write<u8, Error = E>,
+    T: CountDown<Time = Microseconds>,
+    F: FnMut(Progress),
+{
+    if !config.offset.is_multiple_of(ERASE_SIZE) {
+        return Err(Error::Flash(FlashError::Unaligned));
+    }
+    match config.offset.checked_add(config.limit) {
+        Some(end) if end <= flash.size() => {}
+        _ => return Err(Error::Flash(FlashError::OutOfBounds)),
+    }
+
+    let mut link = Link {
+        serial,
+        timer,
+        timeout: config.timeout,
+    };
+    let mut data = [0u8; 1024];
+
+    let mut total = None;
+    let mut reply = Some(CRC_MODE);
+    if config.protocol == Protocol::Ymodem {
+        match link.next_packet(&mut data, reply)? {
+            Packet::Data { block: 0, length } => match parse_header(&data[..length]) {
+                Some(size) => total = size,
+                None => {
+                    link.send(ACK)?;
+                    return Ok(0);
+                }
+            },
+            _ => {
+                link.cancel();
+                return Err(Error::Sequence);
+            }
+        }
+        if total.is_some_and(|size| size > config.limit) {
+            link.cancel();
+            return Err(Error::TooLarge);
+        }
+        link.send(ACK)?;
+    }
+
+    let mut writer = Writer {
+        flash,
+        start: config.offset,
+        address: config.offset,
+        erased: config.offset,
+        page: [0xFF; PAGE_SIZE],
+        fill: 0,
+        crc: 0,
+    };
+    let mut received: u32 = 0;
+    let mut expected: u8 = 1;
+    loop {
+        match link.next_packet(&mut data, reply)? {
+            Packet::Data { block, length } if block == expected => {
+                let length = match total {
+                    Some(size) => size.saturating_sub(received).min(length as u32),
+                    None => length as u32,
+                };
+                if received + length > config.limit {
+                    link.cancel();
+                    return Err(Error::TooLarge);
+                }
+                if let Err(e) = writer.push(&data[..length as usize]) {
+                    link.cancel();
+                    return Err(e);
+                }
+                received += length;
+                expected = expected.wrapping_add(1);
+                progress(Progress { received, total });
+            }
+            Packet::Data { block, .. } if block == expected.wrapping_sub(1) => {}
+            Packet::Data { .. } => {
+                link.cancel();
+                return Err(Error::Sequence);
+            }
+            Packet::End => break,
+        }
+        reply = Some(ACK);
+    }
+
+    if config.protocol == Protocol::Ymodem {
+        // The first EOT is NAKed so a spurious one cannot end the file.
+        match link.next_packet(&mut data, Some(NAK))? {
+            Packet::End => link.send(ACK)?,
+            _ => {
+                link.cancel();
+                return Err(Error::Sequence);
+            }
+        }
+        if let Ok(Packet::Data { block: 0, .. }) = link.next_packet(&mut data, Some(CRC_MODE)) {
+            link.send(ACK)?;
+        }
+    } else {
+        link.send(ACK)?;
+    }
+
+    writer.flush()?;
+    writer.verify(received)?;
+    Ok(received)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ymodem_header() {
+        assert_eq!(parse_header(b"app.bin\x0012345 13770431234\x00"), Some(Some(12345)));
+        assert_eq!(parse_header(b"app.bin\x00\x00"), Some(None));
+        assert_eq!(parse_header(&[0; 128]), None);
+    }
+}
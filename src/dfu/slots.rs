@@ -0,0 +1,265 @@
+//! Two firmware slots, A and B, side by side in flash after a bootloader.
+//!
+//! Each slot ends with an erase block holding its metadata, version, length
+//! and CRC of the image, written only once the image is complete. The
+//! bootloader starts the valid slot with the highest version, and updates
+//! go to the other one, so a failed update leaves the running image alone:
+//!
+//! ```ignore
+//! let slots = Slots::new(BOOTLOADER_SIZE, 960 * 1024);
+//! flash.boot_from_flash()?;
+//! if let Some(slot) = slots.select(&mut flash)? {
+//!     boot::deinit(&mut cp.NVIC, &mut cp.SYST, &pmc);
+//!     unsafe { boot::jump_to_application(&mut cp.SCB, slots.address(slot)) }
+//! }
+//! ```
+//!
+//! From the application, with `dfu::serial::receive`:
+//!
+//! ```ignore
+//! let target = slots.update_target(&mut flash)?;
+//! let config = serial::Config::new(Protocol::Ymodem, slots.offset(target), slots.capacity());
+//! let length = serial::receive(&mut uart, &mut timer, &mut flash, &config, |_| {})?;
+//! slots.commit(&mut flash, target, version, length)?;
+//! ```
+//!
+//! The GPNVM boot bit only chooses between the ROM and the flash, so the
+//! slot itself is chosen by the bootloader jump.
+//!
+//! The images are not position independent: each slot needs its own build,
+//! linked at `address(slot)`, and the update server has to send the one
+//! for `update_target`. `commit` and `validate` refuse an image whose reset
+//! vector points outside its slot.
+
+use crate::crc::crc16;
+use crate::flash::{Flash, FlashError, ERASE_SIZE, FLASH_BASE};
+
+/// "SLOT", little endian.
+const MAGIC: u32 = 0x544F_4C53;
+const METADATA_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommitError {
+    Flash(FlashError),
+    /// Longer than the slot capacity.
+    TooLarge,
+    /// The reset vector of the image is outside the slot: it was built
+    /// for the other one.
+    WrongSlot,
+}
+
+impl From<FlashError> for CommitError {
+    fn from(error: FlashError) -> Self {
+        CommitError::Flash(error)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    pub fn other(self) -> Slot {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    pub version: u32,
+    /// Image length in bytes.
+    pub length: u32,
+    /// CRC-16/XMODEM of the image, as `dfu::serial` checks it.
+    pub crc: u16,
+}
+
+impl Metadata {
+    /// Magic, version, length, image CRC and a CRC of the first 14 bytes,
+    /// one 128-bit flash word.
+    fn to_bytes(self) -> [u8; METADATA_SIZE] {
+        let mut bytes = [0; METADATA_SIZE];
+        bytes[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.version.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.length.to_le_bytes());
+        bytes[12..14].copy_from_slice(&self.crc.to_le_bytes());
+        let check = crc16(0, &bytes[..14]);
+        bytes[14..16].copy_from_slice(&check.to_le_bytes());
+        bytes
+    }
+
+    /// `None` for erased or torn metadata.
+    fn from_bytes(bytes: &[u8; METADATA_SIZE]) -> Option<Metadata> {
+        let word = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let half = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        if word(0) != MAGIC || half(14) != crc16(0, &bytes[..14]) {
+            return None;
+        }
+        Some(Metadata { version: word(4), length: word(8), crc: half(12) })
+    }
+}
+
+/// Slot A at `first`, slot B right after it, both `size` bytes including
+/// their metadata block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slots {
+    first: u32,
+    size: u32,
+}
+
+impl Slots {
+    /// `first` and `size` are multiples of `ERASE_SIZE`; `first` is also
+    /// where the bootloader ends.
+    pub fn new(first: u32, size: u32) -> Slots {
+        assert!(first.is_multiple_of(ERASE_SIZE) && size.is_multiple_of(ERASE_SIZE), "slots not aligned to erase blocks");
+        assert!(size > ERASE_SIZE, "slot too small");
+        Slots { first, size }
+    }
+
+    /// Offset of the image in flash.
+    pub fn offset(&self, slot: Slot) -> u32 {
+        match slot {
+            Slot::A => self.first,
+            Slot::B => self.first + self.size,
+        }
+    }
+
+    /// Address of the vector table of the image, for `boot::jump_to_application`.
+    pub fn address(&self, slot: Slot) -> u32 {
+        FLASH_BASE + self.offset(slot)
+    }
+
+    /// Largest image a slot holds.
+    pub fn capacity(&self) -> u32 {
+        self.size - ERASE_SIZE
+    }
+
+    fn metadata_offset(&self, slot: Slot) -> u32 {
+        self.offset(slot) + self.capacity()
+    }
+
+    pub fn metadata(&self, flash: &mut Flash, slot: Slot) -> Result<Option<Metadata>, FlashError> {
+        let mut bytes = [0; METADATA_SIZE];
+        flash.read(self.metadata_offset(slot), &mut bytes)?;
+        Ok(Metadata::from_bytes(&bytes).filter(|metadata| metadata.length <= self.capacity()))
+    }
+
+    fn image_crc(&self, flash: &mut Flash, slot: Slot, length: u32) -> Result<u16, FlashError> {
+        let start = self.offset(slot);
+        let mut crc = 0;
+        let mut buffer = [0u8; 64];
+        for chunk in (start..start + length).step_by(buffer.len()) {
+            let count = (start + length - chunk).min(buffer.len() as u32) as usize;
+            flash.read(chunk, &mut buffer[..count])?;
+            crc = crc16(crc, &buffer[..count]);
+        }
+        Ok(crc)
+    }
+
+    /// Whether the reset vector of the `length` bytes in `slot` points into
+    /// them, as it does for an image linked at `address(slot)`.
+    fn linked_for(&self, flash: &mut Flash, slot: Slot, length: u32) -> Result<bool, FlashError> {
+        let mut vectors = [0; 8];
+        if length < vectors.len() as u32 {
+            return Ok(false);
+        }
+        flash.read(self.offset(slot), &mut vectors)?;
+        let reset = u32::from_le_bytes([vectors[4], vectors[5], vectors[6], vectors[7]]);
+        Ok(in_image(reset, self.address(slot), length))
+    }
+
+    /// Metadata of `slot` if its image matches it and was linked for it.
+    pub fn validate(&self, flash: &mut Flash, slot: Slot) -> Result<Option<Metadata>, FlashError> {
+        match self.metadata(flash, slot)? {
+            Some(metadata)
+                if self.image_crc(flash, slot, metadata.length)? == metadata.crc
+                    && self.linked_for(flash, slot, metadata.length)? =>
+            {
+                Ok(Some(metadata))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The valid slot with the highest version, A on a tie.
+    pub fn select(&self, flash: &mut Flash) -> Result<Option<Slot>, FlashError> {
+        let a = self.validate(flash, Slot::A)?;
+        let b = self.validate(flash, Slot::B)?;
+        Ok(match (a, b) {
+            (Some(a), Some(b)) if b.version > a.version => Some(Slot::B),
+            (Some(_), _) => Some(Slot::A),
+            (None, Some(_)) => Some(Slot::B),
+            (None, None) => None,
+        })
+    }
+
+    /// The slot an update should go to: the one `select` would not start.
+    pub fn update_target(&self, flash: &mut Flash) -> Result<Slot, FlashError> {
+        Ok(self.select(flash)?.map_or(Slot::A, Slot::other))
+    }
+
+    /// Marks the `length` bytes programmed in `slot` as an image of
+    /// `version`, from the CRC of the flash contents.
+    pub fn commit(&self, flash: &mut Flash, slot: Slot, version: u32, length: u32) -> Result<Metadata, CommitError> {
+        if length > self.capacity() {
+            return Err(CommitError::TooLarge);
+        }
+        if !self.linked_for(flash, slot, length)? {
+            return Err(CommitError::WrongSlot);
+        }
+        let metadata = Metadata { version, length, crc: self.image_crc(flash, slot, length)? };
+        self.invalidate(flash, slot)?;
+        flash.write(self.metadata_offset(slot), &metadata.to_bytes())?;
+        Ok(metadata)
+    }
+
+    /// Erases the metadata of `slot`, so that it is no longer started, for
+    /// example to fall back to the other one.
+    pub fn invalidate(&self, flash: &mut Flash, slot: Slot) -> Result<(), FlashError> {
+        flash.erase(self.metadata_offset(slot), ERASE_SIZE)
+    }
+}
+
+/// Whether `reset`, a Thumb address, falls in the image of `length` bytes
+/// at `start`.
+fn in_image(reset: u32, start: u32, length: u32) -> bool {
+    reset & 1 == 1 && (start..start + length).contains(&(reset & !1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_round_trip() {
+        let metadata = Metadata { version: 7, length: 123_456, crc: 0xBEEF };
+        let mut bytes = metadata.to_bytes();
+        assert_eq!(Metadata::from_bytes(&bytes), Some(metadata));
+        assert_eq!(Metadata::from_bytes(&[0xFF; METADATA_SIZE]), None);
+        bytes[5] ^= 1;
+        assert_eq!(Metadata::from_bytes(&bytes), None);
+    }
+
+    #[test]
+    fn layout() {
+        let slots = Slots::new(64 * 1024, 960 * 1024);
+        assert_eq!(slots.offset(Slot::B), 1024 * 1024);
+        assert_eq!(slots.address(Slot::A), 0x0041_0000);
+        assert_eq!(slots.metadata_offset(Slot::A), 1024 * 1024 - ERASE_SIZE);
+    }
+
+    #[test]
+    fn reset_vector_in_slot() {
+        let slots = Slots::new(64 * 1024, 960 * 1024);
+        let (a, b) = (slots.address(Slot::A), slots.address(Slot::B));
+        assert!(in_image(a + 0x1F5, a, 0x4000));
+        assert!(!in_image(a + 0x1F4, a, 0x4000));
+        // Built for slot A, flashed into slot B.
+        assert!(!in_image(a + 0x1F5, b, 0x4000));
+        assert!(!in_image(a + 0x4001, a, 0x4000));
+    }
+}
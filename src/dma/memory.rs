@@ -0,0 +1,128 @@
+//! Memory to memory transfers.
+
+use core::mem::size_of;
+use core::sync::atomic::{compiler_fence, Ordering};
+use embedded_dma::{ReadBuffer, WriteBuffer};
+use super::{maintain_dcache, CacheOperation, Channel, Transfer, MICROBLOCK_MAX};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Width {
+    Byte,
+    Word,
+}
+
+impl Width {
+    /// Words when everything is 4-byte aligned, bytes otherwise.
+    fn fitting(addresses: &[usize]) -> Width {
+        if addresses.iter().all(|address| address % 4 == 0) {
+            Width::Word
+        } else {
+            Width::Byte
+        }
+    }
+
+    fn units(&self, length: usize) -> usize {
+        match self {
+            Width::Byte => length,
+            Width::Word => length / 4,
+        }
+    }
+}
+
+/// Starts a single microblock from `source` to `destination`, or a fill of
+/// `destination` with `pattern` when `source` is `None`.
+fn start<const CH: u8>(channel: &mut Channel<CH>, source: Option<usize>, destination: usize, length: usize, pattern: u32) {
+    let width = Width::fitting(&[source.unwrap_or(0), destination, length]);
+    let units = width.units(length);
+    assert!(units <= MICROBLOCK_MAX, "transfer longer than one microblock");
+
+    channel.disable();
+    channel.read_status();
+    compiler_fence(Ordering::SeqCst);
+    let registers = channel.registers();
+    unsafe {
+        registers.csa0.write(|w| w.bits(source.unwrap_or(0) as u32));
+        registers.cda0.write(|w| w.bits(destination as u32));
+        registers.cubc0.write(|w| w.ublen().bits(units as u32));
+        registers.cc0.write(|w| {
+            w.type_().mem_tran()
+                .mbsize().sixteen()
+                .memset().bit(source.is_none())
+                .sif().ahb_if1()
+                .dif().ahb_if1()
+                .sam().incremented_am()
+                .dam().incremented_am();
+            match width {
+                Width::Byte => w.dwidth().byte(),
+                Width::Word => w.dwidth().word(),
+            }
+        });
+        registers.cndc0.write(|w| w.bits(0));
+        registers.cbc0.write(|w| w.bits(0));
+        registers.cds_msp0.write(|w| w.bits(pattern));
+        registers.csus0.write(|w| w.bits(0));
+        registers.cdus0.write(|w| w.bits(0));
+    }
+    channel.enable();
+}
+
+/// Copies `source` into `destination`, which must have the same length.
+/// Both are taken by value until the transfer ends, so only buffers that
+/// outlive it, like `&'static mut` slices or `singleton!`s, are accepted.
+/// The source is cleaned from the data cache first, and the destination
+/// invalidated when the transfer is waited on; it should be aligned to
+/// 32-byte cache lines.
+pub fn copy<const CH: u8, W, S, D>(
+    mut channel: Channel<CH>,
+    source: S,
+    mut destination: D,
+) -> Transfer<CH, (S, D)>
+where
+    S: ReadBuffer<Word = W>,
+    D: WriteBuffer<Word = W>,
+{
+    let (from, count) = unsafe { source.read_buffer() };
+    let (to, capacity) = unsafe { destination.write_buffer() };
+    assert_eq!(count, capacity);
+    let (from, to, length) = (from as usize, to as usize, count * size_of::<W>());
+    maintain_dcache(CacheOperation::Clean, from, length);
+    maintain_dcache(CacheOperation::CleanInvalidate, to, length);
+    start(&mut channel, Some(from), to, length, 0);
+    Transfer {
+        channel,
+        buffers: (source, destination),
+        destination: (to, length),
+    }
+}
+
+/// Sets every byte of `destination` to `value`, with the same buffer and
+/// cache handling as `copy`.
+pub fn fill<const CH: u8, D: WriteBuffer>(
+    mut channel: Channel<CH>,
+    mut destination: D,
+    value: u8,
+) -> Transfer<CH, D> {
+    let (to, count) = unsafe { destination.write_buffer() };
+    let (to, length) = (to as usize, count * size_of::<D::Word>());
+    maintain_dcache(CacheOperation::CleanInvalidate, to, length);
+    start(&mut channel, None, to, length, u32::from_ne_bytes([value; 4]));
+    Transfer {
+        channel,
+        buffers: destination,
+        destination: (to, length),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Width;
+
+    #[test]
+    fn width_follows_alignment() {
+        assert_eq!(Width::fitting(&[0x2040_0000, 0x2040_1000, 64]), Width::Word);
+        assert_eq!(Width::fitting(&[0x2040_0000, 0x2040_1002, 64]), Width::Byte);
+        assert_eq!(Width::fitting(&[0x2040_0000, 0x2040_1000, 63]), Width::Byte);
+        assert_eq!(Width::Word.units(64), 16);
+        assert_eq!(Width::Byte.units(63), 63);
+    }
+}
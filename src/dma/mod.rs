@@ -0,0 +1,289 @@
+//! XDMAC channels.
+//!
+//! All 24 channels share one interrupt line. Drivers register a handler or
+//! a waker on their channel, and the application forwards the interrupt to
+//! `on_interrupt`:
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn XDMAC() {
+//!     samv71_hal::dma::on_interrupt();
+//! }
+//! ```
+
+use core::cell::{Cell, RefCell};
+use core::sync::atomic::{compiler_fence, Ordering};
+use core::task::Waker;
+use cortex_m::interrupt::Mutex;
+use cortex_m::peripheral::{CBP, SCB};
+use crate::pac::xdmac::RegisterBlock;
+use crate::pac::{PMC, XDMAC};
+use crate::pmc::PeripheralId;
+use crate::write_protect::WriteProtect;
+
+mod memory;
+pub mod peripheral;
+
+pub use embedded_dma::{ReadBuffer, WriteBuffer};
+pub use memory::{copy, fill};
+
+pub const CHANNELS: usize = 24;
+const CHANNEL_OFFSET: usize = 0x40;
+const CACHE_LINE: usize = 32;
+/// Largest microblock, in data units.
+const MICROBLOCK_MAX: usize = 0xFF_FFFF;
+
+/// Called from `on_interrupt` with the channel number and its status.
+pub type Handler = fn(u8, Status);
+
+static HANDLERS: Mutex<[Cell<Option<Handler>>; CHANNELS]> =
+    Mutex::new([const { Cell::new(None) }; CHANNELS]);
+static WAKERS: Mutex<RefCell<[Option<Waker>; CHANNELS]>> =
+    Mutex::new(RefCell::new([const { None }; CHANNELS]));
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    pub block_end: bool,
+    pub linked_list_end: bool,
+    pub disabled: bool,
+    pub flushed: bool,
+    pub read_bus_error: bool,
+    pub write_bus_error: bool,
+    pub request_overflow: bool,
+}
+
+impl Status {
+    pub fn is_error(&self) -> bool {
+        self.read_bus_error || self.write_bus_error || self.request_overflow
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum CacheOperation {
+    Clean,
+    Invalidate,
+    CleanInvalidate,
+}
+
+/// Cleans or invalidates the data cache lines covering `length` bytes from
+/// `address`. Invalidating a buffer that does not start and end on 32-byte
+/// lines also drops pending writes to whatever shares those lines.
+pub(crate) fn maintain_dcache(operation: CacheOperation, address: usize, length: usize) {
+    if length == 0 || !SCB::dcache_enabled() {
+        return;
+    }
+    let cbp = unsafe { &*CBP::PTR };
+    cortex_m::asm::dsb();
+    for line in (address & !(CACHE_LINE - 1)..address + length).step_by(CACHE_LINE) {
+        unsafe {
+            match operation {
+                CacheOperation::Clean => cbp.dccmvac.write(line as u32),
+                CacheOperation::Invalidate => cbp.dcimvac.write(line as u32),
+                CacheOperation::CleanInvalidate => cbp.dccimvac.write(line as u32),
+            }
+        }
+    }
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+fn xdmac() -> &'static RegisterBlock {
+    unsafe { &*XDMAC::ptr() }
+}
+
+/// Every channel has the same layout as channel 0, 0x40 bytes apart, so the
+/// channel 0 fields of the returned block address channel `ch`.
+fn channel_registers(ch: u8) -> &'static RegisterBlock {
+    unsafe { &*((XDMAC::ptr() as usize + CHANNEL_OFFSET * ch as usize) as *const RegisterBlock) }
+}
+
+/// Reading CIS clears it.
+fn take_status(ch: u8) -> Status {
+    let cis = channel_registers(ch).cis0.read();
+    Status {
+        block_end: cis.bis().bit(),
+        linked_list_end: cis.lis().bit(),
+        disabled: cis.dis().bit(),
+        flushed: cis.fis().bit(),
+        read_bus_error: cis.rbeis().bit(),
+        write_bus_error: cis.wbeis().bit(),
+        request_overflow: cis.rois().bit(),
+    }
+}
+
+/// Dispatches the XDMAC interrupt to the handlers and wakers of the pending
+/// channels. Each channel status is read, and so cleared, once.
+pub fn on_interrupt() {
+    let xdmac = xdmac();
+    let pending = xdmac.gis.read().bits() & xdmac.gim.read().bits();
+    for ch in (0..CHANNELS as u8).filter(|ch| pending & (1 << ch) != 0) {
+        let status = take_status(ch);
+        let (handler, waker) = cortex_m::interrupt::free(|cs| {
+            (
+                HANDLERS.borrow(cs)[ch as usize].get(),
+                WAKERS.borrow(cs).borrow_mut()[ch as usize].take(),
+            )
+        });
+        if let Some(handler) = handler {
+            handler(ch, status);
+        }
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+pub trait XdmacExt {
+    fn split(self, pmc: &PMC) -> Parts;
+}
+
+macro_rules! channels {
+    ($($ch:ident: $CH:expr,)+) => {
+        pub struct Parts {
+            $(pub $ch: Channel<$CH>,)+
+            xdmac: XDMAC,
+        }
+
+        impl XdmacExt for XDMAC {
+            fn split(self, pmc: &PMC) -> Parts {
+                pmc.with_unlocked(|pmc| unsafe {
+                    pmc.pmc_pcer1.write_with_zero(|w| w.bits(1 << (XDMAC::PID - 32)));
+                });
+                Parts {
+                    $($ch: Channel { _private: () },)+
+                    xdmac: self,
+                }
+            }
+        }
+
+        impl Parts {
+            /// Controller registers, global and per channel alike.
+            ///
+            /// # Safety
+            /// Channels owned by a `Transfer` must be left alone.
+            pub unsafe fn registers(&self) -> &crate::pac::xdmac::RegisterBlock {
+                &self.xdmac
+            }
+
+            pub fn free(self, pmc: &PMC) -> XDMAC {
+                $(
+                    let mut channel = self.$ch;
+                    channel.unlisten();
+                    channel.disable();
+                    channel.set_handler(None);
+                )+
+                pmc.with_unlocked(|pmc| unsafe {
+                    pmc.pmc_pcdr1.write_with_zero(|w| w.bits(1 << (XDMAC::PID - 32)));
+                });
+                self.xdmac
+            }
+        }
+    }
+}
+
+channels! {
+    ch0: 0, ch1: 1, ch2: 2, ch3: 3, ch4: 4, ch5: 5, ch6: 6, ch7: 7,
+    ch8: 8, ch9: 9, ch10: 10, ch11: 11, ch12: 12, ch13: 13, ch14: 14, ch15: 15,
+    ch16: 16, ch17: 17, ch18: 18, ch19: 19, ch20: 20, ch21: 21, ch22: 22, ch23: 23,
+}
+
+pub struct Channel<const CH: u8> {
+    _private: (),
+}
+
+impl<const CH: u8> Channel<CH> {
+    fn registers(&self) -> &RegisterBlock {
+        channel_registers(CH)
+    }
+
+    pub fn id(&self) -> u8 {
+        CH
+    }
+
+    pub fn enable(&mut self) {
+        unsafe { xdmac().ge.write_with_zero(|w| w.bits(1 << CH)) };
+    }
+
+    /// Stops the channel and waits for the transfer in flight to end.
+    pub fn disable(&mut self) {
+        unsafe { xdmac().gd.write_with_zero(|w| w.bits(1 << CH)) };
+        while self.is_busy() {}
+    }
+
+    pub fn is_busy(&self) -> bool {
+        xdmac().gs.read().bits() & (1 << CH) != 0
+    }
+
+    /// Enables the end of block and error interrupts of the channel.
+    pub fn listen(&mut self) {
+        unsafe {
+            self.registers().cie0.write_with_zero(|w| {
+                w.bie().set_bit()
+                    .rbie().set_bit()
+                    .wbie().set_bit()
+                    .roie().set_bit()
+            });
+            xdmac().gie.write_with_zero(|w| w.bits(1 << CH));
+        }
+    }
+
+    pub fn unlisten(&mut self) {
+        unsafe {
+            xdmac().gid.write_with_zero(|w| w.bits(1 << CH));
+            self.registers().cid0.write_with_zero(|w| w.bits(0x7F));
+        }
+    }
+
+    /// Reads and clears the status. Not meant for channels listened to, as
+    /// `on_interrupt` takes their status first.
+    pub fn read_status(&mut self) -> Status {
+        take_status(CH)
+    }
+
+    pub fn set_handler(&mut self, handler: Option<Handler>) {
+        cortex_m::interrupt::free(|cs| HANDLERS.borrow(cs)[CH as usize].set(handler));
+    }
+
+    /// Wakes `waker` at the next interrupt of the channel.
+    pub fn register_waker(&mut self, waker: &Waker) {
+        cortex_m::interrupt::free(|cs| {
+            let mut wakers = WAKERS.borrow(cs).borrow_mut();
+            match &wakers[CH as usize] {
+                Some(registered) if registered.will_wake(waker) => {}
+                _ => wakers[CH as usize] = Some(waker.clone()),
+            }
+        });
+    }
+}
+
+/// Transfer in flight. It owns the channel and the buffers until it ends.
+pub struct Transfer<const CH: u8, BUF> {
+    channel: Channel<CH>,
+    buffers: BUF,
+    /// Memory written by the transfer, invalidated from the data cache once
+    /// it ends.
+    destination: (usize, usize),
+}
+
+impl<const CH: u8, BUF> Transfer<CH, BUF> {
+    pub fn is_done(&self) -> bool {
+        !self.channel.is_busy()
+    }
+
+    /// Blocks until the channel goes idle and gives back the channel and
+    /// the buffers.
+    pub fn wait(self) -> (Channel<CH>, BUF) {
+        while !self.is_done() {}
+        compiler_fence(Ordering::SeqCst);
+        let (address, length) = self.destination;
+        maintain_dcache(CacheOperation::Invalidate, address, length);
+        (self.channel, self.buffers)
+    }
+
+    /// Disables the channel, ending the transfer early, and gives back the
+    /// channel and the buffers.
+    pub fn stop(mut self) -> (Channel<CH>, BUF) {
+        self.channel.disable();
+        self.wait()
+    }
+}
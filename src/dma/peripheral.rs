@@ -0,0 +1,563 @@
+//! Hardware request lines, peripheral to peripheral transfers and reads
+//! from full-duplex buses.
+//!
+//! Each marker type stands for one XDMAC request line and the data register
+//! it moves. `Source`s can only be read and `Destination`s only written, so
+//! pairing them the wrong way round does not compile.
+
+use core::ptr::{self, addr_of_mut};
+use core::sync::atomic::{compiler_fence, Ordering};
+use embedded_dma::WriteBuffer;
+use crate::pac;
+use crate::pac::xdmac::cc0;
+use super::{maintain_dcache, CacheOperation, Channel, Status, Transfer, CHANNELS, MICROBLOCK_MAX};
+
+pub trait Request {
+    /// XDMAC hardware interface number.
+    const PERID: u8;
+
+    fn address() -> usize;
+}
+
+/// Data register the XDMAC reads from.
+pub trait Source: Request {}
+
+/// Data register the XDMAC writes to.
+pub trait Destination: Request {}
+
+macro_rules! requests {
+    ($($(#[$doc:meta])* $Name:ident: $kind:ident, $PER:ident, $register:ident $([$index:expr])?, $perid:expr;)+) => {
+        $(
+            $(#[$doc])*
+            #[derive(Clone, Copy, Debug)]
+            pub struct $Name;
+
+            impl Request for $Name {
+                const PERID: u8 = $perid;
+
+                fn address() -> usize {
+                    unsafe { &(*pac::$PER::ptr()).$register$([$index])? as *const _ as usize }
+                }
+            }
+
+            impl $kind for $Name {}
+        )+
+    }
+}
+
+requests! {
+    Spi0Tx: Destination, SPI0, tdr, 1;
+    Spi0Rx: Source, SPI0, rdr, 2;
+    Spi1Tx: Destination, SPI1, tdr, 3;
+    Spi1Rx: Source, SPI1, rdr, 4;
+    Usart0Tx: Destination, USART0, thr, 7;
+    Usart0Rx: Source, USART0, rhr, 8;
+    Usart1Tx: Destination, USART1, thr, 9;
+    Usart1Rx: Source, USART1, rhr, 10;
+    Usart2Tx: Destination, USART2, thr, 11;
+    Usart2Rx: Source, USART2, rhr, 12;
+    Pwm0: Destination, PWM0, dmar, 13;
+    Uart0Tx: Destination, UART0, thr, 20;
+    Uart0Rx: Source, UART0, rhr, 21;
+    Uart1Tx: Destination, UART1, thr, 22;
+    Uart1Rx: Source, UART1, rhr, 23;
+    Uart2Tx: Destination, UART2, thr, 24;
+    Uart2Rx: Source, UART2, rhr, 25;
+    Uart3Tx: Destination, UART3, thr, 26;
+    Uart3Rx: Source, UART3, rhr, 27;
+    Uart4Tx: Destination, UART4, thr, 28;
+    Uart4Rx: Source, UART4, rhr, 29;
+    Dacc0: Destination, DACC, cdr[0], 30;
+    Dacc1: Destination, DACC, cdr[1], 31;
+    SscTx: Destination, SSC, thr, 32;
+    SscRx: Source, SSC, rhr, 33;
+    Afec0: Source, AFEC0, lcdr, 35;
+    Afec1: Source, AFEC1, lcdr, 36;
+    Pwm1: Destination, PWM1, dmar, 39;
+    /// RA and RB captures of channel 0.
+    Tc0: Source, TC0, rab0, 40;
+    /// RA and RB captures of channel 0.
+    Tc1: Source, TC1, rab0, 41;
+    /// RA and RB captures of channel 0.
+    Tc2: Source, TC2, rab0, 42;
+    /// RA and RB captures of channel 0.
+    Tc3: Source, TC3, rab0, 43;
+}
+
+/// Which side's request line paces the transfer. The other side must keep
+/// up, for example a DACC triggered by the same timer as the AFEC feeding it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pace {
+    Source,
+    Destination,
+}
+
+/// Programs a single microblock of `units` data units on a stopped channel
+/// and starts it.
+fn start<const CH: u8>(
+    channel: &mut Channel<CH>,
+    source: usize,
+    destination: usize,
+    units: usize,
+    pattern: u32,
+    perid: u8,
+    config: impl FnOnce(&mut cc0::W) -> &mut cc0::W,
+) {
+    program(channel, source, destination, units, pattern, perid, config);
+    channel.enable();
+}
+
+fn program<const CH: u8>(
+    channel: &mut Channel<CH>,
+    source: usize,
+    destination: usize,
+    units: usize,
+    pattern: u32,
+    perid: u8,
+    config: impl FnOnce(&mut cc0::W) -> &mut cc0::W,
+) {
+    assert!(units <= MICROBLOCK_MAX, "transfer longer than one microblock");
+
+    channel.disable();
+    channel.read_status();
+    compiler_fence(Ordering::SeqCst);
+    let registers = channel.registers();
+    unsafe {
+        registers.csa0.write(|w| w.bits(source as u32));
+        registers.cda0.write(|w| w.bits(destination as u32));
+        registers.cubc0.write(|w| w.ublen().bits(units as u32));
+        registers.cc0.write(|w| {
+            config(w.type_().per_tran()
+                .mbsize().single()
+                .swreq().hwr_connected()
+                .memset().normal_mode()
+                .csize().chk_1()
+                .sif().ahb_if1()
+                .dif().ahb_if1()
+                .perid().bits(perid))
+        });
+        registers.cndc0.write(|w| w.bits(0));
+        registers.cbc0.write(|w| w.bits(0));
+        registers.cds_msp0.write(|w| w.bits(pattern));
+        registers.csus0.write(|w| w.bits(0));
+        registers.cdus0.write(|w| w.bits(0));
+    }
+}
+
+/// Moves `count` 32-bit words from the data register of `S` to the one of
+/// `D`, one per request of the side selected by `pace`, e.g. AFEC results
+/// straight into the DACC.
+pub fn peripheral_to_peripheral<const CH: u8, S: Source, D: Destination>(
+    mut channel: Channel<CH>,
+    source: S,
+    destination: D,
+    pace: Pace,
+    count: usize,
+) -> Transfer<CH, (S, D)> {
+    let perid = match pace {
+        Pace::Source => S::PERID,
+        Pace::Destination => D::PERID,
+    };
+    start(&mut channel, S::address(), D::address(), count, 0, perid, |w| {
+        w.dwidth().word().sam().fixed_am().dam().fixed_am();
+        match pace {
+            Pace::Source => w.dsync().per2mem(),
+            Pace::Destination => w.dsync().mem2per(),
+        }
+    });
+    Transfer {
+        channel,
+        buffers: (source, destination),
+        destination: (0, 0),
+    }
+}
+
+/// Fills `buffer` with 32-bit words read from the data register of `S`, one
+/// per request, e.g. tagged AFEC results.
+pub fn peripheral_to_memory<const CH: u8, S, B>(
+    mut channel: Channel<CH>,
+    source: S,
+    mut buffer: B,
+) -> Transfer<CH, (S, B)>
+where
+    S: Source,
+    B: WriteBuffer<Word = u32>,
+{
+    let (to, count) = unsafe { buffer.write_buffer() };
+    let (to, length) = (to as usize, count * 4);
+    maintain_dcache(CacheOperation::CleanInvalidate, to, length);
+
+    start(&mut channel, S::address(), to, count, 0, S::PERID, |w| {
+        w.dwidth().word()
+            .sam().fixed_am()
+            .dam().incremented_am()
+            .dsync().per2mem()
+    });
+    Transfer {
+        channel,
+        buffers: (source, buffer),
+        destination: (to, length),
+    }
+}
+
+/// Reads `buffer` from a full-duplex bus such as SPI, with the transmit
+/// channel clocking out `fill` for every byte received. The fill value is
+/// kept in a register of the transmit channel, so no transmit buffer is
+/// needed and only the received bytes go through memory.
+///
+/// Both transfers start at once; the transmit one ends first, the receive
+/// one once the last byte is in `buffer`.
+pub fn read_with_fill<const TX: u8, const RX: u8, T, R, B>(
+    mut tx_channel: Channel<TX>,
+    mut rx_channel: Channel<RX>,
+    (tx, rx): (T, R),
+    fill: u8,
+    mut buffer: B,
+) -> (Transfer<TX, T>, Transfer<RX, (R, B)>)
+where
+    T: Destination,
+    R: Source,
+    B: WriteBuffer<Word = u8>,
+{
+    let (to, length) = unsafe { buffer.write_buffer() };
+    let to = to as usize;
+    maintain_dcache(CacheOperation::CleanInvalidate, to, length);
+
+    start(&mut rx_channel, R::address(), to, length, 0, R::PERID, |w| {
+        w.dwidth().byte()
+            .sam().fixed_am()
+            .dam().incremented_am()
+            .dsync().per2mem()
+    });
+    // A whole word keeps the upper bits of TDR or THR, chip select and
+    // friends, at zero.
+    let pattern = &tx_channel.registers().cds_msp0 as *const _ as usize;
+    start(&mut tx_channel, pattern, T::address(), length, fill as u32, T::PERID, |w| {
+        w.dwidth().word()
+            .sam().fixed_am()
+            .dam().fixed_am()
+            .dsync().mem2per()
+    });
+    (
+        Transfer {
+            channel: tx_channel,
+            buffers: tx,
+            destination: (0, 0),
+        },
+        Transfer {
+            channel: rx_channel,
+            buffers: (rx, buffer),
+            destination: (to, length),
+        },
+    )
+}
+
+/// Linked list descriptor, view 1.
+#[repr(C, align(32))]
+#[derive(Debug, PartialEq, Eq)]
+struct Descriptor {
+    next: u32,
+    control: u32,
+    source: u32,
+    destination: u32,
+}
+
+const UBC_NDE: u32 = 1 << 24;
+const UBC_NDEN: u32 = 1 << 26;
+const UBC_NVIEW_1: u32 = 1 << 27;
+
+impl Descriptor {
+    /// Microblock of `units` from `source` to `destination`, after which the
+    /// descriptor at `next` is fetched, with the destination address
+    /// reloaded from it.
+    fn view1(next: u32, source: u32, destination: u32, units: usize) -> Self {
+        assert!(units <= MICROBLOCK_MAX, "transfer longer than one microblock");
+        Descriptor {
+            next,
+            control: UBC_NVIEW_1 | UBC_NDEN | UBC_NDE | units as u32,
+            source,
+            destination,
+        }
+    }
+}
+
+/// Bytes that can be copied in one piece from offset `read` of a ring of
+/// `length` bytes being written at `position`, with `room` bytes left in
+/// the output.
+fn contiguous(read: usize, position: usize, length: usize, room: usize) -> usize {
+    let end = if position >= read { position } else { length };
+    (end - read).min(room)
+}
+
+/// One descriptor per channel, so a channel owner may use its own freely.
+static mut DESCRIPTORS: [Descriptor; CHANNELS] =
+    [const { Descriptor { next: 0, control: 0, source: 0, destination: 0 } }; CHANNELS];
+
+/// Reception into a buffer that is refilled from its start once full,
+/// forever, with a descriptor pointing back to itself. Nothing stops the
+/// XDMAC from overwriting bytes not read yet, so `read` has to keep up.
+pub struct Circular<const CH: u8, S, B> {
+    channel: Channel<CH>,
+    source: S,
+    buffer: B,
+    start: usize,
+    length: usize,
+    read: usize,
+}
+
+/// Receives bytes from the data register of `S` into `buffer` as a ring.
+/// The buffer should be aligned to 32-byte cache lines.
+pub fn peripheral_to_ring<const CH: u8, S, B>(
+    mut channel: Channel<CH>,
+    source: S,
+    mut buffer: B,
+) -> Circular<CH, S, B>
+where
+    S: Source,
+    B: WriteBuffer<Word = u8>,
+{
+    let (to, length) = unsafe { buffer.write_buffer() };
+    let to = to as usize;
+    assert!(length <= MICROBLOCK_MAX, "transfer longer than one microblock");
+    maintain_dcache(CacheOperation::CleanInvalidate, to, length);
+
+    program(&mut channel, S::address(), to, 0, 0, S::PERID, |w| {
+        w.dwidth().byte()
+            .sam().fixed_am()
+            .dam().incremented_am()
+            .dsync().per2mem()
+    });
+    unsafe {
+        let descriptor = addr_of_mut!(DESCRIPTORS[CH as usize]);
+        ptr::write_volatile(descriptor, Descriptor::view1(descriptor as u32, S::address() as u32, to as u32, length));
+        maintain_dcache(CacheOperation::Clean, descriptor as usize, core::mem::size_of::<Descriptor>());
+        let registers = channel.registers();
+        registers.cnda0.write(|w| w.bits(descriptor as u32));
+        registers.cndc0.write(|w| {
+            w.nde().dscr_fetch_en()
+                .ndsup().src_params_unchanged()
+                .nddup().dst_params_updated()
+                .ndview().ndv1()
+        });
+    }
+    channel.enable();
+    Circular { channel, source, buffer, start: to, length, read: 0 }
+}
+
+impl<const CH: u8, S, B> Circular<CH, S, B> {
+    /// Offset in the buffer of the next byte to be written.
+    fn position(&self) -> usize {
+        let address = self.channel.registers().cda0.read().bits() as usize;
+        address.wrapping_sub(self.start) % self.length
+    }
+
+    /// Copies the bytes received since the last call into `out`, oldest
+    /// first, and returns how many.
+    pub fn read(&mut self, out: &mut [u8]) -> usize {
+        let position = self.position();
+        let mut count = 0;
+        while self.read != position && count < out.len() {
+            let chunk = contiguous(self.read, position, self.length, out.len() - count);
+            maintain_dcache(CacheOperation::Invalidate, self.start + self.read, chunk);
+            compiler_fence(Ordering::SeqCst);
+            unsafe {
+                ptr::copy_nonoverlapping((self.start + self.read) as *const u8, out[count..].as_mut_ptr(), chunk);
+            }
+            count += chunk;
+            self.read = (self.read + chunk) % self.length;
+        }
+        count
+    }
+
+    pub fn stop(mut self) -> (Channel<CH>, S, B) {
+        self.channel.disable();
+        unsafe { self.channel.registers().cndc0.write(|w| w.bits(0)) };
+        compiler_fence(Ordering::SeqCst);
+        maintain_dcache(CacheOperation::Invalidate, self.start, self.length);
+        (self.channel, self.source, self.buffer)
+    }
+}
+
+/// Two buffers of `N` words and their descriptors, each pointing at the
+/// other. They have to stay put while the XDMAC loops over them, hence the
+/// `'static` borrow of the ping-pong transfers.
+#[repr(C, align(32))]
+pub struct PingPongBuffers<const N: usize> {
+    descriptors: [Descriptor; 2],
+    data: [[u32; N]; 2],
+}
+
+impl<const N: usize> PingPongBuffers<N> {
+    /// Both buffers start out filled with `fill`, sent before the first
+    /// refill.
+    pub const fn new(fill: u32) -> Self {
+        PingPongBuffers {
+            descriptors: [const { Descriptor { next: 0, control: 0, source: 0, destination: 0 } }; 2],
+            data: [[fill; N]; 2],
+        }
+    }
+}
+
+/// Words moved through two buffers in turn, forever: while the XDMAC works
+/// on one, the other is refilled, or emptied when receiving, through `poll`.
+pub struct PingPong<const CH: u8, P, const N: usize> {
+    channel: Channel<CH>,
+    peripheral: P,
+    buffers: &'static mut PingPongBuffers<N>,
+    receiving: bool,
+    active: usize,
+}
+
+/// Sends `buffers` to the data register of `D`, one word per request,
+/// alternating between the two buffers.
+pub fn memory_to_peripheral_ping_pong<const CH: u8, D, const N: usize>(
+    mut channel: Channel<CH>,
+    destination: D,
+    buffers: &'static mut PingPongBuffers<N>,
+) -> PingPong<CH, D, N>
+where
+    D: Destination,
+{
+    program(&mut channel, 0, D::address(), 0, 0, D::PERID, |w| {
+        w.dwidth().word()
+            .sam().incremented_am()
+            .dam().fixed_am()
+            .dsync().mem2per()
+    });
+    let descriptors = addr_of_mut!(buffers.descriptors) as *mut Descriptor;
+    for i in 0..2 {
+        let next = unsafe { descriptors.add(1 - i) } as u32;
+        let source = buffers.data[i].as_ptr() as u32;
+        unsafe { ptr::write_volatile(descriptors.add(i), Descriptor::view1(next, source, D::address() as u32, N)) };
+    }
+    maintain_dcache(CacheOperation::Clean, buffers as *const _ as usize, core::mem::size_of::<PingPongBuffers<N>>());
+    unsafe {
+        let registers = channel.registers();
+        registers.cnda0.write(|w| w.bits(descriptors as u32));
+        registers.cndc0.write(|w| {
+            w.nde().dscr_fetch_en()
+                .ndsup().src_params_updated()
+                .nddup().dst_params_updated()
+                .ndview().ndv1()
+        });
+    }
+    channel.enable();
+    PingPong { channel, peripheral: destination, buffers, receiving: false, active: 0 }
+}
+
+/// Receives words from the data register of `S` into `buffers`, one word
+/// per request, alternating between the two buffers. `N` is a multiple of
+/// 8, so that no cache line straddles the two buffers.
+pub fn peripheral_to_memory_ping_pong<const CH: u8, S, const N: usize>(
+    mut channel: Channel<CH>,
+    source: S,
+    buffers: &'static mut PingPongBuffers<N>,
+) -> PingPong<CH, S, N>
+where
+    S: Source,
+{
+    assert!(N.is_multiple_of(8), "ping-pong buffers not made of whole cache lines");
+    program(&mut channel, S::address(), 0, 0, 0, S::PERID, |w| {
+        w.dwidth().word()
+            .sam().fixed_am()
+            .dam().incremented_am()
+            .dsync().per2mem()
+    });
+    let descriptors = addr_of_mut!(buffers.descriptors) as *mut Descriptor;
+    for i in 0..2 {
+        let next = unsafe { descriptors.add(1 - i) } as u32;
+        let destination = buffers.data[i].as_ptr() as u32;
+        unsafe { ptr::write_volatile(descriptors.add(i), Descriptor::view1(next, S::address() as u32, destination, N)) };
+    }
+    maintain_dcache(CacheOperation::CleanInvalidate, buffers as *const _ as usize, core::mem::size_of::<PingPongBuffers<N>>());
+    unsafe {
+        let registers = channel.registers();
+        registers.cnda0.write(|w| w.bits(descriptors as u32));
+        registers.cndc0.write(|w| {
+            w.nde().dscr_fetch_en()
+                .ndsup().src_params_unchanged()
+                .nddup().dst_params_updated()
+                .ndview().ndv1()
+        });
+    }
+    channel.enable();
+    PingPong { channel, peripheral: source, buffers, receiving: true, active: 0 }
+}
+
+impl<const CH: u8, P, const N: usize> PingPong<CH, P, N> {
+    /// Buffer the XDMAC works on. Past the last word of the first buffer
+    /// the memory address is already in the second one.
+    fn active_buffer(&self) -> usize {
+        let registers = self.channel.registers();
+        let address = if self.receiving {
+            registers.cda0.read().bits()
+        } else {
+            registers.csa0.read().bits()
+        } as usize;
+        let second = self.buffers.data[1].as_ptr() as usize;
+        (second..second + 4 * N).contains(&address) as usize
+    }
+
+    /// Once the XDMAC moved on to the other buffer, passes the one it left
+    /// to `refill` and returns `true`: the words to send next or, when
+    /// receiving, the words just received. It has to be called at least
+    /// once per buffer, or the old contents are sent again, or received
+    /// words lost.
+    pub fn poll(&mut self, refill: impl FnOnce(&mut [u32; N])) -> bool {
+        let active = self.active_buffer();
+        if active == self.active {
+            return false;
+        }
+        let free = &mut self.buffers.data[self.active];
+        if self.receiving {
+            maintain_dcache(CacheOperation::Invalidate, free.as_ptr() as usize, 4 * N);
+            compiler_fence(Ordering::SeqCst);
+        }
+        refill(free);
+        // Received words the closure wrote over must not reach memory later.
+        let operation = if self.receiving { CacheOperation::Invalidate } else { CacheOperation::Clean };
+        maintain_dcache(operation, free.as_ptr() as usize, 4 * N);
+        self.active = active;
+        true
+    }
+
+    /// Enables the channel interrupt, which comes each time the XDMAC is
+    /// done with a buffer, so that `poll` can run from it.
+    pub fn listen(&mut self) {
+        self.channel.listen();
+    }
+
+    /// Reads and clears the channel status, see `Channel::read_status`.
+    pub fn read_status(&mut self) -> Status {
+        self.channel.read_status()
+    }
+
+    pub fn stop(mut self) -> (Channel<CH>, P, &'static mut PingPongBuffers<N>) {
+        self.channel.unlisten();
+        self.channel.disable();
+        unsafe { self.channel.registers().cndc0.write(|w| w.bits(0)) };
+        compiler_fence(Ordering::SeqCst);
+        (self.channel, self.peripheral, self.buffers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{contiguous, Descriptor};
+
+    #[test]
+    fn view1_descriptor() {
+        let descriptor = Descriptor::view1(0x2040_0000, 0x4002_4018, 0x2040_1000, 25);
+        assert_eq!(descriptor.control, 0x0D00_0019);
+        assert_eq!((descriptor.next, descriptor.destination), (0x2040_0000, 0x2040_1000));
+    }
+
+    #[test]
+    fn ring_chunks() {
+        assert_eq!(contiguous(2, 7, 16, 64), 5);
+        // Wrapped: up to the end of the buffer first.
+        assert_eq!(contiguous(12, 3, 16, 64), 4);
+        assert_eq!(contiguous(0, 3, 16, 64), 3);
+        assert_eq!(contiguous(2, 7, 16, 2), 2);
+    }
+}
@@ -0,0 +1,133 @@
+//! Timestamped edges of input pins, for flow meters, tachometers and other
+//! pulse trains without a TC channel to spare.
+//!
+//! The PIO interrupt of a port calls `capture` with a timestamp from any
+//! free-running counter, and the events wait in an `EdgeQueue` for the
+//! application:
+//!
+//! ```ignore
+//! static EDGES: EdgeQueue<64> = EdgeQueue::new();
+//!
+//! pin.enable_interrupt(Edge::Rising);
+//!
+//! #[interrupt]
+//! fn PIOA() {
+//!     edges::capture('A', &EDGES, DWT::cycle_count());
+//! }
+//!
+//! while let Some(edge) = EDGES.pop() { /* ... */ }
+//! ```
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use crate::gpio::port_registers;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EdgeEvent {
+    pub port: char,
+    pub pin: u8,
+    /// Level read in the interrupt, which tells a rising edge from a
+    /// falling one unless the pin toggled again in between.
+    pub high: bool,
+    pub timestamp: u32,
+}
+
+const EMPTY: EdgeEvent = EdgeEvent { port: 'A', pin: 0, high: false, timestamp: 0 };
+
+/// Single producer, single consumer queue of `N` events. The producer is
+/// `capture`: PIO interrupts sharing a queue must not preempt each other.
+pub struct EdgeQueue<const N: usize> {
+    events: UnsafeCell<[EdgeEvent; N]>,
+    /// Count of events popped, written by the consumer only.
+    head: AtomicUsize,
+    /// Count of events pushed, written by the producer only.
+    tail: AtomicUsize,
+    dropped: AtomicU32,
+}
+
+// Each slot belongs either to the producer or to the consumer, as told by
+// `head` and `tail`.
+unsafe impl<const N: usize> Sync for EdgeQueue<N> {}
+
+impl<const N: usize> EdgeQueue<N> {
+    pub const fn new() -> Self {
+        EdgeQueue {
+            events: UnsafeCell::new([EMPTY; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicU32::new(0),
+        }
+    }
+
+    fn push(&self, event: EdgeEvent) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) == N {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        unsafe { (*self.events.get())[tail % N] = event };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Oldest event, from the one consumer.
+    pub fn pop(&self) -> Option<EdgeEvent> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let event = unsafe { (*self.events.get())[head % N] };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(event)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tail.load(Ordering::Acquire).wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Events lost to a full queue since the last call.
+    pub fn take_dropped(&self) -> u32 {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+impl<const N: usize> Default for EdgeQueue<N> {
+    fn default() -> Self {
+        EdgeQueue::new()
+    }
+}
+
+/// Queues an event for each pin of `port`, 'A' to 'E', with its interrupt
+/// enabled and pending, stamped `timestamp`. Returns the pending pins.
+pub fn capture<const N: usize>(port: char, queue: &EdgeQueue<N>, timestamp: u32) -> u32 {
+    let pio = port_registers(port);
+    let pending = pio.isr.read().bits() & pio.imr.read().bits();
+    let levels = pio.pdsr.read().bits();
+    for pin in (0..32).filter(|pin| pending & (1 << pin) != 0) {
+        queue.push(EdgeEvent { port, pin, high: levels & (1 << pin) != 0, timestamp });
+    }
+    pending
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fifo_until_full() {
+        let queue = EdgeQueue::<2>::new();
+        let event = |timestamp| EdgeEvent { port: 'B', pin: 3, high: true, timestamp };
+        assert!(queue.push(event(1)) && queue.push(event(2)));
+        assert!(!queue.push(event(3)));
+        assert_eq!(queue.pop().map(|e| e.timestamp), Some(1));
+        assert!(queue.push(event(4)));
+        assert_eq!(queue.len(), 2);
+        assert_eq!((queue.pop().unwrap().timestamp, queue.pop().unwrap().timestamp), (2, 4));
+        assert_eq!(queue.pop(), None);
+        assert_eq!((queue.take_dropped(), queue.take_dropped()), (1, 0));
+    }
+}
@@ -0,0 +1,194 @@
+//! Hardware trigger routes between peripherals.
+//!
+//! Some peripherals can start on an event of another one without the CPU:
+//!
+//! | Sink          | Sources                                                  |
+//! |---------------|----------------------------------------------------------|
+//! | AFEC0         | ADTRG pin, TIOA0-2 of TC0, PWM0 event lines, comparator  |
+//! | AFEC1         | ADTRG pin, TIOA0-2 of TC1, PWM1 event lines, comparator  |
+//! | DACC channels | DATRG pin, TIOA0-2 of TC0, PWM0 and PWM1 event lines     |
+//! | PWM faults    | analog comparator                                        |
+//!
+//! Only those pairs implement `Route`, so `connect` does not compile for a
+//! route the silicon lacks. The source still has to be set up to produce
+//! its event, e.g. a TC channel in waveform mode toggling TIOA.
+//!
+//! ```ignore
+//! events::connect(Tioa::<TC0, 1>::new(), &afec0);
+//! events::connect(Comparator, &PwmFault::<PWM0, 0>::new(&pwm0));
+//! ```
+
+use core::marker::PhantomData;
+use crate::pac::{AFEC0, AFEC1, DACC, PWM0, PWM1, TC0, TC1};
+use crate::{afec, dacc, pwm};
+use crate::write_protect::WriteProtect;
+
+/// Peripheral input that an event can start.
+pub trait Sink {
+    /// Selects trigger `select` of the sink and enables it.
+    fn select(&self, select: u8);
+}
+
+/// Event source wired to sink `K`, as its trigger number `SELECT`.
+pub trait Route<K: Sink> {
+    const SELECT: u8;
+}
+
+/// Routes the events of `source` to `sink`, replacing its previous trigger.
+pub fn connect<K: Sink, S: Route<K>>(_source: S, sink: &K) {
+    sink.select(S::SELECT);
+}
+
+/// External trigger pin of the sink, ADTRG or DATRG.
+#[derive(Clone, Copy, Debug)]
+pub struct Pin;
+
+/// Output A of channel `CH` of timer block `TC`.
+#[derive(Clone, Copy, Debug)]
+pub struct Tioa<TC, const CH: u8> {
+    _tc: PhantomData<TC>,
+}
+
+impl<TC, const CH: u8> Tioa<TC, CH> {
+    pub const fn new() -> Self {
+        Tioa { _tc: PhantomData }
+    }
+}
+
+impl<TC, const CH: u8> Default for Tioa<TC, CH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Event line `LINE`, 0 or 1, of the PWM comparison units.
+#[derive(Clone, Copy, Debug)]
+pub struct PwmEvent<PWM, const LINE: u8> {
+    _pwm: PhantomData<PWM>,
+}
+
+impl<PWM, const LINE: u8> PwmEvent<PWM, LINE> {
+    pub const fn new() -> Self {
+        PwmEvent { _pwm: PhantomData }
+    }
+}
+
+impl<PWM, const LINE: u8> Default for PwmEvent<PWM, LINE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Output of the analog comparator controller.
+#[derive(Clone, Copy, Debug)]
+pub struct Comparator;
+
+impl Sink for AFEC0 {
+    fn select(&self, select: u8) {
+        afec::set_trigger::<AFEC0>(Some(select));
+    }
+}
+
+impl Sink for AFEC1 {
+    fn select(&self, select: u8) {
+        afec::set_trigger::<AFEC1>(Some(select));
+    }
+}
+
+/// Trigger input of DACC channel `CH`.
+pub struct DaccTrigger<'a, const CH: u8> {
+    dacc: &'a DACC,
+}
+
+impl<'a, const CH: u8> DaccTrigger<'a, CH> {
+    pub fn new(dacc: &'a DACC) -> Self {
+        assert!(CH < 2, "DACC channel out of range");
+        DaccTrigger { dacc }
+    }
+}
+
+impl<const CH: u8> Sink for DaccTrigger<'_, CH> {
+    fn select(&self, select: u8) {
+        dacc::set_trigger(self.dacc, CH, Some(select));
+    }
+}
+
+/// Fault protection of channel `CH` of `PWM`. Routing a source to it makes
+/// the channel outputs go to their fault values while the source is high.
+pub struct PwmFault<'a, PWM, const CH: u8> {
+    _pwm: &'a PWM,
+}
+
+impl<'a, PWM: pwm::Instance, const CH: u8> PwmFault<'a, PWM, CH> {
+    pub fn new(pwm: &'a PWM) -> Self {
+        assert!(CH < 4, "PWM channel out of range");
+        PwmFault { _pwm: pwm }
+    }
+}
+
+impl<PWM: pwm::Instance, const CH: u8> Sink for PwmFault<'_, PWM, CH> {
+    /// Active high, kept until the fault is cleared in software.
+    fn select(&self, select: u8) {
+        let pwm = unsafe { &*PWM::ptr() };
+        let input = 1 << select;
+        pwm.with_unlocked(|pwm| unsafe {
+            pwm.fmr.modify(|r, w| w.bits(r.bits() | input | input << 8));
+            pwm.fpe.modify(|r, w| w.bits(r.bits() | input << (8 * CH)));
+        });
+    }
+}
+
+macro_rules! routes {
+    ($($Source:ty => $Sink:ty: $select:expr;)+) => {
+        $(
+            impl Route<$Sink> for $Source {
+                const SELECT: u8 = $select;
+            }
+        )+
+    }
+}
+
+routes! {
+    Pin => AFEC0: 0;
+    Tioa<TC0, 0> => AFEC0: 1;
+    Tioa<TC0, 1> => AFEC0: 2;
+    Tioa<TC0, 2> => AFEC0: 3;
+    PwmEvent<PWM0, 0> => AFEC0: 4;
+    PwmEvent<PWM0, 1> => AFEC0: 5;
+    Comparator => AFEC0: 6;
+
+    Pin => AFEC1: 0;
+    Tioa<TC1, 0> => AFEC1: 1;
+    Tioa<TC1, 1> => AFEC1: 2;
+    Tioa<TC1, 2> => AFEC1: 3;
+    PwmEvent<PWM1, 0> => AFEC1: 4;
+    PwmEvent<PWM1, 1> => AFEC1: 5;
+    Comparator => AFEC1: 6;
+}
+
+macro_rules! dacc_routes {
+    ($($CH:literal,)+) => {
+        $(
+            routes! {
+                Pin => DaccTrigger<'_, $CH>: 0;
+                Tioa<TC0, 0> => DaccTrigger<'_, $CH>: 1;
+                Tioa<TC0, 1> => DaccTrigger<'_, $CH>: 2;
+                Tioa<TC0, 2> => DaccTrigger<'_, $CH>: 3;
+                PwmEvent<PWM0, 0> => DaccTrigger<'_, $CH>: 4;
+                PwmEvent<PWM0, 1> => DaccTrigger<'_, $CH>: 5;
+                PwmEvent<PWM1, 0> => DaccTrigger<'_, $CH>: 6;
+                PwmEvent<PWM1, 1> => DaccTrigger<'_, $CH>: 7;
+            }
+        )+
+    }
+}
+
+dacc_routes! {
+    0,
+    1,
+}
+
+/// Fault input 6 of both PWMs is the analog comparator.
+impl<PWM: pwm::Instance, const CH: u8> Route<PwmFault<'_, PWM, CH>> for Comparator {
+    const SELECT: u8 = 6;
+}
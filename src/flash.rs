@@ -0,0 +1,264 @@
+use core::mem::size_of;
+use crate::dma::{maintain_dcache, CacheOperation};
+use crate::gpio::piob::PB12;
+use crate::pac::{EFC, MATRIX};
+use crate::write_protect::WriteProtect;
+
+pub const FLASH_BASE: u32 = 0x0040_0000;
+#[cfg(feature = "samv71q21")]
+pub const FLASH_SIZE: u32 = 2 * 1024 * 1024;
+pub const PAGE_SIZE: usize = 512;
+/// Smallest erase allowed in every sector: 16 pages with EPA.
+pub const ERASE_SIZE: u32 = 16 * PAGE_SIZE as u32;
+
+const FKEY: u32 = 0x5A << 24;
+const FCMD_WP: u32 = 0x01;
+const FCMD_EPA: u32 = 0x07;
+const FCMD_SGPB: u32 = 0x0B;
+const FCMD_GGPB: u32 = 0x0D;
+const FCMD_STUI: u32 = 0x0E;
+const FCMD_SPUI: u32 = 0x0F;
+const EPA_16_PAGES: u32 = 2;
+const FSR_FRDY: u32 = 1 << 0;
+const FSR_FCMDE: u32 = 1 << 1;
+const FSR_FLOCKE: u32 = 1 << 2;
+const FSR_FLERR: u32 = 1 << 3;
+/// GPNVM bit 0.
+const SECURITY_BIT: u32 = 0;
+/// GPNVM bit 1, clear to boot from the ROM (SAM-BA).
+const BOOT_MODE_BIT: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlashError {
+    OutOfBounds,
+    Unaligned,
+    Command,
+    Locked,
+    Flash,
+}
+
+/// Word load for the RAM routines below. Neither `read_volatile` nor an
+/// iterator may be used there: without optimizations they stay calls into
+/// flash.
+#[inline(always)]
+unsafe fn load(address: usize) -> u32 {
+    #[cfg(target_arch = "arm")]
+    {
+        let value;
+        core::arch::asm!("ldr {0}, [{1}]", out(reg) value, in(reg) address, options(nostack, preserves_flags));
+        value
+    }
+    #[cfg(not(target_arch = "arm"))]
+    {
+        core::ptr::read_volatile(address as *const u32)
+    }
+}
+
+/// Word store for the RAM routines below.
+#[inline(always)]
+unsafe fn store(address: usize, value: u32) {
+    #[cfg(target_arch = "arm")]
+    core::arch::asm!("str {0}, [{1}]", in(reg) value, in(reg) address, options(nostack, preserves_flags));
+    #[cfg(not(target_arch = "arm"))]
+    core::ptr::write_volatile(address as *mut u32, value);
+}
+
+/// Runs an EEFC command and waits for it to finish. The flash cannot be
+/// read while a command runs, so this lives in RAM and calls nothing.
+#[inline(never)]
+#[cfg_attr(target_arch = "arm", link_section = ".data.samv71_hal.eefc_command")]
+unsafe fn run_command(fcr: usize, fsr: usize, command: u32) -> u32 {
+    store(fcr, command);
+    loop {
+        let status = load(fsr);
+        if status & FSR_FRDY != 0 {
+            return status;
+        }
+    }
+}
+
+/// Copies the unique identifier, which replaces the first flash words
+/// between STUI and SPUI. Runs from RAM for the same reason as
+/// `run_command`.
+#[inline(never)]
+#[cfg_attr(target_arch = "arm", link_section = ".data.samv71_hal.eefc_unique_id")]
+unsafe fn read_unique_id(fcr: usize, fsr: usize, id: usize) {
+    store(fcr, FKEY | FCMD_STUI);
+    while load(fsr) & FSR_FRDY != 0 {}
+    let mut i = 0;
+    while i < 16 {
+        store(id.wrapping_add(i), load((FLASH_BASE as usize).wrapping_add(i)));
+        i = i.wrapping_add(4);
+    }
+    store(fcr, FKEY | FCMD_SPUI);
+    while load(fsr) & FSR_FRDY == 0 {}
+}
+
+/// Internal flash through the EEFC. Offsets are relative to `FLASH_BASE`.
+/// Erasing and programming invalidate the data cache over the changed
+/// range, so reads afterwards see the new contents.
+pub struct Flash {
+    efc: EFC,
+}
+
+impl Flash {
+    pub fn new(efc: EFC) -> Flash {
+        Flash { efc }
+    }
+
+    /// Raw EEFC registers, for commands without a wrapper such as the
+    /// GPNVM bits.
+    ///
+    /// # Safety
+    /// No command may be left running when the driver is used again.
+    pub unsafe fn registers(&self) -> &crate::pac::efc::RegisterBlock {
+        &self.efc
+    }
+
+    pub fn free(self) -> EFC {
+        self.efc
+    }
+
+    pub fn size(&self) -> u32 {
+        FLASH_SIZE
+    }
+
+    fn check_bounds(offset: u32, length: usize) -> Result<(), FlashError> {
+        match offset.checked_add(length as u32) {
+            Some(end) if end <= FLASH_SIZE => Ok(()),
+            _ => Err(FlashError::OutOfBounds),
+        }
+    }
+
+    fn command(&mut self, command: u32, argument: u32) -> Result<(), FlashError> {
+        let fcr = self.efc.fcr.as_ptr() as usize;
+        let fsr = self.efc.fsr.as_ptr() as usize;
+        let status = cortex_m::interrupt::free(|_| {
+            cortex_m::asm::dsb();
+            unsafe { run_command(fcr, fsr, FKEY | argument << 8 | command) }
+        });
+        if status & FSR_FCMDE != 0 {
+            Err(FlashError::Command)
+        } else if status & FSR_FLOCKE != 0 {
+            Err(FlashError::Locked)
+        } else if status & FSR_FLERR != 0 {
+            Err(FlashError::Flash)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The 128-bit identifier programmed at the factory, distinct on every
+    /// device.
+    pub fn unique_id(&mut self) -> [u32; 4] {
+        let fcr = self.efc.fcr.as_ptr() as usize;
+        let fsr = self.efc.fsr.as_ptr() as usize;
+        let mut id = [0; 4];
+        // The data cache could hold either the flash or the identifier.
+        maintain_dcache(CacheOperation::Invalidate, FLASH_BASE as usize, size_of::<[u32; 4]>());
+        cortex_m::interrupt::free(|_| {
+            cortex_m::asm::dsb();
+            unsafe { read_unique_id(fcr, fsr, id.as_mut_ptr() as usize) }
+        });
+        maintain_dcache(CacheOperation::Invalidate, FLASH_BASE as usize, size_of::<[u32; 4]>());
+        id
+    }
+
+    /// The 9 GPNVM bits: security, boot from ROM or flash, then the TCM
+    /// configuration in bits 7 and 8.
+    pub fn gpnvm_bits(&mut self) -> Result<u16, FlashError> {
+        self.command(FCMD_GGPB, 0)?;
+        Ok(self.efc.frr.read().bits() as u16 & 0x1FF)
+    }
+
+    /// Whether the security bit is set, locking the debugger and the
+    /// fast flash programming interface out.
+    pub fn is_secured(&mut self) -> Result<bool, FlashError> {
+        Ok(self.gpnvm_bits()? & 1 << SECURITY_BIT != 0)
+    }
+
+    /// Sets the security bit, taking effect at the next reset.
+    ///
+    /// # Safety
+    /// There is no going back short of erasing the whole flash through the
+    /// ERASE pin: once set, no debugger can attach, and none can load new
+    /// firmware either unless the application does it itself.
+    pub unsafe fn set_security_bit(&mut self) -> Result<(), FlashError> {
+        self.command(FCMD_SGPB, SECURITY_BIT)
+    }
+
+    /// Boots from flash rather than from the SAM-BA monitor in ROM, from the
+    /// next reset on.
+    pub fn boot_from_flash(&mut self) -> Result<(), FlashError> {
+        if self.gpnvm_bits()? & 1 << BOOT_MODE_BIT == 0 {
+            self.command(FCMD_SGPB, BOOT_MODE_BIT)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(&self, offset: u32, data: &mut [u8]) -> Result<(), FlashError> {
+        Self::check_bounds(offset, data.len())?;
+        let base = (FLASH_BASE + offset) as *const u8;
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile(base.add(i)) };
+        }
+        Ok(())
+    }
+
+    /// Erases `length` bytes from `offset`, both multiples of `ERASE_SIZE`.
+    pub fn erase(&mut self, offset: u32, length: u32) -> Result<(), FlashError> {
+        Self::check_bounds(offset, length as usize)?;
+        if !offset.is_multiple_of(ERASE_SIZE) || !length.is_multiple_of(ERASE_SIZE) {
+            return Err(FlashError::Unaligned);
+        }
+        for block in (offset..offset + length).step_by(ERASE_SIZE as usize) {
+            let page = block / PAGE_SIZE as u32;
+            let erased = self.command(FCMD_EPA, page | EPA_16_PAGES);
+            maintain_dcache(CacheOperation::Invalidate, (FLASH_BASE + block) as usize, ERASE_SIZE as usize);
+            erased?;
+        }
+        Ok(())
+    }
+
+    /// Programs `data` at `offset`, which must be erased. Bytes of the pages
+    /// around `data` are written as 0xFF and so keep their contents.
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError> {
+        Self::check_bounds(offset, data.len())?;
+        let mut offset = offset;
+        let mut data = data;
+        while !data.is_empty() {
+            let page = offset / PAGE_SIZE as u32;
+            let start = offset as usize % PAGE_SIZE;
+            let count = (PAGE_SIZE - start).min(data.len());
+
+            let mut buffer = [0xFFu8; PAGE_SIZE];
+            buffer[start..start + count].copy_from_slice(&data[..count]);
+            let latch = (FLASH_BASE + page * PAGE_SIZE as u32) as *mut u32;
+            for (i, word) in buffer.chunks_exact(4).enumerate() {
+                let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                unsafe { core::ptr::write_volatile(latch.add(i), word) };
+            }
+            let written = self.command(FCMD_WP, page);
+            maintain_dcache(CacheOperation::Invalidate, latch as usize, PAGE_SIZE);
+            written?;
+
+            offset += count as u32;
+            data = &data[count..];
+        }
+        Ok(())
+    }
+}
+
+/// Makes PB12 an I/O until the next reset, so that driving it high no
+/// longer erases the flash and clears the GPNVM bits. The pin comes back
+/// for the PIO controller. Reset hands it back to the ERASE function.
+pub fn disable_erase_pin<MODE>(pin: PB12<MODE>) -> PB12<MODE> {
+    unsafe { (*MATRIX::ptr()).with_unlocked(|matrix| matrix.ccfg_sysio.modify(|_, w| w.sysio12().set_bit())) };
+    pin
+}
+
+/// Gives PB12 back to the ERASE function, which takes the pin for good.
+pub fn enable_erase_pin<MODE>(pin: PB12<MODE>) {
+    let _ = pin;
+    unsafe { (*MATRIX::ptr()).with_unlocked(|matrix| matrix.ccfg_sysio.modify(|_, w| w.sysio12().clear_bit())) };
+}
@@ -0,0 +1,137 @@
+//! Ethernet MAC statistics.
+//!
+//! The GMAC counters clear when read, so `Statistics` keeps the running
+//! totals, for interface counters in the way of SNMP, and `delta` compares
+//! two snapshots of them, wrapping around like the counters themselves.
+//!
+//! ```ignore
+//! let mut totals = Statistics::default();
+//! let before = totals;
+//! totals.accumulate(&gmac);
+//! let errors = totals.delta(&before).fcs_errors;
+//! ```
+
+use crate::pac::GMAC;
+
+macro_rules! statistics {
+    ($($(#[$doc:meta])* $field:ident: $register:ident,)+) => {
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct Statistics {
+            pub octets_sent: u64,
+            pub octets_received: u64,
+            $(
+                $(#[$doc])*
+                pub $field: u32,
+            )+
+        }
+
+        impl Statistics {
+            /// Counts since the last read, clearing the counters.
+            fn read(gmac: &GMAC) -> Self {
+                let octets_sent = gmac.otlo.read().bits() as u64;
+                let octets_sent = octets_sent | (gmac.othi.read().bits() as u64) << 32;
+                let octets_received = gmac.orlo.read().bits() as u64;
+                let octets_received = octets_received | (gmac.orhi.read().bits() as u64) << 32;
+                Statistics {
+                    octets_sent,
+                    octets_received,
+                    $($field: gmac.$register.read().bits(),)+
+                }
+            }
+
+            /// Counts from `earlier` to `self`, both snapshots of the same
+            /// totals.
+            pub fn delta(&self, earlier: &Statistics) -> Statistics {
+                Statistics {
+                    octets_sent: self.octets_sent.wrapping_sub(earlier.octets_sent),
+                    octets_received: self.octets_received.wrapping_sub(earlier.octets_received),
+                    $($field: self.$field.wrapping_sub(earlier.$field),)+
+                }
+            }
+
+            fn add(&mut self, counts: &Statistics) {
+                self.octets_sent = self.octets_sent.wrapping_add(counts.octets_sent);
+                self.octets_received = self.octets_received.wrapping_add(counts.octets_received);
+                $(self.$field = self.$field.wrapping_add(counts.$field);)+
+            }
+        }
+    }
+}
+
+statistics! {
+    frames_sent: ft,
+    broadcast_sent: bcft,
+    multicast_sent: mft,
+    pause_sent: pft,
+    frames_sent_64: bft64,
+    frames_sent_65_127: tbft127,
+    frames_sent_128_255: tbft255,
+    frames_sent_256_511: tbft511,
+    frames_sent_512_1023: tbft1023,
+    frames_sent_1024_1518: tbft1518,
+    frames_sent_over_1518: gtbft1518,
+    underruns: tur,
+    single_collisions: scf,
+    multiple_collisions: mcf,
+    /// Frames dropped after 16 collisions.
+    excessive_collisions: ec,
+    late_collisions: lc,
+    deferred: dtf,
+    carrier_sense_errors: cse,
+    frames_received: fr,
+    broadcast_received: bcfr,
+    multicast_received: mfr,
+    pause_received: pfr,
+    frames_received_64: bfr64,
+    frames_received_65_127: tbfr127,
+    frames_received_128_255: tbfr255,
+    frames_received_256_511: tbfr511,
+    frames_received_512_1023: tbfr1023,
+    frames_received_1024_1518: tbfr1518,
+    frames_received_over_1518: tmxbfr,
+    undersize: ufr,
+    oversize: ofr,
+    jabbers: jr,
+    /// Bad CRC.
+    fcs_errors: fcse,
+    length_errors: lffe,
+    symbol_errors: rse,
+    alignment_errors: ae,
+    /// Frames dropped for lack of a receive buffer.
+    resource_errors: rre,
+    overruns: roe,
+    ip_checksum_errors: ihce,
+    tcp_checksum_errors: tce,
+    udp_checksum_errors: uce,
+}
+
+impl Statistics {
+    /// Adds the counts since the last call to the totals and returns them.
+    /// The GMAC clock has to be enabled.
+    pub fn accumulate(&mut self, gmac: &GMAC) -> Statistics {
+        let counts = Statistics::read(gmac);
+        self.add(&counts);
+        counts
+    }
+
+    pub fn errors_received(&self) -> u32 {
+        [self.fcs_errors, self.length_errors, self.symbol_errors, self.alignment_errors, self.jabbers, self.undersize, self.oversize]
+            .iter()
+            .fold(0, |sum, &count| sum.wrapping_add(count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Statistics;
+
+    #[test]
+    fn delta_across_wrap() {
+        let earlier = Statistics { frames_received: u32::MAX - 1, octets_received: 100, ..Default::default() };
+        let mut totals = earlier;
+        totals.add(&Statistics { frames_received: 3, fcs_errors: 2, octets_received: 1500, ..Default::default() });
+        let delta = totals.delta(&earlier);
+        assert_eq!((delta.frames_received, delta.fcs_errors, delta.octets_received), (3, 2, 1500));
+        assert_eq!(delta.errors_received(), 2);
+    }
+}
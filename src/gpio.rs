@@ -1,7 +1,9 @@
 use core::convert::Infallible;
 use core::marker::PhantomData;
-use crate::pac::PMC;
-use embedded_hal::digital::v2::{toggleable, InputPin, OutputPin, StatefulOutputPin};
+use crate::pac::{pioa::RegisterBlock, PIOA, PIOB, PIOC, PIOD, PIOE, PMC};
+use crate::write_protect::WriteProtect;
+use cortex_m::interrupt::CriticalSection;
+use embedded_hal::digital::v2::{toggleable, InputPin, IoPin, OutputPin, PinState, StatefulOutputPin};
 
 pub trait GpioExt {
     type Parts;
@@ -9,13 +11,6 @@ pub trait GpioExt {
     fn split(self, pmc: &PMC) -> Self::Parts;
 }
 
-trait GpioRegExt {
-    fn is_low(&self, pos: u8) -> bool;
-    fn is_set_low(&self, pos: u8) -> bool;
-    fn set_high(&self, pos: u8);
-    fn set_low(&self, pos: u8);
-}
-
 pub struct AF0;
 pub struct AF1;
 pub struct AF2;
@@ -39,16 +34,264 @@ pub struct Output<MODE> {
     _mode: PhantomData<MODE>
 }
 
-pub struct Pin<MODE> {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// All PIO controllers share the PIOA register layout.
+pub(crate) fn port_registers(port: char) -> &'static RegisterBlock {
+    let ptr = match port {
+        'A' => PIOA::ptr(),
+        'B' => PIOB::ptr() as *const RegisterBlock,
+        'C' => PIOC::ptr() as *const RegisterBlock,
+        'D' => PIOD::ptr() as *const RegisterBlock,
+        'E' => PIOE::ptr() as *const RegisterBlock,
+        _ => unreachable!(),
+    };
+    unsafe { &*ptr }
+}
+
+fn is_low(port: char, i: u8) -> bool {
+    port_registers(port).pdsr.read().bits() & (1 << i) == 0
+}
+
+fn is_set_low(port: char, i: u8) -> bool {
+    port_registers(port).odsr.read().bits() & (1 << i) == 0
+}
+
+fn set_high(port: char, i: u8) {
+    unsafe { port_registers(port).sodr.write_with_zero(|w| w.bits(1 << i)) };
+}
+
+fn set_low(port: char, i: u8) {
+    unsafe { port_registers(port).codr.write_with_zero(|w| w.bits(1 << i)) };
+}
+
+pub struct Pin<const P: char, const N: u8, MODE> {
+    _mode: PhantomData<MODE>,
+}
+
+impl<const P: char, const N: u8, MODE> Pin<P, N, MODE> {
+    const fn new() -> Self {
+        Pin { _mode: PhantomData }
+    }
+
+    fn with_registers<F: FnOnce(&RegisterBlock)>(f: F) {
+        port_registers(P).with_unlocked(f);
+    }
+
+    pub fn enable(self, _cs: &CriticalSection) -> Self {
+        Self::with_registers(|pio| unsafe { pio.per.write_with_zero(|w| w.bits(1 << N)) });
+        self
+    }
+
+    pub fn disable(self, _cs: &CriticalSection) -> Self {
+        Self::with_registers(|pio| unsafe { pio.pdr.write_with_zero(|w| w.bits(1 << N)) });
+        self
+    }
+
+    fn set_alternate_mode(mode: u32) {
+        Self::with_registers(|pio| {
+            let mask = 1 << N;
+            let abcdsr0 = pio.abcdsr.first().unwrap();
+            let abcdsr1 = pio.abcdsr.last().unwrap();
+
+            abcdsr0.modify(|r, w| unsafe {
+                w.bits(if mode & 1 != 0 { r.bits() | mask } else { r.bits() & !mask })
+            });
+            abcdsr1.modify(|r, w| unsafe {
+                w.bits(if mode & 2 != 0 { r.bits() | mask } else { r.bits() & !mask })
+            });
+        });
+    }
+
+    pub fn into_alternate_af0(self, _cs: &CriticalSection) -> Pin<P, N, Alternate<AF0>> {
+        Self::set_alternate_mode(0);
+        Pin::new()
+    }
+
+    pub fn into_alternate_af1(self, _cs: &CriticalSection) -> Pin<P, N, Alternate<AF1>> {
+        Self::set_alternate_mode(1);
+        Pin::new()
+    }
+
+    pub fn into_alternate_af2(self, _cs: &CriticalSection) -> Pin<P, N, Alternate<AF2>> {
+        Self::set_alternate_mode(2);
+        Pin::new()
+    }
+
+    pub fn into_alternate_af3(self, _cs: &CriticalSection) -> Pin<P, N, Alternate<AF3>> {
+        Self::set_alternate_mode(3);
+        Pin::new()
+    }
+
+    //TODO: FALTA FLOATING INPUT
+
+    pub fn into_pull_down_input(self, _cs: &CriticalSection) -> Pin<P, N, Input<PullDown>> {
+        Self::with_registers(|pio| unsafe { pio.ppder.write_with_zero(|w| w.bits(1 << N)) });
+        Pin::new()
+    }
+
+    pub fn into_pull_up_input(self, _cs: &CriticalSection) -> Pin<P, N, Input<PullUp>> {
+        Self::with_registers(|pio| unsafe { pio.puer.write_with_zero(|w| w.bits(1 << N)) });
+        Pin::new()
+    }
+
+    pub fn into_output(self, _cs: &CriticalSection) -> Pin<P, N, Output<OpenDrain>> {
+        Self::with_registers(|pio| unsafe { pio.oer.write_with_zero(|w| w.bits(1 << N)) });
+        Pin::new()
+    }
+
+    //TODO: FALTA ANALOG
+    //TODO: FALTA PUSH PULL OUTPUT
+    //TODO: FALTA PUSH PULL OUTPUT HS
+}
+
+impl<const P: char, const N: u8, MODE> Pin<P, N, Output<MODE>> {
+    pub fn downgrade(self) -> ErasedPin<Output<MODE>> {
+        ErasedPin::new(P, N)
+    }
+}
+
+impl<const P: char, const N: u8, MODE> Pin<P, N, Input<MODE>> {
+    pub fn downgrade(self) -> ErasedPin<Input<MODE>> {
+        ErasedPin::new(P, N)
+    }
+
+    /// Raises the PIO interrupt of the port on `edge`. Reading the port ISR,
+    /// as `edges::capture` does, clears the flags of all its pins at once.
+    pub fn enable_interrupt(&mut self, edge: Edge) {
+        Self::with_registers(|pio| unsafe {
+            let mask = 1 << N;
+            match edge {
+                Edge::Both => pio.aimdr.write_with_zero(|w| w.bits(mask)),
+                Edge::Rising | Edge::Falling => {
+                    pio.aimer.write_with_zero(|w| w.bits(mask));
+                    pio.esr.write_with_zero(|w| w.bits(mask));
+                    if edge == Edge::Rising {
+                        pio.rehlsr.write_with_zero(|w| w.bits(mask));
+                    } else {
+                        pio.fellsr.write_with_zero(|w| w.bits(mask));
+                    }
+                }
+            }
+            pio.ier.write_with_zero(|w| w.bits(mask));
+        });
+    }
+
+    pub fn disable_interrupt(&mut self) {
+        Self::with_registers(|pio| unsafe { pio.idr.write_with_zero(|w| w.bits(1 << N)) });
+    }
+}
+
+/// Direction changes only touch the output enable, so the pull resistor set
+/// up for the input is kept and nothing else needs a critical section.
+/// Meant for bidirectional lines such as DHT22 or 1-Wire data.
+impl<const P: char, const N: u8, IMODE, OMODE> IoPin<Pin<P, N, Input<IMODE>>, Pin<P, N, Output<OMODE>>>
+    for Pin<P, N, Input<IMODE>>
+{
+    type Error = Infallible;
+
+    fn into_input_pin(self) -> Result<Pin<P, N, Input<IMODE>>, Self::Error> {
+        Ok(self)
+    }
+
+    /// The level is latched before the driver turns on, so the line does
+    /// not glitch.
+    fn into_output_pin(self, state: PinState) -> Result<Pin<P, N, Output<OMODE>>, Self::Error> {
+        match state {
+            PinState::High => set_high(P, N),
+            PinState::Low => set_low(P, N),
+        }
+        Self::with_registers(|pio| unsafe { pio.oer.write_with_zero(|w| w.bits(1 << N)) });
+        Ok(Pin::new())
+    }
+}
+
+impl<const P: char, const N: u8, IMODE, OMODE> IoPin<Pin<P, N, Input<IMODE>>, Pin<P, N, Output<OMODE>>>
+    for Pin<P, N, Output<OMODE>>
+{
+    type Error = Infallible;
+
+    fn into_input_pin(self) -> Result<Pin<P, N, Input<IMODE>>, Self::Error> {
+        Self::with_registers(|pio| unsafe { pio.odr.write_with_zero(|w| w.bits(1 << N)) });
+        Ok(Pin::new())
+    }
+
+    fn into_output_pin(mut self, state: PinState) -> Result<Pin<P, N, Output<OMODE>>, Self::Error> {
+        self.set_state(state)?;
+        Ok(self)
+    }
+}
+
+impl<const P: char, const N: u8, MODE> StatefulOutputPin for Pin<P, N, Output<MODE>> {
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        self.is_set_low().map(|v| !v)
+    }
+
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        Ok(is_set_low(P, N))
+    }
+}
+
+impl<const P: char, const N: u8, MODE> OutputPin for Pin<P, N, Output<MODE>> {
+    type Error = Infallible;
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        set_high(P, N);
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        set_low(P, N);
+        Ok(())
+    }
+}
+
+impl<const P: char, const N: u8, MODE> toggleable::Default for Pin<P, N, Output<MODE>> {}
+
+impl<const P: char, const N: u8> InputPin for Pin<P, N, Output<OpenDrain>> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.is_low().map(|v| !v)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(is_low(P, N))
+    }
+}
+
+impl<const P: char, const N: u8, MODE> InputPin for Pin<P, N, Input<MODE>> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.is_low().map(|v| !v)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(is_low(P, N))
+    }
+}
+
+/// Pin with the port and number moved to runtime, so pins of different
+/// ports can be stored together.
+pub struct ErasedPin<MODE> {
+    port: char,
     i: u8,
-    port: *const dyn GpioRegExt,
     _mode: PhantomData<MODE>,
 }
 
-unsafe impl<MODE> Sync for Pin<MODE> {}
-unsafe impl<MODE> Send for Pin<MODE> {}
+impl<MODE> ErasedPin<MODE> {
+    const fn new(port: char, i: u8) -> Self {
+        ErasedPin { port, i, _mode: PhantomData }
+    }
+}
 
-impl<MODE> StatefulOutputPin for Pin<Output<MODE>> {
+impl<MODE> StatefulOutputPin for ErasedPin<Output<MODE>> {
     #[inline(always)]
     fn is_set_high(&self) -> Result<bool, Self::Error> {
         self.is_set_low().map(|v| !v)
@@ -56,29 +299,29 @@ impl<MODE> StatefulOutputPin for Pin<Output<MODE>> {
 
     #[inline(always)]
     fn is_set_low(&self) -> Result<bool, Self::Error> {
-        Ok(unsafe { (*self.port).is_set_low(self.i) })
+        Ok(is_set_low(self.port, self.i))
     }
 }
 
-impl<MODE> OutputPin for Pin<Output<MODE>> {
+impl<MODE> OutputPin for ErasedPin<Output<MODE>> {
     type Error = Infallible;
 
     #[inline(always)]
     fn set_low(&mut self) -> Result<(), Self::Error> {
-        unsafe { (*self.port).set_low(self.i) };
+        set_low(self.port, self.i);
         Ok(())
     }
 
     #[inline(always)]
     fn set_high(&mut self) -> Result<(), Self::Error> {
-        unsafe { (*self.port).set_high(self.i) };
+        set_high(self.port, self.i);
         Ok(())
     }
 }
 
-impl<MODE> toggleable::Default for Pin<Output<MODE>> {}
+impl<MODE> toggleable::Default for ErasedPin<Output<MODE>> {}
 
-impl InputPin for Pin<Output<OpenDrain>> {
+impl InputPin for ErasedPin<Output<OpenDrain>> {
     type Error = Infallible;
 
     #[inline(always)]
@@ -88,11 +331,11 @@ impl InputPin for Pin<Output<OpenDrain>> {
 
     #[inline(always)]
     fn is_low(&self) -> Result<bool, Self::Error> {
-        Ok(unsafe { (*self.port).is_low(self.i) })
+        Ok(is_low(self.port, self.i))
     }
 }
 
-impl<MODE> InputPin for Pin<Input<MODE>> {
+impl<MODE> InputPin for ErasedPin<Input<MODE>> {
     type Error = Infallible;
 
     #[inline(always)]
@@ -102,236 +345,98 @@ impl<MODE> InputPin for Pin<Input<MODE>> {
 
     #[inline(always)]
     fn is_low(&self) -> Result<bool, Self::Error> {
-        Ok(unsafe { (*self.port).is_low(self.i) })
-    }
-}
-
-macro_rules! gpio_trait {
-    ($gpiox:ident) => {
-        impl GpioRegExt for crate::pac::$gpiox::RegisterBlock {
-            fn is_low(&self, pos: u8) -> bool {
-                self.pdsr.read().bits() & (1 << pos) == 0
-            }
-
-            fn is_set_low(&self, pos: u8) -> bool {
-                self.odsr.read().bits() & (1 << pos) == 0
-            }
-
-            fn set_high(&self, pos: u8) {
-                unsafe { self.sodr.write_with_zero(|w| w.bits(1 << pos)); }
-            }
-
-            fn set_low(&self, pos: u8) {
-                unsafe { self.codr.write_with_zero(|w| w.bits(1 << pos)); }
-            }
-        }
+        Ok(is_low(self.port, self.i))
     }
 }
 
-gpio_trait!(pioa);
-gpio_trait!(piob);
-gpio_trait!(pioc);
-gpio_trait!(piod);
-gpio_trait!(pioe);
-
-
-
 macro_rules! gpio {
-    ([$($GPIOX:ident, $gpiox:ident, $iopxenr:ident, $PXx:ident, $pidx:ident => [
+    ([$($GPIOX:ident, $gpiox:ident, $port:literal, $PXx:ident, $pidx:ident => [
         $($PXi:ident: ($pxi:ident, $i:expr, $MODE:ty),)+
     ]),+]) => {
         $(
             pub mod $gpiox {
-                use core::marker::PhantomData;
-                use core::convert::Infallible;
-
-                use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin, toggleable};
                 use crate::pac::{$GPIOX, PMC};
-                use cortex_m::interrupt::CriticalSection;
+                use crate::write_protect::WriteProtect;
 
-                use super::{
-                    Alternate, GpioExt, Input, OpenDrain, Output, Floating, PullUp, PullDown,
-                    AF0, AF1, AF2, AF3,
-                    Pin, GpioRegExt,
-                };
+                use super::{GpioExt, Input, Floating, Pin};
 
                 pub struct Parts {
                     $(
                         pub $pxi: $PXi<$MODE>,
                     )+
+                    pub port: Port,
+                    pio: $GPIOX,
                 }
 
-                impl GpioExt for $GPIOX {
-                    type Parts = Parts;
-
-                    fn split(self, pmc: &PMC) -> Parts {
-                        unsafe { pmc.pmc_pcer0.write_with_zero(|w| w.$pidx().set_bit()) };
-                        Parts {
-                            $(
-                                $pxi: $PXi { _mode:PhantomData },
-                            )+
-                        }
-                    }
-                }
-
-                const MASK: u32 = 1;
-                fn _set_alternate_mode(index: usize, mode: u32) {
-                    unsafe {
-                        let reg = &(*$GPIOX::ptr());
-                        let abcdsr0 = reg.abcdsr.first().unwrap();
-                        let abcdsr1 = reg.abcdsr.last().unwrap();
-
-                        let value0 = mode & MASK;
-                        let value1 = (mode >> 1) & MASK;
-
-                        abcdsr0.modify(|_, w| w.bits(value0 << index));
-                        abcdsr1.modify(|_, w| w.bits(value1 << index));
-                    }
+                pub struct Port {
+                    _private: (),
                 }
 
-                $(
-                    pub struct $PXi<MODE> {
-                        _mode: PhantomData<MODE>,
-                    }
-
-                    impl<MODE> $PXi<MODE> {
-                        pub fn enable(self, _cs: &CriticalSection) -> Self {
-                            unsafe { (*$GPIOX::ptr()).per.write_with_zero(|w| w.bits(1 << $i)) };
-                            self
-                        }
-
-                        pub fn disable(self, _cs: &CriticalSection) -> Self {
-                            unsafe { (*$GPIOX::ptr()).pdr.write_with_zero(|w| w.bits(1 << $i)) };
-                            self
-                        }
-
-                        pub fn into_alternate_af0(
-                            self, _cs: &CriticalSection
-                        ) -> $PXi<Alternate<AF0>> {
-                            _set_alternate_mode($i, 0);
-                            $PXi { _mode: PhantomData }
-                        }
-
-                        pub fn into_alternate_af1(
-                            self, _cs: &CriticalSection
-                        ) -> $PXi<Alternate<AF1>> {
-                            _set_alternate_mode($i, 1);
-                            $PXi { _mode: PhantomData }
-                        }
-
-                        pub fn into_alternate_af2(
-                            self, _cs: &CriticalSection
-                        ) -> $PXi<Alternate<AF2>> {
-                            _set_alternate_mode($i, 2);
-                            $PXi { _mode: PhantomData }
-                        }
-
-                        pub fn into_alternate_af3(
-                            self, _cs: &CriticalSection
-                        ) -> $PXi<Alternate<AF3>> {
-                            _set_alternate_mode($i, 3);
-                            $PXi { _mode: PhantomData }
-                        }
-
-                        //TODO: FALTA FLOATING INPUT
-
-                        pub fn into_pull_down_input(
-                            self, _cs: &CriticalSection
-                        ) -> $PXi<Input<PullDown>> {
-                            unsafe { (*$GPIOX::ptr()).ppder.write_with_zero(|w| w.bits(1 << $i)) };
-                            $PXi { _mode: PhantomData }
-                        }
-
-                        pub fn into_pull_up_input(
-                            self, _cs: &CriticalSection
-                        ) -> $PXi<Input<PullUp>> {
-                            unsafe { (*$GPIOX::ptr()).puer.write_with_zero(|w| w.bits(1 << $i)) };
-                            $PXi { _mode: PhantomData }
+                impl Port {
+                    /// Lets `write` drive the pins in `mask`, on top of the
+                    /// ones already enabled.
+                    pub fn set_write_mask(&mut self, mask: u32) {
+                        unsafe {
+                            (*$GPIOX::ptr()).with_unlocked(|pio| pio.ower.write_with_zero(|w| w.bits(mask)));
                         }
-
-                        pub fn into_output(
-                            self, _cs: &CriticalSection
-                        ) -> $PXi<Output<OpenDrain>> {
-                            unsafe { (*$GPIOX::ptr()).oer.write_with_zero(|w| w.bits(1 << $i)) }
-                            $PXi { _mode: PhantomData }
-                        }
-
-                        //TODO: FALTA ANALOG
-                        //TODO: FALTA PUSH PULL OUTPUT
-                        //TODO: FALTA PUSH PULL OUTPUT HS
                     }
 
-                    //TODO: FALTA INTERNAL PULL UP
-                    //TODO: FALTA INTERNAL PULL UP
-                    //TODO: FALTA INTERNAL OPEN DRAIN
-
-                    impl<MODE> $PXi<Output<MODE>> {
-                        pub fn downgrade(self) -> Pin<Output<MODE>> {
-                            Pin {
-                                i: $i,
-                                port: $GPIOX::ptr() as *const dyn GpioRegExt,
-                                _mode: self._mode
-                            }
+                    pub fn clear_write_mask(&mut self, mask: u32) {
+                        unsafe {
+                            (*$GPIOX::ptr()).with_unlocked(|pio| pio.owdr.write_with_zero(|w| w.bits(mask)));
                         }
                     }
 
-                    impl<MODE> StatefulOutputPin for $PXi<Output<MODE>> {
-                        fn is_set_high(&self) -> Result<bool, Self::Error> {
-                            self.is_set_low().map(|v| !v)
-                        }
-
-                        fn is_set_low(&self) -> Result<bool, Self::Error> {
-                            Ok(unsafe { (*$GPIOX::ptr()).is_set_low($i)})
-                        }
+                    pub fn write(&mut self, value: u32) {
+                        unsafe { (*$GPIOX::ptr()).odsr.write_with_zero(|w| w.bits(value)) };
                     }
 
-                    impl<MODE> OutputPin for $PXi<Output<MODE>> {
-                        type Error = Infallible;
-
-                        fn set_high(&mut self) -> Result<(), Self::Error> {
-                            Ok(unsafe { (*$GPIOX::ptr()).set_high($i) })
-                        }
-
-                        fn set_low(&mut self) -> Result<(), Self::Error> {
-                            Ok(unsafe { (*$GPIOX::ptr()).set_low($i) })
-                        }
+                    /// Raw registers of the whole PIO controller.
+                    ///
+                    /// # Safety
+                    /// They are shared with every pin of the port, including
+                    /// the ones owned by other drivers.
+                    pub unsafe fn registers(&self) -> &crate::pac::$gpiox::RegisterBlock {
+                        &*$GPIOX::ptr()
                     }
 
-                    impl<MODE> toggleable::Default for $PXi<Output<MODE>> {}
-
-                    impl InputPin for $PXi<Output<OpenDrain>> {
-                        type Error = Infallible;
-
-                        fn is_high(&self) -> Result<bool, Self::Error> {
-                            self.is_low().map(|v| !v)
-                        }
-
-                        fn is_low(&self) -> Result<bool, Self::Error> {
-                            Ok(unsafe { (*$GPIOX::ptr()).is_low($i) })
-                        }
+                    /// Drives the pins in `mask` to `value` at once. Pins left
+                    /// enabled by `set_write_mask` are driven too.
+                    pub fn write_masked(&mut self, mask: u32, value: u32) {
+                        self.set_write_mask(mask);
+                        self.write(value);
+                        self.clear_write_mask(mask);
                     }
+                }
 
-                    impl<MODE> $PXi<Input<MODE>> {
-                        pub fn downgrade(self) -> Pin<Input<MODE>> {
-                            Pin {
-                                i: $i,
-                                port: $GPIOX::ptr() as *const dyn GpioRegExt,
-                                _mode: self._mode
-                            }
-                        }
+                impl Parts {
+                    pub fn free(self, pmc: &PMC) -> $GPIOX {
+                        pmc.with_unlocked(|pmc| unsafe {
+                            pmc.pmc_pcdr0.write_with_zero(|w| w.$pidx().set_bit())
+                        });
+                        self.pio
                     }
+                }
 
-                    impl<MODE> InputPin for $PXi<Input<MODE>> {
-                        type Error = Infallible;
-
-                        fn is_high(&self) -> Result<bool, Self::Error> {
-                            self.is_low().map(|v| !v)
-                        }
+                impl GpioExt for $GPIOX {
+                    type Parts = Parts;
 
-                        fn is_low(&self) -> Result<bool, Self::Error> {
-                            Ok(unsafe { (*$GPIOX::ptr()).is_low($i) })
+                    fn split(self, pmc: &PMC) -> Parts {
+                        pmc.with_unlocked(|pmc| unsafe {
+                            pmc.pmc_pcer0.write_with_zero(|w| w.$pidx().set_bit())
+                        });
+                        Parts {
+                            $(
+                                $pxi: Pin::new(),
+                            )+
+                            port: Port { _private: () },
+                            pio: self,
                         }
                     }
+                }
+
+                $(
+                    pub type $PXi<MODE> = Pin<$port, $i, MODE>;
                 )+
             }
         )+
@@ -339,7 +444,7 @@ macro_rules! gpio {
 }
 
 gpio!([
-    PIOA, pioa, iopaen, PA, pid10 => [
+    PIOA, pioa, 'A', PA, pid10 => [
         PA0: (pa0, 0, Input<Floating>),
         PA1: (pa1, 1, Input<Floating>),
         PA2: (pa2, 2, Input<Floating>),
@@ -373,7 +478,7 @@ gpio!([
         PA30: (pa30, 30, Input<Floating>),
         PA31: (pa31, 31, Input<Floating>),
 ],
-    PIOB, piob, iopben, PB, pid11 => [
+    PIOB, piob, 'B', PB, pid11 => [
         PB0: (pb0, 0, Input<Floating>),
         PB1: (pb1, 1, Input<Floating>),
         PB2: (pb2, 2, Input<Floating>),
@@ -407,7 +512,7 @@ gpio!([
         PB30: (pb30, 30, Input<Floating>),
         PB31: (pb31, 31, Input<Floating>),
 ],
-    PIOC, pioc, iopcen, PC, pid12 => [
+    PIOC, pioc, 'C', PC, pid12 => [
         PC0: (pc0, 0, Input<Floating>),
         PC1: (pc1, 1, Input<Floating>),
         PC2: (pc2, 2, Input<Floating>),
@@ -441,7 +546,7 @@ gpio!([
         PC30: (pc30, 30, Input<Floating>),
         PC31: (pc31, 31, Input<Floating>),
 ],
-    PIOD, piod, iopden, PD, pid16 => [
+    PIOD, piod, 'D', PD, pid16 => [
         PD0: (pd0, 0, Input<Floating>),
         PD1: (pd1, 1, Input<Floating>),
         PD2: (pd2, 2, Input<Floating>),
@@ -475,7 +580,7 @@ gpio!([
         PD30: (pd30, 30, Input<Floating>),
         PD31: (pd31, 31, Input<Floating>),
 ],
-    PIOE, pioe, iopeen, PE, pid17 => [
+    PIOE, pioe, 'E', PE, pid17 => [
         PE0: (pe0, 0, Input<Floating>),
         PE1: (pe1, 1, Input<Floating>),
         PE2: (pe2, 2, Input<Floating>),
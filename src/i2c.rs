@@ -0,0 +1,24 @@
+//! Errors of the TWIHS peripherals in I2C mode.
+
+use embedded_hal_1::i2c;
+
+/// TWIHS only reports a single NACK flag, so address and data NACKs cannot
+/// be told apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2cError {
+    Nack,
+    ArbitrationLoss,
+    Overrun,
+    Underrun,
+}
+
+impl i2c::Error for I2cError {
+    fn kind(&self) -> i2c::ErrorKind {
+        match self {
+            I2cError::Nack => i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Unknown),
+            I2cError::ArbitrationLoss => i2c::ErrorKind::ArbitrationLoss,
+            I2cError::Overrun => i2c::ErrorKind::Overrun,
+            I2cError::Underrun => i2c::ErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Key matrix scanning. Rows are driven low one at a time, preferably as
+//! open-drain outputs so that two keys pressed at once cannot short two
+//! rows, and columns are read back through pull-ups.
+//!
+//! ```ignore
+//! let rows = [pa0.into_output(cs).downgrade(), pa1.into_output(cs).downgrade()];
+//! let cols = [pa2.into_pull_up_input(cs).downgrade(), pa3.into_pull_up_input(cs).downgrade()];
+//! let mut keypad = Keypad::new(rows, cols).debounce(5);
+//!
+//! // From a 1 ms timer interrupt:
+//! keypad.scan(|event| queue.push(event));
+//! ```
+
+use core::convert::Infallible;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+/// Core cycles for a column to follow its row, given the pull-ups.
+const SETTLE_CYCLES: u32 = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Pressed { row: u8, col: u8 },
+    Released { row: u8, col: u8 },
+}
+
+struct Debouncer<const ROWS: usize, const COLS: usize> {
+    pressed: [[bool; COLS]; ROWS],
+    count: [[u8; COLS]; ROWS],
+    ticks: u8,
+}
+
+impl<const ROWS: usize, const COLS: usize> Debouncer<ROWS, COLS> {
+    fn new(ticks: u8) -> Self {
+        Debouncer { pressed: [[false; COLS]; ROWS], count: [[0; COLS]; ROWS], ticks }
+    }
+
+    /// Takes a reading of one key, which changes state once it has read
+    /// the same for `ticks` scans in a row.
+    fn update(&mut self, row: usize, col: usize, pressed: bool) -> Option<Event> {
+        let count = &mut self.count[row][col];
+        if pressed == self.pressed[row][col] {
+            *count = 0;
+            return None;
+        }
+        *count += 1;
+        if *count < self.ticks {
+            return None;
+        }
+        *count = 0;
+        self.pressed[row][col] = pressed;
+        let (row, col) = (row as u8, col as u8);
+        Some(if pressed { Event::Pressed { row, col } } else { Event::Released { row, col } })
+    }
+}
+
+pub struct Keypad<R, C, const ROWS: usize, const COLS: usize> {
+    rows: [R; ROWS],
+    cols: [C; COLS],
+    debouncer: Debouncer<ROWS, COLS>,
+}
+
+impl<R, C, const ROWS: usize, const COLS: usize> Keypad<R, C, ROWS, COLS>
+where
+    R: OutputPin<Error = Infallible>,
+    C: InputPin<Error = Infallible>,
+{
+    pub fn new(mut rows: [R; ROWS], cols: [C; COLS]) -> Self {
+        for row in rows.iter_mut() {
+            let _ = row.set_high();
+        }
+        Keypad { rows, cols, debouncer: Debouncer::new(1) }
+    }
+
+    /// Number of scans a key must read the same before a change is
+    /// reported, 1 by default. With a scan every millisecond, 5 to 20 suits
+    /// most keys.
+    pub fn debounce(mut self, ticks: u8) -> Self {
+        self.debouncer = Debouncer::new(ticks.max(1));
+        self
+    }
+
+    /// Scans the whole matrix once, calling `f` for each debounced change.
+    pub fn scan(&mut self, mut f: impl FnMut(Event)) {
+        for (r, row) in self.rows.iter_mut().enumerate() {
+            let _ = row.set_low();
+            cortex_m::asm::delay(SETTLE_CYCLES);
+            for (c, col) in self.cols.iter().enumerate() {
+                let pressed = col.is_low().unwrap_or_else(|e| match e {});
+                if let Some(event) = self.debouncer.update(r, c, pressed) {
+                    f(event);
+                }
+            }
+            let _ = row.set_high();
+        }
+    }
+
+    pub fn is_pressed(&self, row: u8, col: u8) -> bool {
+        self.debouncer.pressed[row as usize][col as usize]
+    }
+
+    pub fn free(self) -> ([R; ROWS], [C; COLS]) {
+        (self.rows, self.cols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debounces_bounces() {
+        let mut debouncer = Debouncer::<1, 1>::new(3);
+        let readings = [true, false, true, true, true, true, false, false, false];
+        let events: [Option<Event>; 9] = readings.map(|pressed| debouncer.update(0, 0, pressed));
+        assert_eq!(events[4], Some(Event::Pressed { row: 0, col: 0 }));
+        assert_eq!(events[8], Some(Event::Released { row: 0, col: 0 }));
+        assert_eq!(events.iter().flatten().count(), 2);
+    }
+}
@@ -1,7 +1,55 @@
 #![no_std]
 
+#[cfg(not(feature = "samv71q21"))]
+compile_error!("select a device with one of the features: samv71q21");
+
+#[cfg(feature = "samv71q21")]
 pub use atsamv71q21 as pac;
+pub mod acc;
+pub mod afec;
+pub mod edges;
+pub mod flash;
+pub mod gmac;
 pub mod serial;
+pub mod pmc;
+pub mod block;
+pub mod boot;
+pub mod can_timing;
+pub mod clock;
+pub mod clocks;
+mod crc;
+pub mod cycles;
+pub mod dacc;
+pub mod delay;
+pub mod device_id;
+pub mod dfu;
+pub mod dma;
+pub mod events;
+pub mod time;
 pub mod gpio;
+pub mod i2c;
+pub mod keypad;
+pub mod monitor;
+pub mod nvstore;
+pub mod onewire;
+pub mod pdm;
+pub mod pwm;
+pub mod tc;
+pub mod trace;
 pub mod watchdog;
-pub mod prelude;
\ No newline at end of file
+pub mod write_protect;
+pub mod resources;
+mod ring;
+pub mod rtc;
+pub mod sampler;
+pub mod spi;
+pub mod supervisor;
+pub mod sync;
+pub mod ssc;
+pub mod prelude;
+#[cfg(feature = "board-xplained")]
+pub mod board;
+#[cfg(feature = "spi-flash")]
+pub mod spi_flash;
+
+pub use resources::init;
@@ -0,0 +1,210 @@
+//! Health monitoring of analog inputs.
+//!
+//! A TC0 channel triggers AFEC0 at a fixed period, the XDMAC moves the tagged
+//! results to memory and `Monitor::poll` folds them into per channel
+//! statistics, calling back when a value leaves its limits.
+
+use embedded_dma::WriteBuffer;
+use crate::afec::{self, Afec, Stream, Trigger, CHANNELS};
+use crate::clocks::Clocks;
+use crate::dma;
+use crate::pac::{AFEC0, PMC, TC0};
+use crate::tc;
+use crate::time::Microseconds;
+
+/// Temperature the sensor output is specified at, in m°C, that output in
+/// mV, and its slope in µV/°C.
+const TEMPERATURE_REFERENCE: i32 = 27_000;
+const TEMPERATURE_OFFSET: i32 = 720;
+const TEMPERATURE_SLOPE: i32 = 2_330;
+const FULL_SCALE: i32 = 4096;
+
+/// Converts a temperature sensor result into millidegrees Celsius, for a
+/// reference voltage of `vref` mV.
+pub fn millicelsius(value: u16, vref: u32) -> i32 {
+    let microvolts = value as i64 * vref as i64 * 1000 / FULL_SCALE as i64;
+    TEMPERATURE_REFERENCE + ((microvolts - TEMPERATURE_OFFSET as i64 * 1000) * 1000 / TEMPERATURE_SLOPE as i64) as i32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Low,
+    High,
+}
+
+/// Called from `poll` with the channel, the value and the limit it crossed.
+pub type Callback = fn(u8, u16, Limit);
+
+/// AFEC0 channel to sample, and the range it should stay in.
+#[derive(Clone, Copy, Debug)]
+pub struct Watch {
+    channel: u8,
+    low: Option<u16>,
+    high: Option<u16>,
+}
+
+impl Watch {
+    pub fn new(channel: u8) -> Self {
+        assert!(channel < CHANNELS, "AFEC channel out of range");
+        Watch { channel, low: None, high: None }
+    }
+
+    pub fn temperature() -> Self {
+        Watch::new(afec::TEMPERATURE)
+    }
+
+    pub fn low(mut self, low: u16) -> Self {
+        self.low = Some(low);
+        self
+    }
+
+    pub fn high(mut self, high: u16) -> Self {
+        self.high = Some(high);
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stats {
+    pub latest: u16,
+    pub min: u16,
+    pub max: u16,
+    pub samples: u32,
+}
+
+impl Stats {
+    const EMPTY: Stats = Stats { latest: 0, min: u16::MAX, max: 0, samples: 0 };
+}
+
+struct Tracked {
+    watch: Watch,
+    stats: Stats,
+    outside: Option<Limit>,
+}
+
+impl Tracked {
+    /// Returns the limit crossed by `value`, only on the first sample out of
+    /// range.
+    fn record(&mut self, value: u16) -> Option<Limit> {
+        let stats = &mut self.stats;
+        stats.latest = value;
+        stats.min = stats.min.min(value);
+        stats.max = stats.max.max(value);
+        stats.samples = stats.samples.wrapping_add(1);
+
+        let outside = if self.watch.low.is_some_and(|low| value < low) {
+            Some(Limit::Low)
+        } else if self.watch.high.is_some_and(|high| value > high) {
+            Some(Limit::High)
+        } else {
+            None
+        };
+        let crossed = outside.filter(|&limit| self.outside != Some(limit));
+        self.outside = outside;
+        crossed
+    }
+}
+
+pub struct Monitor<const N: usize, const TC: u8, const CH: u8, B> {
+    stream: Stream<AFEC0, TC, CH, B>,
+    tracked: [Tracked; N],
+    callback: Option<Callback>,
+}
+
+impl<const N: usize, const TC: u8, const CH: u8, B> Monitor<N, TC, CH, B>
+where
+    B: WriteBuffer<Word = u32>,
+{
+    /// Samples the `watches` every `period`, with TIOA of `timer` as AFEC
+    /// trigger. `buffer` collects the results between two `poll`s; one word
+    /// per watch is enough.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        afec: AFEC0,
+        mut timer: tc::Channel<TC0, TC>,
+        channel: dma::Channel<CH>,
+        buffer: B,
+        watches: [Watch; N],
+        period: Microseconds,
+        clocks: &Clocks,
+        pmc: &PMC,
+    ) -> Self {
+        let channels = watches.iter().fold(0, |mask, watch| mask | 1 << watch.channel);
+        let stream = Afec::new(afec, clocks, pmc).stream(channels, Trigger::Tioa(TC), channel, buffer);
+        timer.start_trigger_output(clocks, period);
+        Monitor {
+            stream: stream.with_timer(timer),
+            tracked: watches.map(|watch| Tracked { watch, stats: Stats::EMPTY, outside: None }),
+            callback: None,
+        }
+    }
+
+    pub fn set_callback(&mut self, callback: Option<Callback>) {
+        self.callback = callback;
+    }
+
+    pub fn stats(&self, channel: u8) -> Option<Stats> {
+        self.tracked.iter()
+            .find(|tracked| tracked.watch.channel == channel)
+            .map(|tracked| tracked.stats)
+    }
+
+    pub fn reset_stats(&mut self) {
+        for tracked in self.tracked.iter_mut() {
+            tracked.stats = Stats::EMPTY;
+        }
+    }
+
+    /// Processes the results once the buffer is full and restarts the
+    /// transfer. Returns whether there were new results. Call it from a
+    /// handler of the DMA channel, or periodically.
+    pub fn poll(&mut self) -> bool {
+        let (tracked, callback) = (&mut self.tracked, self.callback);
+        self.stream.poll(|results| {
+            for (tag, value) in results.iter().map(|&result| afec::untag(result)) {
+                for tracked in tracked.iter_mut().filter(|tracked| tracked.watch.channel == tag) {
+                    if let (Some(limit), Some(callback)) = (tracked.record(value), callback) {
+                        callback(tag, value, limit);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Stops sampling and gives back the peripherals and the buffer.
+    pub fn free(self, pmc: &PMC) -> (AFEC0, tc::Channel<TC0, TC>, dma::Channel<CH>, B) {
+        let (afec, timer, channel, buffer) = self.stream.stop();
+        (afec.free(pmc), timer, channel, buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_and_limits() {
+        let mut tracked = Tracked {
+            watch: Watch::new(3).low(100).high(200),
+            stats: Stats::EMPTY,
+            outside: None,
+        };
+        assert_eq!(tracked.record(150), None);
+        assert_eq!(tracked.record(250), Some(Limit::High));
+        assert_eq!(tracked.record(260), None);
+        assert_eq!(tracked.record(50), Some(Limit::Low));
+        assert_eq!(tracked.record(150), None);
+        assert_eq!(tracked.record(210), Some(Limit::High));
+        assert_eq!(tracked.stats, Stats { latest: 210, min: 50, max: 260, samples: 6 });
+    }
+
+    #[test]
+    fn temperature_at_reference_point() {
+        // 0.72 V with a 3.3 V reference.
+        let value = (720 * 4096 / 3300) as u16;
+        assert!((millicelsius(value, 3300) - 27_000).abs() < 500);
+        // 2.33 mV per degree above it.
+        let value = ((720 + 10 * 2_330 / 1000) * 4096 / 3300) as u16;
+        assert!((millicelsius(value, 3300) - 37_000).abs() < 500);
+    }
+}
@@ -0,0 +1,668 @@
+//! Key-value store kept as an append-only log over a ring of flash sectors.
+//!
+//! Each update appends a record to the active sector. When it fills up, the
+//! latest value of every key is copied to the next sector of the ring, which
+//! spreads the erases over all of them. Records and sector headers carry a
+//! CRC, and a sector only becomes active once its header is written after
+//! the copy, so an interrupted update leaves either the old or the new value.
+//!
+//! With the internal flash, the last sectors are a natural home for it:
+//! `NvStore::mount(flash, FLASH_SIZE - 4 * ERASE_SIZE, ERASE_SIZE, 4)`.
+
+use crate::crc::crc16;
+use crate::flash::{Flash, FlashError};
+
+/// Flash the store can live on.
+pub trait Storage {
+    type Error;
+
+    /// Smallest erasable area, in bytes.
+    fn erase_size(&self) -> u32;
+    /// Smallest programmable area, in bytes. Areas of this size are
+    /// programmed once between erases.
+    fn write_size(&self) -> u32;
+    fn read(&mut self, offset: u32, data: &mut [u8]) -> Result<(), Self::Error>;
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+    fn erase(&mut self, offset: u32, length: u32) -> Result<(), Self::Error>;
+}
+
+impl Storage for Flash {
+    type Error = FlashError;
+
+    fn erase_size(&self) -> u32 {
+        crate::flash::ERASE_SIZE
+    }
+
+    /// The EEFC keeps an ECC per 128 bits, so they cannot be reprogrammed.
+    fn write_size(&self) -> u32 {
+        16
+    }
+
+    fn read(&mut self, offset: u32, data: &mut [u8]) -> Result<(), FlashError> {
+        Flash::read(self, offset, data)
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError> {
+        Flash::write(self, offset, data)
+    }
+
+    fn erase(&mut self, offset: u32, length: u32) -> Result<(), FlashError> {
+        Flash::erase(self, offset, length)
+    }
+}
+
+#[cfg(feature = "spi-flash")]
+impl<T: crate::spi_flash::Transport> Storage for crate::spi_flash::SpiFlash<T> {
+    type Error = crate::spi_flash::Error<T::Error>;
+
+    fn erase_size(&self) -> u32 {
+        self.parameters().erase_types.iter().flatten().map(|erase| erase.size).min().unwrap_or(0)
+    }
+
+    fn write_size(&self) -> u32 {
+        1
+    }
+
+    fn read(&mut self, offset: u32, data: &mut [u8]) -> Result<(), Self::Error> {
+        crate::spi_flash::SpiFlash::read(self, offset, data)
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error> {
+        self.program(offset, data)
+    }
+
+    fn erase(&mut self, offset: u32, length: u32) -> Result<(), Self::Error> {
+        crate::spi_flash::SpiFlash::erase(self, offset, length)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    Storage(E),
+    /// The sectors are not aligned to the erase size, or the write size
+    /// does not divide `CHUNK`.
+    Geometry,
+    /// `0xFFFF` marks erased flash and cannot be used as a key.
+    InvalidKey,
+    /// The value cannot fit in a sector.
+    TooLarge,
+    /// The buffer passed to `get` is shorter than the value.
+    BufferTooSmall,
+    /// The live values fill a whole sector.
+    Full,
+}
+
+const MAGIC: u32 = 0x5356_4E31;
+const ERASED_KEY: u16 = 0xFFFF;
+const DELETED: u16 = 0xFFFF;
+const RECORD_HEADER: u32 = 8;
+/// Records are moved through a buffer of this size, so it bounds the write
+/// size of the storage.
+const CHUNK: usize = 64;
+/// Keys sorted out per pass over the log when compacting.
+const COMPACT_BATCH: usize = 32;
+
+enum Record {
+    Erased,
+    Invalid,
+    Valid { key: u16, length: Option<u16>, size: u32 },
+}
+
+pub struct NvStore<S> {
+    storage: S,
+    base: u32,
+    sector_size: u32,
+    sectors: u32,
+    align: u32,
+    active: u32,
+    sequence: u32,
+    cursor: u32,
+}
+
+impl<S: Storage> NvStore<S> {
+    /// Opens the store in `sectors` sectors of `sector_size` bytes from
+    /// `base`, formatting them when none holds a valid log. A log damaged
+    /// by a power loss is compacted into the next sector right away.
+    pub fn mount(storage: S, base: u32, sector_size: u32, sectors: u32) -> Result<Self, Error<S::Error>> {
+        let erase_size = storage.erase_size();
+        let align = storage.write_size().max(RECORD_HEADER);
+        if erase_size == 0
+            || !base.is_multiple_of(erase_size)
+            || !sector_size.is_multiple_of(erase_size)
+            || !(CHUNK as u32).is_multiple_of(align)
+            || sector_size < 2 * CHUNK as u32
+            || sectors < 2
+        {
+            return Err(Error::Geometry);
+        }
+        let mut store = NvStore {
+            storage,
+            base,
+            sector_size,
+            sectors,
+            align,
+            active: 0,
+            sequence: 0,
+            cursor: align,
+        };
+
+        let mut found = None;
+        for sector in 0..sectors {
+            if let Some(sequence) = store.sector_sequence(sector)? {
+                if found.is_none_or(|(_, latest)| sequence.wrapping_sub(latest) as i32 > 0) {
+                    found = Some((sector, sequence));
+                }
+            }
+        }
+        let (active, sequence) = match found {
+            Some(found) => found,
+            None => {
+                store.format()?;
+                return Ok(store);
+            }
+        };
+        store.active = active;
+        store.sequence = sequence;
+
+        loop {
+            match store.record(store.cursor)? {
+                Record::Valid { size, .. } => store.cursor += size,
+                Record::Erased => break,
+                Record::Invalid => {
+                    store.compact(None)?;
+                    break;
+                }
+            }
+        }
+        Ok(store)
+    }
+
+    pub fn free(self) -> S {
+        self.storage
+    }
+
+    /// Erases every sector and starts an empty log in the first one.
+    pub fn format(&mut self) -> Result<(), Error<S::Error>> {
+        self.storage
+            .erase(self.base, self.sector_size * self.sectors)
+            .map_err(Error::Storage)?;
+        self.active = 0;
+        self.sequence = 0;
+        self.write_header(0, 0)?;
+        self.cursor = self.align;
+        Ok(())
+    }
+
+    /// Copies the value of `key` into `data` and returns its length.
+    pub fn get(&mut self, key: u16, data: &mut [u8]) -> Result<Option<usize>, Error<S::Error>> {
+        let (offset, length) = match self.find(key, self.cursor)? {
+            Some((offset, Some(length))) => (offset, length as usize),
+            _ => return Ok(None),
+        };
+        let data = data.get_mut(..length).ok_or(Error::BufferTooSmall)?;
+        self.storage
+            .read(self.address(self.active, offset + RECORD_HEADER), data)
+            .map_err(Error::Storage)?;
+        Ok(Some(length))
+    }
+
+    pub fn set(&mut self, key: u16, value: &[u8]) -> Result<(), Error<S::Error>> {
+        if value.len() >= DELETED as usize || self.size(value.len() as u32) > self.sector_size - self.align {
+            return Err(Error::TooLarge);
+        }
+        self.append(key, Some(value))
+    }
+
+    pub fn remove(&mut self, key: u16) -> Result<(), Error<S::Error>> {
+        if let Some((_, None)) | None = self.find(key, self.cursor)? {
+            return Ok(());
+        }
+        self.append(key, None)
+    }
+
+    fn address(&self, sector: u32, offset: u32) -> u32 {
+        self.base + sector * self.sector_size + offset
+    }
+
+    fn size(&self, length: u32) -> u32 {
+        (RECORD_HEADER + length).next_multiple_of(self.align)
+    }
+
+    fn sector_sequence(&mut self, sector: u32) -> Result<Option<u32>, Error<S::Error>> {
+        let mut header = [0u8; 8];
+        self.storage
+            .read(self.address(sector, 0), &mut header)
+            .map_err(Error::Storage)?;
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let sequence = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        Ok(if magic == MAGIC { Some(sequence) } else { None })
+    }
+
+    fn write_header(&mut self, sector: u32, sequence: u32) -> Result<(), Error<S::Error>> {
+        let mut header = [0xFFu8; CHUNK];
+        header[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&sequence.to_le_bytes());
+        let address = self.address(sector, 0);
+        self.storage
+            .write(address, &header[..self.align as usize])
+            .map_err(Error::Storage)
+    }
+
+    fn record(&mut self, offset: u32) -> Result<Record, Error<S::Error>> {
+        if offset + RECORD_HEADER > self.sector_size {
+            return Ok(Record::Erased);
+        }
+        let address = self.address(self.active, offset);
+        let mut header = [0u8; RECORD_HEADER as usize];
+        self.storage.read(address, &mut header).map_err(Error::Storage)?;
+        if header.iter().all(|&b| b == 0xFF) {
+            return Ok(Record::Erased);
+        }
+        let key = u16::from_le_bytes([header[0], header[1]]);
+        let length = u16::from_le_bytes([header[2], header[3]]);
+        let crc = u16::from_le_bytes([header[4], header[5]]);
+        let data_length = if length == DELETED { 0 } else { length as u32 };
+        let size = self.size(data_length);
+        if key == ERASED_KEY || header[6..] != [0, 0] || offset + size > self.sector_size {
+            return Ok(Record::Invalid);
+        }
+
+        let mut check = crc16(0, &header[..4]);
+        let mut chunk = [0u8; CHUNK];
+        let mut position = 0;
+        while position < data_length {
+            let count = (data_length - position).min(CHUNK as u32) as usize;
+            self.storage
+                .read(address + RECORD_HEADER + position, &mut chunk[..count])
+                .map_err(Error::Storage)?;
+            check = crc16(check, &chunk[..count]);
+            position += count as u32;
+        }
+        if check != crc {
+            return Ok(Record::Invalid);
+        }
+        let length = if length == DELETED { None } else { Some(length) };
+        Ok(Record::Valid { key, length, size })
+    }
+
+    /// Latest record of `key` before `end`, as its offset and length.
+    #[allow(clippy::type_complexity)]
+    fn find(&mut self, key: u16, end: u32) -> Result<Option<(u32, Option<u16>)>, Error<S::Error>> {
+        if key == ERASED_KEY {
+            return Err(Error::InvalidKey);
+        }
+        let mut found = None;
+        let mut offset = self.align;
+        while offset < end {
+            match self.record(offset)? {
+                Record::Valid { key: k, length, size } => {
+                    if k == key {
+                        found = Some((offset, length));
+                    }
+                    offset += size;
+                }
+                _ => break,
+            }
+        }
+        Ok(found)
+    }
+
+    fn append(&mut self, key: u16, value: Option<&[u8]>) -> Result<(), Error<S::Error>> {
+        if key == ERASED_KEY {
+            return Err(Error::InvalidKey);
+        }
+        let size = self.size(value.map_or(0, |value| value.len() as u32));
+        if self.cursor + size > self.sector_size {
+            return self.compact(Some((key, value)));
+        }
+        self.write_record(self.active, self.cursor, key, value)?;
+        self.cursor += size;
+        Ok(())
+    }
+
+    fn write_record(&mut self, sector: u32, offset: u32, key: u16, value: Option<&[u8]>) -> Result<(), Error<S::Error>> {
+        let data = value.unwrap_or(&[]);
+        let length = value.map_or(DELETED, |value| value.len() as u16);
+        let mut chunk = [0xFFu8; CHUNK];
+        chunk[0..2].copy_from_slice(&key.to_le_bytes());
+        chunk[2..4].copy_from_slice(&length.to_le_bytes());
+        let crc = crc16(crc16(0, &chunk[..4]), data);
+        chunk[4..6].copy_from_slice(&crc.to_le_bytes());
+        chunk[6..8].copy_from_slice(&[0, 0]);
+
+        // Staged through `chunk` so that each write-size unit is programmed
+        // exactly once, with the header in the same unit as the first bytes.
+        let start = self.address(sector, offset);
+        let mut fill = RECORD_HEADER as usize;
+        let mut written = 0;
+        let mut data = data;
+        loop {
+            let count = (CHUNK - fill).min(data.len());
+            chunk[fill..fill + count].copy_from_slice(&data[..count]);
+            fill += count;
+            data = &data[count..];
+            let end = if data.is_empty() { fill.next_multiple_of(self.align as usize) } else { fill };
+            chunk[fill..end].fill(0xFF);
+            self.storage
+                .write(start + written, &chunk[..end])
+                .map_err(Error::Storage)?;
+            written += end as u32;
+            if data.is_empty() {
+                break;
+            }
+            fill = 0;
+        }
+        Ok(())
+    }
+
+    /// Latest record of up to `COMPACT_BATCH` keys above `floor`, the
+    /// smallest ones, as key, offset, size and whether it holds a value,
+    /// sorted by key. Takes a single pass over the log.
+    #[allow(clippy::type_complexity)]
+    fn latest_records(
+        &mut self,
+        floor: Option<u16>,
+        latest: &mut [(u16, u32, u32, bool); COMPACT_BATCH],
+    ) -> Result<usize, Error<S::Error>> {
+        let mut count = 0;
+        let mut offset = self.align;
+        while offset < self.cursor {
+            let Record::Valid { key, length, size } = self.record(offset)? else {
+                break;
+            };
+            if floor.is_none_or(|floor| key > floor) {
+                let entry = (key, offset, size, length.is_some());
+                match latest[..count].binary_search_by_key(&key, |&(k, ..)| k) {
+                    Ok(i) => latest[i] = entry,
+                    Err(i) if i < COMPACT_BATCH => {
+                        count = (count + 1).min(COMPACT_BATCH);
+                        latest.copy_within(i..count - 1, i + 1);
+                        latest[i] = entry;
+                    }
+                    Err(_) => {}
+                }
+            }
+            offset += size;
+        }
+        Ok(count)
+    }
+
+    /// Moves the live records to the next sector, then writes `pending`
+    /// there, so that the sector header commits both at once.
+    fn compact(&mut self, pending: Option<(u16, Option<&[u8]>)>) -> Result<(), Error<S::Error>> {
+        let target = (self.active + 1) % self.sectors;
+        self.storage
+            .erase(self.address(target, 0), self.sector_size)
+            .map_err(Error::Storage)?;
+
+        let skip = pending.map(|(key, _)| key);
+        let mut cursor = self.align;
+        let mut chunk = [0u8; CHUNK];
+        let mut latest = [(0, 0, 0, false); COMPACT_BATCH];
+        let mut floor = None;
+        loop {
+            let count = self.latest_records(floor, &mut latest)?;
+            for &(key, offset, size, live) in &latest[..count] {
+                if !live || Some(key) == skip {
+                    continue;
+                }
+                let mut position = 0;
+                while position < size {
+                    let count = (size - position).min(CHUNK as u32) as usize;
+                    self.storage
+                        .read(self.address(self.active, offset + position), &mut chunk[..count])
+                        .map_err(Error::Storage)?;
+                    self.storage
+                        .write(self.address(target, cursor + position), &chunk[..count])
+                        .map_err(Error::Storage)?;
+                    position += count as u32;
+                }
+                cursor += size;
+            }
+            if count < COMPACT_BATCH {
+                break;
+            }
+            floor = Some(latest[count - 1].0);
+        }
+
+        if let Some((key, value)) = pending {
+            let size = self.size(value.map_or(0, |value| value.len() as u32));
+            if cursor + size > self.sector_size {
+                return Err(Error::Full);
+            }
+            // A removal leaves nothing to write, the key is already gone.
+            if value.is_some() {
+                self.write_record(target, cursor, key, value)?;
+                cursor += size;
+            }
+        }
+
+        let sequence = self.sequence.wrapping_add(1);
+        self.write_header(target, sequence)?;
+        self.active = target;
+        self.sequence = sequence;
+        self.cursor = cursor;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::convert::TryInto;
+    use std::vec;
+    use std::vec::Vec;
+
+    /// Flash in RAM that rejects reprogramming and can fail after a number
+    /// of writes to simulate a power loss.
+    struct RamFlash {
+        memory: Vec<u8>,
+        writes_left: Option<usize>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct PowerLoss;
+
+    impl RamFlash {
+        fn new() -> RamFlash {
+            RamFlash { memory: vec![0xFF; 4 * 1024], writes_left: None }
+        }
+    }
+
+    impl Storage for RamFlash {
+        type Error = PowerLoss;
+
+        fn erase_size(&self) -> u32 {
+            256
+        }
+
+        fn write_size(&self) -> u32 {
+            16
+        }
+
+        fn read(&mut self, offset: u32, data: &mut [u8]) -> Result<(), PowerLoss> {
+            let offset = offset as usize;
+            data.copy_from_slice(&self.memory[offset..offset + data.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), PowerLoss> {
+            assert_eq!(offset % 16, 0);
+            assert_eq!(data.len() % 16, 0);
+            if let Some(left) = self.writes_left.as_mut() {
+                if *left == 0 {
+                    return Err(PowerLoss);
+                }
+                *left -= 1;
+            }
+            for (i, &byte) in data.iter().enumerate() {
+                let cell = &mut self.memory[offset as usize + i];
+                assert_eq!(*cell, 0xFF, "reprogrammed byte at {}", offset as usize + i);
+                *cell = byte;
+            }
+            Ok(())
+        }
+
+        fn erase(&mut self, offset: u32, length: u32) -> Result<(), PowerLoss> {
+            self.memory[offset as usize..(offset + length) as usize].fill(0xFF);
+            Ok(())
+        }
+    }
+
+    const CACHE_LINE: usize = 32;
+
+    /// `RamFlash` behind a data cache that keeps every line read until it
+    /// is invalidated, as `Flash` does after erasing or programming.
+    struct Cached {
+        flash: RamFlash,
+        lines: BTreeMap<usize, [u8; CACHE_LINE]>,
+        invalidate: bool,
+    }
+
+    impl Cached {
+        fn new(invalidate: bool) -> Cached {
+            Cached { flash: RamFlash::new(), lines: BTreeMap::new(), invalidate }
+        }
+
+        fn invalidate(&mut self, offset: u32, length: usize) {
+            if self.invalidate {
+                let (start, end) = (offset as usize / CACHE_LINE, (offset as usize + length).div_ceil(CACHE_LINE));
+                self.lines.retain(|&line, _| !(start..end).contains(&line));
+            }
+        }
+    }
+
+    impl Storage for Cached {
+        type Error = PowerLoss;
+
+        fn erase_size(&self) -> u32 {
+            self.flash.erase_size()
+        }
+
+        fn write_size(&self) -> u32 {
+            self.flash.write_size()
+        }
+
+        fn read(&mut self, offset: u32, data: &mut [u8]) -> Result<(), PowerLoss> {
+            for (i, byte) in data.iter_mut().enumerate() {
+                let address = offset as usize + i;
+                let memory = &self.flash.memory;
+                let line = self.lines.entry(address / CACHE_LINE).or_insert_with(|| {
+                    let start = address - address % CACHE_LINE;
+                    memory[start..start + CACHE_LINE].try_into().unwrap()
+                });
+                *byte = line[address % CACHE_LINE];
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), PowerLoss> {
+            let result = self.flash.write(offset, data);
+            self.invalidate(offset, data.len());
+            result
+        }
+
+        fn erase(&mut self, offset: u32, length: u32) -> Result<(), PowerLoss> {
+            let result = self.flash.erase(offset, length);
+            self.invalidate(offset, length as usize);
+            result
+        }
+    }
+
+    fn get<S: Storage<Error = PowerLoss>>(store: &mut NvStore<S>, key: u16) -> Option<Vec<u8>> {
+        let mut data = [0u8; 128];
+        store.get(key, &mut data).unwrap().map(|length| data[..length].to_vec())
+    }
+
+    #[test]
+    fn values_survive_compaction_and_remount() {
+        let mut store = NvStore::mount(RamFlash::new(), 0, 256, 4).unwrap();
+        store.set(1, b"first").unwrap();
+        store.set(2, b"removed").unwrap();
+        store.remove(2).unwrap();
+        for i in 0..100u8 {
+            store.set(3, &[i; 20]).unwrap();
+        }
+        assert_eq!(get(&mut store, 1).as_deref(), Some(&b"first"[..]));
+        assert_eq!(get(&mut store, 2), None);
+        assert_eq!(get(&mut store, 3), Some(vec![99; 20]));
+
+        let mut store = NvStore::mount(store.free(), 0, 256, 4).unwrap();
+        assert_eq!(get(&mut store, 1).as_deref(), Some(&b"first"[..]));
+        assert_eq!(get(&mut store, 3), Some(vec![99; 20]));
+    }
+
+    #[test]
+    fn interrupted_update_keeps_old_value() {
+        let mut store = NvStore::mount(RamFlash::new(), 0, 256, 4).unwrap();
+        store.set(1, &[1; 100]).unwrap();
+        let mut flash = store.free();
+        flash.writes_left = Some(1);
+        let mut store = NvStore::mount(flash, 0, 256, 4).unwrap();
+        assert_eq!(store.set(1, &[2; 100]), Err(Error::Storage(PowerLoss)));
+
+        let mut flash = store.free();
+        flash.writes_left = None;
+        let mut store = NvStore::mount(flash, 0, 256, 4).unwrap();
+        assert_eq!(get(&mut store, 1), Some(vec![1; 100]));
+        store.set(1, &[3; 100]).unwrap();
+        assert_eq!(get(&mut store, 1), Some(vec![3; 100]));
+    }
+
+    #[test]
+    fn interrupted_compaction_keeps_old_or_new_value() {
+        for writes in 0..8 {
+            let mut store = NvStore::mount(RamFlash::new(), 0, 256, 4).unwrap();
+            store.set(1, &[1; 100]).unwrap();
+            store.set(2, &[2; 100]).unwrap();
+            let mut flash = store.free();
+            flash.writes_left = Some(writes);
+            let mut store = NvStore::mount(flash, 0, 256, 4).unwrap();
+            let result = store.set(2, &[3; 100]);
+
+            let mut flash = store.free();
+            flash.writes_left = None;
+            let mut store = NvStore::mount(flash, 0, 256, 4).unwrap();
+            assert_eq!(get(&mut store, 1), Some(vec![1; 100]));
+            let expected = if result.is_ok() { 3 } else { 2 };
+            assert_eq!(get(&mut store, 2), Some(vec![expected; 100]), "{} writes", writes);
+        }
+    }
+
+    #[test]
+    fn compaction_of_more_keys_than_a_batch() {
+        let mut store = NvStore::mount(RamFlash::new(), 0, 1024, 4).unwrap();
+        for round in 0..3u8 {
+            for key in (0..40u16).rev() {
+                store.set(key, &[key as u8, round]).unwrap();
+            }
+        }
+        for key in 0..40u16 {
+            assert_eq!(get(&mut store, key), Some(vec![key as u8, 2]));
+        }
+    }
+
+    #[test]
+    fn reads_after_compaction_miss_stale_cache_lines() {
+        let run = |invalidate| -> Result<Vec<Option<Vec<u8>>>, Error<PowerLoss>> {
+            let mut store = NvStore::mount(Cached::new(invalidate), 0, 256, 4)?;
+            let mut values = Vec::new();
+            store.set(1, b"first")?;
+            for i in 0..40u8 {
+                store.set(2, &[i; 20])?;
+                values.push(get(&mut store, 2));
+            }
+            let mut store = NvStore::mount(store.free(), 0, 256, 4)?;
+            values.push(get(&mut store, 1));
+            Ok(values)
+        };
+        let mut expected: Vec<_> = (0..40u8).map(|i| Some(vec![i; 20])).collect();
+        expected.push(Some(b"first".to_vec()));
+        assert_eq!(run(true), Ok(expected.clone()));
+        // Without the invalidation the fixture does serve stale lines.
+        assert_ne!(run(false), Ok(expected));
+    }
+}
@@ -0,0 +1,267 @@
+//! 1-Wire bus master at standard speed, bit-banged on an open-drain pin.
+//!
+//! Time slots are timed by a microsecond delay, `cycles::Cycles` being the
+//! most precise, with interrupts masked for the length of each slot, at most
+//! about 70 µs, and of the presence detection after a reset.
+//!
+//! ```ignore
+//! let mut bus = OneWire::new(pin.into_output(cs), cycles);
+//! let mut search = Search::new();
+//! while let Some(rom) = bus.search(&mut search)? {
+//!     // rom[0] is the family code, 0x28 for a DS18B20.
+//! }
+//! ```
+
+use core::convert::Infallible;
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use crate::crc::crc8_maxim;
+
+pub const SEARCH_ROM: u8 = 0xF0;
+pub const READ_ROM: u8 = 0x33;
+pub const MATCH_ROM: u8 = 0x55;
+pub const SKIP_ROM: u8 = 0xCC;
+
+/// Family code, 48-bit serial number and CRC-8, in bus order.
+pub type Rom = [u8; 8];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OneWireError {
+    /// The line stayed low once released.
+    Shorted,
+    /// Both a 0 and a 1 were missing in a ROM search: the devices left.
+    NoResponse,
+    /// ROM code read with a bad CRC-8.
+    Crc,
+}
+
+/// Progress of a ROM search across calls of `OneWire::search`, one device
+/// found per call.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Search {
+    rom: Rom,
+    /// Bit, from 1, where the 0 branch was last taken; 0 for none.
+    last_discrepancy: u8,
+    done: bool,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Search::default()
+    }
+
+    /// Walks down the ROM tree, taking the branch after the previous one.
+    /// `triplet` reads a bit and its complement from the bus, then writes
+    /// the bit chosen: the one read when they differ, and otherwise the
+    /// preferred one it is given.
+    fn next(&mut self, mut triplet: impl FnMut(bool) -> (bool, bool)) -> Result<Option<Rom>, OneWireError> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut last_zero = 0;
+        for bit in 1..=64u8 {
+            let (index, mask) = ((bit - 1) as usize / 8, 1 << ((bit - 1) % 8));
+            let preferred = match bit.cmp(&self.last_discrepancy) {
+                core::cmp::Ordering::Less => self.rom[index] & mask != 0,
+                core::cmp::Ordering::Equal => true,
+                core::cmp::Ordering::Greater => false,
+            };
+            let direction = match triplet(preferred) {
+                (true, true) => {
+                    *self = Search::new();
+                    return Err(OneWireError::NoResponse);
+                }
+                (false, false) => {
+                    if !preferred {
+                        last_zero = bit;
+                    }
+                    preferred
+                }
+                (id, _) => id,
+            };
+            if direction {
+                self.rom[index] |= mask;
+            } else {
+                self.rom[index] &= !mask;
+            }
+        }
+        self.last_discrepancy = last_zero;
+        self.done = last_zero == 0;
+        if crc8_maxim(&self.rom) != 0 {
+            return Err(OneWireError::Crc);
+        }
+        Ok(Some(self.rom))
+    }
+}
+
+pub struct OneWire<P, D> {
+    pin: P,
+    delay: D,
+}
+
+impl<P, D> OneWire<P, D>
+where
+    P: InputPin<Error = Infallible> + OutputPin<Error = Infallible>,
+    D: DelayUs<u32>,
+{
+    /// `pin` has to be open drain, with the bus pull-up, internal or not,
+    /// holding the line high once it is released.
+    pub fn new(mut pin: P, delay: D) -> Self {
+        let _ = pin.set_high();
+        OneWire { pin, delay }
+    }
+
+    pub fn free(self) -> (P, D) {
+        (self.pin, self.delay)
+    }
+
+    /// Resets every device and returns whether any answered with a presence
+    /// pulse.
+    pub fn reset(&mut self) -> Result<bool, OneWireError> {
+        if self.pin.is_low().unwrap() {
+            return Err(OneWireError::Shorted);
+        }
+        let _ = self.pin.set_low();
+        self.delay.delay_us(480);
+        let presence = cortex_m::interrupt::free(|_| {
+            let _ = self.pin.set_high();
+            self.delay.delay_us(70);
+            self.pin.is_low().unwrap()
+        });
+        self.delay.delay_us(410);
+        if self.pin.is_low().unwrap() {
+            return Err(OneWireError::Shorted);
+        }
+        Ok(presence)
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        let (low, high) = if bit { (6, 64) } else { (60, 10) };
+        cortex_m::interrupt::free(|_| {
+            let _ = self.pin.set_low();
+            self.delay.delay_us(low);
+            let _ = self.pin.set_high();
+        });
+        self.delay.delay_us(high);
+    }
+
+    pub fn read_bit(&mut self) -> bool {
+        let bit = cortex_m::interrupt::free(|_| {
+            let _ = self.pin.set_low();
+            self.delay.delay_us(6);
+            let _ = self.pin.set_high();
+            self.delay.delay_us(9);
+            self.pin.is_high().unwrap()
+        });
+        self.delay.delay_us(55);
+        bit
+    }
+
+    /// Least significant bit first, as all of 1-Wire.
+    pub fn write_byte(&mut self, byte: u8) {
+        for i in 0..8 {
+            self.write_bit(byte & (1 << i) != 0);
+        }
+    }
+
+    pub fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |byte, i| byte | (self.read_bit() as u8) << i)
+    }
+
+    pub fn write(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.write_byte(byte);
+        }
+    }
+
+    pub fn read(&mut self, data: &mut [u8]) {
+        for byte in data {
+            *byte = self.read_byte();
+        }
+    }
+
+    /// Resets the bus and addresses the device `rom`, or every device with
+    /// `None`, for the function command that follows. Returns `false` when
+    /// no device is present.
+    pub fn select(&mut self, rom: Option<&Rom>) -> Result<bool, OneWireError> {
+        if !self.reset()? {
+            return Ok(false);
+        }
+        match rom {
+            Some(rom) => {
+                self.write_byte(MATCH_ROM);
+                self.write(rom);
+            }
+            None => self.write_byte(SKIP_ROM),
+        }
+        Ok(true)
+    }
+
+    /// Finds the next device of `search`, `None` once all were found or when
+    /// the bus is empty.
+    pub fn search(&mut self, search: &mut Search) -> Result<Option<Rom>, OneWireError> {
+        if search.done || !self.reset()? {
+            return Ok(None);
+        }
+        self.write_byte(SEARCH_ROM);
+        search.next(|preferred| {
+            let id = self.read_bit();
+            let complement = self.read_bit();
+            self.write_bit(if id != complement { id } else { preferred });
+            (id, complement)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wired-AND of the devices still taking part in the search.
+    fn bus<'a>(devices: &'a [Rom]) -> impl FnMut(bool) -> (bool, bool) + 'a {
+        let mut active = [true; 4];
+        let mut bit = 0;
+        move |preferred| {
+            let bit_of = |rom: &Rom| rom[bit / 8] & (1 << (bit % 8)) != 0;
+            let taking_part = || devices.iter().zip(active.iter()).filter(|(_, &a)| a).map(|(rom, _)| rom);
+            let id = taking_part().all(bit_of);
+            let complement = taking_part().all(|rom| !bit_of(rom));
+            let direction = if id != complement { id } else { preferred };
+            for (rom, active) in devices.iter().zip(active.iter_mut()) {
+                *active &= bit_of(rom) == direction;
+            }
+            bit += 1;
+            (id, complement)
+        }
+    }
+
+    fn with_crc(mut rom: Rom) -> Rom {
+        rom[7] = crc8_maxim(&rom[..7]);
+        rom
+    }
+
+    #[test]
+    fn finds_every_device_once() {
+        let devices = [
+            with_crc([0x28, 0xFF, 0x4B, 0x1A, 0x00, 0x16, 0x03, 0]),
+            with_crc([0x28, 0x01, 0x4B, 0x1A, 0x00, 0x16, 0x03, 0]),
+            with_crc([0x10, 0x7E, 0x00, 0x08, 0x02, 0x00, 0x00, 0]),
+        ];
+        let mut search = Search::new();
+        let mut found = [[0; 8]; 3];
+        for rom in found.iter_mut() {
+            *rom = search.next(bus(&devices)).unwrap().unwrap();
+        }
+        assert_eq!(search.next(bus(&devices)), Ok(None));
+        found.sort();
+        let mut expected = devices;
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn bad_crc() {
+        let devices = [[0x28, 1, 2, 3, 4, 5, 6, 0]];
+        assert_eq!(Search::new().next(bus(&devices)), Err(OneWireError::Crc));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::pac::PMC;
+use crate::write_protect::WriteProtect;
+
+pub trait PeripheralId {
+    const PID: u8;
+}
+
+macro_rules! peripheral_id {
+    ($($PER:ident => $pid:expr,)+) => {
+        $(
+            impl PeripheralId for crate::pac::$PER {
+                const PID: u8 = $pid;
+            }
+        )+
+    }
+}
+
+peripheral_id! {
+    UART0 => 7,
+    UART1 => 8,
+    PIOA => 10,
+    PIOB => 11,
+    PIOC => 12,
+    USART0 => 13,
+    USART1 => 14,
+    USART2 => 15,
+    PIOD => 16,
+    PIOE => 17,
+    SSC => 22,
+    TC0 => 23,
+    TC1 => 26,
+    AFEC0 => 29,
+    DACC => 30,
+    PWM0 => 31,
+    ACC => 33,
+    AFEC1 => 40,
+    UART2 => 44,
+    UART3 => 45,
+    UART4 => 46,
+    TC2 => 47,
+    TC3 => 50,
+    XDMAC => 58,
+    PWM1 => 60,
+}
+
+pub enum PeripheralClockDivider {
+    Mck,
+    Mck2,
+    Mck4,
+    Mck8,
+}
+
+pub trait SleepwalkingExt {
+    fn enable_sleepwalking<P: PeripheralId>(&self);
+
+    fn disable_sleepwalking<P: PeripheralId>(&self);
+
+    fn is_sleepwalking_enabled<P: PeripheralId>(&self) -> bool;
+
+    fn is_sleepwalking_active<P: PeripheralId>(&self) -> bool;
+
+    fn is_activity_in_progress(&self) -> bool;
+
+    fn configure_peripheral_clock<P: PeripheralId>(&self, enable: bool, divider: PeripheralClockDivider);
+}
+
+impl SleepwalkingExt for PMC {
+    fn enable_sleepwalking<P: PeripheralId>(&self) {
+        let mask = 1 << (P::PID % 32);
+        self.with_unlocked(|pmc| unsafe {
+            if P::PID < 32 {
+                pmc.pmc_slpwk_er0.write_with_zero(|w| w.bits(mask));
+            } else {
+                pmc.pmc_slpwk_er1.write_with_zero(|w| w.bits(mask));
+            }
+        });
+    }
+
+    fn disable_sleepwalking<P: PeripheralId>(&self) {
+        let mask = 1 << (P::PID % 32);
+        self.with_unlocked(|pmc| unsafe {
+            if P::PID < 32 {
+                pmc.pmc_slpwk_dr0.write_with_zero(|w| w.bits(mask));
+            } else {
+                pmc.pmc_slpwk_dr1.write_with_zero(|w| w.bits(mask));
+            }
+        });
+    }
+
+    fn is_sleepwalking_enabled<P: PeripheralId>(&self) -> bool {
+        let status = if P::PID < 32 {
+            self.pmc_slpwk_sr0.read().bits()
+        } else {
+            self.pmc_slpwk_sr1.read().bits()
+        };
+        status & (1 << (P::PID % 32)) != 0
+    }
+
+    fn is_sleepwalking_active<P: PeripheralId>(&self) -> bool {
+        let status = if P::PID < 32 {
+            self.pmc_slpwk_asr0.read().bits()
+        } else {
+            self.pmc_slpwk_asr1.read().bits()
+        };
+        status & (1 << (P::PID % 32)) != 0
+    }
+
+    fn is_activity_in_progress(&self) -> bool {
+        self.pmc_slpwk_aipr.read().aip().bit()
+    }
+
+    fn configure_peripheral_clock<P: PeripheralId>(&self, enable: bool, divider: PeripheralClockDivider) {
+        use crate::pac::pmc::pmc_pcr::DIV_A;
+        let div = match divider {
+            PeripheralClockDivider::Mck => DIV_A::PERIPH_DIV_MCK,
+            PeripheralClockDivider::Mck2 => DIV_A::PERIPH_DIV2_MCK,
+            PeripheralClockDivider::Mck4 => DIV_A::PERIPH_DIV4_MCK,
+            PeripheralClockDivider::Mck8 => DIV_A::PERIPH_DIV8_MCK,
+        };
+        self.with_unlocked(|pmc| {
+            pmc.pmc_pcr.write(|w| unsafe {
+                w.pid().bits(P::PID)
+                    .cmd().set_bit()
+                    .div().variant(div)
+                    .en().bit(enable)
+            });
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PckSource {
+    SlowClock,
+    MainClock,
+    PllA,
+    Mck,
+}
+
+pub trait ProgrammableClockExt {
+    /// Starts programmable clock `id`, 0 to 6, at the frequency of `source`
+    /// divided by `prescaler + 1`.
+    fn enable_pck(&self, id: u8, source: PckSource, prescaler: u8);
+
+    fn disable_pck(&self, id: u8);
+}
+
+impl ProgrammableClockExt for PMC {
+    fn enable_pck(&self, id: u8, source: PckSource, prescaler: u8) {
+        assert!(id < 7, "programmable clock out of range");
+        self.disable_pck(id);
+        self.with_unlocked(|pmc| unsafe {
+            pmc.pmc_pck[id as usize].write_with_zero(|w| {
+                match source {
+                    PckSource::SlowClock => w.css().slow_clk(),
+                    PckSource::MainClock => w.css().main_clk(),
+                    PckSource::PllA => w.css().plla_clk(),
+                    PckSource::Mck => w.css().mck(),
+                };
+                w.pres().bits(prescaler)
+            });
+            pmc.pmc_scer.write_with_zero(|w| w.bits(1 << (8 + id)));
+        });
+        while self.pmc_sr.read().bits() & (1 << (8 + id)) == 0 {}
+    }
+
+    fn disable_pck(&self, id: u8) {
+        self.with_unlocked(|pmc| unsafe { pmc.pmc_scdr.write_with_zero(|w| w.bits(1 << (8 + id))) });
+    }
+}
+
+const MCKR_CSS_MAIN: u32 = 1;
+const MCKR_CSS_MASK: u32 = 0x3;
+const MCKR_PRES_MASK: u32 = 0x7 << 4;
+const PLLAR_MULA_MASK: u32 = 0x7FF << 16;
+
+pub trait LowPowerExt {
+    /// Enters WAIT mode and returns after a wake-up event, for example from
+    /// a fast startup input or a sleepwalking peripheral.
+    ///
+    /// Meanwhile MCK runs from the fast RC oscillator with PLLA stopped; the
+    /// crystal, if any, is left running so the clock configuration can be
+    /// restored quickly afterwards.
+    fn enter_wait_mode(&self);
+}
+
+impl LowPowerExt for PMC {
+    fn enter_wait_mode(&self) {
+        cortex_m::interrupt::free(|_| self.with_unlocked(|pmc| unsafe {
+            let mor = pmc.ckgr_mor.read();
+            let pllar = pmc.ckgr_pllar.read().bits();
+            let mckr = pmc.pmc_mckr.read().bits();
+
+            pmc.ckgr_mor.modify(|_, w| w.key().passwd().moscrcen().set_bit());
+            while !pmc.pmc_sr.read().moscrcs().bit() {}
+            if mckr & MCKR_CSS_MASK > MCKR_CSS_MAIN {
+                pmc.pmc_mckr.write(|w| w.bits(mckr & !MCKR_CSS_MASK | MCKR_CSS_MAIN));
+                while !pmc.pmc_sr.read().mckrdy().bit() {}
+            }
+            if mckr & MCKR_PRES_MASK != 0 {
+                pmc.pmc_mckr.modify(|r, w| w.bits(r.bits() & !MCKR_PRES_MASK));
+                while !pmc.pmc_sr.read().mckrdy().bit() {}
+            }
+            pmc.ckgr_pllar.write(|w| w.one().set_bit().mula().bits(0));
+            pmc.ckgr_mor.modify(|_, w| w.key().passwd().moscsel().clear_bit());
+            while !pmc.pmc_sr.read().moscsels().bit() {}
+
+            pmc.pmc_fsmr.modify(|_, w| w.lpm().clear_bit());
+            pmc.ckgr_mor.modify(|_, w| w.key().passwd().waitmode().set_bit());
+            while !pmc.pmc_sr.read().mckrdy().bit() {}
+            while !pmc.ckgr_mor.read().moscrcen().bit() {}
+
+            if mor.moscsel().bit() {
+                pmc.ckgr_mor.modify(|_, w| w.key().passwd().moscsel().set_bit());
+                while !pmc.pmc_sr.read().moscsels().bit() {}
+            }
+            pmc.ckgr_mor.modify(|_, w| w.key().passwd().moscrcen().bit(mor.moscrcen().bit()));
+            if pllar & PLLAR_MULA_MASK != 0 {
+                pmc.ckgr_pllar.write(|w| w.bits(pllar).one().set_bit());
+                while !pmc.pmc_sr.read().locka().bit() {}
+            }
+            pmc.pmc_mckr.modify(|r, w| w.bits(r.bits() & !MCKR_PRES_MASK | mckr & MCKR_PRES_MASK));
+            while !pmc.pmc_sr.read().mckrdy().bit() {}
+            pmc.pmc_mckr.write(|w| w.bits(mckr));
+            while !pmc.pmc_sr.read().mckrdy().bit() {}
+        }));
+    }
+}
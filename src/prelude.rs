@@ -6,3 +6,11 @@ pub use embedded_hal::digital::v2::StatefulOutputPin as _embedded_hal_gpio_State
 pub use embedded_hal::digital::v2::ToggleableOutputPin as _embedded_hal_gpio_ToggleableOutputPin;
 
 pub use crate::gpio::GpioExt as _samv71q_hal_gpio_GpioExt;
+
+pub use crate::clocks::ClocksExt as _samv71q_hal_clocks_ClocksExt;
+pub use crate::time::U32Ext as _samv71q_hal_time_U32Ext;
+pub use crate::write_protect::WriteProtect as _samv71q_hal_write_protect_WriteProtect;
+pub use crate::serial::FlushBlocking as _samv71q_hal_serial_FlushBlocking;
+pub use crate::tc::TcExt as _samv71q_hal_tc_TcExt;
+pub use crate::pwm::PwmExt as _samv71q_hal_pwm_PwmExt;
+pub use crate::dma::XdmacExt as _samv71q_hal_dma_XdmacExt;
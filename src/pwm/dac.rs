@@ -0,0 +1,103 @@
+use embedded_hal::PwmPin;
+use crate::tc::TimerError;
+use crate::time::Hertz;
+use super::{Channel, Instance};
+
+/// `value` in 1/65536 of full scale, with 0xFFFF stretched to 65536 so that
+/// the largest value is a 100% duty, in both the plain and dithered paths.
+fn fraction(value: u16) -> u32 {
+    value as u32 + (value as u32 >> 15)
+}
+
+/// First order sigma-delta modulator: the fraction of a count lost to
+/// the duty resolution is carried over to the next period, so that the
+/// average over several periods reaches the full 16 bits.
+struct SigmaDelta {
+    residual: u32,
+}
+
+impl SigmaDelta {
+    /// Duty for the next period of `period` counts, for `value` as scaled
+    /// by `fraction`.
+    fn step(&mut self, value: u16, period: u16) -> u16 {
+        let accumulated = fraction(value) * period as u32 + self.residual;
+        self.residual = accumulated & 0xFFFF;
+        (accumulated >> 16) as u16
+    }
+}
+
+/// Analog output from a PWM channel through an RC low-pass filter. The
+/// filter corner should sit well below the PWM frequency; the higher the
+/// frequency, the fewer the duty steps, which dithering makes up for.
+pub struct PwmDac<PWM, const CH: u8> {
+    channel: Channel<PWM, CH>,
+    value: u16,
+    dither: Option<SigmaDelta>,
+}
+
+impl<PWM: Instance, const CH: u8> PwmDac<PWM, CH> {
+    pub fn new(mut channel: Channel<PWM, CH>, frequency: Hertz) -> Result<Self, TimerError> {
+        channel.set_frequency(frequency)?;
+        channel.enable();
+        Ok(PwmDac { channel, value: 0, dither: None })
+    }
+
+    /// Dithers the duty around `value` at every `update`.
+    pub fn dithering(mut self, enable: bool) -> Self {
+        self.dither = if enable { Some(SigmaDelta { residual: 0 }) } else { None };
+        self
+    }
+
+    /// Sets the output to `value` / 65535 of the supply.
+    pub fn set(&mut self, value: u16) {
+        self.value = value;
+        if self.dither.is_none() {
+            let period = self.channel.get_max_duty() as u32;
+            let duty = (fraction(self.value) * period + 0x8000) >> 16;
+            self.channel.set_duty(duty as u16);
+        }
+    }
+
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    /// Computes the duty of the next period when dithering. To be called
+    /// once per PWM period, or at least at a steady rate well above the
+    /// filter corner.
+    pub fn update(&mut self) {
+        let period = self.channel.get_max_duty();
+        if let Some(dither) = self.dither.as_mut() {
+            let duty = dither.step(self.value, period);
+            self.channel.set_duty(duty);
+        }
+    }
+
+    pub fn free(mut self) -> Channel<PWM, CH> {
+        self.channel.disable();
+        self.channel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fraction, SigmaDelta};
+
+    #[test]
+    fn dither_averages_to_value() {
+        let mut dither = SigmaDelta { residual: 0 };
+        // A quarter count above 10 out of a period of 100.
+        let value = ((10 * 4 + 1) * 65536 / 400) as u16;
+        let duties: u32 = (0..400).map(|_| dither.step(value, 100) as u32).sum();
+        // 10.25 without the truncation of `value`, never 10 as undithered.
+        assert_eq!(duties, 4099);
+    }
+
+    #[test]
+    fn full_scale_is_full_duty() {
+        let mut dither = SigmaDelta { residual: 0 };
+        assert!((0..100).all(|_| dither.step(0xFFFF, 100) == 100));
+        assert_eq!((fraction(0xFFFF) * 100 + 0x8000) >> 16, 100);
+        assert_eq!((0..100).map(|_| dither.step(0, 100)).max(), Some(0));
+    }
+}
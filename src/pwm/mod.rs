@@ -0,0 +1,323 @@
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU32, Ordering};
+use embedded_hal::PwmPin;
+use crate::clocks::Clocks;
+use crate::tc::TimerError;
+use crate::pac::pwm0::{cmr0::CPRE_A, RegisterBlock};
+use crate::pac::{PMC, PWM0, PWM1};
+use crate::pmc::PeripheralId;
+use crate::time::Hertz;
+use crate::write_protect::WriteProtect;
+
+pub mod dac;
+pub mod servo;
+
+const CHANNEL_OFFSET: usize = 0x20;
+const PERIOD_MAX: u32 = 0xFFFF;
+const PRESCALERS: [CPRE_A; 11] = [
+    CPRE_A::MCK,
+    CPRE_A::MCK_DIV_2,
+    CPRE_A::MCK_DIV_4,
+    CPRE_A::MCK_DIV_8,
+    CPRE_A::MCK_DIV_16,
+    CPRE_A::MCK_DIV_32,
+    CPRE_A::MCK_DIV_64,
+    CPRE_A::MCK_DIV_128,
+    CPRE_A::MCK_DIV_256,
+    CPRE_A::MCK_DIV_512,
+    CPRE_A::MCK_DIV_1024,
+];
+
+/// Index in `PRESCALERS` and period of the smallest prescaler for which
+/// `frequency` fits the counter.
+fn prescaler(mck: Hertz, frequency: Hertz) -> Result<(usize, u32), TimerError> {
+    if frequency.0 == 0 {
+        return Err(TimerError::PeriodTooLong);
+    }
+    if mck.0 / frequency.0 < 2 {
+        return Err(TimerError::PeriodTooShort);
+    }
+    (0..PRESCALERS.len())
+        .map(|k| (k, (mck.0 >> k) / frequency.0))
+        .find(|&(_, period)| period <= PERIOD_MAX)
+        .ok_or(TimerError::PeriodTooLong)
+}
+
+pub trait Instance: PeripheralId {
+    /// Index in `SAFE_STATES`.
+    const INDEX: usize;
+
+    fn ptr() -> *const RegisterBlock;
+}
+
+macro_rules! pwm_instance {
+    ($($PWM:ident: $index:expr,)+) => {
+        $(
+            impl Instance for $PWM {
+                const INDEX: usize = $index;
+
+                fn ptr() -> *const RegisterBlock {
+                    $PWM::ptr() as *const RegisterBlock
+                }
+            }
+        )+
+    }
+}
+
+pwm_instance! {
+    PWM0: 0,
+    PWM1: 1,
+}
+
+/// Outputs given a safe state and their levels, laid out as OOV, for
+/// `emergency_stop` to find without the channels.
+struct SafeState {
+    outputs: AtomicU32,
+    levels: AtomicU32,
+}
+
+static SAFE_STATES: [SafeState; 2] = [
+    SafeState { outputs: AtomicU32::new(0), levels: AtomicU32::new(0) },
+    SafeState { outputs: AtomicU32::new(0), levels: AtomicU32::new(0) },
+];
+
+/// Drives the outputs of every channel of `PWM` given a safe state with
+/// `Channel::set_safe_state` to that state, all switching at the same
+/// instant on a single OSS write. Other channels keep running. It needs no
+/// channel, so it can run from a fault handler; channels stay overridden
+/// until `Channel::release_override`.
+pub fn emergency_stop<PWM: Instance>() {
+    let safe = &SAFE_STATES[PWM::INDEX];
+    let outputs = safe.outputs.load(Ordering::Acquire);
+    let levels = safe.levels.load(Ordering::Acquire);
+    let pwm = unsafe { &*PWM::ptr() };
+    pwm.with_unlocked(|pwm| unsafe {
+        pwm.oov.modify(|r, w| w.bits(r.bits() & !outputs | levels));
+        pwm.oss.write_with_zero(|w| w.bits(outputs));
+    });
+}
+
+pub trait PwmExt: Sized {
+    fn split(self, clocks: &Clocks, pmc: &PMC) -> Parts<Self>;
+}
+
+impl<PWM: Instance> PwmExt for PWM {
+    fn split(self, clocks: &Clocks, pmc: &PMC) -> Parts<PWM> {
+        pmc.with_unlocked(|pmc| unsafe {
+            if PWM::PID < 32 {
+                pmc.pmc_pcer0.write_with_zero(|w| w.bits(1 << PWM::PID));
+            } else {
+                pmc.pmc_pcer1.write_with_zero(|w| w.bits(1 << (PWM::PID - 32)));
+            }
+        });
+        let mck = clocks.mck();
+        Parts {
+            ch0: Channel { mck, _pwm: PhantomData },
+            ch1: Channel { mck, _pwm: PhantomData },
+            ch2: Channel { mck, _pwm: PhantomData },
+            ch3: Channel { mck, _pwm: PhantomData },
+            pwm: self,
+        }
+    }
+}
+
+pub struct Parts<PWM> {
+    pub ch0: Channel<PWM, 0>,
+    pub ch1: Channel<PWM, 1>,
+    pub ch2: Channel<PWM, 2>,
+    pub ch3: Channel<PWM, 3>,
+    pwm: PWM,
+}
+
+impl<PWM: Instance> Parts<PWM> {
+    /// # Safety
+    /// The channel registers belong to `ch0` to `ch3`.
+    pub unsafe fn registers(&self) -> &RegisterBlock {
+        &*PWM::ptr()
+    }
+
+    /// Latches a fault on fault input `input`, active high, that forces
+    /// both outputs of the `channels` bit mask low. It acts on the outputs
+    /// directly, whatever the PWM clock.
+    pub fn enable_fault(&mut self, input: u8, channels: u8) {
+        let pwm = unsafe { &*PWM::ptr() };
+        let outputs = channels as u32 | (channels as u32) << 16;
+        pwm.with_unlocked(|pwm| unsafe {
+            pwm.fpv1.modify(|r, w| w.bits(r.bits() & !outputs));
+            pwm.fpv2.modify(|r, w| w.bits(r.bits() & !outputs));
+            pwm.fmr.modify(|r, w| w.bits(r.bits() | 1 << input | 1 << (input + 8)));
+            pwm.fpe.modify(|r, w| {
+                let enables = (0..4)
+                    .filter(|ch| channels & (1 << ch) != 0)
+                    .fold(0, |enables, ch| enables | 1 << (input + 8 * ch));
+                w.bits(r.bits() | enables)
+            });
+        });
+    }
+
+    /// Fault inputs currently latched, as a bit mask.
+    pub fn faults(&self) -> u8 {
+        unsafe { (*PWM::ptr()).fsr.read().fs().bits() }
+    }
+
+    /// Releases the latched faults whose input went inactive.
+    pub fn clear_faults(&mut self) {
+        let pwm = unsafe { &*PWM::ptr() };
+        pwm.with_unlocked(|pwm| unsafe { pwm.fcr.write_with_zero(|w| w.fclr().bits(0xFF)) });
+    }
+
+    pub fn free(mut self, pmc: &PMC) -> PWM {
+        self.ch0.disable();
+        self.ch1.disable();
+        self.ch2.disable();
+        self.ch3.disable();
+        pmc.with_unlocked(|pmc| unsafe {
+            if PWM::PID < 32 {
+                pmc.pmc_pcdr0.write_with_zero(|w| w.bits(1 << PWM::PID));
+            } else {
+                pmc.pmc_pcdr1.write_with_zero(|w| w.bits(1 << (PWM::PID - 32)));
+            }
+        });
+        self.pwm
+    }
+}
+
+pub struct Channel<PWM, const CH: u8> {
+    mck: Hertz,
+    _pwm: PhantomData<PWM>,
+}
+
+impl<PWM: Instance, const CH: u8> Channel<PWM, CH> {
+    fn pwm(&self) -> &RegisterBlock {
+        unsafe { &*PWM::ptr() }
+    }
+
+    /// Every channel has the same layout as channel 0, 0x20 bytes apart, so
+    /// the channel 0 fields of the returned block address this channel.
+    fn registers(&self) -> &RegisterBlock {
+        unsafe { &*((PWM::ptr() as usize + CHANNEL_OFFSET * CH as usize) as *const RegisterBlock) }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.pwm().sr.read().bits() & (1 << CH) != 0
+    }
+
+    /// Picks the smallest MCK prescaler whose period fits the 16-bit counter.
+    /// The duty cycle is reset to zero. Below MCK / 1024 / 65535, about
+    /// 2.2 Hz at 150 MHz, or above MCK / 2 the channel is left as it was.
+    pub fn set_frequency(&mut self, frequency: Hertz) -> Result<(), TimerError> {
+        let (prescaler, period) = prescaler(self.mck, frequency)?;
+
+        let enabled = self.is_enabled();
+        self.disable();
+        let channel = self.registers();
+        self.pwm().with_unlocked(|_| unsafe {
+            channel.cmr0.modify(|_, w| w.cpre().variant(PRESCALERS[prescaler]));
+            channel.cprd0.write(|w| w.cprd().bits(period));
+            channel.cdty0.write(|w| w.cdty().bits(0));
+        });
+        if enabled {
+            self.enable();
+        }
+        Ok(())
+    }
+
+    /// Takes both outputs away from the waveform, PWMH to `high` and PWML
+    /// to `low`, from now on. The channel keeps counting underneath.
+    pub fn force_output(&mut self, high: bool, low: bool) {
+        let outputs = 1 << CH | 1 << (CH + 16);
+        let levels = (high as u32) << CH | (low as u32) << (CH + 16);
+        // Not interrupted by an `emergency_stop` between the read and the
+        // write of OOV.
+        cortex_m::interrupt::free(|_| {
+            self.pwm().with_unlocked(|pwm| unsafe {
+                pwm.oov.modify(|r, w| w.bits(r.bits() & !outputs | levels));
+                pwm.oss.write_with_zero(|w| w.bits(outputs));
+            });
+        });
+    }
+
+    /// Levels `emergency_stop` drives PWMH and PWML to, for example high
+    /// for active-low gate drivers.
+    pub fn set_safe_state(&mut self, high: bool, low: bool) {
+        let outputs = 1 << CH | 1 << (CH + 16);
+        let levels = (high as u32) << CH | (low as u32) << (CH + 16);
+        let safe = &SAFE_STATES[PWM::INDEX];
+        // Levels first, so that `emergency_stop` never sees the output
+        // with a stale level.
+        cortex_m::interrupt::free(|_| {
+            safe.levels.store(safe.levels.load(Ordering::Relaxed) & !outputs | levels, Ordering::Release);
+            safe.outputs.fetch_or(outputs, Ordering::Release);
+        });
+    }
+
+    /// Hands the outputs back to the waveform.
+    pub fn release_override(&mut self) {
+        let outputs = 1 << CH | 1 << (CH + 16);
+        self.pwm().with_unlocked(|pwm| unsafe { pwm.osc.write_with_zero(|w| w.bits(outputs)) });
+    }
+
+    pub fn is_overridden(&self) -> bool {
+        self.pwm().os.read().bits() & (1 << CH | 1 << (CH + 16)) != 0
+    }
+
+    pub fn counter_clock(&self) -> Hertz {
+        Hertz(self.mck.0 >> self.registers().cmr0.read().cpre().bits())
+    }
+}
+
+impl<PWM: Instance, const CH: u8> PwmPin for Channel<PWM, CH> {
+    type Duty = u16;
+
+    fn disable(&mut self) {
+        unsafe { self.pwm().dis.write_with_zero(|w| w.bits(1 << CH)) };
+    }
+
+    fn enable(&mut self) {
+        unsafe { self.pwm().ena.write_with_zero(|w| w.bits(1 << CH)) };
+    }
+
+    fn get_duty(&self) -> u16 {
+        self.registers().cdty0.read().cdty().bits() as u16
+    }
+
+    fn get_max_duty(&self) -> u16 {
+        self.registers().cprd0.read().cprd().bits() as u16
+    }
+
+    /// While the channel runs the new duty cycle takes effect at the next
+    /// period, through the update register.
+    fn set_duty(&mut self, duty: u16) {
+        let channel = self.registers();
+        let enabled = self.is_enabled();
+        self.pwm().with_unlocked(|_| unsafe {
+            if enabled {
+                channel.cdtyupd0.write_with_zero(|w| w.cdtyupd().bits(duty as u32));
+            } else {
+                channel.cdty0.write(|w| w.cdty().bits(duty as u32));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prescaler_search() {
+        let mck = Hertz(150_000_000);
+        assert_eq!(prescaler(mck, Hertz(20_000)), Ok((0, 7_500)));
+        assert_eq!(prescaler(mck, Hertz(1_000)), Ok((2, 37_500)));
+        assert_eq!(prescaler(mck, Hertz(50)), Ok((6, 46_875)));
+    }
+
+    #[test]
+    fn out_of_range_frequencies() {
+        let mck = Hertz(150_000_000);
+        assert_eq!(prescaler(mck, Hertz(1)), Err(TimerError::PeriodTooLong));
+        assert_eq!(prescaler(mck, Hertz(0)), Err(TimerError::PeriodTooLong));
+        assert_eq!(prescaler(mck, Hertz(75_000_000)), Ok((0, 2)));
+        assert_eq!(prescaler(mck, Hertz(100_000_000)), Err(TimerError::PeriodTooShort));
+    }
+}
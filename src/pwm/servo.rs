@@ -0,0 +1,57 @@
+use embedded_hal::PwmPin;
+use crate::time::{Hertz, Microseconds};
+use super::{Channel, Instance};
+
+const SERVO_FRAME: Hertz = Hertz(50);
+
+pub struct Calibration {
+    min_pulse: Microseconds,
+    max_pulse: Microseconds,
+    range: f32,
+}
+
+impl Calibration {
+    /// `range` is the travel in degrees between `min_pulse` and `max_pulse`.
+    pub fn new(min_pulse: Microseconds, max_pulse: Microseconds, range: f32) -> Calibration {
+        assert!(min_pulse < max_pulse);
+        Calibration { min_pulse, max_pulse, range }
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Calibration {
+        Calibration::new(Microseconds(1_000), Microseconds(2_000), 180.0)
+    }
+}
+
+pub struct Servo<PWM, const CH: u8> {
+    channel: Channel<PWM, CH>,
+    calibration: Calibration,
+}
+
+impl<PWM: Instance, const CH: u8> Servo<PWM, CH> {
+    pub fn new(mut channel: Channel<PWM, CH>, calibration: Calibration) -> Self {
+        // 50 Hz fits the counter from a 100 Hz MCK to well above 150 MHz.
+        channel.set_frequency(SERVO_FRAME).expect("servo frame out of the PWM range");
+        channel.enable();
+        Servo { channel, calibration }
+    }
+
+    pub fn set_pulse_width(&mut self, width: Microseconds) {
+        let ticks = self.channel.counter_clock().0 as u64 * width.0 as u64 / 1_000_000;
+        let max = self.channel.get_max_duty();
+        self.channel.set_duty(ticks.min(max as u64) as u16);
+    }
+
+    pub fn set_angle(&mut self, degrees: f32) {
+        let Calibration { min_pulse, max_pulse, range } = self.calibration;
+        let degrees = degrees.clamp(0.0, range);
+        let span = (max_pulse.0 - min_pulse.0) as f32;
+        self.set_pulse_width(Microseconds(min_pulse.0 + (span * degrees / range) as u32));
+    }
+
+    pub fn free(mut self) -> Channel<PWM, CH> {
+        self.channel.disable();
+        self.channel
+    }
+}
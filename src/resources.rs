@@ -0,0 +1,121 @@
+use cortex_m::peripheral::SYST;
+use crate::clocks::{self, Clocks, ClocksExt};
+use crate::delay::Delay;
+use crate::gpio::{pioa, piob, pioc, piod, pioe, GpioExt};
+use crate::pac;
+use crate::watchdog::{DisabledWatchdog, Watchdog};
+
+pub struct Config {
+    clocks: clocks::Config,
+    disable_watchdog: bool,
+}
+
+impl Config {
+    pub fn new(clocks: clocks::Config, disable_watchdog: bool) -> Config {
+        Config { clocks, disable_watchdog }
+    }
+}
+
+/// The watchdog as `init` leaves it: disabled, which `DisabledWatchdog`
+/// proves, or still to be configured.
+pub enum WatchdogState {
+    Unconfigured(Watchdog),
+    Disabled(DisabledWatchdog),
+}
+
+macro_rules! resources {
+    ($($per:ident: $PER:ident,)+) => {
+        pub struct Resources {
+            pub pioa: pioa::Parts,
+            pub piob: piob::Parts,
+            pub pioc: pioc::Parts,
+            pub piod: piod::Parts,
+            pub pioe: pioe::Parts,
+            pub pmc: pac::PMC,
+            pub clocks: Clocks,
+            pub delay: Delay,
+            pub watchdog: WatchdogState,
+            $(
+                pub $per: pac::$PER,
+            )+
+        }
+
+        pub fn init(dp: pac::Peripherals, syst: SYST, config: Config) -> Resources {
+            let watchdog = Watchdog::new(dp.WDT);
+            let watchdog = if config.disable_watchdog {
+                WatchdogState::Disabled(watchdog.disable())
+            } else {
+                WatchdogState::Unconfigured(watchdog)
+            };
+
+            let clocks = dp.PMC.freeze(config.clocks);
+            let delay = Delay::new(syst, clocks);
+
+            Resources {
+                pioa: dp.PIOA.split(&dp.PMC),
+                piob: dp.PIOB.split(&dp.PMC),
+                pioc: dp.PIOC.split(&dp.PMC),
+                piod: dp.PIOD.split(&dp.PMC),
+                pioe: dp.PIOE.split(&dp.PMC),
+                pmc: dp.PMC,
+                clocks,
+                delay,
+                watchdog,
+                $(
+                    $per: dp.$PER,
+                )+
+            }
+        }
+    }
+}
+
+resources! {
+    hsmci: HSMCI,
+    ssc: SSC,
+    spi0: SPI0,
+    spi1: SPI1,
+    tc0: TC0,
+    tc1: TC1,
+    tc2: TC2,
+    tc3: TC3,
+    twihs0: TWIHS0,
+    twihs1: TWIHS1,
+    twihs2: TWIHS2,
+    pwm0: PWM0,
+    pwm1: PWM1,
+    uart0: UART0,
+    uart1: UART1,
+    uart2: UART2,
+    uart3: UART3,
+    uart4: UART4,
+    usart0: USART0,
+    usart1: USART1,
+    usart2: USART2,
+    mcan0: MCAN0,
+    mcan1: MCAN1,
+    usbhs: USBHS,
+    afec0: AFEC0,
+    afec1: AFEC1,
+    dacc: DACC,
+    acc: ACC,
+    icm: ICM,
+    isi: ISI,
+    gmac: GMAC,
+    mlb: MLB,
+    aes: AES,
+    trng: TRNG,
+    xdmac: XDMAC,
+    qspi: QSPI,
+    smc: SMC,
+    sdramc: SDRAMC,
+    matrix: MATRIX,
+    utmi: UTMI,
+    chipid: CHIPID,
+    efc: EFC,
+    rstc: RSTC,
+    supc: SUPC,
+    rtt: RTT,
+    rtc: RTC,
+    gpbr: GPBR,
+    rswdt: RSWDT,
+}
@@ -0,0 +1,57 @@
+/// Fixed size FIFO, for software buffers in front of the hardware.
+pub(crate) struct Ring<T, const N: usize> {
+    buffer: [T; N],
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> Ring<T, N> {
+    /// `fill` only takes up the free slots, it is never read.
+    pub(crate) const fn new(fill: T) -> Self {
+        Ring { buffer: [fill; N], head: 0, len: 0 }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Returns `false`, dropping `value`, when full.
+    pub(crate) fn push(&mut self, value: T) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.buffer[(self.head + self.len) % N] = value;
+        self.len += 1;
+        true
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let value = self.buffer[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ring;
+
+    #[test]
+    fn wraps_around() {
+        let mut ring = Ring::<u16, 3>::new(0);
+        assert!(ring.push(1) && ring.push(2) && ring.push(3));
+        assert!(!ring.push(4));
+        assert_eq!(ring.pop(), Some(1));
+        assert!(ring.push(4));
+        assert_eq!((ring.pop(), ring.pop(), ring.pop()), (Some(2), Some(3), Some(4)));
+        assert_eq!(ring.pop(), None);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::pac::RTC;
+use crate::pac::rtc::cr::{CALEVSEL_A, TIMEVSEL_A};
+use crate::pac::rtc::mr::{OUT0_A, THIGH_A, TPERIOD_A};
+
+/// RTCOUT0 comes out on PB0 and RTCOUT1 on PB1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    Out0,
+    Out1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PulseHigh {
+    Ms31,
+    Ms16,
+    Ms4,
+    Us976,
+    Us488,
+    Us122,
+    Us30,
+    Us15,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PulsePeriod {
+    S1,
+    Ms500,
+    Ms250,
+    Ms125,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waveform {
+    Low,
+    Hz1,
+    Hz32,
+    Hz64,
+    Hz512,
+    /// Toggles each time the alarm flag rises.
+    AlarmToggle,
+    /// Follows the alarm flag.
+    AlarmFlag,
+    /// Both outputs share the pulse timing, so the last one set applies to
+    /// each of them.
+    Pulse(PulseHigh, PulsePeriod),
+}
+
+/// Time change raising the time event flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeEvent {
+    Minute,
+    Hour,
+    Midnight,
+    Noon,
+}
+
+/// Calendar change raising the calendar event flag, at 00:00:00.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalendarEvent {
+    Week,
+    Month,
+    Year,
+}
+
+/// Calendar time, in 24-hour mode. `weekday` runs from 1, Monday, to 7.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+fn from_bcd(bcd: u8) -> u8 {
+    (bcd >> 4) * 10 + (bcd & 0xF)
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+pub struct Status {
+    pub alarm: bool,
+    pub second: bool,
+    pub time_event: bool,
+    pub calendar_event: bool,
+}
+
+pub struct Rtc {
+    rtc: RTC,
+}
+
+impl Rtc {
+    pub fn new(rtc: RTC) -> Self {
+        Self { rtc }
+    }
+
+    /// # Safety
+    /// Time and calendar updates must follow the UPDTIM/UPDCAL handshake.
+    pub unsafe fn registers(&self) -> &crate::pac::rtc::RegisterBlock {
+        &self.rtc
+    }
+
+    pub fn free(self) -> RTC {
+        self.rtc
+    }
+
+    /// The registers are read until two reads agree, as they may change
+    /// between the time and calendar reads.
+    pub fn date_time(&self) -> DateTime {
+        let read = || (self.rtc.timr.read().bits(), self.rtc.calr.read().bits());
+        let mut last = read();
+        loop {
+            let current = read();
+            if current == last {
+                break;
+            }
+            last = current;
+        }
+        let (timr, calr) = last;
+        let field = |register: u32, shift: u32, mask: u32| from_bcd((register >> shift & mask) as u8);
+        DateTime {
+            year: field(calr, 0, 0x7F) as u16 * 100 + field(calr, 8, 0xFF) as u16,
+            month: field(calr, 16, 0x1F),
+            day: field(calr, 24, 0x3F),
+            weekday: (calr >> 21 & 0x7) as u8,
+            hour: field(timr, 16, 0x3F),
+            minute: field(timr, 8, 0x7F),
+            second: field(timr, 0, 0x7F),
+        }
+    }
+
+    /// Stops the time and calendar, waits for the RTC to acknowledge, which
+    /// takes up to a second, and restarts them at `time`. Returns `false` if
+    /// the RTC rejected `time` as invalid.
+    pub fn set_date_time(&mut self, time: DateTime) -> bool {
+        self.rtc.cr.modify(|_, w| w.updtim().set_bit().updcal().set_bit());
+        while !self.rtc.sr.read().ackupd().bit_is_set() {}
+        unsafe {
+            self.rtc.sccr.write_with_zero(|w| w.ackclr().set_bit());
+            self.rtc.timr.write_with_zero(|w| {
+                w.hour().bits(to_bcd(time.hour))
+                    .min().bits(to_bcd(time.minute))
+                    .sec().bits(to_bcd(time.second))
+            });
+            self.rtc.calr.write_with_zero(|w| {
+                w.cent().bits(to_bcd((time.year / 100) as u8))
+                    .year().bits(to_bcd((time.year % 100) as u8))
+                    .month().bits(to_bcd(time.month))
+                    .date().bits(to_bcd(time.day))
+                    .day().bits(time.weekday)
+            });
+        }
+        self.rtc.cr.modify(|_, w| w.updtim().clear_bit().updcal().clear_bit());
+        let valid = self.rtc.sr.read().tderr().is_correct();
+        unsafe { self.rtc.sccr.write_with_zero(|w| w.tderrclr().set_bit()) };
+        valid
+    }
+
+    /// Speeds the RTC up by `ppm`, or slows it down if negative, to make up
+    /// for the crystal. Under 2 ppm either way is left uncorrected, and the
+    /// correction saturates at 1953 ppm.
+    pub fn set_correction(&mut self, ppm: i32) {
+        let (correction, high) = correction(ppm.unsigned_abs());
+        self.rtc.mr.modify(|_, w| unsafe {
+            w.correction().bits(correction)
+                .highppm().bit(high)
+                .negppm().bit(ppm < 0)
+        });
+    }
+
+    pub fn set_output(&mut self, output: Output, waveform: Waveform) {
+        // OUT0 and OUT1 share their encoding.
+        let source = match waveform {
+            Waveform::Low => OUT0_A::NO_WAVE,
+            Waveform::Hz1 => OUT0_A::FREQ1HZ,
+            Waveform::Hz32 => OUT0_A::FREQ32HZ,
+            Waveform::Hz64 => OUT0_A::FREQ64HZ,
+            Waveform::Hz512 => OUT0_A::FREQ512HZ,
+            Waveform::AlarmToggle => OUT0_A::ALARM_TOGGLE,
+            Waveform::AlarmFlag => OUT0_A::ALARM_FLAG,
+            Waveform::Pulse(..) => OUT0_A::PROG_PULSE,
+        } as u8;
+        self.rtc.mr.modify(|_, w| {
+            if let Waveform::Pulse(high, period) = waveform {
+                w.thigh().variant(match high {
+                    PulseHigh::Ms31 => THIGH_A::H_31MS,
+                    PulseHigh::Ms16 => THIGH_A::H_16MS,
+                    PulseHigh::Ms4 => THIGH_A::H_4MS,
+                    PulseHigh::Us976 => THIGH_A::H_976US,
+                    PulseHigh::Us488 => THIGH_A::H_488US,
+                    PulseHigh::Us122 => THIGH_A::H_122US,
+                    PulseHigh::Us30 => THIGH_A::H_30US,
+                    PulseHigh::Us15 => THIGH_A::H_15US,
+                })
+                .tperiod().variant(match period {
+                    PulsePeriod::S1 => TPERIOD_A::P_1S,
+                    PulsePeriod::Ms500 => TPERIOD_A::P_500MS,
+                    PulsePeriod::Ms250 => TPERIOD_A::P_250MS,
+                    PulsePeriod::Ms125 => TPERIOD_A::P_125MS,
+                });
+            }
+            match output {
+                Output::Out0 => w.out0().bits(source),
+                Output::Out1 => w.out1().bits(source),
+            }
+        });
+    }
+
+    pub fn set_time_event(&mut self, event: TimeEvent) {
+        self.rtc.cr.modify(|_, w| w.timevsel().variant(match event {
+            TimeEvent::Minute => TIMEVSEL_A::MINUTE,
+            TimeEvent::Hour => TIMEVSEL_A::HOUR,
+            TimeEvent::Midnight => TIMEVSEL_A::MIDNIGHT,
+            TimeEvent::Noon => TIMEVSEL_A::NOON,
+        }));
+    }
+
+    pub fn set_calendar_event(&mut self, event: CalendarEvent) {
+        self.rtc.cr.modify(|_, w| w.calevsel().variant(match event {
+            CalendarEvent::Week => CALEVSEL_A::WEEK,
+            CalendarEvent::Month => CALEVSEL_A::MONTH,
+            CalendarEvent::Year => CALEVSEL_A::YEAR,
+        }));
+    }
+
+    pub fn listen_time_event(&mut self) {
+        unsafe { self.rtc.ier.write_with_zero(|w| w.timen().set_bit()) };
+    }
+
+    pub fn unlisten_time_event(&mut self) {
+        unsafe { self.rtc.idr.write_with_zero(|w| w.timdis().set_bit()) };
+    }
+
+    pub fn listen_calendar_event(&mut self) {
+        unsafe { self.rtc.ier.write_with_zero(|w| w.calen().set_bit()) };
+    }
+
+    pub fn unlisten_calendar_event(&mut self) {
+        unsafe { self.rtc.idr.write_with_zero(|w| w.caldis().set_bit()) };
+    }
+
+    pub fn read_status(&self) -> Status {
+        let sr = self.rtc.sr.read();
+        Status {
+            alarm: sr.alarm().bit_is_set(),
+            second: sr.sec().bit_is_set(),
+            time_event: sr.timev().bit_is_set(),
+            calendar_event: sr.calev().bit_is_set(),
+        }
+    }
+
+    pub fn clear_status(&mut self) {
+        unsafe {
+            self.rtc.sccr.write_with_zero(|w| {
+                w.alrclr().set_bit()
+                    .secclr().set_bit()
+                    .timclr().set_bit()
+                    .calclr().set_bit()
+            })
+        };
+    }
+}
+
+/// CORRECTION and HIGHPPM values for `ppm`. The fine range steps by about
+/// 1.5 ppm and reaches 97 ppm, the coarse one covers up to 1953 ppm.
+fn correction(ppm: u32) -> (u8, bool) {
+    match ppm {
+        0..=1 => (0, false),
+        2..=97 => (((3906 + 10 * ppm) / (20 * ppm) - 1) as u8, false),
+        _ => {
+            let ppm = ppm.min(1953);
+            (((3906 + ppm / 2) / ppm - 1) as u8, true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{correction, from_bcd, to_bcd};
+
+    #[test]
+    fn bcd_round_trips() {
+        assert_eq!(to_bcd(59), 0x59);
+        assert_eq!(from_bcd(0x23), 23);
+        assert!((0..100).all(|value| from_bcd(to_bcd(value)) == value));
+    }
+
+    #[test]
+    fn correction_ranges() {
+        assert_eq!(correction(1), (0, false));
+        // 3906 / (20 * (97 + 1)) is 1.99 ppm.
+        assert_eq!(correction(2), (97, false));
+        assert_eq!(correction(97), (1, false));
+        // 3906 / (19 + 1) is 195 ppm.
+        assert_eq!(correction(195), (19, true));
+        assert_eq!(correction(5000), (1, true));
+    }
+}
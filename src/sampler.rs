@@ -0,0 +1,142 @@
+//! Logic analyzer for field debugging: samples pins of a PIO port at a fixed
+//! rate from a TC interrupt.
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn TC0() {
+//!     cortex_m::interrupt::free(|cs| {
+//!         if let Some(sampler) = SAMPLER.borrow(cs).borrow_mut().as_mut() {
+//!             sampler.on_interrupt();
+//!         }
+//!     });
+//! }
+//! ```
+
+use embedded_hal::timer::CountDown;
+use crate::clocks::Clocks;
+use crate::gpio::port_registers;
+use crate::tc::{self, Instance, Timer};
+use crate::time::Microseconds;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// Starts with the first sample.
+    Immediate,
+    /// Starts once the masked pins read `value`.
+    Pattern { mask: u32, value: u32 },
+    /// Starts on the first change of any of the masked pins.
+    Change { mask: u32 },
+}
+
+struct Capture<const N: usize> {
+    trigger: Trigger,
+    previous: Option<u32>,
+    triggered: bool,
+    samples: [u32; N],
+    len: usize,
+}
+
+impl<const N: usize> Capture<N> {
+    fn new(trigger: Trigger) -> Self {
+        Capture { trigger, previous: None, triggered: false, samples: [0; N], len: 0 }
+    }
+
+    /// Records `sample` once triggered. Returns whether the buffer is full.
+    fn push(&mut self, sample: u32) -> bool {
+        if !self.triggered {
+            self.triggered = match self.trigger {
+                Trigger::Immediate => true,
+                Trigger::Pattern { mask, value } => sample & mask == value & mask,
+                Trigger::Change { mask } => self.previous.is_some_and(|previous| (previous ^ sample) & mask != 0),
+            };
+            self.previous = Some(sample);
+        }
+        if self.triggered && self.len < N {
+            self.samples[self.len] = sample;
+            self.len += 1;
+        }
+        self.len == N
+    }
+}
+
+/// Samples the pins in `mask` of port `P` into a buffer of `N` samples. The
+/// sampling instant moves with the interrupt latency, so rates much above a
+/// few hundred kHz are not meaningful.
+pub struct PinSampler<const P: char, TC, const CH: u8, const N: usize> {
+    timer: Timer<TC, CH>,
+    mask: u32,
+    capture: Capture<N>,
+}
+
+impl<const P: char, TC: Instance, const CH: u8, const N: usize> PinSampler<P, TC, CH, N> {
+    pub fn new(channel: tc::Channel<TC, CH>, mask: u32, clocks: &Clocks) -> Self {
+        PinSampler {
+            timer: channel.into_timer(clocks),
+            mask,
+            capture: Capture::new(Trigger::Immediate),
+        }
+    }
+
+    /// Clears the buffer and samples every `period`, recording from the
+    /// `trigger` condition on.
+    pub fn arm(&mut self, trigger: Trigger, period: Microseconds) {
+        self.capture = Capture::new(trigger);
+        self.timer.start(period);
+        self.timer.listen();
+    }
+
+    /// Takes a sample. To be called from the interrupt of the TC channel.
+    pub fn on_interrupt(&mut self) {
+        if self.timer.wait().is_err() {
+            return;
+        }
+        let sample = port_registers(P).pdsr.read().bits() & self.mask;
+        if self.capture.push(sample) {
+            self.timer.unlisten();
+        }
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.capture.triggered
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.capture.len == N
+    }
+
+    /// Samples recorded so far, oldest first.
+    pub fn samples(&self) -> &[u32] {
+        &self.capture.samples[..self.capture.len]
+    }
+
+    pub fn free(self) -> tc::Channel<TC, CH> {
+        self.timer.free()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn change_trigger() {
+        let mut capture = Capture::<3>::new(Trigger::Change { mask: 0b10 });
+        assert!(!capture.push(0b00));
+        assert!(!capture.push(0b01));
+        assert!(!capture.triggered);
+        assert!(!capture.push(0b11));
+        assert!(!capture.push(0b01));
+        assert!(capture.push(0b00));
+        assert!(capture.push(0b10));
+        assert_eq!(capture.samples, [0b11, 0b01, 0b00]);
+    }
+
+    #[test]
+    fn pattern_trigger() {
+        let mut capture = Capture::<2>::new(Trigger::Pattern { mask: 0b11, value: 0b01 });
+        assert!(!capture.push(0b100));
+        assert!(!capture.push(0b101));
+        assert!(capture.push(0b000));
+        assert_eq!(capture.samples, [0b101, 0b000]);
+    }
+}
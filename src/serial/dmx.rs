@@ -0,0 +1,65 @@
+//! DMX512 transmitter. Each packet is a break, a mark after break and the
+//! start code followed by the 512 slots of the universe, at 250 kbps 8N2.
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::serial::Write;
+use super::usart::{Serial, TxPin};
+use crate::pac::{USART0, USART1, USART2};
+use crate::write_protect::WriteProtect;
+
+pub const BAUD_RATE: u32 = 250_000;
+pub const SLOTS: usize = 512;
+/// Null start code, for dimmer levels.
+const START_CODE: u8 = 0;
+/// Break and mark after break, in µs, with some margin over the 92 µs and
+/// 12 µs minimum of a transmitter.
+const BREAK_US: u32 = 100;
+const MARK_AFTER_BREAK_US: u32 = 12;
+
+pub struct DmxTransmitter<USART, TXPIN, RXPIN, D> {
+    serial: Serial<USART, TXPIN, RXPIN>,
+    delay: D,
+}
+
+macro_rules! dmx {
+    ($($USART:ident,)+) => {
+        $(
+            impl<TXPIN: TxPin<$USART>, RXPIN, D: DelayUs<u32>> DmxTransmitter<$USART, TXPIN, RXPIN, D> {
+                /// `serial` must run at `BAUD_RATE` with 8 bit characters and
+                /// no parity; the second stop bit is set here. `delay` times
+                /// the break.
+                pub fn new(serial: Serial<$USART, TXPIN, RXPIN>, delay: D) -> Self {
+                    unsafe { serial.registers() }.with_unlocked(|usart| usart.mr().modify(|_, w| w.nbstop()._2_bit()));
+                    DmxTransmitter { serial, delay }
+                }
+
+                pub fn free(self) -> (Serial<$USART, TXPIN, RXPIN>, D) {
+                    unsafe { self.serial.registers() }.with_unlocked(|usart| usart.mr().modify(|_, w| w.nbstop()._1_bit()));
+                    (self.serial, self.delay)
+                }
+
+                /// Sends one packet and blocks until its last slot is out,
+                /// about 23 ms.
+                pub fn send(&mut self, universe: &[u8; SLOTS]) {
+                    let usart = unsafe { &*$USART::ptr() };
+                    while !usart.csr().read().txempty().bit() {}
+                    self.serial.send_break();
+                    self.delay.delay_us(BREAK_US);
+                    self.serial.stop_break();
+                    self.delay.delay_us(MARK_AFTER_BREAK_US);
+
+                    for &slot in core::iter::once(&START_CODE).chain(universe.iter()) {
+                        let _ = nb::block!(self.serial.write(slot as u16));
+                    }
+                    while !usart.csr().read().txempty().bit() {}
+                }
+            }
+        )+
+    }
+}
+
+dmx! {
+    USART0,
+    USART1,
+    USART2,
+}
@@ -0,0 +1,138 @@
+//! Assembles received bytes into lines, e.g. NMEA sentences from a GPS.
+//!
+//! Bytes come either from a serial port with `read`, or from anywhere else,
+//! such as a DMA ring, with `push`:
+//!
+//! ```ignore
+//! let mut lines = LineReader::<82>::new().nmea();
+//! let count = ring.read(&mut chunk);
+//! for &byte in &chunk[..count] {
+//!     if let Some(Ok(sentence)) = lines.push(byte) {
+//!         parse(sentence);
+//!     }
+//! }
+//! ```
+
+use embedded_hal::serial::Read;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineError<E> {
+    Serial(E),
+    /// The line did not fit in the buffer and was dropped.
+    Overflow,
+    /// Missing or wrong NMEA checksum.
+    Checksum,
+}
+
+/// Lines end with `\n`, and an optional `\r` before it. Neither is part of
+/// the lines returned, and empty lines are skipped.
+pub struct LineReader<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+    overflow: bool,
+    nmea: bool,
+}
+
+impl<const N: usize> LineReader<N> {
+    pub const fn new() -> Self {
+        LineReader { buffer: [0; N], len: 0, overflow: false, nmea: false }
+    }
+
+    /// Only passes lines with a valid NMEA checksum: `$` or `!`, the
+    /// sentence, `*` and the XOR of the sentence bytes in hexadecimal.
+    pub fn nmea(mut self) -> Self {
+        self.nmea = true;
+        self
+    }
+
+    /// Adds `byte`, returning the line it completes.
+    pub fn push<E>(&mut self, byte: u8) -> Option<Result<&[u8], LineError<E>>> {
+        let line = self.end_of_line(byte)?;
+        let buffer = &self.buffer;
+        Some(line.map(move |len| &buffer[..len]))
+    }
+
+    /// Reads from `rx` until a line is complete.
+    pub fn read<W, R>(&mut self, rx: &mut R) -> nb::Result<&[u8], LineError<R::Error>>
+    where
+        W: Into<u16>,
+        R: Read<W>,
+    {
+        loop {
+            let byte = rx.read().map_err(|e| e.map(LineError::Serial))?.into() as u8;
+            if let Some(line) = self.end_of_line(byte) {
+                let len = line.map_err(nb::Error::Other)?;
+                return Ok(&self.buffer[..len]);
+            }
+        }
+    }
+
+    /// Length of the line completed by `byte`, if any.
+    fn end_of_line<E>(&mut self, byte: u8) -> Option<Result<usize, LineError<E>>> {
+        if byte != b'\n' {
+            match self.buffer.get_mut(self.len) {
+                Some(slot) if !self.overflow => {
+                    *slot = byte;
+                    self.len += 1;
+                }
+                _ => self.overflow = true,
+            }
+            return None;
+        }
+
+        let len = core::mem::take(&mut self.len);
+        if core::mem::take(&mut self.overflow) {
+            return Some(Err(LineError::Overflow));
+        }
+        let line = self.buffer[..len].strip_suffix(b"\r").unwrap_or(&self.buffer[..len]);
+        if line.is_empty() {
+            return None;
+        }
+        if self.nmea && !nmea_checksum_valid(line) {
+            return Some(Err(LineError::Checksum));
+        }
+        Some(Ok(line.len()))
+    }
+}
+
+impl<const N: usize> Default for LineReader<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn nmea_checksum_valid(line: &[u8]) -> bool {
+    let (sentence, checksum) = match line {
+        [b'$' | b'!', rest @ ..] if rest.len() >= 3 && rest[rest.len() - 3] == b'*' => rest.split_at(rest.len() - 3),
+        _ => return false,
+    };
+    let expected = core::str::from_utf8(&checksum[1..])
+        .ok()
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    expected == Some(sentence.iter().fold(0, |sum, byte| sum ^ byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed<const N: usize>(reader: &mut LineReader<N>, bytes: &[u8]) -> Option<Result<usize, LineError<()>>> {
+        bytes.iter().fold(None, |_, &byte| reader.end_of_line(byte))
+    }
+
+    #[test]
+    fn nmea_sentences() {
+        let mut reader = LineReader::<82>::new().nmea();
+        let sentence = b"$GPGLL,4916.45,N,12311.12,W,225444,A,*1D\r\n";
+        assert_eq!(feed(&mut reader, sentence), Some(Ok(sentence.len() - 2)));
+        assert_eq!(feed(&mut reader, b"$GPGLL,4916.45,N,12311.12,W,225444,A,*1E\r\n"), Some(Err(LineError::Checksum)));
+        assert_eq!(feed(&mut reader, b"\r\n"), None);
+    }
+
+    #[test]
+    fn overflow_drops_line() {
+        let mut reader = LineReader::<4>::new();
+        assert_eq!(feed(&mut reader, b"too long\n"), Some(Err(LineError::Overflow)));
+        assert_eq!(feed(&mut reader, b"ok\r\n"), Some(Ok(2)));
+    }
+}
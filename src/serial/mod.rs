@@ -1,10 +1,136 @@
+use embedded_hal::serial::Write;
+use embedded_hal::timer::CountDown;
+use embedded_hal_nb::serial;
+
+/// Counts the error flags of `$status`, a status register read just before
+/// the errors are cleared.
+macro_rules! count_errors {
+    ($SERIAL:ty, $status:expr) => {
+        #[cfg(feature = "serial-stats")]
+        {
+            let status = &$status;
+            <$SERIAL as crate::serial::stats::Instance>::counters()
+                .record(status.ovre().bit(), status.frame().bit(), status.pare().bit());
+        }
+    };
+}
+
+#[cfg(feature = "modbus")]
+pub mod modbus;
+#[cfg(feature = "serial-stats")]
+pub mod stats;
+pub mod dmx;
+pub mod line_reader;
+pub mod sbus;
 pub mod uart;
 pub mod usart;
 
-pub struct BaudRate(pub u32);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SerialError {
+    Parity,
+    Framing,
+    Overrun,
+    /// A 9-bit character was read as `u8`, or a word to write is wider
+    /// than the characters.
+    Truncated,
+    /// USART in SPI slave mode: the master clocked out a character before
+    /// one was written.
+    Underrun,
+}
+
+impl serial::Error for SerialError {
+    fn kind(&self) -> serial::ErrorKind {
+        match self {
+            SerialError::Parity => serial::ErrorKind::Parity,
+            SerialError::Framing => serial::ErrorKind::FrameFormat,
+            SerialError::Overrun => serial::ErrorKind::Overrun,
+            SerialError::Truncated => serial::ErrorKind::Other,
+            SerialError::Underrun => serial::ErrorKind::Other,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlushError<E> {
+    Timeout,
+    Serial(E),
+}
+
+/// A baud rate the divider cannot reach from its clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BaudRateError {
+    /// Faster than the clock allows with a divisor of 1.
+    TooFast,
+    /// Slower than the largest divisor allows, or zero.
+    TooSlow,
+}
+
+/// `flush` of every UART and USART writer, `Tx` halves included, only
+/// succeeds once TXEMPTY is set: the last stop bit is on the line, not just
+/// the holding register free. `BufferedSerial` also waits for its software
+/// queue to drain. So a flush before switching an RS-485 driver or the
+/// baud rate loses nothing.
+pub trait FlushBlocking<Word>: Write<Word> {
+    /// Blocks until the transmitter is empty or `timeout` expires. The timer
+    /// must already be started with the desired period.
+    fn flush_blocking<T>(&mut self, timeout: &mut T) -> Result<(), FlushError<Self::Error>>
+    where
+        T: CountDown,
+    {
+        loop {
+            match self.flush() {
+                Ok(()) => return Ok(()),
+                Err(nb::Error::Other(e)) => return Err(FlushError::Serial(e)),
+                Err(nb::Error::WouldBlock) => {}
+            }
+            if timeout.wait().is_ok() {
+                return Err(FlushError::Timeout);
+            }
+        }
+    }
+}
+
+impl<S, Word> FlushBlocking<Word> for S where S: Write<Word> {}
+
+#[cfg(test)]
+mod tests {
+    struct Draining(u32);
+
+    impl super::Write<u8> for Draining {
+        type Error = ();
+
+        fn write(&mut self, _: u8) -> nb::Result<(), ()> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), ()> {
+            match self.0.checked_sub(1) {
+                Some(left) => {
+                    self.0 = left;
+                    Err(nb::Error::WouldBlock)
+                }
+                None => Ok(()),
+            }
+        }
+    }
+
+    struct Ticks(u32);
+
+    impl super::CountDown for Ticks {
+        type Time = ();
+
+        fn start<T: Into<()>>(&mut self, _: T) {}
+
+        fn wait(&mut self) -> nb::Result<(), void::Void> {
+            self.0 = self.0.saturating_sub(1);
+            if self.0 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+        }
+    }
 
-impl Into<BaudRate> for u32 {
-    fn into(self) -> BaudRate {
-        BaudRate(self)
+    #[test]
+    fn flush_blocking_times_out() {
+        use super::{FlushBlocking, FlushError};
+        assert_eq!(Draining(3).flush_blocking(&mut Ticks(5)), Ok(()));
+        assert_eq!(Draining(10).flush_blocking(&mut Ticks(5)), Err(FlushError::Timeout));
     }
 }
@@ -0,0 +1,162 @@
+//! Modbus RTU transport: frames are delimited by 3.5 characters of silence,
+//! detected with the USART receiver time-out, and the RS-485 driver enable
+//! is the RTS pin, driven by the USART itself. Function codes and register
+//! maps are left to a protocol crate working on the frames.
+
+use core::convert::TryFrom;
+use embedded_hal::serial::Write;
+use super::usart::{Serial, TxPin};
+use crate::crc::crc16_modbus;
+use super::SerialError;
+use crate::pac::{USART0, USART1, USART2};
+use crate::time::Bps;
+use crate::write_protect::WriteProtect;
+
+/// Largest RTU frame, CRC included.
+pub const MAX_FRAME: usize = 256;
+/// Start, 8 data, parity or second stop, stop.
+const CHARACTER_BITS: u32 = 11;
+/// Above this baud rate the silence is a fixed 1.75 ms instead of 3.5
+/// characters.
+const FIXED_SILENCE_BAUD: u32 = 19_200;
+const FIXED_SILENCE_US: u64 = 1_750;
+
+/// Receiver time-out, in bit periods, marking the end of a frame.
+fn silence_bits(baud: Bps) -> u32 {
+    if baud.0 > FIXED_SILENCE_BAUD {
+        (FIXED_SILENCE_US * baud.0 as u64).div_ceil(1_000_000) as u32
+    } else {
+        (7 * CHARACTER_BITS).div_ceil(2)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModbusError {
+    Serial(SerialError),
+    /// More characters than fit in a frame.
+    Overflow,
+    /// Shorter than an address, a function code and the CRC.
+    Truncated,
+    Crc,
+}
+
+pub struct Modbus<USART, TXPIN, RXPIN> {
+    serial: Serial<USART, TXPIN, RXPIN>,
+    mode: u32,
+    frame: [u8; MAX_FRAME],
+    len: usize,
+    error: Option<ModbusError>,
+}
+
+macro_rules! modbus {
+    ($($USART:ident,)+) => {
+        $(
+            impl<TXPIN: TxPin<$USART>, RXPIN> Modbus<$USART, TXPIN, RXPIN> {
+                /// Switches `serial`, already set up at `baud`, to RS-485
+                /// mode. Its RTS pin must be muxed to the USART to drive the
+                /// transceiver.
+                pub fn new(serial: Serial<$USART, TXPIN, RXPIN>, baud: Bps) -> Self {
+                    let usart = unsafe { serial.registers() };
+                    let mode = usart.mr().read().bits();
+                    usart.with_unlocked(|usart| unsafe {
+                        usart.mr().modify(|_, w| w.usart_mode().rs485());
+                        usart.rtor.write(|w| w.to().bits(silence_bits(baud)));
+                    });
+                    unsafe { usart.cr().write_with_zero(|w| w.rststa().set_bit().sttto().set_bit()) };
+                    Modbus { serial, mode, frame: [0; MAX_FRAME], len: 0, error: None }
+                }
+
+                pub fn free(self) -> Serial<$USART, TXPIN, RXPIN> {
+                    let usart = unsafe { self.serial.registers() };
+                    usart.with_unlocked(|usart| unsafe {
+                        usart.rtor.write(|w| w.to().bits(0));
+                        usart.mr().write(|w| w.bits(self.mode));
+                    });
+                    self.serial
+                }
+
+                /// Sends `frame`, address to data, followed by its CRC, and
+                /// blocks until the last character is out so the driver is
+                /// released in time for the reply.
+                pub fn send_frame(&mut self, frame: &[u8]) {
+                    assert!(frame.len() <= MAX_FRAME - 2, "Modbus frame too long");
+                    let crc = crc16_modbus(frame).to_le_bytes();
+                    for &byte in frame.iter().chain(crc.iter()) {
+                        let _ = nb::block!(self.serial.write(byte as u16));
+                    }
+                    let usart = unsafe { self.serial.registers() };
+                    while !usart.csr().read().txempty().bit() {}
+                }
+
+                /// Collects received characters and, once the line has been
+                /// silent, returns the frame with its CRC checked and
+                /// stripped. Must run at least once per character time, e.g.
+                /// from the USART interrupt with RXRDY and TIMEOUT enabled.
+                pub fn recv_frame(&mut self) -> nb::Result<&[u8], ModbusError> {
+                    let usart = unsafe { self.serial.registers() };
+                    let status = usart.csr().read();
+                    if status.ovre().bit() || status.frame().bit() || status.pare().bit() {
+                        self.error.get_or_insert(ModbusError::Serial(if status.ovre().bit() {
+                            SerialError::Overrun
+                        } else if status.frame().bit() {
+                            SerialError::Framing
+                        } else {
+                            SerialError::Parity
+                        }));
+                        count_errors!($USART, status);
+                        unsafe { usart.cr().write_with_zero(|w| w.rststa().set_bit()) };
+                    }
+                    if status.rxrdy().bit() {
+                        // A ninth bit means the USART is not set up for RTU.
+                        match (u8::try_from(usart.rhr.read().rxchr().bits()), self.frame.get_mut(self.len)) {
+                            (Err(_), _) => {
+                                self.error.get_or_insert(ModbusError::Serial(SerialError::Truncated));
+                            }
+                            (Ok(data), Some(slot)) => {
+                                *slot = data;
+                                self.len += 1;
+                            }
+                            (Ok(_), None) => {
+                                self.error.get_or_insert(ModbusError::Overflow);
+                            }
+                        }
+                    }
+                    if !status.timeout().bit() {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    // Counts again only from the next character.
+                    unsafe { usart.cr().write_with_zero(|w| w.sttto().set_bit()) };
+                    let len = core::mem::take(&mut self.len);
+                    if let Some(error) = self.error.take() {
+                        return Err(nb::Error::Other(error));
+                    }
+                    if len < 4 {
+                        return Err(nb::Error::Other(ModbusError::Truncated));
+                    }
+                    if crc16_modbus(&self.frame[..len]) != 0 {
+                        return Err(nb::Error::Other(ModbusError::Crc));
+                    }
+                    Ok(&self.frame[..len - 2])
+                }
+            }
+        )+
+    }
+}
+
+modbus! {
+    USART0,
+    USART1,
+    USART2,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silence() {
+        assert_eq!(silence_bits(Bps(9_600)), 39);
+        assert_eq!(silence_bits(Bps(115_200)), 202);
+    }
+}
@@ -0,0 +1,196 @@
+//! SBUS receiver, as found on RC receivers for drones and robots: inverted
+//! 100 kbps 8E2 frames of 16 proportional channels, received through a DMA
+//! ring so none is lost while the application is busy.
+//!
+//! The USART is set up by the caller:
+//!
+//! ```ignore
+//! let config = usart::Config::new(Bps(sbus::BAUD_RATE), Parity::Even, ChannelMode::Normal,
+//!     CharLength::EightBit, SyncMode::Async, UsartMode::Normal)
+//!     .invert_data(true)
+//!     .two_stop_bits(true);
+//! ```
+
+use embedded_dma::WriteBuffer;
+use super::usart::Serial;
+use crate::dma::peripheral::{peripheral_to_ring, Circular, Usart0Rx, Usart1Rx, Usart2Rx};
+use crate::dma::Channel;
+use crate::pac::{USART0, USART1, USART2};
+
+pub const BAUD_RATE: u32 = 100_000;
+const FRAME: usize = 25;
+const HEADER: u8 = 0x0F;
+const FOOTER: u8 = 0x00;
+const FLAG_CH17: u8 = 1 << 0;
+const FLAG_CH18: u8 = 1 << 1;
+const FLAG_FRAME_LOST: u8 = 1 << 2;
+const FLAG_FAILSAFE: u8 = 1 << 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// 11-bit channel values, 172 to 1811 over the usual stick travel.
+    pub channels: [u16; 16],
+    pub ch17: bool,
+    pub ch18: bool,
+    /// The receiver missed this frame and repeats the last one.
+    pub frame_lost: bool,
+    /// The receiver lost the transmitter; channels hold failsafe values.
+    pub failsafe: bool,
+}
+
+impl Frame {
+    fn decode(bytes: &[u8; FRAME]) -> Frame {
+        let mut channels = [0; 16];
+        for (i, channel) in channels.iter_mut().enumerate() {
+            let bit = i * 11;
+            let byte = 1 + bit / 8;
+            let bits = bytes[byte] as u32 | (bytes[byte + 1] as u32) << 8 | (bytes[byte + 2] as u32) << 16;
+            *channel = (bits >> (bit % 8)) as u16 & 0x7FF;
+        }
+        let flags = bytes[23];
+        Frame {
+            channels,
+            ch17: flags & FLAG_CH17 != 0,
+            ch18: flags & FLAG_CH18 != 0,
+            frame_lost: flags & FLAG_FRAME_LOST != 0,
+            failsafe: flags & FLAG_FAILSAFE != 0,
+        }
+    }
+}
+
+/// Finds frames in a byte stream, resynchronizing on the header when a
+/// frame does not end with the footer.
+struct Parser {
+    bytes: [u8; FRAME],
+    len: usize,
+}
+
+impl Parser {
+    const fn new() -> Self {
+        Parser { bytes: [0; FRAME], len: 0 }
+    }
+
+    fn push(&mut self, byte: u8) -> Option<Frame> {
+        if self.len == 0 && byte != HEADER {
+            return None;
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        if self.len < FRAME {
+            return None;
+        }
+        if byte == FOOTER {
+            self.len = 0;
+            return Some(Frame::decode(&self.bytes));
+        }
+        // Starts over from the next header candidate.
+        match self.bytes[1..].iter().position(|&byte| byte == HEADER) {
+            Some(offset) => {
+                self.bytes.copy_within(offset + 1.., 0);
+                self.len = FRAME - 1 - offset;
+            }
+            None => self.len = 0,
+        }
+        None
+    }
+}
+
+pub struct Sbus<USART, TXPIN, RXPIN, const CH: u8, S, B> {
+    serial: Serial<USART, TXPIN, RXPIN>,
+    ring: Circular<CH, S, B>,
+    parser: Parser,
+    latest: Option<Frame>,
+}
+
+macro_rules! sbus {
+    ($($USART:ident: $Rx:ident,)+) => {
+        $(
+            impl<TXPIN, RXPIN, const CH: u8, B> Sbus<$USART, TXPIN, RXPIN, CH, $Rx, B>
+            where
+                B: WriteBuffer<Word = u8>,
+            {
+                /// `buffer` should hold a few frames, and be polled before
+                /// the DMA wraps around it.
+                pub fn new(serial: Serial<$USART, TXPIN, RXPIN>, channel: Channel<CH>, buffer: B) -> Self {
+                    Sbus {
+                        serial,
+                        ring: peripheral_to_ring(channel, $Rx, buffer),
+                        parser: Parser::new(),
+                        latest: None,
+                    }
+                }
+
+                pub fn free(self) -> (Serial<$USART, TXPIN, RXPIN>, Channel<CH>, B) {
+                    let (channel, _, buffer) = self.ring.stop();
+                    (self.serial, channel, buffer)
+                }
+            }
+        )+
+    }
+}
+
+sbus! {
+    USART0: Usart0Rx,
+    USART1: Usart1Rx,
+    USART2: Usart2Rx,
+}
+
+impl<USART, TXPIN, RXPIN, const CH: u8, S, B> Sbus<USART, TXPIN, RXPIN, CH, S, B> {
+    /// Parses what arrived since the last call and returns the newest
+    /// complete frame, if any.
+    pub fn poll(&mut self) -> Option<Frame> {
+        let mut bytes = [0; FRAME];
+        let mut frame = None;
+        loop {
+            let count = self.ring.read(&mut bytes);
+            if count == 0 {
+                break;
+            }
+            for &byte in &bytes[..count] {
+                frame = self.parser.push(byte).or(frame);
+            }
+        }
+        if frame.is_some() {
+            self.latest = frame;
+        }
+        frame
+    }
+
+    /// Last frame returned by `poll`.
+    pub fn latest(&self) -> Option<Frame> {
+        self.latest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(channels: &[u16; 16], flags: u8) -> [u8; FRAME] {
+        let mut bytes = [0; FRAME];
+        bytes[0] = HEADER;
+        for (i, &channel) in channels.iter().enumerate() {
+            for bit in 0..11 {
+                if channel & (1 << bit) != 0 {
+                    let position = i * 11 + bit;
+                    bytes[1 + position / 8] |= 1 << (position % 8);
+                }
+            }
+        }
+        bytes[23] = flags;
+        bytes
+    }
+
+    #[test]
+    fn decodes_after_garbage() {
+        let channels = core::array::from_fn(|i| 172 + 100 * i as u16);
+        let frame = encode(&channels, FLAG_FAILSAFE | FLAG_CH18);
+        let mut parser = Parser::new();
+        let stream = [0x12, HEADER, 0x34].iter().chain(frame.iter());
+        let mut frames = stream.filter_map(|&byte| parser.push(byte));
+        let decoded = frames.next().unwrap();
+        assert!(frames.next().is_none());
+        assert_eq!(decoded.channels, channels);
+        assert!(decoded.failsafe && decoded.ch18 && !decoded.ch17 && !decoded.frame_lost);
+    }
+}
@@ -0,0 +1,90 @@
+use core::cell::Cell;
+use cortex_m::interrupt::Mutex;
+use crate::cycles::Instant;
+
+/// Receive errors seen by a UART or USART since reset. Errors latch in the
+/// status register until cleared, so each one is counted once, when the
+/// driver clears it: in `clear_status`, or in the interrupt handlers of the
+/// buffered, timestamped and Modbus ports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ErrorStats {
+    pub overrun: u32,
+    pub framing: u32,
+    pub parity: u32,
+    /// Cycle counter when the last error was counted. Only meaningful with
+    /// the counter running, see `cycles::Cycles::new`.
+    pub last_error: Option<Instant>,
+}
+
+impl ErrorStats {
+    pub fn total(&self) -> u32 {
+        self.overrun.saturating_add(self.framing).saturating_add(self.parity)
+    }
+}
+
+pub(crate) struct Counters(Mutex<Cell<ErrorStats>>);
+
+impl Counters {
+    pub(crate) const fn new() -> Self {
+        Counters(Mutex::new(Cell::new(ErrorStats {
+            overrun: 0,
+            framing: 0,
+            parity: 0,
+            last_error: None,
+        })))
+    }
+
+    /// Counts the error flags of a status register about to be cleared.
+    pub(crate) fn record(&self, overrun: bool, framing: bool, parity: bool) {
+        if !(overrun || framing || parity) {
+            return;
+        }
+        let now = Instant::now();
+        cortex_m::interrupt::free(|cs| {
+            let cell = self.0.borrow(cs);
+            let mut stats = cell.get();
+            stats.overrun = stats.overrun.wrapping_add(overrun as u32);
+            stats.framing = stats.framing.wrapping_add(framing as u32);
+            stats.parity = stats.parity.wrapping_add(parity as u32);
+            stats.last_error = Some(now);
+            cell.set(stats);
+        });
+    }
+
+    pub(crate) fn get(&self) -> ErrorStats {
+        cortex_m::interrupt::free(|cs| self.0.borrow(cs).get())
+    }
+
+    pub(crate) fn reset(&self) {
+        cortex_m::interrupt::free(|cs| self.0.borrow(cs).set(ErrorStats::default()));
+    }
+}
+
+/// Serial peripherals with error counters.
+pub(crate) trait Instance {
+    fn counters() -> &'static Counters;
+}
+
+macro_rules! stats_instance {
+    ($($SERIAL:ident,)+) => {
+        $(
+            impl Instance for crate::pac::$SERIAL {
+                fn counters() -> &'static Counters {
+                    static COUNTERS: Counters = Counters::new();
+                    &COUNTERS
+                }
+            }
+        )+
+    }
+}
+
+stats_instance! {
+    UART0,
+    UART1,
+    UART2,
+    UART3,
+    UART4,
+    USART0,
+    USART1,
+    USART2,
+}
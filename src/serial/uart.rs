@@ -1,9 +1,18 @@
 use embedded_hal::serial::{Read, Write};
-use core::convert::Infallible;
 use core::marker::PhantomData;
-use crate::gpio::*;
-use crate::serial::BaudRate;
+use crate::gpio::{pioa::*, piod::*, Alternate, AF0, AF1, AF2, AF3};
+use crate::serial::{BaudRateError, SerialError};
+#[cfg(feature = "serial-stats")]
+use crate::serial::stats::{ErrorStats, Instance as _};
+use crate::time::{Bps, Hertz};
+use crate::clocks::Clocks;
 use crate::pac::PMC;
+use crate::pmc::{LowPowerExt, PckSource, ProgrammableClockExt, SleepwalkingExt};
+use crate::write_protect::WriteProtect;
+
+const SLOW_CLOCK: u32 = 32_768;
+/// Programmable clock the UARTs can take their baud rate from.
+const UART_PCK: u8 = 4;
 
 pub enum Parity {
     Even,
@@ -13,17 +22,41 @@ pub enum Parity {
     NoParity
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ChannelMode {
     Normal,
+    /// Every character received on RXD is sent back on TXD while still
+    /// reaching the receiver. The transmitter is cut off from TXD.
     Automatic,
+    /// The transmitter feeds the receiver internally and TXD stays high.
     LocalLoopback,
+    /// RXD is wired to TXD, with the receiver and transmitter cut off.
     RemoteLoopback
 }
 
-pub enum UartError {
-    Parity,
-    Framing,
-    Overrun,
+pub enum Event {
+    RxReady,
+    TxReady,
+    TxEmpty,
+    CharacterMatch,
+}
+
+pub enum ComparisonMode {
+    FlagOnly,
+    StartCondition,
+}
+
+pub struct Comparison {
+    val1: u8,
+    val2: u8,
+    mode: ComparisonMode,
+    check_parity: bool,
+}
+
+impl Comparison {
+    pub fn new(val1: u8, val2: u8, mode: ComparisonMode, check_parity: bool) -> Comparison {
+        Comparison { val1, val2, mode, check_parity }
+    }
 }
 
 pub trait RxPin<UART> {}
@@ -31,15 +64,15 @@ pub trait TxPin<UART> {}
 
 macro_rules! uart_pins {
     ($($UART:ident => {
-        tx => [$($tx:ty),+ $(,)*],
-        rx => [$($rx:ty),+ $(,)*],
+        tx => [$($tx:ident: $txaf:ident),+ $(,)*],
+        rx => [$($rx:ident: $rxaf:ident),+ $(,)*],
     })+) => {
         $(
             $(
-                impl TxPin<crate::pac::$UART> for $tx {}
+                impl TxPin<crate::pac::$UART> for $tx<Alternate<$txaf>> {}
             )+
             $(
-                impl RxPin<crate::pac::$UART> for $rx {}
+                impl RxPin<crate::pac::$UART> for $rx<Alternate<$rxaf>> {}
             )+
         )+
     }
@@ -47,24 +80,24 @@ macro_rules! uart_pins {
 
 uart_pins! {
     UART0 => {
-        tx => [pioa::PA10<Alternate<AF0>>],
-        rx => [pioa::PA9<Alternate<AF0>>],
+        tx => [PA10: AF0],
+        rx => [PA9: AF0],
     }
     UART1 => {
-        tx => [pioa::PA4<Alternate<AF2>>, pioa::PA6<Alternate<AF2>>, piod::PD26<Alternate<AF3>>],
-        rx => [pioa::PA5<Alternate<AF2>>],
+        tx => [PA4: AF2, PA6: AF2, PD26: AF3],
+        rx => [PA5: AF2],
     }
     UART2 => {
-        tx => [piod::PD26<Alternate<AF2>>],
-        rx => [piod::PD25<Alternate<AF2>>],
+        tx => [PD26: AF2],
+        rx => [PD25: AF2],
     }
     UART3 => {
-        tx => [piod::PD30<Alternate<AF0>>, piod::PD31<Alternate<AF1>>],
-        rx => [piod::PD28<Alternate<AF0>>],
+        tx => [PD30: AF0, PD31: AF1],
+        rx => [PD28: AF0],
     }
     UART4 => {
-        tx => [piod::PD19<Alternate<AF2>>, piod::PD3<Alternate<AF2>>],
-        rx => [piod::PD18<Alternate<AF2>>],
+        tx => [PD19: AF2, PD3: AF2],
+        rx => [PD18: AF2],
     }
 }
 
@@ -103,16 +136,77 @@ impl<UART, TXPIN, RXPIN> Serial<UART, TXPIN, RXPIN>
     }
 }
 
+/// Clock kept running in WAIT mode for `LowPowerSerial`.
+pub enum WakeClock {
+    /// 32.768 kHz, for 2048 Bd at most.
+    SlowClock,
+    /// The main clock. It runs from the fast RC oscillator in WAIT mode, so
+    /// that must be set to the same frequency.
+    MainClock,
+}
+
+/// Serial port clocked from PCK4 so it keeps receiving in WAIT mode, and
+/// wakes the system up on a character match.
+pub struct LowPowerSerial<UART, TXPIN, RXPIN> {
+    serial: Serial<UART, TXPIN, RXPIN>,
+    brgr: u32,
+}
+
+impl<UART, TXPIN, RXPIN> LowPowerSerial<UART, TXPIN, RXPIN> {
+    pub fn serial(&mut self) -> &mut Serial<UART, TXPIN, RXPIN> {
+        &mut self.serial
+    }
+}
+
+/// Clock divided down to 16 times the baud rate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BaudClock {
+    /// The peripheral clock, MCK.
+    Peripheral,
+    /// PCK4 taken from `source`, which runs at `frequency`. The baud rate
+    /// then stays put across MCK changes.
+    Programmable { source: PckSource, frequency: Hertz },
+}
+
 pub struct Config {
-    baud_rate: BaudRate,
+    baud_rate: Bps,
     parity: Parity,
     channel_mode: ChannelMode,
-    digital_filter: bool
+    rx_filter: bool,
+    baud_clock: BaudClock,
 }
 
 impl Config {
-    pub fn new(baud_rate: BaudRate, parity: Parity, channel_mode: ChannelMode, digital_filter: bool) -> Config {
-        Config {baud_rate, parity, channel_mode, digital_filter}
+    pub fn new(baud_rate: Bps, parity: Parity, channel_mode: ChannelMode) -> Config {
+        Config {
+            baud_rate,
+            parity,
+            channel_mode,
+            rx_filter: false,
+            baud_clock: BaudClock::Peripheral,
+        }
+    }
+
+    /// Each bit is sampled 16 times; with the filter the receive line
+    /// only changes after three samples in a row agree, which rejects
+    /// glitches shorter than two sixteenths of a bit.
+    pub fn rx_filter(mut self, enable: bool) -> Self {
+        self.rx_filter = enable;
+        self
+    }
+
+    pub fn baud_clock(mut self, clock: BaudClock) -> Self {
+        self.baud_clock = clock;
+        self
+    }
+
+    /// CD for the baud rate, from MCK or the programmable clock.
+    fn divisor(&self, mck: Hertz) -> Result<u16, BaudRateError> {
+        let frequency = match self.baud_clock {
+            BaudClock::Peripheral => mck,
+            BaudClock::Programmable { frequency, .. } => frequency,
+        };
+        baud_divisor(frequency.0, self.baud_rate.0)
     }
 }
 
@@ -124,11 +218,11 @@ trait ConfigMethod {
 
     fn get_mode(&self, mode: &ChannelMode) -> Self::Mode;
 
-    fn configure(&self, config: Config);
+    fn configure(&self, config: Config, cd: u16, pmc: &PMC);
 }
 
 macro_rules! uart {
-    ($($UART:ident: ($uart:ident, $uarttx: ident, $uartrx:ident, $pmc_pcerx:ident, $pidx:ident),)+) => {
+    ($($UART:ident: ($uart:ident, $uarttx: ident, $uartrx:ident, $pmc_pcerx:ident, $pmc_pcdrx:ident, $pidx:ident),)+) => {
         $(
             use crate::pac::$UART;
 
@@ -137,10 +231,11 @@ macro_rules! uart {
                     TXPIN: TxPin<$UART>,
                     RXPIN: RxPin<$UART>,
             {
-                pub fn $uart(uart: $UART, pins: (TXPIN, RXPIN), config: Config, pmc: &PMC) -> Self {
+                pub fn $uart(uart: $UART, pins: (TXPIN, RXPIN), config: Config, clocks: &Clocks, pmc: &PMC) -> Result<Self, BaudRateError> {
+                    let cd = config.divisor(clocks.mck())?;
                     let serial = Serial { uart, pins };
                     unsafe {
-                        pmc.$pmc_pcerx.write_with_zero(|w| w.$pidx().set_bit());
+                        pmc.with_unlocked(|pmc| pmc.$pmc_pcerx.write_with_zero(|w| w.$pidx().set_bit()));
                         serial.uart.cr.write_with_zero(|w|
                             w.rstrx().set_bit()
                                 .rxdis().set_bit()
@@ -149,9 +244,9 @@ macro_rules! uart {
                                 .rststa().set_bit()
                         );
                     }
-                    serial.configure(config);
+                    serial.configure(config, cd, pmc);
                     unsafe { serial.uart.cr.write_with_zero(|w| w.txen().set_bit().rxen().set_bit()); }
-                    serial
+                    Ok(serial)
                 }
             }
 
@@ -159,11 +254,12 @@ macro_rules! uart {
                 where
                     RXPIN: RxPin<$UART>
             {
-                pub fn $uartrx(uart: $UART, rxpin: RXPIN, config: Config, pmc: &PMC) -> Self {
+                pub fn $uartrx(uart: $UART, rxpin: RXPIN, config: Config, clocks: &Clocks, pmc: &PMC) -> Result<Self, BaudRateError> {
+                    let cd = config.divisor(clocks.mck())?;
                     let txpin = ();
                     let serial = Serial { uart, pins: (txpin, rxpin)};
                     unsafe {
-                        pmc.$pmc_pcerx.write_with_zero(|w| w.$pidx().set_bit());
+                        pmc.with_unlocked(|pmc| pmc.$pmc_pcerx.write_with_zero(|w| w.$pidx().set_bit()));
                         serial.uart.cr.write_with_zero(|w|
                             w.rstrx().set_bit()
                                 .rxdis().set_bit()
@@ -172,9 +268,9 @@ macro_rules! uart {
                                 .rststa().set_bit()
                         );
                     }
-                    serial.configure(config);
+                    serial.configure(config, cd, pmc);
                     unsafe { serial.uart.cr.write_with_zero(|w| w.rxen().set_bit()); }
-                    serial
+                    Ok(serial)
                 }
             }
 
@@ -182,11 +278,12 @@ macro_rules! uart {
                 where
                     TXPIN: TxPin<$UART>,
             {
-                pub fn $uarttx(uart: $UART, txpin: TXPIN, config: Config, pmc: &PMC) -> Self {
+                pub fn $uarttx(uart: $UART, txpin: TXPIN, config: Config, clocks: &Clocks, pmc: &PMC) -> Result<Self, BaudRateError> {
+                    let cd = config.divisor(clocks.mck())?;
                     let rxpin = ();
                     let serial = Serial { uart, pins: (txpin, rxpin) };
                     unsafe {
-                        pmc.$pmc_pcerx.write_with_zero(|w| w.$pidx().set_bit());
+                        pmc.with_unlocked(|pmc| pmc.$pmc_pcerx.write_with_zero(|w| w.$pidx().set_bit()));
                         serial.uart.cr.write_with_zero(|w|
                             w.rstrx().set_bit()
                                 .rxdis().set_bit()
@@ -195,9 +292,207 @@ macro_rules! uart {
                                 .rststa().set_bit()
                         );
                     }
-                    serial.configure(config);
+                    serial.configure(config, cd, pmc);
                     unsafe { serial.uart.cr.write_with_zero(|w| w.txen().set_bit()); }
-                    serial
+                    Ok(serial)
+                }
+            }
+
+            impl<TXPIN, RXPIN> Serial<$UART, TXPIN, RXPIN> {
+                pub fn free(self, pmc: &PMC) -> ($UART, (TXPIN, RXPIN)) {
+                    if self.uart.mr.read().brsrcck().is_pmc_pck() {
+                        pmc.disable_pck(UART_PCK);
+                    }
+                    unsafe {
+                        self.uart.cr.write_with_zero(|w| w.rxdis().set_bit().txdis().set_bit());
+                        pmc.with_unlocked(|pmc| pmc.$pmc_pcdrx.write_with_zero(|w| w.$pidx().set_bit()));
+                    }
+                    (self.uart, self.pins)
+                }
+
+                /// Raw registers, for what the driver does not cover.
+                ///
+                /// # Safety
+                /// Nothing stops them from being changed behind the driver's
+                /// back; mode and baud rate changes go unnoticed by it.
+                pub unsafe fn registers(&self) -> &crate::pac::$uart::RegisterBlock {
+                    &self.uart
+                }
+
+                pub fn set_comparison(&mut self, comparison: Comparison) {
+                    use crate::pac::$uart::cmpr::CMPMODE_A;
+                    let mode = match comparison.mode {
+                        ComparisonMode::FlagOnly => CMPMODE_A::FLAG_ONLY,
+                        ComparisonMode::StartCondition => CMPMODE_A::START_CONDITION,
+                    };
+                    self.uart.with_unlocked(|uart| {
+                        uart.cmpr.write(|w| unsafe {
+                            w.val1().bits(comparison.val1)
+                                .val2().bits(comparison.val2)
+                                .cmpmode().variant(mode)
+                                .cmppar().bit(comparison.check_parity)
+                        });
+                    });
+                }
+
+                pub fn listen(&mut self, event: Event) {
+                    unsafe {
+                        self.uart.ier.write_with_zero(|w| match event {
+                            Event::RxReady => w.rxrdy().set_bit(),
+                            Event::TxReady => w.txrdy().set_bit(),
+                            Event::TxEmpty => w.txempty().set_bit(),
+                            Event::CharacterMatch => w.cmp().set_bit(),
+                        });
+                    }
+                }
+
+                pub fn unlisten(&mut self, event: Event) {
+                    unsafe {
+                        self.uart.idr.write_with_zero(|w| match event {
+                            Event::RxReady => w.rxrdy().set_bit(),
+                            Event::TxReady => w.txrdy().set_bit(),
+                            Event::TxEmpty => w.txempty().set_bit(),
+                            Event::CharacterMatch => w.cmp().set_bit(),
+                        });
+                    }
+                }
+
+                pub fn clear_status(&mut self) {
+                    count_errors!($UART, self.uart.sr.read());
+                    unsafe { self.uart.cr.write_with_zero(|w| w.rststa().set_bit()); }
+                }
+
+                pub fn is_comparison_match(&self) -> bool {
+                    self.uart.sr.read().cmp().bit()
+                }
+
+                pub fn clear_wakeup_request(&mut self) {
+                    unsafe { self.uart.cr.write_with_zero(|w| w.reqclr().set_bit()); }
+                }
+
+                pub fn set_channel_mode(&mut self, mode: ChannelMode) {
+                    let mode = self.get_mode(&mode);
+                    self.uart.with_unlocked(|uart| uart.mr.modify(|_, w| w.chmode().variant(mode)));
+                }
+
+                pub fn channel_mode(&self) -> ChannelMode {
+                    use crate::pac::$uart::mr::CHMODE_A;
+                    match self.uart.mr.read().chmode().variant() {
+                        CHMODE_A::NORMAL => ChannelMode::Normal,
+                        CHMODE_A::AUTOMATIC => ChannelMode::Automatic,
+                        CHMODE_A::LOCAL_LOOPBACK => ChannelMode::LocalLoopback,
+                        CHMODE_A::REMOTE_LOOPBACK => ChannelMode::RemoteLoopback,
+                    }
+                }
+
+                /// Repeats the line, as an RS-485 repeater or for line
+                /// diagnostics, while still receiving. `set_channel_mode`
+                /// with `ChannelMode::Normal` ends it.
+                pub fn enable_automatic_echo(&mut self) {
+                    self.set_channel_mode(ChannelMode::Automatic);
+                }
+
+                /// Loops the line back to the remote end without the UART
+                /// taking part.
+                pub fn enable_remote_loopback(&mut self) {
+                    self.set_channel_mode(ChannelMode::RemoteLoopback);
+                }
+
+                /// Receives what the UART transmits, with the pins out of the
+                /// loop: a self test that needs no jumper.
+                pub fn enable_local_loopback(&mut self) {
+                    self.set_channel_mode(ChannelMode::LocalLoopback);
+                }
+            }
+
+            impl<TXPIN, RXPIN> LowPowerSerial<$UART, TXPIN, RXPIN> {
+                /// Fails, handing `serial` back, when the wake clock cannot
+                /// divide down to `baud_rate`.
+                pub fn new(
+                    serial: Serial<$UART, TXPIN, RXPIN>,
+                    clock: WakeClock,
+                    baud_rate: Bps,
+                    clocks: &Clocks,
+                    pmc: &PMC,
+                ) -> Result<Self, (Serial<$UART, TXPIN, RXPIN>, BaudRateError)> {
+                    let (source, frequency) = match clock {
+                        WakeClock::SlowClock => (PckSource::SlowClock, SLOW_CLOCK),
+                        WakeClock::MainClock => (PckSource::MainClock, clocks.main_clk().0),
+                    };
+                    let cd = match baud_divisor(frequency, baud_rate.0) {
+                        Ok(cd) => cd,
+                        Err(e) => return Err((serial, e)),
+                    };
+
+                    pmc.enable_pck(UART_PCK, source, 0);
+                    let brgr = serial.uart.brgr.read().bits();
+                    serial.uart.with_unlocked(|uart| unsafe {
+                        uart.mr.modify(|_, w| w.brsrcck().pmc_pck());
+                        uart.brgr.write_with_zero(|w| w.cd().bits(cd));
+                    });
+                    Ok(LowPowerSerial { serial, brgr })
+                }
+
+                /// Enters WAIT mode until a character matching `comparison`
+                /// arrives, then restores the clocks. Returns `false` without
+                /// sleeping if a sleepwalking peripheral was busy.
+                pub fn sleep_until_match(&mut self, comparison: Comparison, pmc: &PMC) -> bool {
+                    self.serial.set_comparison(comparison);
+                    self.serial.clear_status();
+                    // The PMC hands the clock back to the UART on its own
+                    // while it sleepwalks.
+                    unsafe { pmc.with_unlocked(|pmc| pmc.$pmc_pcdrx.write_with_zero(|w| w.$pidx().set_bit())); }
+                    pmc.enable_sleepwalking::<$UART>();
+                    let idle = !pmc.is_activity_in_progress();
+                    if idle {
+                        pmc.enter_wait_mode();
+                    }
+                    pmc.disable_sleepwalking::<$UART>();
+                    unsafe { pmc.with_unlocked(|pmc| pmc.$pmc_pcerx.write_with_zero(|w| w.$pidx().set_bit())); }
+                    idle
+                }
+
+                /// Goes back to the peripheral clock and the previous baud rate.
+                pub fn free(self, pmc: &PMC) -> Serial<$UART, TXPIN, RXPIN> {
+                    self.serial.uart.with_unlocked(|uart| unsafe {
+                        uart.mr.modify(|_, w| w.brsrcck().periph_clk());
+                        uart.brgr.write_with_zero(|w| w.bits(self.brgr));
+                    });
+                    pmc.disable_pck(UART_PCK);
+                    self.serial
+                }
+            }
+
+            impl Rx<$UART> {
+                pub fn is_comparison_match(&self) -> bool {
+                    unsafe { (&*$UART::ptr()).sr.read().cmp().bit() }
+                }
+
+                pub fn clear_status(&mut self) {
+                    count_errors!($UART, unsafe { (&*$UART::ptr()).sr.read() });
+                    unsafe { (&*$UART::ptr()).cr.write_with_zero(|w| w.rststa().set_bit()); }
+                }
+            }
+
+            #[cfg(feature = "serial-stats")]
+            impl<TXPIN, RXPIN> Serial<$UART, TXPIN, RXPIN> {
+                pub fn stats(&self) -> ErrorStats {
+                    $UART::counters().get()
+                }
+
+                pub fn reset_stats(&mut self) {
+                    $UART::counters().reset();
+                }
+            }
+
+            #[cfg(feature = "serial-stats")]
+            impl Rx<$UART> {
+                pub fn stats(&self) -> ErrorStats {
+                    $UART::counters().get()
+                }
+
+                pub fn reset_stats(&mut self) {
+                    $UART::counters().reset();
                 }
             }
 
@@ -229,16 +524,16 @@ macro_rules! uart {
                 where
                     RXPIN: RxPin<$UART>
             {
-                type Error = UartError;
+                type Error = SerialError;
 
                 fn read(&mut self) -> nb::Result<u8, Self::Error> {
                     let status_register = unsafe { (&*$UART::ptr()).sr.read() };
                     if status_register.ovre().bit() {
-                        Err(nb::Error::Other(UartError::Overrun))
+                        Err(nb::Error::Other(SerialError::Overrun))
                     } else if status_register.frame().bit() {
-                        Err(nb::Error::Other(UartError::Framing))
+                        Err(nb::Error::Other(SerialError::Framing))
                     } else if status_register.pare().bit() {
-                        Err(nb::Error::Other(UartError::Parity))
+                        Err(nb::Error::Other(SerialError::Parity))
                     } else if status_register.rxrdy().bit() {
                         let rhr = unsafe { (&*$UART::ptr()).rhr.read() };
                         let value = rhr.rxchr().bits();
@@ -251,17 +546,17 @@ macro_rules! uart {
 
             impl Read<u8> for Rx<$UART>
             {
-                type Error = UartError;
+                type Error = SerialError;
 
                 fn read(&mut self) -> nb::Result<u8, Self::Error>
                 {
                     let status_register = unsafe { (&*$UART::ptr()).sr.read() };
                     if status_register.ovre().bit() {
-                        Err(nb::Error::Other(UartError::Overrun))
+                        Err(nb::Error::Other(SerialError::Overrun))
                     } else if status_register.frame().bit() {
-                        Err(nb::Error::Other(UartError::Framing))
+                        Err(nb::Error::Other(SerialError::Framing))
                     } else if status_register.pare().bit() {
-                        Err(nb::Error::Other(UartError::Parity))
+                        Err(nb::Error::Other(SerialError::Parity))
                     } else if status_register.rxrdy().bit() {
                         let rhr = unsafe { (&*$UART::ptr()).rhr.read() };
                         let value = rhr.rxchr().bits();
@@ -277,7 +572,7 @@ macro_rules! uart {
                 where
                     TXPIN: TxPin<$UART>,
             {
-                type Error = Infallible;
+                type Error = SerialError;
 
                 fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error>
                 {
@@ -304,7 +599,7 @@ macro_rules! uart {
 
             impl Write<u8> for Tx<$UART>
             {
-                type Error = Infallible;
+                type Error = SerialError;
 
                 fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error>
                 {
@@ -352,21 +647,27 @@ macro_rules! uart {
                     }
                 }
 
-                fn configure(&self, config: Config) {
-                    let uart = &self.uart;
+                fn configure(&self, config: Config, cd: u16, pmc: &PMC) {
                     let mode = self.get_mode(&config.channel_mode);
                     let parity = self.get_parity(&config.parity);
-                    unsafe {
-                        uart.mr.write_with_zero(|w|
-                            w.chmode().variant(mode)
-                             .par().variant(parity)
-                             .filter().bit(config.digital_filter)
-                             .brsrcck().periph_clk()
-                        );
+                    if let BaudClock::Programmable { source, .. } = config.baud_clock {
+                        pmc.enable_pck(UART_PCK, source, 0);
                     }
-
-                    let cd = 150_000_000 / (config.baud_rate.0 * 16);
-                    unsafe { uart.brgr.write_with_zero(|w| w.cd().bits(cd as u16)); }
+                    self.uart.with_unlocked(|uart| {
+                        unsafe {
+                            uart.mr.write_with_zero(|w| {
+                                let w = w.chmode().variant(mode)
+                                    .par().variant(parity)
+                                    .filter().bit(config.rx_filter);
+                                match config.baud_clock {
+                                    BaudClock::Peripheral => w.brsrcck().periph_clk(),
+                                    BaudClock::Programmable { .. } => w.brsrcck().pmc_pck(),
+                                }
+                            });
+                        }
+
+                        unsafe { uart.brgr.write_with_zero(|w| w.cd().bits(cd)); }
+                    });
                 }
             }
         )+
@@ -374,9 +675,51 @@ macro_rules! uart {
 }
 
 uart! {
-    UART0: (uart0, uart0tx, uart0rx, pmc_pcer0, pid7),
-    UART1: (uart1, uart1tx, uart1rx, pmc_pcer0, pid8),
-    UART2: (uart2, uart2tx, uart2rx, pmc_pcer1, pid44),
-    UART3: (uart3, uart3tx, uart3rx, pmc_pcer1, pid45),
-    UART4: (uart4, uart4tx, uart4rx, pmc_pcer1, pid46),
+    UART0: (uart0, uart0tx, uart0rx, pmc_pcer0, pmc_pcdr0, pid7),
+    UART1: (uart1, uart1tx, uart1rx, pmc_pcer0, pmc_pcdr0, pid8),
+    UART2: (uart2, uart2tx, uart2rx, pmc_pcer1, pmc_pcdr1, pid44),
+    UART3: (uart3, uart3tx, uart3rx, pmc_pcer1, pmc_pcdr1, pid45),
+    UART4: (uart4, uart4tx, uart4rx, pmc_pcer1, pmc_pcdr1, pid46),
+}
+
+/// The UART samples each bit 16 times and has no fractional divider. CD
+/// is rounded to the nearest, and 0 would stop the baud rate generator.
+fn baud_divisor(clock: u32, baud: u32) -> Result<u16, BaudRateError> {
+    if baud == 0 {
+        return Err(BaudRateError::TooSlow);
+    }
+    let sixteenths = 16 * baud as u64;
+    match (clock as u64 + sixteenths / 2) / sixteenths {
+        0 => Err(BaudRateError::TooFast),
+        cd if cd > u16::MAX as u64 => Err(BaudRateError::TooSlow),
+        cd => Ok(cd as u16),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::baud_divisor;
+    use crate::serial::BaudRateError;
+
+    #[test]
+    fn baud_divisors() {
+        // 150 MHz / 16 / 115200 is 81.38.
+        assert_eq!(baud_divisor(150_000_000, 115_200), Ok(81));
+        // 150 MHz / 16 / 230400 is 40.69, rounded up.
+        assert_eq!(baud_divisor(150_000_000, 230_400), Ok(41));
+        assert_eq!(baud_divisor(32_768, 2_048), Ok(1));
+        assert_eq!(baud_divisor(32_768, 1_200), Ok(2));
+    }
+
+    #[test]
+    fn baud_divisor_limits() {
+        assert_eq!(baud_divisor(32_768, 9_600), Err(BaudRateError::TooFast));
+        assert_eq!(baud_divisor(150_000_000, 9_375_000), Ok(1));
+        assert_eq!(baud_divisor(150_000_000, 20_000_000), Err(BaudRateError::TooFast));
+        // CD 65535 and 65536.
+        assert_eq!(baud_divisor(16 * 65_535, 1), Ok(65_535));
+        assert_eq!(baud_divisor(16 * 65_536, 1), Err(BaudRateError::TooSlow));
+        assert_eq!(baud_divisor(150_000_000, 100), Err(BaudRateError::TooSlow));
+        assert_eq!(baud_divisor(150_000_000, 0), Err(BaudRateError::TooSlow));
+    }
 }
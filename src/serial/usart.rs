@@ -1,6 +1,10 @@
 use embedded_hal::serial::{Read, Write};
-use core::{convert::Infallible, marker::PhantomData };
-use crate::{gpio::*, serial::BaudRate, pac::PMC};
+use core::{convert::TryFrom, marker::PhantomData };
+use crate::ring::Ring;
+use super::SerialError;
+#[cfg(feature = "serial-stats")]
+use super::stats::{ErrorStats, Instance as _};
+use crate::{clocks::Clocks, cycles::Instant, gpio::{pioa::*, piob::*, piod::*, Alternate, AF0, AF1, AF2, AF3}, time::{Bps, Hertz}, pac::PMC, write_protect::WriteProtect};
 
 pub enum Parity {
     Even,
@@ -18,6 +22,14 @@ pub enum ChannelMode {
     RemoteLoopback
 }
 
+pub enum Event {
+    RxReady,
+    TxReady,
+    TxEmpty,
+    /// Start and end of a received break.
+    RxBreak,
+}
+
 #[derive(PartialOrd, PartialEq)]
 pub enum SyncMode {
     Async,
@@ -28,7 +40,9 @@ pub enum CharLength {
     FiveBit,
     SixBit,
     SevenBit,
-    EightBit
+    EightBit,
+    /// Only readable as `u16`, see `Word`.
+    NineBit
 }
 
 pub enum UsartMode {
@@ -40,46 +54,107 @@ pub enum UsartMode {
     SPISlave
 }
 
-pub enum UsartError {
-    Parity,
-    Framing,
-    Overrun
-}
+const MR_SYNC: u32 = 1 << 8;
+const MR_MSBF: u32 = 1 << 16;
+const MR_MODE9: u32 = 1 << 17;
+const MR_CLKO: u32 = 1 << 18;
+const MR_INVDATA: u32 = 1 << 23;
+const MR_FILTER: u32 = 1 << 28;
 
 pub trait RxPin<USART> {}
 pub trait TxPin<USART> {}
+pub trait CtsPin<USART> {}
+pub trait RtsPin<USART> {}
+pub trait SckPin<USART> {}
 
 macro_rules! usart_pins {
     ($($USART:ident => {
-        tx => [$($tx:ty), +$(,)*],
-        rx => [$($rx:ty), +$(,)*],
+        tx => [$($tx:ident: $txaf:ident),+ $(,)*],
+        rx => [$($rx:ident: $rxaf:ident),+ $(,)*],
+        cts => [$($cts:ident: $ctsaf:ident),+ $(,)*],
+        rts => [$($rts:ident: $rtsaf:ident),+ $(,)*],
+        sck => [$($sck:ident: $sckaf:ident),+ $(,)*],
     })+) => {
         $(
             $(
-                impl TxPin<crate::pac::$USART> for $tx {}
+                impl TxPin<crate::pac::$USART> for $tx<Alternate<$txaf>> {}
+            )+
+            $(
+                impl RxPin<crate::pac::$USART> for $rx<Alternate<$rxaf>> {}
+            )+
+            $(
+                impl CtsPin<crate::pac::$USART> for $cts<Alternate<$ctsaf>> {}
+            )+
+            $(
+                impl RtsPin<crate::pac::$USART> for $rts<Alternate<$rtsaf>> {}
             )+
             $(
-                impl RxPin<crate::pac::$USART> for $rx {}
+                impl SckPin<crate::pac::$USART> for $sck<Alternate<$sckaf>> {}
             )+
         )+
     }
 }
 
-pub struct Rx<USART> {
-    _instance: PhantomData<USART>,
+/// Type characters are read and written as. Characters are at most 9 bits
+/// wide, so `u16` always fits them; `u8` fits up to `CharLength::EightBit`
+/// and reading a 9-bit character as `u8` fails with
+/// `SerialError::Truncated`.
+pub trait Word: Copy + From<u8> + sealed::Sealed {
+    #[doc(hidden)]
+    fn from_char(char: u16) -> Option<Self>;
+    #[doc(hidden)]
+    fn into_char(self) -> u16;
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+}
+
+impl Word for u8 {
+    fn from_char(char: u16) -> Option<Self> {
+        u8::try_from(char).ok()
+    }
+
+    fn into_char(self) -> u16 {
+        self.into()
+    }
+}
+
+impl Word for u16 {
+    fn from_char(char: u16) -> Option<Self> {
+        Some(char)
+    }
+
+    fn into_char(self) -> u16 {
+        self
+    }
 }
 
-pub struct Tx<USART> {
-    _instance: PhantomData<USART>,
+pub struct Rx<USART, W = u16> {
+    _instance: PhantomData<(USART, W)>,
 }
 
-pub struct Serial<USART, TXPIN, RXPIN> {
+pub struct Tx<USART, W = u16> {
+    _instance: PhantomData<(USART, W)>,
+}
+
+/// Characters are `u16` by default; `words` switches to `u8` for the
+/// `embedded-hal` consumers that expect bytes.
+pub struct Serial<USART, TXPIN, RXPIN, W = u16> {
     usart: USART,
     pins: (TXPIN, RXPIN),
+    _word: PhantomData<W>,
 }
 
-impl<USART, TXPIN, RXPIN> Serial<USART, TXPIN, RXPIN> {
-    pub fn split(self) -> (Tx<USART>, Rx<USART>)
+impl<USART, TXPIN, RXPIN, W: Word> Serial<USART, TXPIN, RXPIN, W> {
+    /// Reads and writes characters as `W2` from then on.
+    pub fn words<W2: Word>(self) -> Serial<USART, TXPIN, RXPIN, W2> {
+        Serial { usart: self.usart, pins: self.pins, _word: PhantomData }
+    }
+
+    pub fn split(self) -> (Tx<USART, W>, Rx<USART, W>)
         where
             TXPIN: TxPin<USART>,
             RXPIN: RxPin<USART>,
@@ -97,33 +172,71 @@ impl<USART, TXPIN, RXPIN> Serial<USART, TXPIN, RXPIN> {
     pub fn release(self) -> (TXPIN, RXPIN) { self.pins }
 }
 
+/// Serial port with software FIFOs of `RX` and `TX` characters, filled and
+/// drained from the USART interrupt, so that bursts at high baud rates do
+/// not overrun while the application is busy. `on_interrupt` must be
+/// called from the USART interrupt handler.
+pub struct BufferedSerial<USART, TXPIN, RXPIN, const RX: usize, const TX: usize> {
+    serial: Serial<USART, TXPIN, RXPIN>,
+    rx: Ring<u16, RX>,
+    tx: Ring<u16, TX>,
+    error: Option<SerialError>,
+}
+
+/// Receiver pairing each character with the cycle counter value read in the
+/// USART interrupt, for protocols where the gaps between characters carry
+/// meaning. The stamps are only as accurate as the interrupt latency, and
+/// the cycle counter must be running, see `cycles::Cycles::new`.
+/// Characters are `u16`, as for `BufferedSerial`, to keep 9-bit ones whole.
+/// Transmission goes through `serial`.
+pub struct TimestampedSerial<USART, TXPIN, RXPIN, const RX: usize> {
+    serial: Serial<USART, TXPIN, RXPIN>,
+    rx: Ring<(u16, Instant), RX>,
+    error: Option<SerialError>,
+}
+
 usart_pins! {
     USART0 => {
-        tx => [piob::PB1<Alternate<AF2>>],
-        rx => [piob::PB0<Alternate<AF2>>],
+        tx => [PB1: AF2],
+        rx => [PB0: AF2],
+        cts => [PB2: AF2],
+        rts => [PB3: AF2],
+        sck => [PB13: AF2],
     }
     USART1 => {
-        tx => [piob::PB4<Alternate<AF3>>],
-        rx => [pioa::PA21<Alternate<AF0>>],
+        tx => [PB4: AF3],
+        rx => [PA21: AF0],
+        cts => [PA25: AF0],
+        rts => [PA24: AF0],
+        sck => [PA23: AF0],
     }
     USART2 => {
-        tx => [piod::PD16<Alternate<AF1>>],
-        rx => [piod::PD15<Alternate<AF1>>],
+        tx => [PD16: AF1],
+        rx => [PD15: AF1],
+        cts => [PD19: AF1],
+        rts => [PD18: AF1],
+        sck => [PD17: AF1],
     }
 }
 
 pub struct Config {
-    baud_rate: BaudRate,
+    baud_rate: Bps,
     parity: Parity,
     channel_mode: ChannelMode,
     char_length: CharLength,
     sync_mode: SyncMode,
     usart_mode: UsartMode,
+    timeguard: u8,
+    invert_data: bool,
+    msb_first: bool,
+    clock_output: bool,
+    rx_filter: bool,
+    two_stop_bits: bool,
 }
 
 impl Config {
     pub fn new(
-        baud_rate: BaudRate,
+        baud_rate: Bps,
         parity: Parity,
         channel_mode: ChannelMode,
         char_length: CharLength,
@@ -136,29 +249,127 @@ impl Config {
             char_length,
             sync_mode,
             usart_mode,
+            timeguard: 0,
+            invert_data: false,
+            msb_first: false,
+            clock_output: false,
+            rx_filter: false,
+            two_stop_bits: false,
         }
     }
-}
 
-trait ConfigMethod {
-    type Parity;
-    type Mode;
-    type CharLength;
-    type UsartMode;
+    /// Idle time inserted after each transmitted character, in bit periods.
+    pub fn timeguard(mut self, bit_periods: u8) -> Self {
+        self.timeguard = bit_periods;
+        self
+    }
 
-    fn get_parity(config: &Config) -> Self::Parity;
+    /// Inverts the logic level of transmitted and received data bits.
+    pub fn invert_data(mut self, invert: bool) -> Self {
+        self.invert_data = invert;
+        self
+    }
 
-    fn get_mode(config: &Config) -> Self::Mode;
+    pub fn msb_first(mut self, msb_first: bool) -> Self {
+        self.msb_first = msb_first;
+        self
+    }
 
-    fn get_char_length(config: &Config) -> Self::CharLength;
+    /// Drives the baud clock on the SCK pin.
+    pub fn clock_output(mut self, enable: bool) -> Self {
+        self.clock_output = enable;
+        self
+    }
 
-    fn get_usart_mode(config: &Config) -> Self::UsartMode;
+    /// Filters the receive line with a three-sample majority vote.
+    pub fn rx_filter(mut self, enable: bool) -> Self {
+        self.rx_filter = enable;
+        self
+    }
+
+    pub fn two_stop_bits(mut self, enable: bool) -> Self {
+        self.two_stop_bits = enable;
+        self
+    }
+}
+
+/// MR, BRGR and TTGR for a `Config`, with the baud rate generator on the
+/// peripheral clock `clock`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Registers {
+    mr: u32,
+    brgr: u32,
+    ttgr: u32,
+}
+
+impl Registers {
+    fn new(config: &Config, clock: Hertz) -> Registers {
+        let usart_mode = match config.usart_mode {
+            UsartMode::Normal => 0x0,
+            UsartMode::Rs485 => 0x1,
+            UsartMode::HWHandsahking => 0x2,
+            UsartMode::LON => 0x9,
+            UsartMode::SPIMaster => 0xE,
+            UsartMode::SPISlave => 0xF,
+        };
+        // MODE9 overrides CHRL.
+        let char_length = match config.char_length {
+            CharLength::FiveBit => 0,
+            CharLength::SixBit => 1,
+            CharLength::SevenBit => 2,
+            CharLength::EightBit | CharLength::NineBit => 3,
+        };
+        let parity = match config.parity {
+            Parity::Even => 0,
+            Parity::Odd => 1,
+            Parity::Space => 2,
+            Parity::Mark => 3,
+            Parity::NoParity => 4,
+            Parity::MultridropMode => 6,
+        };
+        let channel_mode = match config.channel_mode {
+            ChannelMode::Normal => 0,
+            ChannelMode::Automatic => 1,
+            ChannelMode::LocalLoopback => 2,
+            ChannelMode::RemoteLoopback => 3,
+        };
+        let flags = [
+            (config.sync_mode == SyncMode::Sync, MR_SYNC),
+            (config.msb_first, MR_MSBF),
+            (matches!(config.char_length, CharLength::NineBit), MR_MODE9),
+            (config.clock_output, MR_CLKO),
+            (config.invert_data, MR_INVDATA),
+            (config.rx_filter, MR_FILTER),
+        ];
+        let mut mr = usart_mode | char_length << 6 | parity << 9 | channel_mode << 14;
+        if config.two_stop_bits {
+            mr |= 2 << 12;
+        }
+        for (set, bit) in flags {
+            if set {
+                mr |= bit;
+            }
+        }
+
+        let synchronous = config.sync_mode == SyncMode::Sync
+            || matches!(config.usart_mode, UsartMode::SPIMaster | UsartMode::SPISlave);
+        let (cd, fp) = if synchronous {
+            // SCK runs at the divided clock itself, without oversampling
+            // or fractional part.
+            (((clock.0 + config.baud_rate.0 / 2) / config.baud_rate.0) as u16, 0)
+        } else {
+            baud_divider(clock.0, config.baud_rate.0)
+        };
+        Registers { mr, brgr: cd as u32 | (fp as u32) << 16, ttgr: config.timeguard as u32 }
+    }
+}
 
-    fn configure(&self, config: &Config, pmc: &PMC);
+trait ConfigMethod {
+    fn configure(&self, config: &Config, clock: Hertz, pmc: &PMC);
 }
 
 macro_rules! usart {
-    ($($USART:ident: ($usart:ident, $usarttx:ident, $usartrx:ident, $pmc_pcerx:ident, $pid:ident),)+) => {
+    ($($USART:ident: ($usart:ident, $usarttx:ident, $usartrx:ident, $pmc_pcerx:ident, $pmc_pcdrx:ident, $pid:ident),)+) => {
         $(
             use crate::pac::$USART;
 
@@ -167,22 +378,156 @@ macro_rules! usart {
                 TXPIN: TxPin<$USART>,
                 RXPIN: RxPin<$USART>,
             {
-                pub fn $usart(usart: $USART, pins: (TXPIN, RXPIN), config: &Config, pmc: &PMC) -> Self {
-                    let serial = Serial { usart, pins };
-                    serial.configure(config, pmc);
+                pub fn $usart(usart: $USART, pins: (TXPIN, RXPIN), config: &Config, clocks: &Clocks, pmc: &PMC) -> Self {
+                    let serial = Serial { usart, pins, _word: PhantomData };
+                    serial.configure(config, clocks.mck(), pmc);
                     unsafe { serial.usart.cr().write_with_zero(|w| w.txen().set_bit().rxen().set_bit()); }
                     serial
                 }
             }
 
+            impl<TXPIN, RXPIN, W> Serial<$USART, TXPIN, RXPIN, W> {
+                pub fn free(self, pmc: &PMC) -> ($USART, (TXPIN, RXPIN)) {
+                    unsafe {
+                        self.usart.cr().write_with_zero(|w| w.rxdis().set_bit().txdis().set_bit());
+                        pmc.with_unlocked(|pmc| pmc.$pmc_pcdrx.write_with_zero(|w| w.$pid().set_bit()));
+                    }
+                    (self.usart, self.pins)
+                }
+
+                /// Raw registers, for the modes the driver does not cover.
+                ///
+                /// # Safety
+                /// The USART must be back in the configured mode before the
+                /// driver is used again.
+                pub unsafe fn registers(&self) -> &crate::pac::$usart::RegisterBlock {
+                    &self.usart
+                }
+
+                /// Clears the overrun, framing, parity and underrun errors, which
+                /// otherwise keep being returned by `read` and `write`.
+                pub fn clear_status(&mut self) {
+                    count_errors!($USART, self.usart.csr().read());
+                    unsafe { self.usart.cr().write_with_zero(|w| w.rststa().set_bit()); }
+                }
+
+                pub fn set_channel_mode(&mut self, mode: ChannelMode) {
+                    use crate::pac::$usart::mr::CHMODE_A;
+                    let mode = match mode {
+                        ChannelMode::Normal => CHMODE_A::NORMAL,
+                        ChannelMode::Automatic => CHMODE_A::AUTOMATIC,
+                        ChannelMode::LocalLoopback => CHMODE_A::LOCAL_LOOPBACK,
+                        ChannelMode::RemoteLoopback => CHMODE_A::REMOTE_LOOPBACK,
+                    };
+                    self.usart.with_unlocked(|usart| usart.mr().modify(|_, w| w.chmode().variant(mode)));
+                }
+
+                pub fn channel_mode(&self) -> ChannelMode {
+                    use crate::pac::$usart::mr::CHMODE_A;
+                    match self.usart.mr().read().chmode().variant() {
+                        CHMODE_A::NORMAL => ChannelMode::Normal,
+                        CHMODE_A::AUTOMATIC => ChannelMode::Automatic,
+                        CHMODE_A::LOCAL_LOOPBACK => ChannelMode::LocalLoopback,
+                        CHMODE_A::REMOTE_LOOPBACK => ChannelMode::RemoteLoopback,
+                    }
+                }
+
+                /// Feeds the transmitter into the receiver inside the USART.
+                /// TXD stays idle and RXD is ignored, so no wiring is needed.
+                pub fn enable_local_loopback(&mut self) {
+                    self.set_channel_mode(ChannelMode::LocalLoopback);
+                }
+
+                pub fn listen(&mut self, event: Event) {
+                    unsafe {
+                        self.usart.ier().write_with_zero(|w| match event {
+                            Event::RxReady => w.rxrdy().set_bit(),
+                            Event::TxReady => w.txrdy().set_bit(),
+                            Event::TxEmpty => w.txempty().set_bit(),
+                            Event::RxBreak => w.rxbrk().set_bit(),
+                        });
+                    }
+                }
+
+                pub fn unlisten(&mut self, event: Event) {
+                    unsafe {
+                        self.usart.idr().write_with_zero(|w| match event {
+                            Event::RxReady => w.rxrdy().set_bit(),
+                            Event::TxReady => w.txrdy().set_bit(),
+                            Event::TxEmpty => w.txempty().set_bit(),
+                            Event::RxBreak => w.rxbrk().set_bit(),
+                        });
+                    }
+                }
+
+                /// Holds TXD low once the characters already written are out,
+                /// until `stop_break`. The break lasts at least one character.
+                pub fn send_break(&mut self) {
+                    unsafe { self.usart.cr().write_with_zero(|w| w.sttbrk().set_bit()); }
+                }
+
+                /// Releases TXD after a break. The USART then keeps it high for
+                /// at least 12 bit periods before the next character.
+                pub fn stop_break(&mut self) {
+                    unsafe { self.usart.cr().write_with_zero(|w| w.stpbrk().set_bit()); }
+                }
+
+                /// Set at the start of a received break and again at its end,
+                /// until `clear_status`.
+                pub fn is_break_received(&self) -> bool {
+                    self.usart.csr().read().rxbrk().bit()
+                }
+            }
+
+            impl<W> Rx<$USART, W> {
+                pub fn clear_status(&mut self) {
+                    let usart = unsafe { &*$USART::ptr() };
+                    count_errors!($USART, usart.csr().read());
+                    unsafe { usart.cr().write_with_zero(|w| w.rststa().set_bit()); }
+                }
+            }
+
+            #[cfg(feature = "serial-stats")]
+            impl<TXPIN, RXPIN, W> Serial<$USART, TXPIN, RXPIN, W> {
+                pub fn stats(&self) -> ErrorStats {
+                    $USART::counters().get()
+                }
+
+                pub fn reset_stats(&mut self) {
+                    $USART::counters().reset();
+                }
+            }
+
+            #[cfg(feature = "serial-stats")]
+            impl<W> Rx<$USART, W> {
+                pub fn stats(&self) -> ErrorStats {
+                    $USART::counters().get()
+                }
+
+                pub fn reset_stats(&mut self) {
+                    $USART::counters().reset();
+                }
+            }
+
+            #[cfg(feature = "serial-stats")]
+            impl<TXPIN, RXPIN, const RX: usize, const TX: usize> BufferedSerial<$USART, TXPIN, RXPIN, RX, TX> {
+                pub fn stats(&self) -> ErrorStats {
+                    $USART::counters().get()
+                }
+
+                pub fn reset_stats(&mut self) {
+                    $USART::counters().reset();
+                }
+            }
+
             impl<TXPIN> Serial<$USART, TXPIN, ()>
                 where
                     TXPIN: TxPin<$USART>,
             {
-                pub fn $usarttx(usart: $USART, txpin: TXPIN, config: &Config, pmc: &PMC) -> Self {
+                pub fn $usarttx(usart: $USART, txpin: TXPIN, config: &Config, clocks: &Clocks, pmc: &PMC) -> Self {
                     let rxpin = ();
-                    let serial = Serial { usart, pins: (txpin, rxpin) };
-                    serial.configure(config, pmc);
+                    let serial = Serial { usart, pins: (txpin, rxpin), _word: PhantomData };
+                    serial.configure(config, clocks.mck(), pmc);
                     unsafe { serial.usart.cr().write_with_zero(|w| w.txen().set_bit()); }
                     serial
                 }
@@ -192,21 +537,24 @@ macro_rules! usart {
                 where
                     RXPIN: RxPin<$USART>
             {
-                pub fn $usartrx(usart: $USART, rxpin: RXPIN, config: &Config, pmc: &PMC) -> Self {
+                pub fn $usartrx(usart: $USART, rxpin: RXPIN, config: &Config, clocks: &Clocks, pmc: &PMC) -> Self {
                     let txpin = ();
-                    let serial = Serial { usart, pins: (txpin, rxpin) };
-                    serial.configure(config, pmc);
+                    let serial = Serial { usart, pins: (txpin, rxpin), _word: PhantomData };
+                    serial.configure(config, clocks.mck(), pmc);
                     unsafe { serial.usart.cr().write_with_zero(|w| w.rxen().set_bit()); }
                     serial
                 }
             }
 
 
-            impl core::fmt::Write for Tx<$USART>
-                where
-                    Tx<$USART>: embedded_hal::serial::Write<u16>
+            /// Fails in 9-bit mode, where text bytes would go out with the
+            /// ninth bit cleared instead of being rejected.
+            impl<W: Word> core::fmt::Write for Tx<$USART, W>
             {
                 fn write_str(&mut self, s: &str) -> core::fmt::Result {
+                    if Tx::<$USART>::is_nine_bit() {
+                        return Err(core::fmt::Error);
+                    }
                     s.as_bytes()
                         .iter()
                         .try_for_each(|c| nb::block!(self.write((*c).into())))
@@ -214,11 +562,15 @@ macro_rules! usart {
                 }
             }
 
-            impl<TXPIN, RXPIN> core::fmt::Write for Serial<$USART, TXPIN, RXPIN>
+            /// Fails in 9-bit mode, like the `Tx` implementation.
+            impl<TXPIN, RXPIN, W: Word> core::fmt::Write for Serial<$USART, TXPIN, RXPIN, W>
                 where
                     TXPIN: TxPin<$USART>
             {
                 fn write_str(&mut self, s: &str) -> core::fmt::Result {
+                    if Tx::<$USART>::is_nine_bit() {
+                        return Err(core::fmt::Error);
+                    }
                     s.as_bytes()
                         .iter()
                         .try_for_each(|c| nb::block!(self.write((*c).into())))
@@ -226,72 +578,80 @@ macro_rules! usart {
                 }
             }
 
-            impl<TXPIN, RXPIN> Read<u16> for Serial<$USART, TXPIN, RXPIN>
+            impl<TXPIN, RXPIN, W: Word> Read<W> for Serial<$USART, TXPIN, RXPIN, W>
                 where
                     RXPIN: RxPin<$USART>
             {
-                type Error = UsartError;
+                type Error = SerialError;
 
-                fn read(&mut self) -> nb::Result<u16, Self::Error>
+                fn read(&mut self) -> nb::Result<W, Self::Error>
                 {
                     let status_register = unsafe { (&*$USART::ptr()).csr().read() };
                     if status_register.ovre().bit() {
-                        Err(nb::Error::Other(UsartError::Overrun))
+                        Err(nb::Error::Other(SerialError::Overrun))
                     } else if status_register.frame().bit() {
-                        Err(nb::Error::Other(UsartError::Framing))
+                        Err(nb::Error::Other(SerialError::Framing))
                     } else if status_register.pare().bit() {
-                        Err(nb::Error::Other(UsartError::Parity))
+                        Err(nb::Error::Other(SerialError::Parity))
                     } else if status_register.rxrdy().bit() {
                         let rhr = unsafe { (&*$USART::ptr()).rhr.read() };
-                        let value = rhr.rxchr().bits();
-                        Ok(value)
+                        W::from_char(rhr.rxchr().bits()).ok_or(nb::Error::Other(SerialError::Truncated))
                     } else {
                         Err(nb::Error::WouldBlock)
                     }
                 }
             }
 
-            impl Read<u16> for Rx<$USART>
+            impl<W: Word> Read<W> for Rx<$USART, W>
             {
-                type Error = UsartError;
+                type Error = SerialError;
 
-                fn read(&mut self) -> nb::Result<u16, Self::Error>
+                fn read(&mut self) -> nb::Result<W, Self::Error>
                 {
                     let status_register = unsafe { (&*$USART::ptr()).csr().read() };
                     if status_register.ovre().bit() {
-                        Err(nb::Error::Other(UsartError::Overrun))
+                        Err(nb::Error::Other(SerialError::Overrun))
                     } else if status_register.frame().bit() {
-                        Err(nb::Error::Other(UsartError::Framing))
+                        Err(nb::Error::Other(SerialError::Framing))
                     } else if status_register.pare().bit() {
-                        Err(nb::Error::Other(UsartError::Parity))
+                        Err(nb::Error::Other(SerialError::Parity))
                     } else if status_register.rxrdy().bit() {
                         let rhr = unsafe { (&*$USART::ptr()).rhr.read() };
-                        let value = rhr.rxchr().bits();
-                        Ok(value)
+                        W::from_char(rhr.rxchr().bits()).ok_or(nb::Error::Other(SerialError::Truncated))
                     } else {
                         Err(nb::Error::WouldBlock)
                     }
                 }
             }
 
-            impl<TXPIN, RXPIN> Write<u16> for Serial<$USART, TXPIN, RXPIN>
+            impl<TXPIN, RXPIN, W: Word> Write<W> for Serial<$USART, TXPIN, RXPIN, W>
                 where
                     TXPIN: TxPin<$USART>
             {
-                type Error = Infallible;
+                type Error = SerialError;
 
-                fn write(&mut self, data: u16) -> nb::Result<(), Self::Error>
+                /// Takes `data` as soon as the holding register is free
+                /// (TXRDY), while the previous character may still be
+                /// shifting out. In SPI slave mode, fails with `Underrun`
+                /// once the master clocked out a character that was not
+                /// written in time, until `clear_status`.
+                fn write(&mut self, data: W) -> nb::Result<(), Self::Error>
                 {
+                    if Tx::<$USART>::is_underrun() {
+                        return Err(nb::Error::Other(SerialError::Underrun));
+                    }
                     let status_register = unsafe { (&*$USART::ptr()).csr().read() };
                     if status_register.txrdy().bit() {
                         let usart = unsafe { (&*$USART::ptr())};
-                        unsafe { usart.thr.write_with_zero(|w| w.txchr().bits(data)); }
+                        unsafe { usart.thr.write_with_zero(|w| w.txchr().bits(data.into_char())); }
                         Ok(())
                     } else {
                         Err(nb::Error::WouldBlock)
                     }
                 }
 
+                /// Completes once the shift register is empty as well
+                /// (TXEMPTY), with the last character on the line.
                 fn flush(&mut self) -> nb::Result<(), Self::Error>
                 {
                     let status_register = unsafe { (&*$USART::ptr()).csr().read() };
@@ -303,22 +663,32 @@ macro_rules! usart {
                 }
             }
 
-            impl Write<u16> for Tx<$USART>
+            impl<W: Word> Write<W> for Tx<$USART, W>
             {
-                type Error = Infallible;
+                type Error = SerialError;
 
-                fn write(&mut self, data: u16) -> nb::Result<(), Self::Error>
+                /// Takes `data` as soon as the holding register is free
+                /// (TXRDY), while the previous character may still be
+                /// shifting out. In SPI slave mode, fails with `Underrun`
+                /// once the master clocked out a character that was not
+                /// written in time, until `clear_status`.
+                fn write(&mut self, data: W) -> nb::Result<(), Self::Error>
                 {
+                    if Tx::<$USART>::is_underrun() {
+                        return Err(nb::Error::Other(SerialError::Underrun));
+                    }
                     let status_register = unsafe { (&*$USART::ptr()).csr().read() };
                     if status_register.txrdy().bit() {
                         let usart = unsafe { (&*$USART::ptr())};
-                        unsafe { usart.thr.write_with_zero(|w| w.txchr().bits(data)); }
+                        unsafe { usart.thr.write_with_zero(|w| w.txchr().bits(data.into_char())); }
                         Ok(())
                     } else {
                         Err(nb::Error::WouldBlock)
                     }
                 }
 
+                /// Completes once the shift register is empty as well
+                /// (TXEMPTY), with the last character on the line.
                 fn flush(&mut self) -> nb::Result<(), Self::Error>
                 {
                     let status_register = unsafe { (&*$USART::ptr()).csr().read() };
@@ -330,72 +700,259 @@ macro_rules! usart {
                 }
             }
 
-            impl<TXPIN, RXPIN> ConfigMethod for Serial<$USART, TXPIN, RXPIN> {
-                type Parity = crate::pac::$usart::mr::PAR_A;
-                type Mode = crate::pac::$usart::mr::CHMODE_A;
-                type CharLength = crate::pac::$usart::mr::CHRL_A;
-                type UsartMode = crate::pac::$usart::mr::USART_MODE_A;
-
-                fn get_parity(config: &Config) -> Self::Parity {
-                    match config.parity {
-                        Parity::Even => Self::Parity::EVEN,
-                        Parity::Mark => Self::Parity::MARK,
-                        Parity::MultridropMode => Self::Parity::MULTIDROP,
-                        Parity::NoParity => Self::Parity::NO,
-                        Parity::Odd => Self::Parity::ODD,
-                        Parity::Space => Self::Parity::SPACE,
+            impl<TXPIN, RXPIN, W: Word> Serial<$USART, TXPIN, RXPIN, W>
+                where
+                    TXPIN: TxPin<$USART>
+            {
+                /// Sends `address` with the parity bit set, marking it as an
+                /// address byte. Requires `Parity::MultridropMode`.
+                pub fn write_address(&mut self, address: u8) -> nb::Result<(), SerialError> {
+                    let usart = unsafe { &*$USART::ptr() };
+                    if usart.csr().read().txrdy().bit() {
+                        unsafe {
+                            usart.cr().write_with_zero(|w| w.senda().set_bit());
+                            usart.thr.write_with_zero(|w| w.txchr().bits(address.into()));
+                        }
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Sends `data` with the parity bit cleared.
+                pub fn write_data(&mut self, data: u8) -> nb::Result<(), SerialError> {
+                    self.write(data.into())
+                }
+            }
+
+            impl Tx<$USART> {
+                /// The same status bit means something else outside SPI slave
+                /// mode.
+                fn is_underrun() -> bool {
+                    let usart = unsafe { &*$USART::ptr() };
+                    usart.mr().read().usart_mode().is_spi_slave()
+                        && usart.spi_mode_csr_spi_mode().read().unre().bit()
+                }
+
+                fn is_nine_bit() -> bool {
+                    unsafe { (*$USART::ptr()).mr().read().mode9().bit() }
+                }
+
+                /// Writes `words` in order, blocking. A word wider than the
+                /// characters, 9 bits with MODE9 and 8 bits otherwise, stops
+                /// the stream with `Truncated` before it is sent.
+                fn write_all<T, W: Word>(tx: &mut T, words: impl IntoIterator<Item = W>) -> Result<(), SerialError>
+                where
+                    T: Write<W, Error = SerialError>,
+                {
+                    let max = if Self::is_nine_bit() { 0x1FF } else { 0xFF };
+                    for word in words {
+                        if word.into_char() > max {
+                            return Err(SerialError::Truncated);
+                        }
+                        nb::block!(tx.write(word))?;
+                    }
+                    Ok(())
+                }
+            }
+
+            impl<TXPIN: TxPin<$USART>, RXPIN, W: Word> Serial<$USART, TXPIN, RXPIN, W> {
+                /// Blocking write of a stream of characters. Each must fit the
+                /// character length, so a 9-bit address/data stream is never
+                /// sent cut down to 8 bits.
+                pub fn write_iter<I: IntoIterator<Item = W>>(&mut self, words: I) -> Result<(), SerialError> {
+                    Tx::<$USART>::write_all(self, words)
+                }
+            }
+
+            impl<W: Word> Tx<$USART, W> {
+                /// Same as `Serial::write_iter`.
+                pub fn write_iter<I: IntoIterator<Item = W>>(&mut self, words: I) -> Result<(), SerialError> {
+                    Tx::<$USART>::write_all(self, words)
+                }
+            }
+
+            impl<W: Word> Tx<$USART, W> {
+                /// Sends `address` with the parity bit set, marking it as an
+                /// address byte. Requires `Parity::MultridropMode`.
+                pub fn write_address(&mut self, address: u8) -> nb::Result<(), SerialError> {
+                    let usart = unsafe { &*$USART::ptr() };
+                    if usart.csr().read().txrdy().bit() {
+                        unsafe {
+                            usart.cr().write_with_zero(|w| w.senda().set_bit());
+                            usart.thr.write_with_zero(|w| w.txchr().bits(address.into()));
+                        }
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Sends `data` with the parity bit cleared.
+                pub fn write_data(&mut self, data: u8) -> nb::Result<(), SerialError> {
+                    self.write(data.into())
+                }
+            }
+
+            impl<TXPIN, RXPIN, const RX: usize, const TX: usize> BufferedSerial<$USART, TXPIN, RXPIN, RX, TX> {
+                pub fn new(serial: Serial<$USART, TXPIN, RXPIN>) -> Self {
+                    unsafe {
+                        serial.usart.ier().write_with_zero(|w|
+                            w.rxrdy().set_bit()
+                                .ovre().set_bit()
+                                .frame().set_bit()
+                                .pare().set_bit()
+                        );
                     }
+                    BufferedSerial { serial, rx: Ring::new(0), tx: Ring::new(0), error: None }
                 }
 
-                fn get_mode(config: &Config) -> Self::Mode {
-                    match config.channel_mode  {
-                        ChannelMode::Normal => Self::Mode::NORMAL,
-                        ChannelMode::Automatic => Self::Mode::AUTOMATIC,
-                        ChannelMode::LocalLoopback => Self::Mode::LOCAL_LOOPBACK,
-                        ChannelMode::RemoteLoopback => Self::Mode::REMOTE_LOOPBACK,
+                /// Characters still buffered are dropped.
+                pub fn free(self) -> Serial<$USART, TXPIN, RXPIN> {
+                    unsafe {
+                        self.serial.usart.idr().write_with_zero(|w|
+                            w.rxrdy().set_bit()
+                                .txrdy().set_bit()
+                                .ovre().set_bit()
+                                .frame().set_bit()
+                                .pare().set_bit()
+                        );
                     }
+                    self.serial
                 }
 
-                fn get_char_length(config: &Config) -> Self::CharLength {
-                    match config.char_length {
-                        CharLength::FiveBit => Self::CharLength::_5_BIT,
-                        CharLength::SixBit => Self::CharLength::_6_BIT,
-                        CharLength::SevenBit => Self::CharLength::_7_BIT,
-                        CharLength::EightBit => Self::CharLength::_8_BIT,
+                /// Moves the received character into the receive FIFO and
+                /// refills the transmitter from the transmit FIFO.
+                pub fn on_interrupt(&mut self) {
+                    let usart = &self.serial.usart;
+                    let status = usart.csr().read();
+                    if status.ovre().bit() || status.frame().bit() || status.pare().bit() {
+                        self.error = Some(if status.ovre().bit() {
+                            SerialError::Overrun
+                        } else if status.frame().bit() {
+                            SerialError::Framing
+                        } else {
+                            SerialError::Parity
+                        });
+                        count_errors!($USART, status);
+                        unsafe { usart.cr().write_with_zero(|w| w.rststa().set_bit()); }
+                    }
+                    if status.rxrdy().bit() && !self.rx.push(usart.rhr.read().rxchr().bits()) {
+                        self.error = Some(SerialError::Overrun);
+                    }
+                    if status.txrdy().bit() {
+                        match self.tx.pop() {
+                            Some(data) => unsafe { usart.thr.write_with_zero(|w| w.txchr().bits(data)); },
+                            None => unsafe { usart.idr().write_with_zero(|w| w.txrdy().set_bit()); },
+                        }
                     }
                 }
+            }
 
-                fn get_usart_mode(config: &Config) -> Self::UsartMode {
-                    match config.usart_mode {
-                        UsartMode::Normal => Self::UsartMode::NORMAL,
-                        UsartMode::HWHandsahking => Self::UsartMode::HW_HANDSHAKING,
-                        UsartMode::LON => Self::UsartMode::LON,
-                        UsartMode::Rs485 => Self::UsartMode::RS485,
-                        UsartMode::SPISlave => Self::UsartMode::SPI_SLAVE,
-                        UsartMode::SPIMaster => Self::UsartMode::SPI_MASTER,
+            impl<TXPIN, RXPIN, const RX: usize> TimestampedSerial<$USART, TXPIN, RXPIN, RX> {
+                pub fn new(serial: Serial<$USART, TXPIN, RXPIN>) -> Self {
+                    unsafe {
+                        serial.usart.ier().write_with_zero(|w|
+                            w.rxrdy().set_bit()
+                                .ovre().set_bit()
+                                .frame().set_bit()
+                                .pare().set_bit()
+                        );
                     }
+                    TimestampedSerial { serial, rx: Ring::new((0, Instant::now())), error: None }
                 }
 
-                fn configure(&self, config: &Config, pmc: &PMC) {
-                    let usart = &self.usart;
-                    unsafe { pmc.$pmc_pcerx.write_with_zero(|w| w.$pid().set_bit()); }
-                    let mode = Self::get_mode(config);
-                    let parity = Self::get_parity(config);
-                    let usart_mode = Self::get_usart_mode(config);
-                    let char_length = Self::get_char_length(config);
-                    let is_sync = config.sync_mode == SyncMode::Sync;
+                pub fn free(self) -> Serial<$USART, TXPIN, RXPIN> {
                     unsafe {
-                        usart.mr().write_with_zero(|w| {
-                            w.usart_mode().variant(usart_mode)
-                                .par().variant(parity)
-                                .chmode().variant(mode)
-                                .chrl().variant(char_length)
-                                .sync().bit(is_sync)
+                        self.serial.usart.idr().write_with_zero(|w|
+                            w.rxrdy().set_bit()
+                                .ovre().set_bit()
+                                .frame().set_bit()
+                                .pare().set_bit()
+                        );
+                    }
+                    self.serial
+                }
+
+                pub fn serial(&mut self) -> &mut Serial<$USART, TXPIN, RXPIN> {
+                    &mut self.serial
+                }
+
+                /// Stamps the received character. To be called from the
+                /// USART interrupt handler, as early as possible.
+                pub fn on_interrupt(&mut self) {
+                    let now = Instant::now();
+                    let usart = &self.serial.usart;
+                    let status = usart.csr().read();
+                    if status.ovre().bit() || status.frame().bit() || status.pare().bit() {
+                        self.error = Some(if status.ovre().bit() {
+                            SerialError::Overrun
+                        } else if status.frame().bit() {
+                            SerialError::Framing
+                        } else {
+                            SerialError::Parity
                         });
+                        count_errors!($USART, status);
+                        unsafe { usart.cr().write_with_zero(|w| w.rststa().set_bit()); }
+                    }
+                    if status.rxrdy().bit() && !self.rx.push((usart.rhr.read().rxchr().bits(), now)) {
+                        self.error = Some(SerialError::Overrun);
+                    }
+                }
+
+                /// Oldest received character with the instant it was taken
+                /// out of the receiver. Errors come first, as with
+                /// `BufferedSerial`.
+                pub fn read(&mut self) -> nb::Result<(u16, Instant), SerialError> {
+                    if let Some(error) = self.error.take() {
+                        return Err(nb::Error::Other(error));
                     }
+                    self.rx.pop().ok_or(nb::Error::WouldBlock)
+                }
+            }
+
+            impl<TXPIN, RXPIN, const RX: usize, const TX: usize> Read<u16> for BufferedSerial<$USART, TXPIN, RXPIN, RX, TX> {
+                type Error = SerialError;
 
-                    let read_baud_rate = 12_000_000u32 / ((config.baud_rate.0 as u32) * 16u32);
-                    unsafe { usart.brgr.write_with_zero(|w| w.bits(read_baud_rate)); }
+                /// Errors are reported once, before the characters received
+                /// after them.
+                fn read(&mut self) -> nb::Result<u16, Self::Error> {
+                    if let Some(error) = self.error.take() {
+                        return Err(nb::Error::Other(error));
+                    }
+                    self.rx.pop().ok_or(nb::Error::WouldBlock)
+                }
+            }
+
+            impl<TXPIN, RXPIN, const RX: usize, const TX: usize> Write<u16> for BufferedSerial<$USART, TXPIN, RXPIN, RX, TX> {
+                type Error = SerialError;
+
+                fn write(&mut self, data: u16) -> nb::Result<(), Self::Error> {
+                    if !self.tx.push(data) {
+                        return Err(nb::Error::WouldBlock);
+                    }
+                    unsafe { self.serial.usart.ier().write_with_zero(|w| w.txrdy().set_bit()); }
+                    Ok(())
+                }
+
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    if self.tx.is_empty() && self.serial.usart.csr().read().txempty().bit() {
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
+            impl<TXPIN, RXPIN> ConfigMethod for Serial<$USART, TXPIN, RXPIN> {
+                fn configure(&self, config: &Config, clock: Hertz, pmc: &PMC) {
+                    pmc.with_unlocked(|pmc| unsafe { pmc.$pmc_pcerx.write_with_zero(|w| w.$pid().set_bit()); });
+                    let registers = Registers::new(config, clock);
+                    self.usart.with_unlocked(|usart| unsafe {
+                        usart.mr().write_with_zero(|w| w.bits(registers.mr));
+                        usart.brgr.write_with_zero(|w| w.bits(registers.brgr));
+                        usart.ttgr().write(|w| w.bits(registers.ttgr));
+                    });
                 }
             }
         )+
@@ -403,7 +960,79 @@ macro_rules! usart {
 }
 
 usart! {
-    USART0: (usart0, usart0tx, usart0rx, pmc_pcer0, pid13),
-    USART1: (usart1, usart1tx, usart1rx, pmc_pcer0, pid14),
-    USART2: (usart2, usart2tx, usart2rx, pmc_pcer0, pid15),
+    USART0: (usart0, usart0tx, usart0rx, pmc_pcer0, pmc_pcdr0, pid13),
+    USART1: (usart1, usart1tx, usart1rx, pmc_pcer0, pmc_pcdr0, pid14),
+    USART2: (usart2, usart2tx, usart2rx, pmc_pcer0, pmc_pcdr0, pid15),
+}
+
+/// CD and FP, the integer and eighths parts of the divider of `clock` by 16
+/// times `baud`, rounded to the nearest eighth.
+fn baud_divider(clock: u32, baud: u32) -> (u16, u8) {
+    let eighths = (clock + baud) / (2 * baud);
+    ((eighths >> 3) as u16, (eighths & 7) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fractional_baud_divider() {
+        // 12 MHz / 16 / 115200 is 6.51, closest to 6 + 4/8.
+        assert_eq!(baud_divider(12_000_000, 115_200), (6, 4));
+        assert_eq!(baud_divider(12_000_000, 250_000), (3, 0));
+        assert_eq!(baud_divider(12_000_000, 9_600), (78, 1));
+    }
+
+    #[test]
+    fn registers_from_config() {
+        let config = Config::new(
+            Bps(115_200),
+            Parity::NoParity,
+            ChannelMode::Normal,
+            CharLength::EightBit,
+            SyncMode::Async,
+            UsartMode::Normal,
+        );
+        // 150 MHz / 16 / 115200 is 81.38, closest to 81 + 3/8.
+        assert_eq!(
+            Registers::new(&config, Hertz(150_000_000)),
+            Registers { mr: 4 << 9 | 3 << 6, brgr: 3 << 16 | 81, ttgr: 0 }
+        );
+
+        let config = Config::new(
+            Bps(9_600),
+            Parity::Odd,
+            ChannelMode::LocalLoopback,
+            CharLength::NineBit,
+            SyncMode::Async,
+            UsartMode::Rs485,
+        )
+        .two_stop_bits(true)
+        .rx_filter(true)
+        .timeguard(4);
+        let registers = Registers::new(&config, Hertz(12_000_000));
+        assert_eq!(registers.mr, 0x1 | 3 << 6 | 1 << 9 | 2 << 12 | 2 << 14 | MR_MODE9 | MR_FILTER);
+        assert_eq!((registers.brgr, registers.ttgr), (1 << 16 | 78, 4));
+
+        // 150 MHz / 1 MHz, with neither the /16 nor FP.
+        let config = Config::new(
+            Bps(1_000_000),
+            Parity::NoParity,
+            ChannelMode::Normal,
+            CharLength::EightBit,
+            SyncMode::Async,
+            UsartMode::SPIMaster,
+        );
+        assert_eq!(Registers::new(&config, Hertz(150_000_000)).brgr, 150);
+        let config = Config::new(
+            Bps(400_000),
+            Parity::NoParity,
+            ChannelMode::Normal,
+            CharLength::EightBit,
+            SyncMode::Sync,
+            UsartMode::Normal,
+        );
+        assert_eq!(Registers::new(&config, Hertz(150_000_000)).brgr, 375);
+    }
 }
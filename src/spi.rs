@@ -0,0 +1,130 @@
+//! SPI bus sharing.
+//!
+//! Several `SpiDevice`s borrow one bus through a critical section mutex, each
+//! with its own GPIO chip select and, optionally, its own bus settings:
+//!
+//! ```ignore
+//! let bus = Mutex::new(RefCell::new(spi));
+//! let mut flash = SpiDevice::new(&bus, flash_cs, delay);
+//! let mut sensor = SpiDevice::with_config(&bus, sensor_cs, delay, sensor_mode);
+//! ```
+
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_1::delay::DelayNs;
+use embedded_hal_1::spi::{self, ErrorType, Operation, SpiBus};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpiError {
+    Overrun,
+    ModeFault,
+    Underrun,
+}
+
+impl spi::Error for SpiError {
+    fn kind(&self) -> spi::ErrorKind {
+        match self {
+            SpiError::Overrun => spi::ErrorKind::Overrun,
+            SpiError::ModeFault => spi::ErrorKind::ModeFault,
+            SpiError::Underrun => spi::ErrorKind::Other,
+        }
+    }
+}
+
+/// Error of a device on a shared SPI bus, from the bus itself or from
+/// driving its chip select.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpiDeviceError<BUS, CS> {
+    Spi(BUS),
+    ChipSelect(CS),
+}
+
+impl<BUS: spi::Error, CS: core::fmt::Debug> spi::Error for SpiDeviceError<BUS, CS> {
+    fn kind(&self) -> spi::ErrorKind {
+        match self {
+            SpiDeviceError::Spi(e) => e.kind(),
+            SpiDeviceError::ChipSelect(_) => spi::ErrorKind::ChipSelectFault,
+        }
+    }
+}
+
+/// Bus settings, such as speed and mode, applied each time a device with
+/// config `C` acquires the bus.
+pub trait ConfigureBus<C> {
+    fn configure(&mut self, config: &C);
+}
+
+/// Devices without a config leave the bus as it is.
+impl<BUS> ConfigureBus<()> for BUS {
+    fn configure(&mut self, _: &()) {}
+}
+
+pub struct SpiDevice<'a, BUS, CS, D, C = ()> {
+    bus: &'a Mutex<RefCell<BUS>>,
+    cs: CS,
+    delay: D,
+    config: C,
+}
+
+impl<'a, BUS, CS: OutputPin, D> SpiDevice<'a, BUS, CS, D> {
+    pub fn new(bus: &'a Mutex<RefCell<BUS>>, cs: CS, delay: D) -> Self {
+        SpiDevice::with_config(bus, cs, delay, ())
+    }
+}
+
+impl<'a, BUS, CS: OutputPin, D, C> SpiDevice<'a, BUS, CS, D, C> {
+    /// The chip select is driven high, deselecting the device, right away.
+    pub fn with_config(bus: &'a Mutex<RefCell<BUS>>, mut cs: CS, delay: D, config: C) -> Self {
+        let _ = cs.set_high();
+        SpiDevice { bus, cs, delay, config }
+    }
+
+    pub fn free(self) -> (CS, D, C) {
+        (self.cs, self.delay, self.config)
+    }
+}
+
+impl<BUS, CS, D, C> ErrorType for SpiDevice<'_, BUS, CS, D, C>
+where
+    BUS: ErrorType,
+    CS: OutputPin,
+    CS::Error: core::fmt::Debug,
+{
+    type Error = SpiDeviceError<BUS::Error, CS::Error>;
+}
+
+impl<BUS, CS, D, C> spi::SpiDevice<u8> for SpiDevice<'_, BUS, CS, D, C>
+where
+    BUS: SpiBus<u8> + ConfigureBus<C>,
+    CS: OutputPin,
+    CS::Error: core::fmt::Debug,
+    D: DelayNs,
+{
+    /// Holds the bus, with interrupts disabled, for the whole transaction.
+    /// The bus is flushed before the chip select is released, also when an
+    /// operation fails.
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        cortex_m::interrupt::free(|cs| {
+            let mut bus = self.bus.borrow(cs).borrow_mut();
+            ConfigureBus::<C>::configure(&mut *bus, &self.config);
+            self.cs.set_low().map_err(SpiDeviceError::ChipSelect)?;
+
+            let result = operations.iter_mut().try_for_each(|operation| match operation {
+                Operation::Read(words) => bus.read(words),
+                Operation::Write(words) => bus.write(words),
+                Operation::Transfer(read, write) => bus.transfer(read, write),
+                Operation::TransferInPlace(words) => bus.transfer_in_place(words),
+                Operation::DelayNs(ns) => {
+                    bus.flush()?;
+                    self.delay.delay_ns(*ns);
+                    Ok(())
+                }
+            });
+            let flushed = result.and_then(|_| bus.flush());
+            let released = self.cs.set_high();
+            flushed.map_err(SpiDeviceError::Spi)?;
+            released.map_err(SpiDeviceError::ChipSelect)
+        })
+    }
+}
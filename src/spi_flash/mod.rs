@@ -0,0 +1,272 @@
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::OutputPin;
+
+pub mod sfdp;
+
+use sfdp::{AddressBytes, EraseType, FlashParameters, SfdpError, Width};
+
+const CMD_READ_SFDP: u8 = 0x5A;
+const CMD_READ_JEDEC_ID: u8 = 0x9F;
+const CMD_FAST_READ: u8 = 0x0B;
+const CMD_WRITE_ENABLE: u8 = 0x06;
+const CMD_READ_STATUS: u8 = 0x05;
+const CMD_PAGE_PROGRAM: u8 = 0x02;
+const CMD_ENTER_4_BYTE_ADDRESS: u8 = 0xB7;
+const STATUS_BUSY: u8 = 0x01;
+const THREE_BYTE_LIMIT: u32 = 1 << 24;
+const MAX_BASIC_TABLE_DWORDS: usize = 24;
+
+pub struct Command {
+    pub width: Width,
+    pub opcode: u8,
+    pub address: Option<u32>,
+    pub address_bytes: u8,
+    pub dummy_cycles: u8,
+}
+
+impl Command {
+    fn new(opcode: u8) -> Command {
+        Command { width: Width::Single, opcode, address: None, address_bytes: 0, dummy_cycles: 0 }
+    }
+
+    fn address(mut self, address: u32, address_bytes: u8) -> Command {
+        self.address = Some(address);
+        self.address_bytes = address_bytes;
+        self
+    }
+
+    fn dummy_cycles(mut self, dummy_cycles: u8) -> Command {
+        self.dummy_cycles = dummy_cycles;
+        self
+    }
+}
+
+/// Bus used to talk to the flash, either SPI or QSPI.
+pub trait Transport {
+    type Error;
+
+    fn supports(&self, width: Width) -> bool;
+
+    fn read(&mut self, command: &Command, data: &mut [u8]) -> Result<(), Self::Error>;
+
+    fn write(&mut self, command: &Command, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Single-line transport over any blocking SPI bus and chip-select pin.
+pub struct SpiTransport<SPI, CS> {
+    spi: SPI,
+    cs: CS,
+}
+
+impl<SPI, CS> SpiTransport<SPI, CS> {
+    pub fn new(spi: SPI, cs: CS) -> Self {
+        SpiTransport { spi, cs }
+    }
+
+    pub fn free(self) -> (SPI, CS) {
+        (self.spi, self.cs)
+    }
+}
+
+impl<SPI, CS, E> SpiTransport<SPI, CS>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin,
+{
+    fn transaction<F>(&mut self, command: &Command, f: F) -> Result<(), E>
+    where
+        F: FnOnce(&mut SPI) -> Result<(), E>,
+    {
+        let mut header = [0u8; 9];
+        header[0] = command.opcode;
+        let mut len = 1;
+        if let Some(address) = command.address {
+            let bytes = address.to_be_bytes();
+            let count = command.address_bytes as usize;
+            header[len..len + count].copy_from_slice(&bytes[4 - count..]);
+            len += count;
+        }
+        len += command.dummy_cycles as usize / 8;
+
+        self.cs.set_low().ok();
+        let result = self.spi.write(&header[..len]).and_then(|_| f(&mut self.spi));
+        self.cs.set_high().ok();
+        result
+    }
+}
+
+impl<SPI, CS, E> Transport for SpiTransport<SPI, CS>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin,
+{
+    type Error = E;
+
+    fn supports(&self, width: Width) -> bool {
+        width == Width::Single
+    }
+
+    fn read(&mut self, command: &Command, data: &mut [u8]) -> Result<(), E> {
+        self.transaction(command, |spi| spi.transfer(data).map(|_| ()))
+    }
+
+    fn write(&mut self, command: &Command, data: &[u8]) -> Result<(), E> {
+        self.transaction(command, |spi| if data.is_empty() { Ok(()) } else { spi.write(data) })
+    }
+}
+
+#[derive(Debug)]
+pub enum Error<E> {
+    Transport(E),
+    Sfdp(SfdpError),
+    OutOfBounds,
+    Unaligned,
+}
+
+impl<E> From<SfdpError> for Error<E> {
+    fn from(error: SfdpError) -> Self {
+        Error::Sfdp(error)
+    }
+}
+
+pub struct SpiFlash<T> {
+    transport: T,
+    jedec_id: [u8; 3],
+    parameters: FlashParameters,
+    address_bytes: u8,
+    read_opcode: u8,
+    read_width: Width,
+    read_dummy_cycles: u8,
+}
+
+impl<T: Transport> SpiFlash<T> {
+    /// Reads the JEDEC ID and SFDP tables, then picks the widest read mode
+    /// supported by both the flash and the transport.
+    pub fn probe(mut transport: T) -> Result<Self, Error<T::Error>> {
+        let mut jedec_id = [0u8; 3];
+        transport.read(&Command::new(CMD_READ_JEDEC_ID), &mut jedec_id).map_err(Error::Transport)?;
+
+        let mut headers = [0u8; 64];
+        transport.read(&Command::new(CMD_READ_SFDP).address(0, 3).dummy_cycles(8), &mut headers)
+            .map_err(Error::Transport)?;
+        let (pointer, length) = sfdp::find_basic_table(&headers)?;
+
+        let mut table = [0u8; MAX_BASIC_TABLE_DWORDS * 4];
+        let length = length.min(MAX_BASIC_TABLE_DWORDS);
+        transport.read(&Command::new(CMD_READ_SFDP).address(pointer, 3).dummy_cycles(8), &mut table[..length * 4])
+            .map_err(Error::Transport)?;
+        let mut dwords = [0u32; MAX_BASIC_TABLE_DWORDS];
+        for (dword, bytes) in dwords.iter_mut().zip(table.chunks_exact(4)) {
+            *dword = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let parameters = sfdp::parse_basic_table(&dwords[..length])?;
+
+        let address_bytes = match parameters.address_bytes {
+            AddressBytes::Three => 3,
+            AddressBytes::ThreeOrFour if parameters.size <= THREE_BYTE_LIMIT => 3,
+            AddressBytes::ThreeOrFour => {
+                transport.write(&Command::new(CMD_ENTER_4_BYTE_ADDRESS), &[]).map_err(Error::Transport)?;
+                4
+            }
+            AddressBytes::Four => 4,
+        };
+
+        let (read_opcode, read_width, read_dummy_cycles) = parameters.read_modes
+            .iter()
+            .rev()
+            .flatten()
+            .find(|mode| transport.supports(mode.width))
+            .map_or((CMD_FAST_READ, Width::Single, 8), |mode| (mode.opcode, mode.width, mode.dummy_cycles));
+
+        Ok(SpiFlash {
+            transport,
+            jedec_id,
+            parameters,
+            address_bytes,
+            read_opcode,
+            read_width,
+            read_dummy_cycles,
+        })
+    }
+
+    pub fn jedec_id(&self) -> [u8; 3] {
+        self.jedec_id
+    }
+
+    pub fn parameters(&self) -> &FlashParameters {
+        &self.parameters
+    }
+
+    pub fn free(self) -> T {
+        self.transport
+    }
+
+    fn check_bounds(&self, address: u32, length: usize) -> Result<(), Error<T::Error>> {
+        match address.checked_add(length as u32) {
+            Some(end) if end <= self.parameters.size => Ok(()),
+            _ => Err(Error::OutOfBounds),
+        }
+    }
+
+    fn write_enable(&mut self) -> Result<(), Error<T::Error>> {
+        self.transport.write(&Command::new(CMD_WRITE_ENABLE), &[]).map_err(Error::Transport)
+    }
+
+    fn wait_ready(&mut self) -> Result<(), Error<T::Error>> {
+        let mut status = [STATUS_BUSY];
+        while status[0] & STATUS_BUSY != 0 {
+            self.transport.read(&Command::new(CMD_READ_STATUS), &mut status).map_err(Error::Transport)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(&mut self, address: u32, data: &mut [u8]) -> Result<(), Error<T::Error>> {
+        self.check_bounds(address, data.len())?;
+        let mut command = Command::new(self.read_opcode)
+            .address(address, self.address_bytes)
+            .dummy_cycles(self.read_dummy_cycles);
+        command.width = self.read_width;
+        self.transport.read(&command, data).map_err(Error::Transport)
+    }
+
+    /// Programs `data` page by page; the area must have been erased.
+    pub fn program(&mut self, address: u32, data: &[u8]) -> Result<(), Error<T::Error>> {
+        self.check_bounds(address, data.len())?;
+        let page_size = self.parameters.page_size;
+        let mut address = address;
+        let mut data = data;
+        while !data.is_empty() {
+            let chunk = ((page_size - address % page_size) as usize).min(data.len());
+            self.write_enable()?;
+            self.transport.write(&Command::new(CMD_PAGE_PROGRAM).address(address, self.address_bytes), &data[..chunk])
+                .map_err(Error::Transport)?;
+            self.wait_ready()?;
+            address += chunk as u32;
+            data = &data[chunk..];
+        }
+        Ok(())
+    }
+
+    /// Erases `length` bytes from `address` using the largest erase type
+    /// that fits at each step. Both must be aligned to the smallest one.
+    pub fn erase(&mut self, address: u32, length: u32) -> Result<(), Error<T::Error>> {
+        self.check_bounds(address, length as usize)?;
+        let end = address + length;
+        let mut address = address;
+        while address < end {
+            let erase_type = self.parameters.erase_types
+                .iter()
+                .rev()
+                .flatten()
+                .find(|erase| address.is_multiple_of(erase.size) && end - address >= erase.size)
+                .copied();
+            let EraseType { size, opcode } = erase_type.ok_or(Error::Unaligned)?;
+            self.write_enable()?;
+            self.transport.write(&Command::new(opcode).address(address, self.address_bytes), &[])
+                .map_err(Error::Transport)?;
+            self.wait_ready()?;
+            address += size;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+//! Parsing of the JEDEC (JESD216) Serial Flash Discoverable Parameters.
+
+pub const SIGNATURE: u32 = 0x5044_4653;
+const BASIC_TABLE_ID: u16 = 0xFF00;
+const DEFAULT_PAGE_SIZE: u32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SfdpError {
+    Signature,
+    NoBasicTable,
+    TableTooShort,
+}
+
+/// Bus widths as command-address-data lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Width {
+    Single,
+    Dual,
+    DualIo,
+    Quad,
+    QuadIo,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadMode {
+    pub width: Width,
+    pub opcode: u8,
+    /// Mode clocks are included in the dummy cycles.
+    pub dummy_cycles: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EraseType {
+    pub size: u32,
+    pub opcode: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlashParameters {
+    pub size: u32,
+    pub page_size: u32,
+    pub address_bytes: AddressBytes,
+    /// Sorted from the smallest to the largest erase size.
+    pub erase_types: [Option<EraseType>; 4],
+    /// Sorted from the narrowest to the widest bus.
+    pub read_modes: [Option<ReadMode>; 4],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressBytes {
+    Three,
+    ThreeOrFour,
+    Four,
+}
+
+/// Returns the byte address and length in dwords of the basic flash
+/// parameter table, from the SFDP header and the parameter headers that
+/// follow it.
+pub fn find_basic_table(headers: &[u8]) -> Result<(u32, usize), SfdpError> {
+    if headers.len() < 16 || u32::from_le_bytes([headers[0], headers[1], headers[2], headers[3]]) != SIGNATURE {
+        return Err(SfdpError::Signature);
+    }
+    let count = headers[6] as usize + 1;
+    headers[8..]
+        .chunks_exact(8)
+        .take(count)
+        .find(|header| u16::from_le_bytes([header[0], header[7]]) == BASIC_TABLE_ID)
+        .map(|header| {
+            let pointer = u32::from_le_bytes([header[4], header[5], header[6], 0]);
+            (pointer, header[3] as usize)
+        })
+        .ok_or(SfdpError::NoBasicTable)
+}
+
+pub fn parse_basic_table(dwords: &[u32]) -> Result<FlashParameters, SfdpError> {
+    if dwords.len() < 9 {
+        return Err(SfdpError::TableTooShort);
+    }
+    let field = |dword: u32, shift: u32, bits: u32| (dword >> shift) & ((1 << bits) - 1);
+
+    let address_bytes = match field(dwords[0], 17, 2) {
+        0 => AddressBytes::Three,
+        1 => AddressBytes::ThreeOrFour,
+        _ => AddressBytes::Four,
+    };
+
+    let density = dwords[1];
+    let size_bits = if density & 0x8000_0000 == 0 {
+        density as u64 + 1
+    } else {
+        1u64 << (density & 0x7FFF_FFFF)
+    };
+    let size = (size_bits / 8).min(u32::MAX as u64) as u32;
+
+    let mut erase_types = [None; 4];
+    for (i, erase_type) in erase_types.iter_mut().enumerate() {
+        let dword = dwords[7 + i / 2];
+        let shift = (i as u32 % 2) * 16;
+        let exponent = field(dword, shift, 8);
+        if exponent != 0 {
+            *erase_type = Some(EraseType {
+                size: 1 << exponent,
+                opcode: field(dword, shift + 8, 8) as u8,
+            });
+        }
+    }
+    if erase_types.iter().all(Option::is_none) && field(dwords[0], 0, 2) == 1 {
+        erase_types[0] = Some(EraseType { size: 4096, opcode: field(dwords[0], 8, 8) as u8 });
+    }
+    erase_types.sort_unstable_by_key(|erase_type| erase_type.map_or(u32::MAX, |e| e.size));
+
+    let read_mode = |supported: bool, width: Width, dword: u32, shift: u32| {
+        if supported {
+            Some(ReadMode {
+                width,
+                opcode: field(dword, shift + 8, 8) as u8,
+                dummy_cycles: (field(dword, shift, 5) + field(dword, shift + 5, 3)) as u8,
+            })
+        } else {
+            None
+        }
+    };
+    let read_modes = [
+        read_mode(dwords[0] & (1 << 16) != 0, Width::Dual, dwords[3], 0),
+        read_mode(dwords[0] & (1 << 20) != 0, Width::DualIo, dwords[3], 16),
+        read_mode(dwords[0] & (1 << 22) != 0, Width::Quad, dwords[2], 16),
+        read_mode(dwords[0] & (1 << 21) != 0, Width::QuadIo, dwords[2], 0),
+    ];
+
+    let page_size = match dwords.get(10) {
+        Some(&dword) => 1 << field(dword, 4, 4),
+        None => DEFAULT_PAGE_SIZE,
+    };
+
+    Ok(FlashParameters { size, page_size, address_bytes, erase_types, read_modes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SFDP of a Winbond W25Q128JV: the header, one parameter header and
+    /// the 16 dwords of the basic table it points to at 0x80.
+    const W25Q128_HEADERS: [u8; 16] = [
+        0x53, 0x46, 0x44, 0x50, 0x05, 0x01, 0x00, 0xFF,
+        0x00, 0x05, 0x01, 0x10, 0x80, 0x00, 0x00, 0xFF,
+    ];
+    const W25Q128_BASIC: [u32; 16] = [
+        0xFFF9_20E5, 0x07FF_FFFF, 0x6B08_EB44, 0xBB42_3B08,
+        0xFFFF_FFFE, 0x0000_FFFF, 0xEB44_FFFF, 0x520F_200C,
+        0x0000_D810, 0x00A6_0236, 0xC914_EA82, 0x3376_63E9,
+        0x757A_757A, 0x5CD5_A2F7, 0xFF4D_F719, 0x80F8_30E9,
+    ];
+
+    #[test]
+    fn w25q128() {
+        assert_eq!(find_basic_table(&W25Q128_HEADERS), Ok((0x80, 16)));
+
+        let parameters = parse_basic_table(&W25Q128_BASIC).unwrap();
+        assert_eq!(parameters.size, 16 * 1024 * 1024);
+        assert_eq!(parameters.page_size, 256);
+        assert_eq!(parameters.address_bytes, AddressBytes::Three);
+        assert_eq!(parameters.erase_types, [
+            Some(EraseType { size: 4096, opcode: 0x20 }),
+            Some(EraseType { size: 32 * 1024, opcode: 0x52 }),
+            Some(EraseType { size: 64 * 1024, opcode: 0xD8 }),
+            None,
+        ]);
+        assert_eq!(parameters.read_modes, [
+            Some(ReadMode { width: Width::Dual, opcode: 0x3B, dummy_cycles: 8 }),
+            Some(ReadMode { width: Width::DualIo, opcode: 0xBB, dummy_cycles: 4 }),
+            Some(ReadMode { width: Width::Quad, opcode: 0x6B, dummy_cycles: 8 }),
+            Some(ReadMode { width: Width::QuadIo, opcode: 0xEB, dummy_cycles: 6 }),
+        ]);
+    }
+
+    #[test]
+    fn legacy_4k_erase() {
+        // No erase types in dwords 8 and 9: only the 4-KiB opcode of dword 1.
+        let mut dwords = W25Q128_BASIC;
+        dwords[7] = 0;
+        dwords[8] = 0;
+        let parameters = parse_basic_table(&dwords).unwrap();
+        assert_eq!(parameters.erase_types[0], Some(EraseType { size: 4096, opcode: 0x20 }));
+        assert_eq!(parameters.erase_types[1..], [None; 3]);
+    }
+
+    #[test]
+    fn density_as_power_of_two() {
+        let mut dwords = W25Q128_BASIC;
+        // 4 Gbit.
+        dwords[1] = 0x8000_0020;
+        assert_eq!(parse_basic_table(&dwords).unwrap().size, 512 * 1024 * 1024);
+        // 32 Gbit no longer fits the 32-bit size.
+        dwords[1] = 0x8000_0023;
+        assert_eq!(parse_basic_table(&dwords).unwrap().size, u32::MAX);
+    }
+
+    #[test]
+    fn malformed_headers() {
+        // A vendor table only.
+        let mut headers = W25Q128_HEADERS;
+        headers[8] = 0x84;
+        headers[15] = 0xEF;
+        assert_eq!(find_basic_table(&headers), Err(SfdpError::NoBasicTable));
+        headers[0] = 0;
+        assert_eq!(find_basic_table(&headers), Err(SfdpError::Signature));
+        assert_eq!(parse_basic_table(&W25Q128_BASIC[..8]), Err(SfdpError::TableTooShort));
+    }
+}
@@ -0,0 +1,434 @@
+//! Synchronous serial controller, with clock and frame sync fully exposed
+//! for TDM links and sensor interfaces that are not plain I2S.
+//!
+//! Each direction takes its own `Config`. Pins go to the SSC peripheral
+//! function before `GenericSsc::new`.
+//!
+//! ```ignore
+//! // 8 slots of 24 bits, with a one clock wide frame sync pulse.
+//! let config = ssc::Config::new(24, 8)
+//!     .clock_output(ClockOutput::Continuous)
+//!     .frame_sync(FrameSync::PositivePulse, 1)
+//!     .frame_period(256);
+//! let mut ssc = GenericSsc::new(hal.ssc, 12_288_000.hz(), &hal.clocks, &hal.pmc);
+//! ssc.configure_transmitter(&config);
+//! ssc.enable_transmitter();
+//! ```
+//!
+//! `Tdm` covers the usual multi-slot audio links, with frames streamed
+//! interleaved, slot 0 first, through XDMAC ping-pong buffers:
+//!
+//! ```ignore
+//! static mut BUFFERS: PingPongBuffers<256> = PingPongBuffers::new(0);
+//!
+//! let tdm = Tdm::new(8, 32);
+//! let mut ssc = GenericSsc::new(hal.ssc, tdm.bit_clock(48_000.hz()), &hal.clocks, &hal.pmc);
+//! let mut mics = ssc.tdm_receive(&tdm, true, xdmac.ch1, buffers);
+//! mics.poll(|words| for frame in tdm.frames(words) { /* frame[slot] */ });
+//! ```
+
+use core::convert::Infallible;
+use crate::clocks::Clocks;
+use crate::dma::{self, peripheral::{PingPong, PingPongBuffers, SscRx, SscTx}};
+use crate::pac::{PMC, SSC};
+use crate::pmc::PeripheralId;
+use crate::time::Hertz;
+use crate::write_protect::WriteProtect;
+
+const TXRDY: u32 = 1 << 0;
+const TXEMPTY: u32 = 1 << 1;
+const RXRDY: u32 = 1 << 4;
+const OVRUN: u32 = 1 << 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SscError {
+    /// A received word was overwritten before being read.
+    Overrun,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockSource {
+    /// MCK divided down to the rate given to `GenericSsc::new`.
+    Divided,
+    /// The clock of the other direction.
+    Other,
+    /// The TK or RK pin.
+    Pin,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockOutput {
+    /// The pin is an input.
+    None,
+    Continuous,
+    /// Only while data is transferred.
+    Transfer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StartCondition {
+    /// As soon as the direction is enabled, then right after each frame.
+    Continuous,
+    /// Along with the other direction.
+    Other,
+    SyncLow,
+    SyncHigh,
+    SyncFalling,
+    SyncRising,
+    SyncLevelChange,
+    SyncAnyEdge,
+}
+
+/// Frame sync driven on TF or RF, at each start of frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameSync {
+    /// The pin is an input.
+    None,
+    NegativePulse,
+    PositivePulse,
+    Low,
+    High,
+    Toggling,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    clock: ClockSource,
+    clock_output: ClockOutput,
+    invert_clock: bool,
+    start: StartCondition,
+    start_delay: u8,
+    period: u8,
+    data_length: u8,
+    words: u8,
+    msb_first: bool,
+    sync: FrameSync,
+    sync_length: u16,
+    sync_falling: bool,
+}
+
+impl Config {
+    /// Frames of `words`, 1 to 16, of `data_length` bits, 2 to 32, MSB
+    /// first, clocked from MCK and started continuously.
+    pub fn new(data_length: u8, words: u8) -> Self {
+        assert!((2..=32).contains(&data_length), "SSC data length out of range");
+        assert!((1..=16).contains(&words), "SSC words per frame out of range");
+        Config {
+            clock: ClockSource::Divided,
+            clock_output: ClockOutput::None,
+            invert_clock: false,
+            start: StartCondition::Continuous,
+            start_delay: 0,
+            period: 0,
+            data_length,
+            words,
+            msb_first: true,
+            sync: FrameSync::None,
+            sync_length: 1,
+            sync_falling: false,
+        }
+    }
+
+    pub fn clock(mut self, source: ClockSource) -> Self {
+        self.clock = source;
+        self
+    }
+
+    pub fn clock_output(mut self, output: ClockOutput) -> Self {
+        self.clock_output = output;
+        self
+    }
+
+    /// The transmitter shifts out on rising edges rather than falling ones,
+    /// the receiver samples on rising edges rather than falling ones.
+    pub fn invert_clock(mut self, invert: bool) -> Self {
+        self.invert_clock = invert;
+        self
+    }
+
+    /// Starts a frame on `condition`, `delay` clocks later.
+    pub fn start(mut self, condition: StartCondition, delay: u8) -> Self {
+        self.start = condition;
+        self.start_delay = delay;
+        self
+    }
+
+    /// Generates a start of frame every `clocks` clocks, an even number up
+    /// to 512.
+    pub fn frame_period(mut self, clocks: u16) -> Self {
+        assert!((2..=512).contains(&clocks) && clocks.is_multiple_of(2), "SSC frame period out of range");
+        self.period = (clocks / 2 - 1) as u8;
+        self
+    }
+
+    pub fn lsb_first(mut self) -> Self {
+        self.msb_first = false;
+        self
+    }
+
+    /// Drives `sync` for `length` clocks, 1 to 256.
+    pub fn frame_sync(mut self, sync: FrameSync, length: u16) -> Self {
+        assert!((1..=256).contains(&length), "SSC frame sync length out of range");
+        self.sync = sync;
+        self.sync_length = length;
+        self
+    }
+
+    /// Flags frame sync falling edges in the status rather than rising ones.
+    pub fn sync_edge_falling(mut self, falling: bool) -> Self {
+        self.sync_falling = falling;
+        self
+    }
+
+    /// RCMR or TCMR value.
+    fn clock_mode(&self) -> u32 {
+        self.clock as u32
+            | (self.clock_output as u32) << 2
+            | (self.invert_clock as u32) << 5
+            | (self.start as u32) << 8
+            | (self.start_delay as u32) << 16
+            | (self.period as u32) << 24
+    }
+
+    /// RFMR or TFMR value.
+    fn frame_mode(&self) -> u32 {
+        let sync_length = self.sync_length as u32 - 1;
+        (self.data_length as u32 - 1)
+            | (self.msb_first as u32) << 7
+            | (self.words as u32 - 1) << 8
+            | (sync_length & 0xF) << 16
+            | (self.sync as u32) << 20
+            | (self.sync_falling as u32) << 24
+            | (sync_length >> 4) << 28
+    }
+}
+
+/// Frames of `slots` words of `slot_width` bits, started by a one clock
+/// frame sync pulse, data from the next clock on, as DSP mode A codecs and
+/// TDM microphones expect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tdm {
+    slots: u8,
+    slot_width: u8,
+}
+
+impl Tdm {
+    /// Up to 16 slots, of 8 to 32 bits, as long as a frame is at most 512
+    /// clocks and an even number of them.
+    pub fn new(slots: u8, slot_width: u8) -> Self {
+        assert!((1..=16).contains(&slots), "TDM slot count out of range");
+        assert!((8..=32).contains(&slot_width), "TDM slot width out of range");
+        let tdm = Tdm { slots, slot_width };
+        assert!(tdm.frame_clocks() <= 512 && tdm.frame_clocks().is_multiple_of(2), "TDM frame length out of range");
+        tdm
+    }
+
+    pub fn slots(&self) -> u8 {
+        self.slots
+    }
+
+    fn frame_clocks(&self) -> u16 {
+        self.slots as u16 * self.slot_width as u16
+    }
+
+    /// Bit clock for frames at `sample_rate`.
+    pub fn bit_clock(&self, sample_rate: Hertz) -> Hertz {
+        Hertz(sample_rate.0 * self.frame_clocks() as u32)
+    }
+
+    /// `master` drives the clock and the frame sync of the direction,
+    /// otherwise both are inputs.
+    fn config(&self, master: bool) -> Config {
+        let config = Config::new(self.slot_width, self.slots).start(StartCondition::SyncRising, 1);
+        if master {
+            config
+                .clock_output(ClockOutput::Continuous)
+                .frame_sync(FrameSync::PositivePulse, 1)
+                .frame_period(self.frame_clocks())
+        } else {
+            config.clock(ClockSource::Pin)
+        }
+    }
+
+    /// Data shifted out on falling edges.
+    pub fn transmitter(&self, master: bool) -> Config {
+        self.config(master)
+    }
+
+    /// Data sampled on rising edges.
+    pub fn receiver(&self, master: bool) -> Config {
+        self.config(master).invert_clock(true)
+    }
+
+    /// Splits interleaved `words` into frames, indexed by slot. Words past
+    /// the last whole frame are left out.
+    pub fn frames<'a>(&self, words: &'a [u32]) -> core::slice::ChunksExact<'a, u32> {
+        words.chunks_exact(self.slots as usize)
+    }
+
+    pub fn frames_mut<'a>(&self, words: &'a mut [u32]) -> core::slice::ChunksExactMut<'a, u32> {
+        words.chunks_exact_mut(self.slots as usize)
+    }
+}
+
+/// DIV of CMR for a divided clock of `bit_clock`, MCK / (2 × DIV).
+fn divider(mck: Hertz, bit_clock: Hertz) -> u16 {
+    let div = (mck.0 + bit_clock.0) / (2 * bit_clock.0);
+    assert!((1..4096).contains(&div), "SSC bit clock out of range");
+    div as u16
+}
+
+pub struct GenericSsc {
+    ssc: SSC,
+}
+
+impl GenericSsc {
+    /// Resets the SSC, both directions disabled, with the divided clock at
+    /// the closest to `bit_clock`.
+    pub fn new(ssc: SSC, bit_clock: Hertz, clocks: &Clocks, pmc: &PMC) -> Self {
+        pmc.with_unlocked(|pmc| unsafe { pmc.pmc_pcer0.write_with_zero(|w| w.bits(1 << SSC::PID)) });
+        let div = divider(clocks.mck(), bit_clock);
+        ssc.with_unlocked(|ssc| unsafe {
+            ssc.cr.write_with_zero(|w| w.swrst().set_bit());
+            ssc.cmr.write(|w| w.div().bits(div));
+        });
+        GenericSsc { ssc }
+    }
+
+    pub fn free(self, pmc: &PMC) -> SSC {
+        unsafe { self.ssc.cr.write_with_zero(|w| w.rxdis().set_bit().txdis().set_bit()) };
+        pmc.with_unlocked(|pmc| unsafe { pmc.pmc_pcdr0.write_with_zero(|w| w.bits(1 << SSC::PID)) });
+        self.ssc
+    }
+
+    pub fn configure_transmitter(&mut self, config: &Config) {
+        self.ssc.with_unlocked(|ssc| unsafe {
+            ssc.tcmr.write(|w| w.bits(config.clock_mode()));
+            ssc.tfmr.write(|w| w.bits(config.frame_mode()));
+        });
+    }
+
+    pub fn configure_receiver(&mut self, config: &Config) {
+        self.ssc.with_unlocked(|ssc| unsafe {
+            ssc.rcmr.write(|w| w.bits(config.clock_mode()));
+            ssc.rfmr.write(|w| w.bits(config.frame_mode()));
+        });
+    }
+
+    pub fn enable_transmitter(&mut self) {
+        unsafe { self.ssc.cr.write_with_zero(|w| w.txen().set_bit()) };
+    }
+
+    pub fn disable_transmitter(&mut self) {
+        unsafe { self.ssc.cr.write_with_zero(|w| w.txdis().set_bit()) };
+    }
+
+    pub fn enable_receiver(&mut self) {
+        unsafe { self.ssc.cr.write_with_zero(|w| w.rxen().set_bit()) };
+    }
+
+    pub fn disable_receiver(&mut self) {
+        unsafe { self.ssc.cr.write_with_zero(|w| w.rxdis().set_bit()) };
+    }
+
+    /// Queues one word, right aligned.
+    pub fn write(&mut self, word: u32) -> nb::Result<(), Infallible> {
+        if self.ssc.sr.read().bits() & TXRDY == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        unsafe { self.ssc.thr.write_with_zero(|w| w.bits(word)) };
+        Ok(())
+    }
+
+    /// Whether every queued word was shifted out.
+    pub fn is_tx_empty(&self) -> bool {
+        self.ssc.sr.read().bits() & TXEMPTY != 0
+    }
+
+    /// Takes one received word, right aligned. An overrun is reported once,
+    /// the word that came last being kept.
+    pub fn read(&mut self) -> nb::Result<u32, SscError> {
+        let sr = self.ssc.sr.read().bits();
+        if sr & RXRDY == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        let word = self.ssc.rhr.read().bits();
+        if sr & OVRUN != 0 {
+            return Err(nb::Error::Other(SscError::Overrun));
+        }
+        Ok(word)
+    }
+
+    /// Sends frames of `tdm` from `buffers`, interleaved, through `dma`, `N`
+    /// being a whole number of frames.
+    pub fn tdm_transmit<const CH: u8, const N: usize>(
+        &mut self,
+        tdm: &Tdm,
+        master: bool,
+        dma: dma::Channel<CH>,
+        buffers: &'static mut PingPongBuffers<N>,
+    ) -> PingPong<CH, SscTx, N> {
+        assert!(N.is_multiple_of(tdm.slots as usize), "TDM buffers not made of whole frames");
+        self.configure_transmitter(&tdm.transmitter(master));
+        let stream = dma::peripheral::memory_to_peripheral_ping_pong(dma, SscTx, buffers);
+        self.enable_transmitter();
+        stream
+    }
+
+    /// Receives frames of `tdm` into `buffers`, interleaved, through `dma`,
+    /// `N` being a whole number of frames.
+    pub fn tdm_receive<const CH: u8, const N: usize>(
+        &mut self,
+        tdm: &Tdm,
+        master: bool,
+        dma: dma::Channel<CH>,
+        buffers: &'static mut PingPongBuffers<N>,
+    ) -> PingPong<CH, SscRx, N> {
+        assert!(N.is_multiple_of(tdm.slots as usize), "TDM buffers not made of whole frames");
+        self.configure_receiver(&tdm.receiver(master));
+        let stream = dma::peripheral::peripheral_to_memory_ping_pong(dma, SscRx, buffers);
+        self.enable_receiver();
+        stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_values() {
+        let config = Config::new(24, 8)
+            .clock_output(ClockOutput::Continuous)
+            .start(StartCondition::SyncRising, 1)
+            .frame_sync(FrameSync::PositivePulse, 20)
+            .frame_period(256);
+        assert_eq!(config.clock_mode(), 0x7F01_0504);
+        assert_eq!(config.frame_mode(), 0x1023_0797);
+        assert_eq!(Config::new(32, 1).lsb_first().frame_mode(), 0x1F);
+    }
+
+    #[test]
+    fn clock_divider() {
+        assert_eq!(divider(Hertz(150_000_000), Hertz(1_000_000)), 75);
+        // 5.77 MHz rather than 6 MHz.
+        assert_eq!(divider(Hertz(150_000_000), Hertz(6_000_000)), 13);
+    }
+
+    #[test]
+    fn tdm_configs() {
+        let tdm = Tdm::new(8, 32);
+        assert_eq!(tdm.bit_clock(Hertz(48_000)), Hertz(12_288_000));
+        // Divided clock out, rising edge of TF, one clock of delay, 256
+        // clock frames.
+        assert_eq!(tdm.transmitter(true).clock_mode(), 0x7F01_0504);
+        assert_eq!(tdm.transmitter(true).frame_mode(), 0x0020_079F);
+        assert_eq!(tdm.receiver(false).clock_mode(), 0x0001_0522);
+        assert_eq!(tdm.receiver(false).frame_mode(), 0x0000_079F);
+
+        let words = [0, 1, 2, 3, 4, 5, 6, 7, 10, 11, 12, 13, 14, 15, 16, 17, 20];
+        let mut frames = tdm.frames(&words);
+        assert_eq!(frames.nth(1).unwrap()[3], 13);
+        assert_eq!(frames.next(), None);
+    }
+}
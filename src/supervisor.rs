@@ -0,0 +1,109 @@
+//! Feeds the watchdog on behalf of several tasks, and only while each of
+//! them keeps checking in within its own deadline, so that one task stuck
+//! while the others run still ends in a reset.
+//!
+//! Times are ticks of any free-running counter wrapping at 32 bits, such as
+//! RTT or `cycles::Instant` ones.
+//!
+//! ```ignore
+//! let mut supervisor: Supervisor<_, 2> = Supervisor::new(watchdog);
+//! let network = supervisor.register(500, now()).unwrap();
+//! let sensors = supervisor.register(50, now()).unwrap();
+//! loop {
+//!     supervisor.check_in(sensors, now());
+//!     // ...
+//!     if let Err(late) = supervisor.service(now()) {
+//!         // Reset coming: log `late` while there is time.
+//!     }
+//! }
+//! ```
+
+use embedded_hal::watchdog::Watchdog;
+
+/// A task registered with a `Supervisor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TaskId(usize);
+
+pub struct Supervisor<W, const N: usize> {
+    watchdog: W,
+    deadlines: [u32; N],
+    check_ins: [u32; N],
+    tasks: usize,
+}
+
+impl<W: Watchdog, const N: usize> Supervisor<W, N> {
+    /// Takes a watchdog already started, with a period longer than the
+    /// interval between `service` calls.
+    pub fn new(watchdog: W) -> Self {
+        Supervisor { watchdog, deadlines: [0; N], check_ins: [0; N], tasks: 0 }
+    }
+
+    pub fn free(self) -> W {
+        self.watchdog
+    }
+
+    /// Adds a task that has to check in at most `deadline` ticks apart,
+    /// counted from `now`. `None` once `N` tasks are registered.
+    pub fn register(&mut self, deadline: u32, now: u32) -> Option<TaskId> {
+        if self.tasks == N {
+            return None;
+        }
+        self.deadlines[self.tasks] = deadline;
+        self.check_ins[self.tasks] = now;
+        self.tasks += 1;
+        Some(TaskId(self.tasks - 1))
+    }
+
+    pub fn check_in(&mut self, task: TaskId, now: u32) {
+        self.check_ins[task.0] = now;
+    }
+
+    /// First task past its deadline at `now`, if any.
+    pub fn late(&self, now: u32) -> Option<TaskId> {
+        (0..self.tasks)
+            .find(|&i| now.wrapping_sub(self.check_ins[i]) > self.deadlines[i])
+            .map(TaskId)
+    }
+
+    /// Feeds the watchdog, unless a task is late, which is returned. Once
+    /// late, a task stays so until it checks in again.
+    pub fn service(&mut self, now: u32) -> Result<(), TaskId> {
+        match self.late(now) {
+            Some(task) => Err(task),
+            None => {
+                self.watchdog.feed();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Feeds(u32);
+
+    impl Watchdog for Feeds {
+        fn feed(&mut self) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn hung_task_stops_feeding() {
+        let mut supervisor: Supervisor<_, 2> = Supervisor::new(Feeds(0));
+        let fast = supervisor.register(10, u32::MAX - 5).unwrap();
+        let slow = supervisor.register(100, u32::MAX - 5).unwrap();
+        assert_eq!(supervisor.register(1, 0), None);
+
+        // Across the wrap of the tick counter.
+        assert_eq!(supervisor.service(4), Ok(()));
+        assert_eq!(supervisor.service(5), Err(fast));
+        supervisor.check_in(fast, 5);
+        assert_eq!(supervisor.service(15), Ok(()));
+        supervisor.check_in(fast, 90);
+        assert_eq!(supervisor.service(95), Err(slow));
+        assert_eq!(supervisor.free().0, 2);
+    }
+}
@@ -0,0 +1,78 @@
+//! Statics shared between the main loop and interrupt handlers, typically
+//! holding a driver half such as a `Tx<UART2>`, without `static mut`.
+//!
+//! ```ignore
+//! static TX: InterruptMutex<Tx<UART2>> = InterruptMutex::new();
+//!
+//! TX.init(tx);
+//! TX.lock(|tx| tx.write(b'!')).unwrap().ok();
+//! ```
+
+use core::cell::RefCell;
+use cortex_m::interrupt::{self, CriticalSection, Mutex};
+
+/// A value moved in once set up, and borrowed or taken back from within
+/// critical sections the caller already holds.
+pub struct CsCell<T> {
+    inner: Mutex<RefCell<Option<T>>>,
+}
+
+impl<T> CsCell<T> {
+    pub const fn new() -> Self {
+        CsCell { inner: Mutex::new(RefCell::new(None)) }
+    }
+
+    /// Moves `value` in and hands back the one it replaces.
+    pub fn put(&self, cs: &CriticalSection, value: T) -> Option<T> {
+        self.inner.borrow(cs).replace(Some(value))
+    }
+
+    pub fn take(&self, cs: &CriticalSection) -> Option<T> {
+        self.inner.borrow(cs).take()
+    }
+
+    /// Runs `f` on the value, `None` while there is none. Reaching the same
+    /// cell again from within `f` panics.
+    pub fn with<R>(&self, cs: &CriticalSection, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.inner.borrow(cs).borrow_mut().as_mut().map(f)
+    }
+}
+
+impl<T> Default for CsCell<T> {
+    fn default() -> Self {
+        CsCell::new()
+    }
+}
+
+/// A `CsCell` that masks interrupts by itself, for code that does not
+/// otherwise need a critical section.
+pub struct InterruptMutex<T> {
+    cell: CsCell<T>,
+}
+
+impl<T> InterruptMutex<T> {
+    pub const fn new() -> Self {
+        InterruptMutex { cell: CsCell::new() }
+    }
+
+    /// Moves `value` in and hands back the one it replaces.
+    pub fn init(&self, value: T) -> Option<T> {
+        interrupt::free(|cs| self.cell.put(cs, value))
+    }
+
+    pub fn take(&self) -> Option<T> {
+        interrupt::free(|cs| self.cell.take(cs))
+    }
+
+    /// Runs `f` on the value with interrupts masked, `None` before `init`.
+    /// `f` should be short, as it delays every interrupt.
+    pub fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        interrupt::free(|cs| self.cell.with(cs, f))
+    }
+}
+
+impl<T> Default for InterruptMutex<T> {
+    fn default() -> Self {
+        InterruptMutex::new()
+    }
+}
@@ -0,0 +1,107 @@
+use crate::time::Microseconds;
+use super::{Block, Channel, ClockSource, Instance, TioaPin, TiobPin};
+
+/// 2π in Q16.16.
+const TWO_PI_Q16: i64 = 411_775;
+
+/// Quadrature encoder on TIOA0 (phase A) and TIOB0 (phase B) of a TC, with
+/// the quadrature decoder counting every edge of both phases on channel 0.
+/// The counter is 16 bits and wraps; `sample` must run often enough that
+/// the shaft moves less than 32768 counts between two calls.
+pub struct Encoder<TC, PHA, PHB> {
+    channel: Channel<TC, 0>,
+    pins: (PHA, PHB),
+    counts_per_rev: u32,
+    window: Microseconds,
+    last: u16,
+    velocity: i32,
+}
+
+impl<TC, PHA, PHB> Encoder<TC, PHA, PHB>
+where
+    TC: Instance,
+    PHA: TioaPin<TC, 0>,
+    PHB: TiobPin<TC, 0>,
+{
+    /// `counts_per_rev` is four times the lines of the encoder, and
+    /// `window` the period at which `sample` is called.
+    pub fn new(
+        mut channel: Channel<TC, 0>,
+        block: &mut Block<TC>,
+        pins: (PHA, PHB),
+        counts_per_rev: u32,
+        window: Microseconds,
+    ) -> Self {
+        block.set_quadrature_decoder(true);
+        channel.disable();
+        channel.with_unlocked(|ch| ch.cmr0().reset());
+        channel.set_clock_source(ClockSource::Xc0);
+        channel.enable();
+        channel.trigger();
+        let last = channel.counter();
+        Encoder { channel, pins, counts_per_rev, window, last, velocity: 0 }
+    }
+
+    pub fn free(mut self, block: &mut Block<TC>) -> (Channel<TC, 0>, (PHA, PHB)) {
+        self.channel.disable();
+        block.set_quadrature_decoder(false);
+        (self.channel, self.pins)
+    }
+
+    /// Position in counts, wrapping at 16 bits.
+    pub fn position(&self) -> u16 {
+        self.channel.counter()
+    }
+
+    /// Updates the velocity with the counts since the previous call. To be
+    /// called every `window`, e.g. from a timer interrupt. Returns them.
+    pub fn sample(&mut self) -> i32 {
+        let position = self.channel.counter();
+        self.velocity = delta(self.last, position);
+        self.last = position;
+        self.velocity
+    }
+
+    /// Counts over the last window, positive in the direction phase A leads.
+    pub fn counts_per_window(&self) -> i32 {
+        self.velocity
+    }
+
+    /// Speed in thousandths of a revolution per minute.
+    pub fn milli_rpm(&self) -> i32 {
+        milli_rpm(self.velocity, self.counts_per_rev, self.window)
+    }
+
+    /// Angular speed in rad/s, Q16.16 fixed point.
+    pub fn rad_per_sec_q16(&self) -> i32 {
+        rad_per_sec_q16(self.velocity, self.counts_per_rev, self.window)
+    }
+}
+
+fn delta(last: u16, position: u16) -> i32 {
+    position.wrapping_sub(last) as i16 as i32
+}
+
+fn milli_rpm(counts: i32, counts_per_rev: u32, window: Microseconds) -> i32 {
+    (counts as i64 * 60_000_000_000 / (counts_per_rev as i64 * window.0 as i64)) as i32
+}
+
+fn rad_per_sec_q16(counts: i32, counts_per_rev: u32, window: Microseconds) -> i32 {
+    (counts as i64 * TWO_PI_Q16 * 1_000_000 / (counts_per_rev as i64 * window.0 as i64)) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraparound_and_units() {
+        assert_eq!(delta(0xFFF0, 0x0010), 32);
+        assert_eq!(delta(0x0010, 0xFFF0), -32);
+        // 1000 line encoder at 600 RPM, sampled every 10 ms.
+        let counts = 4000 * 10 / 100;
+        assert_eq!(milli_rpm(counts, 4000, Microseconds(10_000)), 600_000);
+        // 20π rad/s.
+        assert_eq!(rad_per_sec_q16(counts, 4000, Microseconds(10_000)), 4_117_750);
+    }
+}
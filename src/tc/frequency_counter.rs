@@ -0,0 +1,75 @@
+use embedded_hal::timer::CountDown;
+use crate::time::{Hertz, Microseconds};
+use super::{Block, Channel, ClockSource, Instance, TclkPin, Xc0Source, Xc1Source, Xc2Source};
+
+/// Counts the edges on the channel's TCLK pin while `gate` runs for
+/// `gate_time`. The counter is only 16 bits wide, so overflows are tallied
+/// while polling the gate.
+pub struct FrequencyCounter<TC, const CH: u8, PIN, GATE> {
+    channel: Channel<TC, CH>,
+    pin: PIN,
+    gate: GATE,
+    gate_time: Microseconds,
+}
+
+impl<TC, const CH: u8, PIN, GATE> FrequencyCounter<TC, CH, PIN, GATE>
+where
+    TC: Instance,
+    PIN: TclkPin<TC, CH>,
+    GATE: CountDown<Time = Microseconds>,
+{
+    pub fn new(
+        mut channel: Channel<TC, CH>,
+        block: &mut Block<TC>,
+        pin: PIN,
+        gate: GATE,
+        gate_time: Microseconds) -> Self {
+        assert!(gate_time.0 > 0);
+        let source = match CH {
+            0 => {
+                block.set_xc0(Xc0Source::Tclk0);
+                ClockSource::Xc0
+            }
+            1 => {
+                block.set_xc1(Xc1Source::Tclk1);
+                ClockSource::Xc1
+            }
+            _ => {
+                block.set_xc2(Xc2Source::Tclk2);
+                ClockSource::Xc2
+            }
+        };
+        channel.set_clock_source(source);
+        channel.set_external_trigger(None);
+        FrequencyCounter { channel, pin, gate, gate_time }
+    }
+
+    pub fn set_gate_time(&mut self, gate_time: Microseconds) {
+        assert!(gate_time.0 > 0);
+        self.gate_time = gate_time;
+    }
+
+    pub fn read(&mut self) -> Hertz {
+        let mut overflows = 0u64;
+        self.channel.read_status();
+        self.channel.enable();
+        self.channel.trigger();
+        self.gate.start(self.gate_time);
+        while self.gate.wait().is_err() {
+            if self.channel.read_status().overflow {
+                overflows += 1;
+            }
+        }
+        self.channel.disable();
+        if self.channel.read_status().overflow {
+            overflows += 1;
+        }
+
+        let count = (overflows << 16) + self.channel.counter() as u64;
+        Hertz((count * 1_000_000 / self.gate_time.0 as u64) as u32)
+    }
+
+    pub fn free(self) -> (Channel<TC, CH>, PIN, GATE) {
+        (self.channel, self.pin, self.gate)
+    }
+}
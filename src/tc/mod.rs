@@ -0,0 +1,557 @@
+use core::marker::PhantomData;
+use embedded_hal::timer::{CountDown, Periodic};
+use void::Void;
+use crate::clocks::Clocks;
+use crate::gpio::{pioa::*, pioc::*, piod::*, pioe::*, Alternate, AF1, AF2};
+use crate::pac::tc0::{bmr::{TC0XC0S_A, TC1XC1S_A, TC2XC2S_A}, cmr0::{ETRGEDG_A, TCCLKS_A}, RegisterBlock};
+use crate::pac::{PMC, TC0, TC1, TC2, TC3};
+use crate::pmc::PeripheralId;
+use crate::time::{Hertz, Microseconds};
+use crate::write_protect::WriteProtect;
+
+pub mod encoder;
+pub mod frequency_counter;
+pub mod one_shot;
+pub mod pwm_input;
+pub mod soft_pwm;
+
+const CHANNEL_OFFSET: usize = 0x40;
+const SLOW_CLOCK: u32 = 32_768;
+const COUNTER_MAX: u64 = 0xFFFF;
+
+pub trait Instance: PeripheralId {
+    fn ptr() -> *const RegisterBlock;
+}
+
+macro_rules! tc_instance {
+    ($($TC:ident,)+) => {
+        $(
+            impl Instance for $TC {
+                fn ptr() -> *const RegisterBlock {
+                    $TC::ptr() as *const RegisterBlock
+                }
+            }
+        )+
+    }
+}
+
+tc_instance! {
+    TC0,
+    TC1,
+    TC2,
+    TC3,
+}
+
+pub trait TclkPin<TC, const CH: u8> {}
+pub trait TioaPin<TC, const CH: u8> {}
+pub trait TiobPin<TC, const CH: u8> {}
+
+macro_rules! tc_pins {
+    ($($TC:ident => {
+        $($ch:literal: [$tclk:ident: $tclkaf:ident, $tioa:ident: $tioaaf:ident, $tiob:ident: $tiobaf:ident],)+
+    })+) => {
+        $(
+            $(
+                impl TclkPin<$TC, $ch> for $tclk<Alternate<$tclkaf>> {}
+                impl TioaPin<$TC, $ch> for $tioa<Alternate<$tioaaf>> {}
+                impl TiobPin<$TC, $ch> for $tiob<Alternate<$tiobaf>> {}
+            )+
+        )+
+    }
+}
+
+tc_pins! {
+    TC0 => {
+        0: [PA4: AF1, PA0: AF1, PA1: AF1],
+        1: [PA28: AF1, PA15: AF1, PA16: AF1],
+        2: [PA29: AF1, PA26: AF1, PA27: AF1],
+    }
+    TC1 => {
+        0: [PC25: AF1, PC23: AF1, PC24: AF1],
+        1: [PC28: AF1, PC26: AF1, PC27: AF1],
+        2: [PC31: AF1, PC29: AF1, PC30: AF1],
+    }
+    TC2 => {
+        0: [PC7: AF1, PC5: AF1, PC6: AF1],
+        1: [PC10: AF1, PC8: AF1, PC9: AF1],
+        2: [PC14: AF1, PC11: AF1, PC12: AF1],
+    }
+    TC3 => {
+        0: [PE2: AF1, PE0: AF1, PE1: AF1],
+        1: [PE5: AF1, PE3: AF1, PE4: AF1],
+        2: [PD24: AF2, PD21: AF2, PD22: AF2],
+    }
+}
+
+/// A timer period out of reach of the counter and its clocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerError {
+    /// Longer than the counter holds on its slowest clock, or a zero
+    /// frequency.
+    PeriodTooLong,
+    /// Shorter than two ticks of the fastest clock.
+    PeriodTooShort,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockSource {
+    Pck6,
+    MckDiv8,
+    MckDiv32,
+    MckDiv128,
+    SlowClock,
+    Xc0,
+    Xc1,
+    Xc2,
+}
+
+pub enum Xc0Source {
+    Tclk0,
+    Tioa1,
+    Tioa2,
+}
+
+pub enum Xc1Source {
+    Tclk1,
+    Tioa0,
+    Tioa2,
+}
+
+pub enum Xc2Source {
+    Tclk2,
+    Tioa0,
+    Tioa1,
+}
+
+pub enum TriggerInput {
+    Tioa,
+    Tiob,
+}
+
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+pub struct ExternalTrigger {
+    input: TriggerInput,
+    edge: Edge,
+}
+
+impl ExternalTrigger {
+    pub fn new(input: TriggerInput, edge: Edge) -> ExternalTrigger {
+        ExternalTrigger { input, edge }
+    }
+}
+
+/// Fastest internal counter clock accepted by `fits`, if any.
+fn select_clock<F>(mck: Hertz, fits: F) -> Option<(ClockSource, Hertz)>
+where
+    F: Fn(Hertz) -> bool,
+{
+    let clocks = [
+        (ClockSource::MckDiv8, Hertz(mck.0 / 8)),
+        (ClockSource::MckDiv32, Hertz(mck.0 / 32)),
+        (ClockSource::MckDiv128, Hertz(mck.0 / 128)),
+        (ClockSource::SlowClock, Hertz(SLOW_CLOCK)),
+    ];
+    IntoIterator::into_iter(clocks).find(|&(_, clock)| fits(clock))
+}
+
+/// RC and number of counter periods for `ticks`, spread evenly over as few
+/// periods as fit in the counter.
+fn split_ticks(ticks: u64) -> (u32, u32) {
+    let periods = ticks.div_ceil(COUNTER_MAX).max(1);
+    (((ticks + periods / 2) / periods).max(1) as u32, periods as u32)
+}
+
+pub enum Event {
+    Overflow,
+    CompareA,
+    CompareB,
+    CompareC,
+    LoadedA,
+    LoadedB,
+    ExternalTrigger,
+}
+
+impl Event {
+    /// Bit of the event in the status and interrupt registers.
+    fn mask(&self) -> u32 {
+        match self {
+            Event::Overflow => 1 << 0,
+            Event::CompareA => 1 << 2,
+            Event::CompareB => 1 << 3,
+            Event::CompareC => 1 << 4,
+            Event::LoadedA => 1 << 5,
+            Event::LoadedB => 1 << 6,
+            Event::ExternalTrigger => 1 << 7,
+        }
+    }
+}
+
+pub struct Status {
+    pub overflow: bool,
+    pub load_overrun: bool,
+    pub compare_a: bool,
+    pub compare_b: bool,
+    pub compare_c: bool,
+    pub loaded_a: bool,
+    pub loaded_b: bool,
+    pub external_trigger: bool,
+}
+
+pub trait TcExt: Sized {
+    fn split(self, pmc: &PMC) -> Parts<Self>;
+}
+
+impl<TC: Instance> TcExt for TC {
+    fn split(self, pmc: &PMC) -> Parts<TC> {
+        pmc.with_unlocked(|pmc| unsafe {
+            for pid in TC::PID..TC::PID + 3 {
+                if pid < 32 {
+                    pmc.pmc_pcer0.write_with_zero(|w| w.bits(1 << pid));
+                } else {
+                    pmc.pmc_pcer1.write_with_zero(|w| w.bits(1 << (pid - 32)));
+                }
+            }
+        });
+        Parts {
+            block: Block { _tc: PhantomData },
+            ch0: Channel { _tc: PhantomData },
+            ch1: Channel { _tc: PhantomData },
+            ch2: Channel { _tc: PhantomData },
+            tc: self,
+        }
+    }
+}
+
+pub struct Parts<TC> {
+    pub block: Block<TC>,
+    pub ch0: Channel<TC, 0>,
+    pub ch1: Channel<TC, 1>,
+    pub ch2: Channel<TC, 2>,
+    tc: TC,
+}
+
+impl<TC: Instance> Parts<TC> {
+    pub fn free(mut self, pmc: &PMC) -> TC {
+        self.ch0.disable();
+        self.ch1.disable();
+        self.ch2.disable();
+        pmc.with_unlocked(|pmc| unsafe {
+            for pid in TC::PID..TC::PID + 3 {
+                if pid < 32 {
+                    pmc.pmc_pcdr0.write_with_zero(|w| w.bits(1 << pid));
+                } else {
+                    pmc.pmc_pcdr1.write_with_zero(|w| w.bits(1 << (pid - 32)));
+                }
+            }
+        });
+        self.tc
+    }
+}
+
+/// Registers shared by the three channels of a TC: the external clock
+/// routing (XC0..XC2) and the synchronized software trigger.
+pub struct Block<TC> {
+    _tc: PhantomData<TC>,
+}
+
+impl<TC: Instance> Block<TC> {
+    /// Registers of the whole TC block, channels included.
+    ///
+    /// # Safety
+    /// Channels handed out by `split` may be in use elsewhere.
+    pub unsafe fn registers(&self) -> &RegisterBlock {
+        &*TC::ptr()
+    }
+
+    pub fn set_xc0(&mut self, source: Xc0Source) {
+        let source = match source {
+            Xc0Source::Tclk0 => TC0XC0S_A::TCLK0,
+            Xc0Source::Tioa1 => TC0XC0S_A::TIOA1,
+            Xc0Source::Tioa2 => TC0XC0S_A::TIOA2,
+        };
+        unsafe { self.registers().with_unlocked(|tc| tc.bmr.modify(|_, w| w.tc0xc0s().variant(source))) };
+    }
+
+    pub fn set_xc1(&mut self, source: Xc1Source) {
+        let source = match source {
+            Xc1Source::Tclk1 => TC1XC1S_A::TCLK1,
+            Xc1Source::Tioa0 => TC1XC1S_A::TIOA0,
+            Xc1Source::Tioa2 => TC1XC1S_A::TIOA2,
+        };
+        unsafe { self.registers().with_unlocked(|tc| tc.bmr.modify(|_, w| w.tc1xc1s().variant(source))) };
+    }
+
+    pub fn set_xc2(&mut self, source: Xc2Source) {
+        let source = match source {
+            Xc2Source::Tclk2 => TC2XC2S_A::TCLK2,
+            Xc2Source::Tioa0 => TC2XC2S_A::TIOA0,
+            Xc2Source::Tioa1 => TC2XC2S_A::TIOA1,
+        };
+        unsafe { self.registers().with_unlocked(|tc| tc.bmr.modify(|_, w| w.tc2xc2s().variant(source))) };
+    }
+
+    /// Counts the edges of both phases, TIOA0 and TIOB0, on channel 0, up
+    /// or down with the direction. See `encoder::Encoder`.
+    pub fn set_quadrature_decoder(&mut self, enable: bool) {
+        unsafe {
+            self.registers().with_unlocked(|tc| tc.bmr.modify(|_, w| {
+                w.qden().bit(enable)
+                    .posen().bit(enable)
+                    .edgpha().bit(enable)
+            }));
+        }
+    }
+
+    pub fn sync(&mut self) {
+        unsafe { self.registers().bcr.write_with_zero(|w| w.sync().set_bit()) };
+    }
+}
+
+pub struct Channel<TC, const CH: u8> {
+    _tc: PhantomData<TC>,
+}
+
+impl<TC: Instance, const CH: u8> Channel<TC, CH> {
+    /// Every channel has the same layout as channel 0, 0x40 bytes apart, so
+    /// the channel 0 fields of the returned block address this channel.
+    fn registers(&self) -> &RegisterBlock {
+        unsafe { &*((TC::ptr() as usize + CHANNEL_OFFSET * CH as usize) as *const RegisterBlock) }
+    }
+
+    fn with_unlocked<F: FnOnce(&RegisterBlock)>(&self, f: F) {
+        let channel = self.registers();
+        unsafe { (*TC::ptr()).with_unlocked(|_| f(channel)) };
+    }
+
+    pub fn set_clock_source(&mut self, source: ClockSource) {
+        let source = match source {
+            ClockSource::Pck6 => TCCLKS_A::TIMER_CLOCK1,
+            ClockSource::MckDiv8 => TCCLKS_A::TIMER_CLOCK2,
+            ClockSource::MckDiv32 => TCCLKS_A::TIMER_CLOCK3,
+            ClockSource::MckDiv128 => TCCLKS_A::TIMER_CLOCK4,
+            ClockSource::SlowClock => TCCLKS_A::TIMER_CLOCK5,
+            ClockSource::Xc0 => TCCLKS_A::XC0,
+            ClockSource::Xc1 => TCCLKS_A::XC1,
+            ClockSource::Xc2 => TCCLKS_A::XC2,
+        };
+        self.with_unlocked(|ch| ch.cmr0().modify(|_, w| w.tcclks().variant(source)));
+    }
+
+    /// Resets and starts the counter on the selected edge of TIOA or TIOB.
+    pub fn set_external_trigger(&mut self, trigger: Option<ExternalTrigger>) {
+        let (edge, tioa) = match trigger {
+            None => (ETRGEDG_A::NONE, false),
+            Some(trigger) => {
+                let edge = match trigger.edge {
+                    Edge::Rising => ETRGEDG_A::RISING,
+                    Edge::Falling => ETRGEDG_A::FALLING,
+                    Edge::Both => ETRGEDG_A::EDGE,
+                };
+                (edge, matches!(trigger.input, TriggerInput::Tioa))
+            }
+        };
+        self.with_unlocked(|ch| ch.cmr0().modify(|_, w| w.etrgedg().variant(edge).abetrg().bit(tioa)));
+    }
+
+    pub fn enable(&mut self) {
+        unsafe { self.registers().ccr0.write_with_zero(|w| w.clken().set_bit()) };
+    }
+
+    pub fn disable(&mut self) {
+        unsafe { self.registers().ccr0.write_with_zero(|w| w.clkdis().set_bit()) };
+    }
+
+    pub fn trigger(&mut self) {
+        unsafe { self.registers().ccr0.write_with_zero(|w| w.swtrg().set_bit()) };
+    }
+
+    pub fn listen(&mut self, event: Event) {
+        unsafe { self.registers().ier0.write_with_zero(|w| w.bits(event.mask())) };
+    }
+
+    pub fn unlisten(&mut self, event: Event) {
+        unsafe { self.registers().idr0.write_with_zero(|w| w.bits(event.mask())) };
+    }
+
+    pub fn counter(&self) -> u16 {
+        self.registers().cv0.read().bits() as u16
+    }
+
+    /// Status flags are cleared by the read.
+    pub fn read_status(&self) -> Status {
+        let sr = self.registers().sr0.read();
+        Status {
+            overflow: sr.covfs().bit(),
+            load_overrun: sr.lovrs().bit(),
+            compare_a: sr.cpas().bit(),
+            compare_b: sr.cpbs().bit(),
+            compare_c: sr.cpcs().bit(),
+            loaded_a: sr.ldras().bit(),
+            loaded_b: sr.ldrbs().bit(),
+            external_trigger: sr.etrgs().bit(),
+        }
+    }
+
+    /// Runs the counter with one rising edge on the internal TIOA every
+    /// `period`, to trigger other peripherals such as the AFEC.
+    pub(crate) fn start_trigger_output(&mut self, clocks: &Clocks, period: Microseconds) {
+        self.start_trigger(clocks, |clock| clock.0 as u64 * period.0 as u64 / 1_000_000);
+    }
+
+    /// Same as `start_trigger_output`, `rate` times per second.
+    pub(crate) fn start_trigger_rate(&mut self, clocks: &Clocks, rate: Hertz) {
+        self.start_trigger(clocks, |clock| (clock.0 / rate.0) as u64);
+    }
+
+    fn start_trigger(&mut self, clocks: &Clocks, ticks: impl Fn(Hertz) -> u64) {
+        use crate::pac::tc0::waveform_mode_cmr0_waveform_mode::{ACPA_A, ACPC_A};
+
+        let (source, clock) = select_clock(clocks.mck(), |clock| ticks(clock) <= COUNTER_MAX)
+            .expect("trigger period too long for the TC counter");
+        let rc = ticks(clock).max(2) as u32;
+
+        self.disable();
+        self.with_unlocked(|ch| unsafe {
+            ch.waveform_mode_cmr0_waveform_mode().write(|w| {
+                w.wave().set_bit()
+                    .wavsel().up_rc()
+                    .acpa().variant(ACPA_A::SET)
+                    .acpc().variant(ACPC_A::CLEAR)
+            });
+            ch.ra0.write(|w| w.ra().bits(rc / 2));
+            ch.rc0.write(|w| w.rc().bits(rc));
+        });
+        self.set_clock_source(source);
+        self.enable();
+        self.trigger();
+    }
+
+    pub fn into_timer(self, clocks: &Clocks) -> Timer<TC, CH> {
+        Timer { channel: self, mck: clocks.mck(), periods: 1, remaining: 1 }
+    }
+}
+
+pub struct Timer<TC, const CH: u8> {
+    channel: Channel<TC, CH>,
+    mck: Hertz,
+    /// Counter periods per timeout, more than one for timeouts longer than
+    /// the counter holds on the slow clock.
+    periods: u32,
+    remaining: u32,
+}
+
+impl<TC: Instance, const CH: u8> Timer<TC, CH> {
+    /// Interrupts at the end of every counter period, several times per
+    /// timeout for the long ones `start` splits.
+    pub fn listen(&mut self) {
+        self.channel.listen(Event::CompareC);
+    }
+
+    pub fn unlisten(&mut self) {
+        self.channel.unlisten(Event::CompareC);
+    }
+
+    pub fn free(mut self) -> Channel<TC, CH> {
+        self.channel.unlisten(Event::CompareC);
+        self.channel.disable();
+        self.channel.with_unlocked(|ch| ch.cmr0().reset());
+        self.channel
+    }
+}
+
+impl<TC: Instance, const CH: u8> Timer<TC, CH> {
+    /// Same as `start`, but fails rather than counting several counter
+    /// periods in software, so that `listen` interrupts once per timeout.
+    pub fn try_start<T>(&mut self, timeout: T) -> Result<(), TimerError>
+    where
+        T: Into<Microseconds>,
+    {
+        let us = timeout.into().0 as u64;
+        let ticks = |clock: Hertz| clock.0 as u64 * us / 1_000_000;
+        let (source, clock) = select_clock(self.mck, |clock| ticks(clock) <= COUNTER_MAX)
+            .ok_or(TimerError::PeriodTooLong)?;
+        self.run(source, ticks(clock).max(1) as u32, 1);
+        Ok(())
+    }
+
+    fn run(&mut self, source: ClockSource, rc: u32, periods: u32) {
+        self.periods = periods;
+        self.remaining = periods;
+        self.channel.disable();
+        self.channel.with_unlocked(|ch| unsafe {
+            ch.waveform_mode_cmr0_waveform_mode().write(|w| w.wave().set_bit().wavsel().up_rc());
+            ch.rc0.write(|w| w.rc().bits(rc));
+        });
+        self.channel.set_clock_source(source);
+        self.channel.read_status();
+        self.channel.enable();
+        self.channel.trigger();
+    }
+}
+
+impl<TC: Instance, const CH: u8> CountDown for Timer<TC, CH> {
+    type Time = Microseconds;
+
+    /// Picks the fastest clock that fits the timeout in the 16-bit counter.
+    /// Past about 2 s even the slow clock overflows, and the timeout is then
+    /// counted in several periods of the counter.
+    fn start<T>(&mut self, timeout: T)
+    where
+        T: Into<Microseconds>,
+    {
+        let us = timeout.into().0 as u64;
+        let ticks = |clock: Hertz| clock.0 as u64 * us / 1_000_000;
+        match select_clock(self.mck, |clock| ticks(clock) <= COUNTER_MAX) {
+            Some((source, clock)) => self.run(source, ticks(clock).max(1) as u32, 1),
+            None => {
+                let (rc, periods) = split_ticks(ticks(Hertz(SLOW_CLOCK)));
+                self.run(ClockSource::SlowClock, rc, periods);
+            }
+        }
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        if !self.channel.read_status().compare_c {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.remaining = self.periods;
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl<TC: Instance, const CH: u8> Periodic for Timer<TC, CH> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fastest_clock_that_fits() {
+        let mck = Hertz(150_000_000);
+        // 1 kHz on the 16-bit RC needs at most 65.5 MHz: MCK/8 does.
+        assert_eq!(select_clock(mck, |clock| clock.0 / 1_000 <= 0xFFFF), Some((ClockSource::MckDiv8, Hertz(18_750_000))));
+        // 100 Hz: 4.69 MHz is the first below 6.55 MHz.
+        assert_eq!(select_clock(mck, |clock| clock.0 / 100 <= 0xFFFF), Some((ClockSource::MckDiv32, Hertz(4_687_500))));
+        // 1 Hz only fits on the slow clock.
+        assert_eq!(select_clock(mck, |clock| clock.0 <= 0xFFFF), Some((ClockSource::SlowClock, Hertz(32_768))));
+        assert_eq!(select_clock(mck, |clock| clock.0 * 3 <= 0xFFFF), None);
+    }
+
+    #[test]
+    fn long_timeouts_split() {
+        assert_eq!(split_ticks(1_000), (1_000, 1));
+        assert_eq!(split_ticks(COUNTER_MAX), (COUNTER_MAX as u32, 1));
+        // 10 s on the slow clock: six periods of 1.67 s.
+        assert_eq!(split_ticks(327_680), (54_613, 6));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::clocks::Clocks;
+use crate::pac::tc0::waveform_mode_cmr0_waveform_mode::{ACPA_A, ACPC_A, AEEVT_A, ASWTRG_A, EEVTEDG_A, EEVT_A};
+use crate::time::{Hertz, Microseconds};
+use super::{select_clock, Channel, Edge, Instance, TioaPin, COUNTER_MAX};
+
+pub enum Trigger {
+    Software,
+    Tiob(Edge),
+}
+
+/// Generates a single pulse on TIOA, `delay` after the trigger and `width`
+/// long. The counter clock stops on the RC compare at the end of the pulse
+/// and the next trigger starts it again.
+pub struct OneShot<TC, const CH: u8, PIN> {
+    channel: Channel<TC, CH>,
+    pin: PIN,
+    mck: Hertz,
+}
+
+impl<TC, const CH: u8, PIN> OneShot<TC, CH, PIN>
+where
+    TC: Instance,
+    PIN: TioaPin<TC, CH>,
+{
+    pub fn new(
+        mut channel: Channel<TC, CH>,
+        pin: PIN,
+        clocks: &Clocks,
+        trigger: Trigger,
+        delay: Microseconds,
+        width: Microseconds) -> Self {
+        let (eevt, edge) = match trigger {
+            Trigger::Software => (EEVT_A::XC0, EEVTEDG_A::NONE),
+            Trigger::Tiob(Edge::Rising) => (EEVT_A::TIOB, EEVTEDG_A::RISING),
+            Trigger::Tiob(Edge::Falling) => (EEVT_A::TIOB, EEVTEDG_A::FALLING),
+            Trigger::Tiob(Edge::Both) => (EEVT_A::TIOB, EEVTEDG_A::EDGE),
+        };
+
+        channel.disable();
+        channel.with_unlocked(|ch| {
+            ch.waveform_mode_cmr0_waveform_mode().write(|w|
+                w.wave().set_bit()
+                    .wavsel().up_rc()
+                    .cpcstop().set_bit()
+                    .eevt().variant(eevt)
+                    .eevtedg().variant(edge)
+                    .enetrg().bit(!matches!(edge, EEVTEDG_A::NONE))
+                    .acpa().variant(ACPA_A::SET)
+                    .acpc().variant(ACPC_A::CLEAR)
+                    .aeevt().variant(AEEVT_A::CLEAR)
+                    .aswtrg().variant(ASWTRG_A::CLEAR)
+            );
+        });
+        let mut one_shot = OneShot { channel, pin, mck: clocks.mck() };
+        one_shot.set_timing(delay, width);
+        one_shot.channel.enable();
+        one_shot
+    }
+
+    pub fn set_timing(&mut self, delay: Microseconds, width: Microseconds) {
+        let end = delay.0 as u64 + width.0 as u64;
+        let ticks = |clock: Hertz, us: u64| clock.0 as u64 * us / 1_000_000;
+        let (source, clock) = select_clock(self.mck, |clock| ticks(clock, end) <= COUNTER_MAX)
+            .expect("pulse too long for the TC counter");
+
+        self.channel.with_unlocked(|ch| unsafe {
+            ch.ra0.write(|w| w.ra().bits(ticks(clock, delay.0 as u64).max(1) as u32));
+            ch.rc0.write(|w| w.rc().bits(ticks(clock, end).max(2) as u32));
+        });
+        self.channel.set_clock_source(source);
+    }
+
+    pub fn fire(&mut self) {
+        self.channel.trigger();
+    }
+
+    /// True once the pulse has ended, until the next trigger.
+    pub fn is_done(&self) -> bool {
+        self.channel.read_status().compare_c
+    }
+
+    pub fn free(mut self) -> (Channel<TC, CH>, PIN) {
+        self.channel.disable();
+        self.channel.with_unlocked(|ch| ch.cmr0().reset());
+        (self.channel, self.pin)
+    }
+}
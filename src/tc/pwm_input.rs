@@ -0,0 +1,68 @@
+use void::Void;
+use crate::clocks::Clocks;
+use super::TimerError;
+use crate::pac::tc0::cmr0::{ETRGEDG_A, LDRA_A, LDRB_A};
+use crate::time::Hertz;
+use super::{select_clock, Channel, Instance, TioaPin, COUNTER_MAX};
+
+/// Measures a PWM signal on TIOA. Every rising edge restarts the counter and
+/// loads RB with the period, every falling edge loads RA with the high time.
+pub struct PwmInput<TC, const CH: u8, PIN> {
+    channel: Channel<TC, CH>,
+    pin: PIN,
+    clock: Hertz,
+}
+
+impl<TC, const CH: u8, PIN> PwmInput<TC, CH, PIN>
+where
+    TC: Instance,
+    PIN: TioaPin<TC, CH>,
+{
+    /// `min_frequency` is the slowest signal that must still fit in the
+    /// counter; the fastest counter clock allowing it is selected, down to
+    /// the slow clock for 1 Hz.
+    pub fn new(mut channel: Channel<TC, CH>, pin: PIN, clocks: &Clocks, min_frequency: Hertz) -> Result<Self, TimerError> {
+        if min_frequency.0 == 0 {
+            return Err(TimerError::PeriodTooLong);
+        }
+        let (source, clock) = select_clock(clocks.mck(), |clock| {
+            (clock.0 / min_frequency.0) as u64 <= COUNTER_MAX
+        }).ok_or(TimerError::PeriodTooLong)?;
+
+        channel.disable();
+        channel.with_unlocked(|ch| {
+            ch.cmr0().write(|w|
+                w.etrgedg().variant(ETRGEDG_A::RISING)
+                    .abetrg().set_bit()
+                    .ldra().variant(LDRA_A::FALLING)
+                    .ldrb().variant(LDRB_A::RISING)
+            );
+        });
+        channel.set_clock_source(source);
+        channel.read_status();
+        channel.enable();
+        channel.trigger();
+        Ok(PwmInput { channel, pin, clock })
+    }
+
+    /// Returns the frequency and duty cycle (0.0 to 1.0) once a new period
+    /// has been captured.
+    pub fn read(&mut self) -> nb::Result<(Hertz, f32), Void> {
+        if !self.channel.read_status().loaded_b {
+            return Err(nb::Error::WouldBlock);
+        }
+        let ch = self.channel.registers();
+        let high = ch.ra0.read().ra().bits();
+        let period = ch.rb0.read().rb().bits();
+        if period == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok((Hertz(self.clock.0 / period), high as f32 / period as f32))
+    }
+
+    pub fn free(mut self) -> (Channel<TC, CH>, PIN) {
+        self.channel.disable();
+        self.channel.with_unlocked(|ch| ch.cmr0().reset());
+        (self.channel, self.pin)
+    }
+}
@@ -0,0 +1,114 @@
+//! PWM in software on any output pins, for slow loads such as LEDs or
+//! heaters on pins the PWM peripheral does not reach.
+//!
+//! ```ignore
+//! let pins = [pa5.into_output(cs).downgrade(), pc8.into_output(cs).downgrade()];
+//! let mut pwm = SoftPwm::new(tc0.ch0.into_timer(&clocks), pins, 200.hz(), 100)?;
+//! pwm.set_duty(1, 25);
+//!
+//! // From the TC0 channel 0 interrupt:
+//! pwm.on_interrupt();
+//! ```
+
+use core::convert::Infallible;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::timer::CountDown;
+use crate::time::{Hertz, Microseconds};
+use super::{Instance, Timer, TimerError};
+
+/// Interrupt period, in µs, of `steps` steps per period at `frequency`.
+fn tick(frequency: Hertz, steps: u16) -> Result<u32, TimerError> {
+    match frequency.0.checked_mul(steps as u32) {
+        Some(0) => Err(TimerError::PeriodTooLong),
+        Some(rate) if rate <= 1_000_000 => Ok(1_000_000 / rate),
+        _ => Err(TimerError::PeriodTooShort),
+    }
+}
+
+/// Drives `N` pins from the interrupts of one timer, each with its own duty
+/// cycle out of `steps`. The timer interrupts `steps` times per period, so
+/// the product of frequency and steps bounds the CPU load; the edges also
+/// move by the interrupt latency.
+pub struct SoftPwm<TC, const CH: u8, P, const N: usize> {
+    timer: Timer<TC, CH>,
+    pins: [P; N],
+    duty: [u16; N],
+    steps: u16,
+    phase: u16,
+}
+
+impl<TC, const CH: u8, P, const N: usize> SoftPwm<TC, CH, P, N>
+where
+    TC: Instance,
+    P: OutputPin<Error = Infallible>,
+{
+    /// All pins start low. Fails for 0 Hz, and when frequency times steps
+    /// is above 1 MHz.
+    pub fn new(mut timer: Timer<TC, CH>, mut pins: [P; N], frequency: Hertz, steps: u16) -> Result<Self, TimerError> {
+        assert!(steps > 0, "soft PWM needs at least one step");
+        timer.try_start(Microseconds(tick(frequency, steps)?))?;
+        for pin in pins.iter_mut() {
+            pin.set_low().ok();
+        }
+        timer.listen();
+        Ok(SoftPwm { timer, pins, duty: [0; N], steps, phase: 0 })
+    }
+
+    /// Takes effect from the next period.
+    pub fn set_duty(&mut self, pin: usize, duty: u16) {
+        self.duty[pin] = duty.min(self.steps);
+    }
+
+    pub fn duty(&self, pin: usize) -> u16 {
+        self.duty[pin]
+    }
+
+    pub fn max_duty(&self) -> u16 {
+        self.steps
+    }
+
+    /// Advances one step, switching only the pins whose edge falls on it.
+    /// To be called from the timer interrupt.
+    pub fn on_interrupt(&mut self) {
+        if self.timer.wait().is_err() {
+            return;
+        }
+        self.phase = (self.phase + 1) % self.steps;
+        for (pin, &duty) in self.pins.iter_mut().zip(self.duty.iter()) {
+            if self.phase == duty && duty != self.steps {
+                pin.set_low().ok();
+            } else if self.phase == 0 && duty != 0 {
+                pin.set_high().ok();
+            }
+        }
+    }
+
+    /// Leaves the pins low.
+    pub fn free(mut self) -> (Timer<TC, CH>, [P; N]) {
+        self.timer.unlisten();
+        for pin in self.pins.iter_mut() {
+            pin.set_low().ok();
+        }
+        (self.timer, self.pins)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks() {
+        assert_eq!(tick(Hertz(200), 100), Ok(50));
+        assert_eq!(tick(Hertz(10_000), 100), Ok(1));
+        assert_eq!(tick(Hertz(1), 1), Ok(1_000_000));
+    }
+
+    #[test]
+    fn unreachable_ticks() {
+        assert_eq!(tick(Hertz(0), 100), Err(TimerError::PeriodTooLong));
+        assert_eq!(tick(Hertz(20_000), 100), Err(TimerError::PeriodTooShort));
+        // The product overflows u32.
+        assert_eq!(tick(Hertz(u32::MAX / 2), 3), Err(TimerError::PeriodTooShort));
+    }
+}
@@ -0,0 +1,60 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Hertz(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Bps(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Microseconds(pub u32);
+
+impl From<u32> for Bps {
+    fn from(bps: u32) -> Self {
+        Bps(bps)
+    }
+}
+
+impl From<u16> for Bps {
+    fn from(bps: u16) -> Self {
+        Bps(bps as u32)
+    }
+}
+
+pub trait U32Ext {
+    fn bps(self) -> Bps;
+
+    fn hz(self) -> Hertz;
+
+    fn khz(self) -> Hertz;
+
+    fn mhz(self) -> Hertz;
+
+    fn us(self) -> Microseconds;
+
+    fn ms(self) -> Microseconds;
+}
+
+impl U32Ext for u32 {
+    fn bps(self) -> Bps {
+        Bps(self)
+    }
+
+    fn hz(self) -> Hertz {
+        Hertz(self)
+    }
+
+    fn khz(self) -> Hertz {
+        Hertz(self * 1_000)
+    }
+
+    fn mhz(self) -> Hertz {
+        Hertz(self * 1_000_000)
+    }
+
+    fn us(self) -> Microseconds {
+        Microseconds(self)
+    }
+
+    fn ms(self) -> Microseconds {
+        Microseconds(self * 1_000)
+    }
+}
@@ -0,0 +1,121 @@
+//! Debug output through the ITM stimulus ports and the SWO pin, leaving the
+//! UARTs to the application. PB5 carries SWO as long as it is left to the
+//! debug port, its reset state.
+//!
+//! The TPIU runs from TRACECLKIN, which is PCK3 rather than the core clock,
+//! so `Trace::new` starts PCK3 on MCK and divides that.
+//!
+//! ```ignore
+//! let mut trace = Trace::new(cp.ITM, &mut cp.TPIU, &mut cp.DCB, Bps(2_000_000), &clocks, &pmc)?;
+//! writeln!(trace.port(0), "mck = {} Hz", clocks.mck().0).ok();
+//! ```
+
+use core::fmt;
+use cortex_m::itm;
+use cortex_m::peripheral::{DCB, ITM, TPIU};
+use crate::clocks::Clocks;
+use crate::pac::PMC;
+use crate::pmc::{PckSource, ProgrammableClockExt};
+use crate::time::{Bps, Hertz};
+
+pub const PORTS: u8 = 32;
+const LOCK_KEY: u32 = 0xC5AC_CE55;
+/// Programmable clock wired to TRACECLKIN.
+const TRACE_PCK: u8 = 3;
+const ACPR_MAX: u32 = 0x1FFF;
+/// Asynchronous SWO output, NRZ encoded.
+const SPPR_NRZ: u32 = 2;
+/// Continuous formatting off, so the stream is plain ITM packets.
+const FFCR_TRIGIN: u32 = 1 << 8;
+const TCR_ITMENA: u32 = 1 << 0;
+const TCR_SYNCENA: u32 = 1 << 2;
+const TCR_TRACE_BUS_ID: u32 = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceError {
+    /// Faster than the trace clock, or too slow for the SWO prescaler.
+    BaudRate,
+}
+
+pub struct Trace {
+    itm: ITM,
+}
+
+impl Trace {
+    /// Sends all stimulus ports out of SWO at `baud`, derived from MCK
+    /// through PCK3. The debug probe must expect the same rate.
+    pub fn new(itm: ITM, tpiu: &mut TPIU, dcb: &mut DCB, baud: Bps, clocks: &Clocks, pmc: &PMC) -> Result<Self, TraceError> {
+        let prescaler = swo_prescaler(clocks.mck(), baud)?;
+        pmc.enable_pck(TRACE_PCK, PckSource::Mck, 0);
+        dcb.enable_trace();
+        unsafe {
+            tpiu.sppr.write(SPPR_NRZ);
+            tpiu.acpr.write(prescaler);
+            tpiu.ffcr.write(FFCR_TRIGIN);
+
+            itm.lar.write(LOCK_KEY);
+            itm.tcr.write(TCR_TRACE_BUS_ID | TCR_SYNCENA | TCR_ITMENA);
+            itm.tpr.write(0);
+            itm.ter[0].write(u32::MAX);
+        }
+        Ok(Trace { itm })
+    }
+
+    pub fn port(&mut self, port: u8) -> Port<'_> {
+        assert!(port < PORTS, "ITM stimulus port out of range");
+        Port { stim: &mut self.itm.stim[port as usize] }
+    }
+
+    pub fn free(self) -> ITM {
+        unsafe {
+            self.itm.ter[0].write(0);
+            self.itm.tcr.write(0);
+        }
+        self.itm
+    }
+}
+
+/// Writer on one stimulus port. Each write waits for room in the ITM FIFO,
+/// so without a probe attached output only costs the SWO bit rate.
+pub struct Port<'a> {
+    stim: &'a mut cortex_m::peripheral::itm::Stim,
+}
+
+impl Port<'_> {
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        itm::write_all(self.stim, bytes);
+    }
+}
+
+impl fmt::Write for Port<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        itm::write_str(self.stim, s);
+        Ok(())
+    }
+}
+
+/// ACPR for `baud` out of the trace clock `clock`, rounded to the closest
+/// rate.
+fn swo_prescaler(clock: Hertz, baud: Bps) -> Result<u32, TraceError> {
+    if baud.0 == 0 || baud.0 > clock.0 {
+        return Err(TraceError::BaudRate);
+    }
+    let prescaler = (clock.0 + baud.0 / 2) / baud.0 - 1;
+    if prescaler > ACPR_MAX {
+        return Err(TraceError::BaudRate);
+    }
+    Ok(prescaler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prescaler_from_trace_clock() {
+        assert_eq!(swo_prescaler(Hertz(150_000_000), Bps(2_000_000)), Ok(74));
+        assert_eq!(swo_prescaler(Hertz(150_000_000), Bps(150_000_000)), Ok(0));
+        assert_eq!(swo_prescaler(Hertz(150_000_000), Bps(300_000_000)), Err(TraceError::BaudRate));
+        assert_eq!(swo_prescaler(Hertz(150_000_000), Bps(9_600)), Err(TraceError::BaudRate));
+    }
+}
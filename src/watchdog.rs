@@ -1,40 +1,134 @@
 use crate::pac::WDT;
 use embedded_hal::watchdog;
 
+const WATCHDOG_VALUE_MASK: u16 = 0x0FFF;
+
+/// Unconfigured watchdog. WDT_MR can only be written once after reset, so the
+/// watchdog can be turned into a `RunningWatchdog` or a `DisabledWatchdog`
+/// exactly once.
 pub struct Watchdog {
     wdt: WDT,
 }
 
+pub struct RunningWatchdog {
+    wdt: WDT,
+}
+
+pub struct DisabledWatchdog {
+    wdt: WDT,
+}
+
+pub struct Config {
+    period: u16,
+    window: u16,
+    fault_interrupt: bool,
+    reset: bool,
+    halt_on_debug: bool,
+    halt_on_idle: bool,
+}
+
+impl Config {
+    pub fn new(
+        period: u16,
+        window: u16,
+        fault_interrupt: bool,
+        reset: bool,
+        halt_on_debug: bool,
+        halt_on_idle: bool) -> Config {
+        Config {
+            period: period & WATCHDOG_VALUE_MASK,
+            window: window & WATCHDOG_VALUE_MASK,
+            fault_interrupt,
+            reset,
+            halt_on_debug,
+            halt_on_idle,
+        }
+    }
+}
+
+pub struct Status {
+    pub underflow: bool,
+    pub error: bool,
+}
+
 impl Watchdog {
     pub fn new(wdt: WDT) -> Self {
         Self { wdt }
     }
-}
 
-impl watchdog::WatchdogEnable for Watchdog {
-    type Time = u16;
-    fn start<T>(&mut self, period: T)
+    pub fn start<T>(self, period: T) -> RunningWatchdog
     where
-        T: Into<Self::Time>
+        T: Into<u16>
     {
-        const WATCHDOG_VALUE_MASK: u16 = 0x0FFF;
-        let period = period.into() & WATCHDOG_VALUE_MASK;
+        self.start_with_config(Config::new(period.into(), WATCHDOG_VALUE_MASK, false, true, true, true))
+    }
+
+    pub fn start_with_config(self, config: Config) -> RunningWatchdog {
         let mr = &self.wdt.mr;
         mr.write(|w| {
-            w.wddis().clear_bit();
-            unsafe { w.wdv().bits(period) }
+            w.wddis().clear_bit()
+                .wdfien().bit(config.fault_interrupt)
+                .wdrsten().bit(config.reset)
+                .wddbghlt().bit(config.halt_on_debug)
+                .wdidlehlt().bit(config.halt_on_idle);
+            unsafe { w.wdv().bits(config.period).wdd().bits(config.window) }
         });
+        RunningWatchdog { wdt: self.wdt }
     }
-}
 
-impl watchdog::WatchdogDisable for Watchdog {
-    fn disable(&mut self) {
+    pub fn disable(self) -> DisabledWatchdog {
         let mr = &self.wdt.mr;
-        mr.write(|w| w.wddis().set_bit())
+        mr.write(|w| w.wddis().set_bit());
+        DisabledWatchdog { wdt: self.wdt }
+    }
+
+    pub fn free(self) -> WDT {
+        self.wdt
+    }
+
+    /// Raw registers. The mode register can only be written once after
+    /// reset, so there is little left to change here.
+    ///
+    /// # Safety
+    /// Writes to the mode register are lost, or lock in the wrong mode.
+    pub unsafe fn registers(&self) -> &crate::pac::wdt::RegisterBlock {
+        &self.wdt
+    }
+}
+
+impl RunningWatchdog {
+    pub fn read_status(&self) -> Status {
+        let sr = self.wdt.sr.read();
+        Status {
+            underflow: sr.wdunf().bit(),
+            error: sr.wderr().bit(),
+        }
+    }
+
+    pub fn free(self) -> WDT {
+        self.wdt
+    }
+
+    /// # Safety
+    /// See `Watchdog::registers`.
+    pub unsafe fn registers(&self) -> &crate::pac::wdt::RegisterBlock {
+        &self.wdt
+    }
+}
+
+impl DisabledWatchdog {
+    pub fn free(self) -> WDT {
+        self.wdt
+    }
+
+    /// # Safety
+    /// See `Watchdog::registers`.
+    pub unsafe fn registers(&self) -> &crate::pac::wdt::RegisterBlock {
+        &self.wdt
     }
 }
 
-impl watchdog::Watchdog for Watchdog {
+impl watchdog::Watchdog for RunningWatchdog {
     fn feed(&mut self) {
         let cr = &self.wdt.cr;
         unsafe {
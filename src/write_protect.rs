@@ -0,0 +1,116 @@
+pub trait WriteProtect {
+    fn lock(&self);
+
+    fn unlock(&self);
+
+    fn is_locked(&self) -> bool;
+
+    fn with_unlocked<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&Self) -> R
+    {
+        let locked = self.is_locked();
+        if locked {
+            self.unlock();
+        }
+        let result = f(self);
+        if locked {
+            self.lock();
+        }
+        result
+    }
+}
+
+macro_rules! write_protect {
+    ($($per:ident: $wpmr:ident,)+) => {
+        $(
+            impl WriteProtect for crate::pac::$per::RegisterBlock {
+                fn lock(&self) {
+                    self.$wpmr.write(|w| w.wpkey().passwd().wpen().set_bit());
+                }
+
+                fn unlock(&self) {
+                    self.$wpmr.write(|w| w.wpkey().passwd().wpen().clear_bit());
+                }
+
+                fn is_locked(&self) -> bool {
+                    self.$wpmr.read().wpen().bit()
+                }
+            }
+        )+
+    }
+}
+
+write_protect! {
+    acc: wpmr,
+    afec0: wpmr,
+    afec1: wpmr,
+    dacc: wpmr,
+    pioa: wpmr,
+    piob: wpmr,
+    pioc: wpmr,
+    piod: wpmr,
+    pioe: wpmr,
+    pmc: pmc_wpmr,
+    efc: wpmr,
+    matrix: matrix_wpmr,
+    ssc: wpmr,
+    tc0: wpmr,
+    uart0: wpmr,
+    uart1: wpmr,
+    uart2: wpmr,
+    uart3: wpmr,
+    uart4: wpmr,
+    usart0: wpmr,
+    usart1: wpmr,
+    usart2: wpmr,
+}
+
+macro_rules! pwm_write_protect {
+    ($($pwm:ident,)+) => {
+        $(
+            impl WriteProtect for crate::pac::$pwm::RegisterBlock {
+                fn lock(&self) {
+                    unsafe {
+                        self.wpcr.write_with_zero(|w|
+                            w.wpkey().passwd()
+                                .wpcmd().enable_sw_prot()
+                                .wprg0().set_bit()
+                                .wprg1().set_bit()
+                                .wprg2().set_bit()
+                                .wprg3().set_bit()
+                                .wprg4().set_bit()
+                                .wprg5().set_bit()
+                        );
+                    }
+                }
+
+                fn unlock(&self) {
+                    unsafe {
+                        self.wpcr.write_with_zero(|w|
+                            w.wpkey().passwd()
+                                .wpcmd().disable_sw_prot()
+                                .wprg0().set_bit()
+                                .wprg1().set_bit()
+                                .wprg2().set_bit()
+                                .wprg3().set_bit()
+                                .wprg4().set_bit()
+                                .wprg5().set_bit()
+                        );
+                    }
+                }
+
+                fn is_locked(&self) -> bool {
+                    let wpsr = self.wpsr.read();
+                    wpsr.wpsws0().bit() || wpsr.wpsws1().bit() || wpsr.wpsws2().bit()
+                        || wpsr.wpsws3().bit() || wpsr.wpsws4().bit() || wpsr.wpsws5().bit()
+                }
+            }
+        )+
+    }
+}
+
+pwm_write_protect! {
+    pwm0,
+    pwm1,
+}